@@ -1,8 +1,12 @@
 use riichi::algo::agari::{self, AgariCalculator};
 use riichi::algo::shanten;
 use riichi::algo::sp::{InitState, SPCalculator};
+use riichi::batch;
 use riichi::hand::hand;
+use riichi::mjai::Event;
 use riichi::state::PlayerState;
+use riichi::tile::AkaConfig;
+use riichi::wall::Wall;
 use riichi::{t, tu8};
 use std::hint::black_box;
 
@@ -19,6 +23,87 @@ fn shanten(c: &mut Criterion) {
     });
 }
 
+fn shanten_replace_and_test(c: &mut Criterion) {
+    shanten::ensure_init();
+    // tenpai-rich, so most of the replacements end up with the same few hands
+    let tehai = hand("2345666m 34588p 77s").unwrap();
+    for (name, calc) in [
+        (
+            "shanten replace and test",
+            shanten::calc_all as fn(&[u8; 34], u8) -> i8,
+        ),
+        ("shanten replace and test cached", shanten::calc_all_cached),
+    ] {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let tehai = black_box(tehai);
+                for discard in 0..34 {
+                    if tehai[discard] == 0 {
+                        continue;
+                    }
+                    for tsumo in 0..34 {
+                        let mut tehai = tehai;
+                        tehai[discard] -= 1;
+                        tehai[tsumo] += 1;
+                        if tehai[tsumo] <= 4 {
+                            black_box(calc(&tehai, 4));
+                        }
+                    }
+                }
+            });
+        });
+    }
+}
+
+fn shanten_update(c: &mut Criterion) {
+    shanten::ensure_init();
+    // A simulated kyoku of seat 0, where every 4th tile of the live wall is
+    // drawn and the tile with the largest ID is discarded.
+    let wall = Wall::from_seed(0);
+    let haipai = wall.haipai(0)[0];
+    let mut init = [0; 34];
+    haipai.iter().for_each(|t| init[t.deaka().as_usize()] += 1);
+    let draws: Vec<_> = wall
+        .live_wall()
+        .iter()
+        .step_by(4)
+        .map(|t| t.deaka().as_usize())
+        .collect();
+
+    for (name, incremental) in [
+        ("shanten update full recompute", false),
+        ("shanten update incremental", true),
+    ] {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let mut tehai = black_box(init);
+                let mut inc = shanten::Incremental::new(&tehai, 4);
+                for &tsumo in &draws {
+                    for delta in [1, -1] {
+                        let tid = if delta > 0 {
+                            tsumo
+                        } else {
+                            (0..34).rev().find(|&t| tehai[t] > 0).unwrap()
+                        };
+                        tehai[tid] = tehai[tid].wrapping_add_signed(delta);
+                        let shanten = if incremental {
+                            if delta > 0 {
+                                inc.add(tid);
+                            } else {
+                                inc.remove(tid);
+                            }
+                            inc.shanten()
+                        } else {
+                            shanten::calc_all(&tehai, 4)
+                        };
+                        black_box(shanten);
+                    }
+                }
+            });
+        });
+    }
+}
+
 fn agari(c: &mut Criterion) {
     agari::ensure_init();
     let tehai = hand("111m 9m 9m").unwrap();
@@ -36,6 +121,7 @@ fn agari(c: &mut Criterion) {
                 jikaze: tu8!(N),
                 winning_tile: tu8!(9m),
                 is_ron: true,
+                ..Default::default()
             };
             black_box(calc.search_yakus().unwrap());
         });
@@ -52,6 +138,7 @@ fn sp(c: &mut Criterion) {
         bakaze: tu8!(E),
         jikaze: tu8!(E),
         prefer_riichi: true,
+        dama_needs_yaku: false,
         is_menzen: true,
         num_doras_in_fuuro: 0,
         dora_indicators: &[t!(6m)],
@@ -61,6 +148,11 @@ fn sp(c: &mut Criterion) {
         maximize_win_prob: true,
         calc_tegawari: true,
         calc_shanten_down: true,
+        compare_open_vs_closed: false,
+        calc_yakuman_prob: false,
+        kazoe_yakuman: false,
+        calc_kan_upgrade: false,
+        calc_win_curve: false,
     };
     let tehai = hand("3667m 23489p 34688s").unwrap();
     let mut tiles_seen = tehai;
@@ -72,9 +164,10 @@ fn sp(c: &mut Criterion) {
     let tsumos_left = 12;
     let init_state = InitState {
         tehai,
-        akas_in_hand: [false; 3],
+        akas_in_hand: [0; 3],
         tiles_seen,
-        akas_seen: [false; 3],
+        akas_seen: [0; 3],
+        aka_config: AkaConfig::default(),
     };
     c.bench_function(&format!("sp {cur_shanten} shanten"), |b| {
         b.iter(|| {
@@ -97,6 +190,7 @@ fn sp(c: &mut Criterion) {
         bakaze: tu8!(E),
         jikaze: tu8!(E),
         prefer_riichi: true,
+        dama_needs_yaku: false,
         is_menzen: true,
         num_doras_in_fuuro: 0,
         dora_indicators: &[t!(6m)],
@@ -106,6 +200,11 @@ fn sp(c: &mut Criterion) {
         maximize_win_prob: true,
         calc_tegawari: true,
         calc_shanten_down: true,
+        compare_open_vs_closed: false,
+        calc_yakuman_prob: false,
+        kazoe_yakuman: false,
+        calc_kan_upgrade: false,
+        calc_win_curve: false,
     };
     let tehai = hand("45677m 456778p 248s").unwrap();
     let mut tiles_seen = tehai;
@@ -117,9 +216,10 @@ fn sp(c: &mut Criterion) {
     let tsumos_left = 12;
     let init_state = InitState {
         tehai,
-        akas_in_hand: [false; 3],
+        akas_in_hand: [0; 3],
         tiles_seen,
-        akas_seen: [false; 3],
+        akas_seen: [0; 3],
+        aka_config: AkaConfig::default(),
     };
     c.bench_function(&format!("sp {cur_shanten} shanten"), |b| {
         b.iter(|| {
@@ -249,6 +349,62 @@ fn encode_obs(c: &mut Criterion) {
     });
 }
 
-criterion_group!(algo, shanten, agari, sp);
-criterion_group!(state, encode_obs);
+fn batch_unconditional_tenpai(c: &mut Criterion) {
+    shanten::ensure_init();
+    // Oya's first draws that are at most 1-shanten, since the others return
+    // early.
+    let states: Vec<_> = (0..)
+        .filter_map(|seed| {
+            let wall = Wall::from_seed(seed);
+            let mut tehais = [[t!(?); 13]; 4];
+            tehais[0] = wall.haipai(0)[0];
+            let mut ps = PlayerState::new(0);
+            ps.update(&Event::StartKyoku {
+                bakaze: t!(E),
+                dora_marker: wall.dora_indicators()[0],
+                kyoku: 1,
+                honba: 0,
+                kyotaku: 0,
+                oya: 0,
+                scores: [25000; 4],
+                tehais,
+            })
+            .unwrap();
+            ps.update(&Event::Tsumo {
+                actor: 0,
+                pai: wall.live_wall()[0],
+            })
+            .unwrap();
+            (ps.shanten() <= 1).then_some(ps)
+        })
+        .take(10_000)
+        .collect();
+
+    c.bench_function("unconditional tenpai serial", |b| {
+        b.iter(|| {
+            let states = black_box(&states);
+            let result: Vec<_> = states
+                .iter()
+                .map(PlayerState::discard_candidates_with_unconditional_tenpai)
+                .collect();
+            black_box(result);
+        });
+    });
+    c.bench_function("unconditional tenpai parallel", |b| {
+        b.iter(|| {
+            let states = black_box(&states);
+            black_box(batch::unconditional_tenpai(states));
+        });
+    });
+}
+
+criterion_group!(
+    algo,
+    shanten,
+    shanten_replace_and_test,
+    shanten_update,
+    agari,
+    sp
+);
+criterion_group!(state, encode_obs, batch_unconditional_tenpai);
 criterion_main!(algo, state);
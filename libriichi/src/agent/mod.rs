@@ -1,15 +1,23 @@
 mod akochan;
 mod batchify;
 mod defs;
+#[cfg(feature = "python")]
 mod mjai_log;
+#[cfg(feature = "python")]
 mod mortal;
+#[cfg(feature = "python")]
 mod py_agent;
+mod rule_based;
 mod tsumogiri;
 
 pub use akochan::AkochanAgent;
 pub use batchify::BatchifiedAgent;
 pub use defs::{Agent, BatchAgent, InvisibleState};
+#[cfg(feature = "python")]
 pub use mjai_log::MjaiLogBatchAgent;
+#[cfg(feature = "python")]
 pub use mortal::MortalBatchAgent;
+#[cfg(feature = "python")]
 pub use py_agent::new_py_agent;
+pub use rule_based::RuleBasedAgent;
 pub use tsumogiri::Tsumogiri;
@@ -0,0 +1,178 @@
+use super::{Agent, BatchifiedAgent, InvisibleState};
+use crate::mjai::{Event, EventExt};
+use crate::state::{PlayerState, SinglePlayerOptions};
+use crate::tile::Tile;
+
+use anyhow::{Context, Result};
+
+/// `RuleBasedAgent` is a reference agent built purely from the heuristics in
+/// `PlayerState`, useful as a baseline opponent and for smoke tests.
+///
+/// - agari whenever `rule_based_agari` says so;
+/// - 九種九牌 ryukyoku whenever `rule_based_ryukyoku` says so;
+/// - riichi whenever possible;
+/// - discard the best tile of `single_player_tables`, falling back to the
+///   drawn tile, or the first legal discard, if the tables can't be made,
+///   e.g. at the last tsumo;
+/// - never call.
+pub struct RuleBasedAgent(pub u8);
+
+impl RuleBasedAgent {
+    pub fn new_batched(player_ids: &[u8]) -> Result<BatchifiedAgent<Self>> {
+        BatchifiedAgent::new(|id| Ok(Self(id)), player_ids)
+    }
+
+    fn choose_discard(state: &PlayerState) -> Result<Tile> {
+        let candidates = state.discard_candidates_aka();
+        let is_legal = |t: Tile| candidates[t.as_usize()];
+
+        if let Some(tile) = state
+            .single_player_tables_opts(SinglePlayerOptions::default())
+            .ok()
+            .and_then(|tables| tables.best_discard())
+            .filter(|&t| is_legal(t))
+        {
+            return Ok(tile);
+        }
+        if let Some(tile) = state.last_self_tsumo().filter(|&t| is_legal(t)) {
+            return Ok(tile);
+        }
+        candidates
+            .iter()
+            .position(|&b| b)
+            .map(|i| Tile::try_from(i).unwrap())
+            .context("no legal discard")
+    }
+}
+
+impl Agent for RuleBasedAgent {
+    fn name(&self) -> String {
+        "rule-based".to_owned()
+    }
+
+    fn react(
+        &mut self,
+        _: &[EventExt],
+        state: &PlayerState,
+        _: Option<InvisibleState>,
+    ) -> Result<EventExt> {
+        let cans = state.last_cans();
+        let ev = if state.rule_based_agari() {
+            Event::Hora {
+                actor: self.0,
+                target: cans.target_actor,
+                deltas: None,
+                ura_markers: None,
+            }
+        } else if state.rule_based_ryukyoku() {
+            Event::Ryukyoku { deltas: None }
+        } else if cans.can_riichi {
+            Event::Reach { actor: self.0 }
+        } else if cans.can_discard {
+            let pai = Self::choose_discard(state)?;
+            Event::Dahai {
+                actor: self.0,
+                pai,
+                tsumogiri: state.last_self_tsumo() == Some(pai),
+            }
+        } else {
+            Event::None
+        };
+        Ok(EventExt::no_meta(ev))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::selfplay;
+    use crate::t;
+    use serde_json as json;
+
+    /// Feeds `line` to `state` and returns the validated reaction of `agent`.
+    fn step(agent: &mut RuleBasedAgent, state: &mut PlayerState, line: &str) -> Event {
+        let ev: Event = json::from_str(line.trim()).unwrap();
+        state.update(&ev).unwrap();
+        let reaction = agent.react(&[], state, None).unwrap().event;
+        state.validate_reaction(&reaction).unwrap();
+        reaction
+    }
+
+    #[test]
+    fn scripted_kyoku() {
+        let mut agent = RuleBasedAgent(0);
+        let mut state = PlayerState::new(0);
+        step(
+            &mut agent,
+            &mut state,
+            r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","S","N"],["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","4p"],["1s","2s","3s","4s","5s","6s","7s","8s","9s","1p","2p","3p","4p"],["2m","3m","4m","5m","6m","7m","2p","3p","4p","2s","3s","4s","5s"]]}"#,
+        );
+
+        // 1 shanten, one of the isolated guest winds goes first.
+        let ev = step(
+            &mut agent,
+            &mut state,
+            r#"{"type":"tsumo","actor":0,"pai":"5s"}"#,
+        );
+        let Event::Dahai { pai: first, .. } = ev else {
+            panic!("expected a discard, got {ev:?}");
+        };
+        assert!(first == t!(S) || first == t!(N), "{first}");
+        let kept = if first == t!(S) { t!(N) } else { t!(S) };
+
+        step(
+            &mut agent,
+            &mut state,
+            &format!(r#"{{"type":"dahai","actor":0,"pai":"{first}","tsumogiri":false}}"#),
+        );
+
+        // Never calls, not even a pon of the double east.
+        step(
+            &mut agent,
+            &mut state,
+            r#"{"type":"tsumo","actor":1,"pai":"E"}"#,
+        );
+        let ev = step(
+            &mut agent,
+            &mut state,
+            r#"{"type":"dahai","actor":1,"pai":"E","tsumogiri":true}"#,
+        );
+        assert!(state.last_cans().can_pon);
+        assert_eq!(ev, Event::None);
+        for line in [
+            r#"{"type":"tsumo","actor":2,"pai":"C"}"#,
+            r#"{"type":"dahai","actor":2,"pai":"C","tsumogiri":true}"#,
+            r#"{"type":"tsumo","actor":3,"pai":"C"}"#,
+            r#"{"type":"dahai","actor":3,"pai":"C","tsumogiri":true}"#,
+        ] {
+            assert_eq!(step(&mut agent, &mut state, line), Event::None);
+        }
+
+        // Tenpai on 6s, so riichi and cut the other wind for the 4-7s wait.
+        let ev = step(
+            &mut agent,
+            &mut state,
+            r#"{"type":"tsumo","actor":0,"pai":"6s"}"#,
+        );
+        assert_eq!(ev, Event::Reach { actor: 0 });
+        let ev = step(&mut agent, &mut state, r#"{"type":"reach","actor":0}"#);
+        assert_eq!(
+            ev,
+            Event::Dahai {
+                actor: 0,
+                pai: kept,
+                tsumogiri: false,
+            },
+        );
+    }
+
+    #[test]
+    fn selfplay() {
+        let logs = selfplay::run(1, 1009, |id| Ok(RuleBasedAgent(id))).unwrap();
+        let log = &logs[0];
+        assert!(log.contains(r#""type":"reach""#));
+        assert!(log.contains(r#""type":"hora""#));
+        assert!(!log.contains(r#""type":"pon""#));
+        assert!(!log.contains(r#""type":"chi""#));
+    }
+}
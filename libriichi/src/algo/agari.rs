@@ -6,7 +6,7 @@
 //! * Java: <http://hp.vector.co.jp/authors/VA046927/mjscore/AgariIndex.java>
 //! * Algorithm: <http://hp.vector.co.jp/authors/VA046927/mjscore/mjalgorism.html>
 
-use super::point::Point;
+use super::point::{Point, TsumoRounding};
 use super::shanten;
 use crate::tile::Tile;
 use crate::{matches_tu8, must_tile, tu8};
@@ -50,6 +50,19 @@ static AGARI_TABLE: LazyLock<BoomHashMap<u32, ArrayVec<[Div; 4]>>> = LazyLock::n
     BoomHashMap::new(keys, values)
 });
 
+/// How a hand reads as a complete hand, see `AgariCalculator::reading`.
+enum Reading {
+    /// 国士無双, which is not in the table.
+    Kokushi,
+    Divisions(Divisions),
+}
+
+/// Every division of a hand, as looked up from `AGARI_TABLE`.
+struct Divisions {
+    tile14: [u8; 14],
+    divs: &'static [Div],
+}
+
 #[derive(Debug, Default)]
 struct Div {
     pair_idx: u8,
@@ -73,6 +86,160 @@ pub enum Agari {
     Yakuman(u8),
 }
 
+/// Names of yakus, used by [`AgariCalculator::yaku_list`].
+///
+/// Situational yakus like 立直 and 嶺上開花 are never returned by
+/// `yaku_list`, they are here for callers to build a complete list on their
+/// own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YakuName {
+    /// 立直
+    Riichi,
+    /// 両立直
+    DoubleRiichi,
+    /// 一发
+    Ippatsu,
+    /// 門前清自摸和
+    MenzenTsumo,
+    /// 海底摸月
+    Haitei,
+    /// 河底撈魚
+    Houtei,
+    /// 嶺上開花
+    Rinshan,
+    /// 槍槓
+    Chankan,
+    /// 平和
+    Pinfu,
+    /// 七対子
+    Chiitoitsu,
+    /// 二盃口
+    Ryanpeikou,
+    /// 断幺九
+    Tanyao,
+    /// 対々和
+    Toitoi,
+    /// 混一色
+    Honitsu,
+    /// 清一色
+    Chinitsu,
+    /// 一盃口
+    Iipeikou,
+    /// 一気通貫
+    Ittsuu,
+    /// 三色同順
+    SanshokuDoujun,
+    /// 三色同刻
+    SanshokuDoukou,
+    /// 三暗刻
+    Sanankou,
+    /// 三槓子
+    Sankantsu,
+    /// 役牌:場風牌
+    Bakaze,
+    /// 役牌:門風牌
+    Jikaze,
+    /// 役牌:白
+    Haku,
+    /// 役牌:發
+    Hatsu,
+    /// 役牌:中
+    Chun,
+    /// 小三元
+    Shousangen,
+    /// 混老頭
+    Honroutou,
+    /// 混全帯幺九
+    Chanta,
+    /// 純全帯幺九
+    Junchan,
+    /// 天和
+    Tenhou,
+    /// 地和
+    Chiihou,
+    /// 国士無双
+    KokushiMusou,
+    /// 九蓮宝燈
+    ChuurenPoutou,
+    /// 字一色
+    Tsuuiisou,
+    /// 四暗刻
+    Suuankou,
+    /// 四槓子
+    Suukantsu,
+    /// 緑一色
+    Ryuuiisou,
+    /// 大三元
+    Daisangen,
+    /// 小四喜
+    Shousuushii,
+    /// 大四喜
+    Daisuushii,
+    /// 清老頭
+    Chinroutou,
+    /// 三連刻, local yaku
+    Sanrenkou,
+    /// 大車輪, local yaku
+    Daisharin,
+}
+
+/// The arrangement of the tiles in `AgariCalculator::tehai` that scores the
+/// highest, as returned by `AgariCalculator::best_decomposition`. Called sets
+/// and ankans are not included. Each group is represented by its lowest tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decomposition {
+    pub agari: Agari,
+    /// The pair, or all 7 pairs for 七対子. Empty for 国士無双.
+    pub pairs: ArrayVec<[u8; 7]>,
+    pub kotsu: ArrayVec<[u8; 4]>,
+    pub shuntsu: ArrayVec<[u8; 4]>,
+}
+
+/// The fu of a win broken down into its components, as returned by
+/// `AgariCalculator::fu_detail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuDetail {
+    /// Each component with the fu it adds.
+    pub components: Vec<(FuComponent, u8)>,
+    /// The sum of `components`.
+    pub raw: u8,
+    /// `raw` rounded up to 10, or 25 for 七対子, which is the fu used for
+    /// `Point`.
+    pub total: u8,
+}
+
+/// A source of fu. Tiles are the lowest tile of the group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuComponent {
+    /// 副底
+    Base,
+    /// 七対子, a fixed 25 fu that replaces everything else.
+    Chiitoitsu,
+    /// 門前加符
+    MenzenRon,
+    /// ツモ符, not given to 平和.
+    Tsumo,
+    /// 嵌張待ち
+    Kanchan,
+    /// 辺張待ち
+    Penchan,
+    /// 単騎待ち
+    Tanki,
+    /// 明刻
+    Minkou(u8),
+    /// 暗刻
+    Ankou(u8),
+    /// 明槓
+    Minkan(u8),
+    /// 暗槓
+    Ankan(u8),
+    /// 役牌 as the pair, 4 fu for 連風牌.
+    YakuhaiPair(u8),
+    /// The extra fu of an open hand that would otherwise be 20 fu (喰い平和),
+    /// which is always 30 fu.
+    OpenPinfu,
+}
+
 #[derive(Debug)]
 pub struct AgariCalculator<'a> {
     /// Must include the winning tile (i.e. must be 3n+2)
@@ -93,6 +260,42 @@ pub struct AgariCalculator<'a> {
     /// ankou/ankan-related yakus like 三/四暗刻. It will not be used to
     /// determine 門前清自摸和.
     pub is_ron: bool,
+    /// Local yakus to score in addition to the standard ones.
+    pub local_yaku: LocalYaku,
+}
+
+/// A closed tsumo with no fuuro nor local yaku, to be completed with the
+/// struct update syntax. `tehai` and `winning_tile` must always be set.
+impl Default for AgariCalculator<'_> {
+    fn default() -> Self {
+        Self {
+            tehai: &[0; 34],
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(E),
+            winning_tile: 0,
+            is_ron: false,
+            local_yaku: LocalYaku::default(),
+        }
+    }
+}
+
+/// Local yakus (ローカル役) that are not part of the standard ruleset. All of
+/// them are disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LocalYaku {
+    /// 大車輪, 22334455667788p, as a yakuman. Closed only.
+    pub daisharin: bool,
+    /// Also count the same shape in manzu (大数隣) and souzu (大竹林) as
+    /// 大車輪. Only effective with `daisharin`.
+    pub daisharin_any_suit: bool,
+    /// 三連刻, three kotsu or kantsu of the same suit with consecutive
+    /// numbers, such as 222333444m, as 2 han. Open hands are not reduced.
+    pub sanrenkou: bool,
 }
 
 struct DivWorker<'a> {
@@ -191,15 +394,74 @@ impl Ord for Agari {
 }
 
 impl Agari {
+    /// `is_oya` is whether the winner is the dealer, which decides both the
+    /// ron points and how a tsumo is split, see `Point`.
     #[must_use]
     pub fn point(self, is_oya: bool) -> Point {
+        self.point_with_kiriage(is_oya, false)
+    }
+
+    /// Same as `point`, for batch scoring where the seat of the winner
+    /// varies.
+    #[inline]
+    #[must_use]
+    pub fn point_for(self, is_dealer: bool) -> Point {
+        self.point(is_dealer)
+    }
+
+    /// See `Point::calc_with_kiriage`.
+    #[must_use]
+    pub fn point_with_kiriage(self, is_oya: bool, kiriage_mangan: bool) -> Point {
+        self.point_with_rules(is_oya, kiriage_mangan, true, TsumoRounding::default())
+    }
+
+    /// See `Point::calc_with_rules`. Yakuman from yaku are never capped.
+    #[must_use]
+    pub fn point_with_rules(
+        self,
+        is_oya: bool,
+        kiriage_mangan: bool,
+        kazoe_yakuman: bool,
+        tsumo_rounding: TsumoRounding,
+    ) -> Point {
         match self {
-            Self::Normal { fu, han } => Point::calc(is_oya, fu, han),
-            Self::Yakuman(n) => Point::yakuman(is_oya, n as i32),
+            Self::Normal { fu, han } => Point::calc_with_rules(
+                is_oya,
+                fu,
+                han,
+                kiriage_mangan,
+                kazoe_yakuman,
+                tsumo_rounding,
+            ),
+            Self::Yakuman(n) => {
+                Point::yakuman(is_oya, n as i32).with_tsumo_rounding(is_oya, tsumo_rounding)
+            }
         }
     }
 }
 
+impl YakuName {
+    #[must_use]
+    pub const fn is_yakuman(self) -> bool {
+        matches!(
+            self,
+            Self::Tenhou
+                | Self::Chiihou
+                | Self::KokushiMusou
+                | Self::ChuurenPoutou
+                | Self::Tsuuiisou
+                | Self::Suuankou
+                | Self::Suukantsu
+                | Self::Ryuuiisou
+                | Self::Daisangen
+                | Self::Shousuushii
+                | Self::Daisuushii
+                | Self::Chinroutou
+                | Self::Daisharin
+        )
+    }
+}
+
 impl AgariCalculator<'_> {
     #[inline]
     #[must_use]
@@ -207,6 +469,11 @@ impl AgariCalculator<'_> {
         self.search_yakus_impl(true).is_some()
     }
 
+    /// A hand that reads as both 二盃口 and 七対子 is always scored as 二盃口,
+    /// since every other yaku of the 七対子 reading applies to it too and 二盃口
+    /// is worth one han more, so the 七対子 reading is not even in the table.
+    /// The same goes for `agari` and `best_decomposition`, see `all_agari`
+    /// for both.
     #[inline]
     #[must_use]
     pub fn search_yakus(&self) -> Option<Agari> {
@@ -239,14 +506,10 @@ impl AgariCalculator<'_> {
                 han: additional_hans + doras,
             })
         } else {
-            let (tile14, key) = get_tile14_and_key(self.tehai);
-            let divs = AGARI_TABLE.get(&key)?;
-
-            let fu = divs
-                .iter()
-                .map(|div| DivWorker::new(self, &tile14, div))
-                .map(|w| w.calc_fu(false))
-                .max()?;
+            let Some(Reading::Divisions(divs)) = self.reading() else {
+                return None;
+            };
+            let fu = divs.workers(self).map(|w| w.calc_fu(false)).max()?;
             Some(Agari::Normal {
                 fu,
                 han: additional_hans + doras,
@@ -254,7 +517,188 @@ impl AgariCalculator<'_> {
         }
     }
 
+    /// Lists the yakus of the division that scores the highest, each with its
+    /// han value, which has already been reduced for open hands (食い下がり).
+    /// For yakumans, the value is the number of yakumans it counts as, and all
+    /// non-yakuman yakus are omitted.
+    ///
+    /// `additional_hans` is the same as in `agari`. They are not listed as
+    /// their names are only known to the caller, but they are taken into
+    /// account when picking the division.
+    ///
+    /// An empty list is returned if the hand has no pattern-based yaku.
+    #[must_use]
+    pub fn yaku_list(&self, additional_hans: u8) -> Vec<(YakuName, u8)> {
+        let divs = match self.reading() {
+            Some(Reading::Kokushi) => return vec![(YakuName::KokushiMusou, 1)],
+            Some(Reading::Divisions(divs)) => divs,
+            None => return vec![],
+        };
+
+        divs.workers(self)
+            .filter_map(|w| {
+                let mut yakus = vec![];
+                let agari = w.search_yakus::<false>(Some(&mut yakus))?;
+                let agari = match agari {
+                    Agari::Normal { fu, han } => Agari::Normal {
+                        fu,
+                        han: han + additional_hans,
+                    },
+                    Agari::Yakuman(_) => {
+                        yakus.retain(|(y, _)| y.is_yakuman());
+                        agari
+                    }
+                };
+                Some((agari, yakus))
+            })
+            .max_by_key(|(agari, _)| *agari)
+            .map(|(_, yakus)| yakus)
+            .unwrap_or_default()
+    }
+
+    /// Whether `yaku` is in the `yaku_list` of the division that scores the
+    /// highest. Situational yakus such as 立直 and 海底 are never included,
+    /// and neither are non-yakuman yakus when the hand is a yakuman.
+    #[must_use]
+    pub fn contains_yaku(&self, yaku: YakuName, additional_hans: u8) -> bool {
+        self.yaku_list(additional_hans)
+            .iter()
+            .any(|&(y, _)| y == yaku)
+    }
+
+    /// Same as `agari`, but also returns the division it picks, so that the
+    /// winning structure can be rendered.
+    #[must_use]
+    pub fn best_decomposition(&self, additional_hans: u8, doras: u8) -> Option<Decomposition> {
+        let divs = match self.reading()? {
+            Reading::Kokushi => {
+                return Some(Decomposition {
+                    agari: Agari::Yakuman(1),
+                    pairs: ArrayVec::new(),
+                    kotsu: ArrayVec::new(),
+                    shuntsu: ArrayVec::new(),
+                });
+            }
+            Reading::Divisions(divs) => divs,
+        };
+
+        divs.workers(self)
+            .filter_map(|w| {
+                let agari = w.agari(additional_hans, doras)?;
+                let pairs = if w.div.has_chitoi {
+                    w.chitoi_pairs().collect()
+                } else {
+                    iter::once(w.pair_tile).collect()
+                };
+                Some(Decomposition {
+                    agari,
+                    pairs,
+                    kotsu: w.menzen_kotsu,
+                    shuntsu: w.menzen_shuntsu,
+                })
+            })
+            .max_by_key(|d| d.agari)
+    }
+
+    /// Same as `agari`, but returns the score of every division of the hand
+    /// that is a valid win instead of only the highest one, e.g. both the
+    /// 二盃口 and the 七対子 readings of `223344m 556677p 99s`. The order is
+    /// unspecified.
+    #[must_use]
+    pub fn all_agari(&self, additional_hans: u8, doras: u8) -> Vec<Agari> {
+        let divs = match self.reading() {
+            Some(Reading::Kokushi) => return vec![Agari::Yakuman(1)],
+            Some(Reading::Divisions(divs)) => divs,
+            None => return vec![],
+        };
+
+        let mut ret: Vec<_> = divs
+            .workers(self)
+            .filter_map(|w| w.agari(additional_hans, doras))
+            .collect();
+
+        // The table drops the 七対子 reading of a 二盃口 hand as it never
+        // scores higher, so it has to be made up here.
+        let is_seven_pairs = self.tehai.iter().all(|&c| c == 0 || c == 2);
+        if is_seven_pairs
+            && divs.divs.iter().any(|div| div.has_ryanpeikou)
+            && !divs.divs.iter().any(|div| div.has_chitoi)
+        {
+            let div = Div {
+                has_chitoi: true,
+                ..Default::default()
+            };
+            ret.extend(DivWorker::new(self, &divs.tile14, &div).agari(additional_hans, doras));
+        }
+        ret
+    }
+
+    /// Breaks down the fu of the division that `best_decomposition` picks.
+    /// `additional_hans` is the same as in `agari`.
+    ///
+    /// Unlike `agari`, the fu is given even for a hand of 5 han or more.
+    /// `None` is returned for yakumans, and for hands that `agari` returns
+    /// `None` for.
+    #[must_use]
+    pub fn fu_detail(&self, additional_hans: u8) -> Option<FuDetail> {
+        let Reading::Divisions(divs) = self.reading()? else {
+            return None;
+        };
+
+        let (agari, detail) = divs
+            .workers(self)
+            .filter_map(|w| {
+                let agari = match w.search_yakus::<false>(None) {
+                    Some(Agari::Normal { han, .. }) => Agari::Normal {
+                        fu: 0,
+                        han: han + additional_hans,
+                    },
+                    Some(yakuman) => yakuman,
+                    None if additional_hans > 0 => Agari::Normal {
+                        fu: 0,
+                        han: additional_hans,
+                    },
+                    None => return None,
+                };
+                let detail = w.fu_detail(w.has_pinfu());
+                let agari = match agari {
+                    Agari::Normal { han, .. } => Agari::Normal {
+                        fu: detail.total,
+                        han,
+                    },
+                    yakuman => yakuman,
+                };
+                Some((agari, detail))
+            })
+            .max_by_key(|(agari, _)| *agari)?;
+
+        match agari {
+            Agari::Normal { .. } => Some(detail),
+            Agari::Yakuman(_) => None,
+        }
+    }
+
     fn search_yakus_impl(&self, return_if_any: bool) -> Option<Agari> {
+        let divs = match self.reading()? {
+            // 国士無双
+            Reading::Kokushi => return Some(Agari::Yakuman(1)),
+            Reading::Divisions(divs) => divs,
+        };
+
+        if return_if_any {
+            // Benchmark result indicates it is too trivial to use rayon here.
+            divs.workers(self)
+                .find_map(|w| w.search_yakus::<true>(None))
+        } else {
+            divs.workers(self)
+                .filter_map(|w| w.search_yakus::<false>(None))
+                .max()
+        }
+    }
+
+    /// Looks up how `tehai` reads as a complete hand, or returns `None` if it
+    /// is not one.
+    fn reading(&self) -> Option<Reading> {
         assert_eq!(
             self.is_menzen,
             self.chis.is_empty() && self.pons.is_empty() && self.minkans.is_empty(),
@@ -263,24 +707,20 @@ impl AgariCalculator<'_> {
         // Kokushi has a special pattern and cannot be combined with other
         // pattern-based yakus.
         if self.is_menzen && shanten::calc_kokushi(self.tehai) == -1 {
-            // 国士無双
-            return Some(Agari::Yakuman(1));
+            return Some(Reading::Kokushi);
         }
 
         let (tile14, key) = get_tile14_and_key(self.tehai);
         let divs = AGARI_TABLE.get(&key)?;
+        Some(Reading::Divisions(Divisions { tile14, divs }))
+    }
+}
 
-        if return_if_any {
-            // Benchmark result indicates it is too trivial to use rayon here.
-            divs.iter()
-                .map(|div| DivWorker::new(self, &tile14, div))
-                .find_map(|w| w.search_yakus::<true>())
-        } else {
-            divs.iter()
-                .map(|div| DivWorker::new(self, &tile14, div))
-                .filter_map(|w| w.search_yakus::<false>())
-                .max()
-        }
+impl Divisions {
+    fn workers<'a>(&'a self, calc: &'a AgariCalculator<'a>) -> impl Iterator<Item = DivWorker<'a>> {
+        self.divs
+            .iter()
+            .map(move |div| DivWorker::new(calc, &self.tile14, div))
     }
 }
 
@@ -359,108 +799,163 @@ impl<'a> DivWorker<'a> {
         self.all_kotsu_and_kantsu().chain(self.all_shuntsu())
     }
 
-    fn calc_fu(&self, has_pinfu: bool) -> u8 {
-        if self.div.has_chitoi {
-            return 25;
-        }
-        let mut fu = 20;
+    /// The score of this division alone, see `AgariCalculator::agari`.
+    fn agari(&self, additional_hans: u8, doras: u8) -> Option<Agari> {
+        let agari = match self.search_yakus::<false>(None) {
+            Some(Agari::Normal { fu, han }) => Agari::Normal {
+                fu,
+                han: han + additional_hans + doras,
+            },
+            Some(yakuman) => yakuman,
+            None if additional_hans > 0 => Agari::Normal {
+                fu: if additional_hans + doras >= 5 {
+                    0
+                } else {
+                    self.calc_fu(false)
+                },
+                han: additional_hans + doras,
+            },
+            None => return None,
+        };
+        Some(agari)
+    }
 
-        fu += self
-            .menzen_kotsu
-            .iter()
-            .map(|&t| {
-                // `menzen_kotsu` are usually ankou, except when the winning
-                // tile makes a minkou and the tile is the winning tile.
-                let is_minkou = self.winning_tile_makes_minkou && t == self.sup.winning_tile;
-                match (is_minkou, must_tile!(t).is_yaokyuu()) {
-                    (false, true) => 8,
-                    (false, false) | (true, true) => 4,
-                    (true, false) => 2,
-                }
+    fn has_pinfu(&self) -> bool {
+        self.sup.is_menzen
+            && self.menzen_shuntsu.len() == 4
+            && !must_tile!(self.pair_tile)
+                .is_yakuhai(must_tile!(self.sup.bakaze), must_tile!(self.sup.jikaze))
+            && self.menzen_shuntsu.iter().any(|&s| {
+                let num = s % 9 + 1;
+                num <= 6 && s == self.sup.winning_tile || num >= 2 && s + 2 == self.sup.winning_tile
             })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .pons
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 4 } else { 2 })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .ankans
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 32 } else { 16 })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .minkans
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 16 } else { 8 })
-            .sum::<u8>();
+    }
 
-        if matches_tu8!(self.pair_tile, P | F | C) {
-            fu += 2;
-        } else {
-            // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
-            //
-            // > 連風牌は4符
-            if self.pair_tile == self.sup.bakaze {
-                fu += 2;
-            }
-            if self.pair_tile == self.sup.jikaze {
-                fu += 2;
+    /// Calls `add` with each fu component of this division, which `calc_fu`
+    /// sums up and `fu_detail` lists.
+    fn walk_fu(&self, has_pinfu: bool, mut add: impl FnMut(FuComponent, u8)) {
+        if self.div.has_chitoi {
+            add(FuComponent::Chiitoitsu, 25);
+            return;
+        }
+        add(FuComponent::Base, 20);
+
+        for &t in &self.menzen_kotsu {
+            // `menzen_kotsu` are usually ankou, except when the winning tile
+            // makes a minkou and the tile is the winning tile.
+            let is_minkou = self.winning_tile_makes_minkou && t == self.sup.winning_tile;
+            let yaokyuu = must_tile!(t).is_yaokyuu() as u8 + 1;
+            if is_minkou {
+                add(FuComponent::Minkou(t), 2 * yaokyuu);
+            } else {
+                add(FuComponent::Ankou(t), 4 * yaokyuu);
             }
         }
+        for &t in self.sup.pons {
+            let yaokyuu = must_tile!(t).is_yaokyuu() as u8 + 1;
+            add(FuComponent::Minkou(t), 2 * yaokyuu);
+        }
+        for &t in self.sup.ankans {
+            let yaokyuu = must_tile!(t).is_yaokyuu() as u8 + 1;
+            add(FuComponent::Ankan(t), 16 * yaokyuu);
+        }
+        for &t in self.sup.minkans {
+            let yaokyuu = must_tile!(t).is_yaokyuu() as u8 + 1;
+            add(FuComponent::Minkan(t), 8 * yaokyuu);
+        }
 
-        if fu == 20 {
-            return if !self.sup.is_menzen {
-                30
-            } else if has_pinfu {
-                if self.sup.is_ron { 30 } else { 20 }
-            } else if self.sup.is_ron {
-                40
-            } else {
-                30
-            };
+        // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
+        //
+        // > 連風牌は4符
+        let pair_fu = if must_tile!(self.pair_tile).is_dragon() {
+            2
+        } else {
+            2 * ((self.pair_tile == self.sup.bakaze) as u8
+                + (self.pair_tile == self.sup.jikaze) as u8)
+        };
+        if pair_fu > 0 {
+            add(FuComponent::YakuhaiPair(self.pair_tile), pair_fu);
+        }
+
+        // Every kotsu and kantsu has fu, so this is whether nothing but the
+        // base has been added so far.
+        let is_plain = pair_fu == 0 && self.all_kotsu_and_kantsu().next().is_none();
+        if is_plain && self.sup.is_menzen && has_pinfu {
+            if self.sup.is_ron {
+                add(FuComponent::MenzenRon, 10);
+            }
+            return;
         }
 
         if !self.sup.is_ron {
-            fu += 2;
+            add(FuComponent::Tsumo, 2);
         } else if self.sup.is_menzen {
-            fu += 10;
+            add(FuComponent::MenzenRon, 10);
         }
 
-        if !self.winning_tile_makes_minkou {
-            if self.pair_tile == self.sup.winning_tile {
-                // tanki wait
-                fu += 2;
-            } else {
-                let is_kanchan_penchan = self.menzen_shuntsu.iter().any(|&s| {
-                    s + 1 == self.sup.winning_tile
-                        || s % 9 == 0 && s + 2 == self.sup.winning_tile
-                        || s % 9 == 6 && s == self.sup.winning_tile
-                });
-                if is_kanchan_penchan {
-                    fu += 2;
-                }
-            }
+        let wait = if self.winning_tile_makes_minkou {
+            None
+        } else if self.pair_tile == self.sup.winning_tile {
+            Some(FuComponent::Tanki)
+        } else if self
+            .menzen_shuntsu
+            .iter()
+            .any(|&s| s + 1 == self.sup.winning_tile)
+        {
+            Some(FuComponent::Kanchan)
+        } else if self.menzen_shuntsu.iter().any(|&s| {
+            s % 9 == 0 && s + 2 == self.sup.winning_tile || s % 9 == 6 && s == self.sup.winning_tile
+        }) {
+            Some(FuComponent::Penchan)
+        } else {
+            None
+        };
+        if let Some(wait) = wait {
+            add(wait, 2);
+        }
+
+        // Anything else rounds up to 30 anyways.
+        if is_plain && !self.sup.is_menzen && self.sup.is_ron && wait.is_none() {
+            add(FuComponent::OpenPinfu, 10);
+        }
+    }
+
+    fn fu_detail(&self, has_pinfu: bool) -> FuDetail {
+        let mut components = vec![];
+        self.walk_fu(has_pinfu, |component, fu| components.push((component, fu)));
+        let raw = components.iter().map(|&(_, fu)| fu).sum::<u8>();
+        FuDetail {
+            components,
+            raw,
+            total: self.round_fu(raw),
         }
+    }
 
-        ((fu - 1) / 10 + 1) * 10
+    /// Same as `fu_detail(has_pinfu).total`, without allocating as it is on
+    /// the hot path.
+    fn calc_fu(&self, has_pinfu: bool) -> u8 {
+        let mut raw = 0;
+        self.walk_fu(has_pinfu, |_, fu| raw += fu);
+        self.round_fu(raw)
+    }
+
+    const fn round_fu(&self, raw: u8) -> u8 {
+        if self.div.has_chitoi {
+            raw
+        } else {
+            raw.div_ceil(10) * 10
+        }
     }
 
-    fn search_yakus<const RETURN_IF_ANY: bool>(&self) -> Option<Agari> {
+    /// Found yakus will be pushed into `yakus` if it is `Some`.
+    fn search_yakus<const RETURN_IF_ANY: bool>(
+        &self,
+        mut yakus: Option<&mut Vec<(YakuName, u8)>>,
+    ) -> Option<Agari> {
         let mut han = 0;
         let mut yakuman = 0;
 
-        let has_pinfu = self.menzen_shuntsu.len() == 4
-            && !matches_tu8!(self.pair_tile, P | F | C)
-            && self.pair_tile != self.sup.bakaze
-            && self.pair_tile != self.sup.jikaze
-            && self.menzen_shuntsu.iter().any(|&s| {
-                let num = s % 9 + 1;
-                num <= 6 && s == self.sup.winning_tile || num >= 2 && s + 2 == self.sup.winning_tile
-            });
+        let has_pinfu = self.has_pinfu();
 
         macro_rules! make_return {
             () => {
@@ -479,8 +974,12 @@ impl<'a> DivWorker<'a> {
             };
         }
         macro_rules! check_early_return {
-            ($($block:tt)*) => {{
-                $($block)*;
+            ($yaku:ident => $counter:ident += $n:expr) => {{
+                let n = $n;
+                $counter += n;
+                if let Some(yakus) = yakus.as_deref_mut() {
+                    yakus.push((YakuName::$yaku, n));
+                }
                 if RETURN_IF_ANY {
                     make_return!();
                 }
@@ -489,19 +988,33 @@ impl<'a> DivWorker<'a> {
 
         if has_pinfu {
             // 平和
-            check_early_return! { han += 1 };
+            check_early_return! { Pinfu => han += 1 };
         }
         if self.div.has_chitoi {
             // 七対子
-            check_early_return! { han += 2 };
+            check_early_return! { Chiitoitsu => han += 2 };
         }
-        if self.div.has_ryanpeikou {
+        if self.sup.is_menzen && self.div.has_ryanpeikou {
             // 二盃口
-            check_early_return! { han += 3 };
+            check_early_return! { Ryanpeikou => han += 3 };
         }
         if self.div.has_chuuren {
             // 九蓮宝燈
-            check_early_return! { yakuman += 1 };
+            check_early_return! { ChuurenPoutou => yakuman += 1 };
+        }
+
+        let local_yaku = self.sup.local_yaku;
+        if local_yaku.daisharin && self.sup.is_menzen && self.sup.ankans.is_empty() {
+            const SHAPE: [u8; 9] = [0, 2, 2, 2, 2, 2, 2, 2, 0];
+            let has_daisharin = if local_yaku.daisharin_any_suit {
+                self.sup.tehai[..3 * 9].chunks_exact(9).any(|c| c == SHAPE)
+            } else {
+                self.sup.tehai[9..2 * 9] == SHAPE
+            };
+            if has_daisharin {
+                // 大車輪
+                check_early_return! { Daisharin => yakuman += 1 };
+            }
         }
 
         let has_tanyao = if self.div.has_chitoi {
@@ -525,14 +1038,14 @@ impl<'a> DivWorker<'a> {
         };
         if has_tanyao {
             // 断幺九
-            check_early_return! { han += 1 };
+            check_early_return! { Tanyao => han += 1 };
         }
 
         let has_toitoi =
             !self.div.has_chitoi && self.menzen_shuntsu.is_empty() && self.sup.chis.is_empty();
         if has_toitoi {
             // 対々和
-            check_early_return! { han += 2 };
+            check_early_return! { Toitoi => han += 2 };
         }
 
         let mut isou_kind = None;
@@ -564,17 +1077,22 @@ impl<'a> DivWorker<'a> {
         }
         if isou_kind.is_none() {
             // 字一色
-            check_early_return! { yakuman += 1 };
+            check_early_return! { Tsuuiisou => yakuman += 1 };
         } else if is_chinitsu_or_honitsu {
-            // 混一色, 清一色
-            let n = if has_jihai { 2 } else { 5 } + self.sup.is_menzen as u8;
-            check_early_return! { han += n };
+            let n = self.sup.is_menzen as u8;
+            if has_jihai {
+                // 混一色
+                check_early_return! { Honitsu => han += 2 + n };
+            } else {
+                // 清一色
+                check_early_return! { Chinitsu => han += 5 + n };
+            }
         }
 
         if !self.div.has_chitoi {
             // 一盃口
-            if self.div.has_ipeikou {
-                check_early_return! { han += 1 };
+            if self.sup.is_menzen && self.div.has_ipeikou {
+                check_early_return! { Iipeikou => han += 1 };
             } else if !self.sup.ankans.is_empty()
                 && self.sup.is_menzen
                 && self.menzen_shuntsu.len() >= 2
@@ -592,15 +1110,15 @@ impl<'a> DivWorker<'a> {
                     }
                 });
                 if has_ipeikou {
-                    check_early_return! { han += 1 };
+                    check_early_return! { Iipeikou => han += 1 };
                 }
             }
 
             // 一気通貫
             if self.sup.is_menzen && self.div.has_ittsuu {
-                check_early_return! { han += 2 };
+                check_early_return! { Ittsuu => han += 2 };
             } else if self.sup.chis.is_empty() && self.div.has_ittsuu {
-                check_early_return! { han += 1 };
+                check_early_return! { Ittsuu => han += 1 };
             } else if self.menzen_shuntsu.len() + self.sup.chis.len() >= 3 {
                 let mut kinds = [0; 3];
                 for s in self.all_shuntsu() {
@@ -614,7 +1132,7 @@ impl<'a> DivWorker<'a> {
                     };
                 }
                 if kinds.contains(&0b111) {
-                    check_early_return! { han += 1 };
+                    check_early_return! { Ittsuu => han += 1 };
                 }
             }
 
@@ -627,7 +1145,7 @@ impl<'a> DivWorker<'a> {
             if s_counter.contains(&0b111) {
                 // 三色同順
                 let n = if self.sup.is_menzen { 2 } else { 1 };
-                check_early_return! { han += n };
+                check_early_return! { SanshokuDoujun => han += n };
             } else {
                 let mut k_counter = [0; 9];
                 for k in self.all_kotsu_and_kantsu() {
@@ -639,7 +1157,24 @@ impl<'a> DivWorker<'a> {
                 }
                 if k_counter.contains(&0b111) {
                     // 三色同刻
-                    check_early_return! { han += 2 };
+                    check_early_return! { SanshokuDoukou => han += 2 };
+                }
+            }
+
+            if local_yaku.sanrenkou {
+                let mut k_marks = [0_u16; 3];
+                for k in self.all_kotsu_and_kantsu() {
+                    let kind = k as usize / 9;
+                    if kind < 3 {
+                        k_marks[kind] |= 0b1 << (k % 9);
+                    }
+                }
+                let has_sanrenkou = k_marks
+                    .iter()
+                    .any(|&m| (0..7).any(|num| (m >> num) & 0b111 == 0b111));
+                if has_sanrenkou {
+                    // 三連刻
+                    check_early_return! { Sanrenkou => han += 2 };
                 }
             }
 
@@ -647,18 +1182,18 @@ impl<'a> DivWorker<'a> {
                 - self.winning_tile_makes_minkou as usize;
             match ankous_count {
                 // 四暗刻
-                4 => check_early_return! { yakuman += 1 },
+                4 => check_early_return! { Suuankou => yakuman += 1 },
                 // 三暗刻
-                3 => check_early_return! { han += 2 },
+                3 => check_early_return! { Sanankou => han += 2 },
                 _ => (),
             };
 
             let kans_count = self.sup.ankans.len() + self.sup.minkans.len();
             match kans_count {
                 // 四槓子
-                4 => check_early_return! { yakuman += 1 },
+                4 => check_early_return! { Suukantsu => yakuman += 1 },
                 // 三槓子
-                3 => check_early_return! { han += 2 },
+                3 => check_early_return! { Sankantsu => han += 2 },
                 _ => (),
             };
 
@@ -669,7 +1204,7 @@ impl<'a> DivWorker<'a> {
                 && self.all_shuntsu().all(|s| s == tu8!(2s)); // only 234s is possible for shuntsu in ryuisou
             if has_ryuisou {
                 // 緑一色
-                check_early_return! { yakuman += 1 };
+                check_early_return! { Ryuuiisou => yakuman += 1 };
             }
 
             if !has_tanyao {
@@ -681,35 +1216,41 @@ impl<'a> DivWorker<'a> {
                     }
                 }
                 if has_jihai[self.sup.bakaze as usize - 3 * 9] {
-                    // 役牌:門風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:場風牌
+                    check_early_return! { Bakaze => han += 1 };
                 }
                 if has_jihai[self.sup.jikaze as usize - 3 * 9] {
-                    // 役牌:場風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:門風牌
+                    check_early_return! { Jikaze => han += 1 };
                 }
 
-                let saneins = (4..7).filter(|&i| has_jihai[i]).count() as u8;
-                if saneins > 0 {
-                    // 役牌:三元牌
-                    check_early_return! { han += saneins };
-                    if saneins == 3 {
-                        // 大三元
-                        check_early_return! { yakuman += 1 };
-                    } else if saneins == 2 && matches_tu8!(self.pair_tile, P | F | C) {
-                        // 小三元
-                        check_early_return! { han += 2 };
-                    }
+                // 役牌:三元牌
+                if has_jihai[4] {
+                    check_early_return! { Haku => han += 1 };
+                }
+                if has_jihai[5] {
+                    check_early_return! { Hatsu => han += 1 };
+                }
+                if has_jihai[6] {
+                    check_early_return! { Chun => han += 1 };
+                }
+                let saneins = (4..7).filter(|&i| has_jihai[i]).count();
+                if saneins == 3 {
+                    // 大三元
+                    check_early_return! { Daisangen => yakuman += 1 };
+                } else if saneins == 2 && must_tile!(self.pair_tile).is_dragon() {
+                    // 小三元
+                    check_early_return! { Shousangen => han += 2 };
                 }
 
                 let winds = (0..4).filter(|&i| has_jihai[i]).count();
                 #[allow(clippy::if_same_then_else)]
                 if winds == 4 {
                     // 大四喜
-                    check_early_return! { yakuman += 1 };
-                } else if winds == 3 && matches_tu8!(self.pair_tile, E | S | W | N) {
+                    check_early_return! { Daisuushii => yakuman += 1 };
+                } else if winds == 3 && must_tile!(self.pair_tile).is_wind() {
                     // 小四喜
-                    check_early_return! { yakuman += 1 };
+                    check_early_return! { Shousuushii => yakuman += 1 };
                 }
             }
         }
@@ -737,10 +1278,10 @@ impl<'a> DivWorker<'a> {
                 if self.div.has_chitoi || has_toitoi {
                     if has_jihai {
                         // 混老頭
-                        check_early_return! { han += 2 };
+                        check_early_return! { Honroutou => han += 2 };
                     } else {
                         // 清老頭
-                        check_early_return! { yakuman += 1 };
+                        check_early_return! { Chinroutou => yakuman += 1 };
                     }
                 } else {
                     let is_junchan_or_chanta = self.all_shuntsu().all(|s| {
@@ -748,9 +1289,14 @@ impl<'a> DivWorker<'a> {
                         num == 0 || num == 6
                     });
                     if is_junchan_or_chanta {
-                        // 混全帯幺九, 純全帯幺九
-                        let n = if has_jihai { 1 } else { 2 } + self.sup.is_menzen as u8;
-                        check_early_return! { han += n };
+                        let n = self.sup.is_menzen as u8;
+                        if has_jihai {
+                            // 混全帯幺九
+                            check_early_return! { Chanta => han += 1 + n };
+                        } else {
+                            // 純全帯幺九
+                            check_early_return! { Junchan => han += 2 + n };
+                        }
                     }
                 }
             }
@@ -970,6 +1516,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(3m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 40, han: 4 });
@@ -986,6 +1533,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(3m),
             is_ron: false,
+            ..Default::default()
         };
         let points = calc.agari(2, 0).unwrap().point(true);
         // 立直, 門前清自摸和
@@ -1010,6 +1558,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5p),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 25, han: 3 });
@@ -1027,6 +1576,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(4m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 1 });
@@ -1043,6 +1593,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(3m),
             is_ron: false,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 4 });
@@ -1059,6 +1610,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            ..Default::default()
         };
         assert_eq!(calc.search_yakus(), None);
 
@@ -1074,6 +1626,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (without ankan)
@@ -1091,6 +1644,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (with ankan)
@@ -1108,6 +1662,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(7m),
             is_ron: false,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 四暗刻
@@ -1130,6 +1685,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 平和, 二盃口
@@ -1152,6 +1708,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1169,6 +1726,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1186,6 +1744,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: false,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 門前清自摸和 is not accounted.
@@ -1203,6 +1762,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(C),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Yakuman(3));
@@ -1219,6 +1779,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: false,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 純全, 三色
@@ -1236,6 +1797,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5s),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻 (5s is ankou)
@@ -1255,6 +1817,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(E),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 混全帯幺九, 役牌*1
@@ -1273,6 +1836,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(9m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 混一色, 混老頭, 役牌*3, 対々和
@@ -1301,6 +1865,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1319,6 +1884,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(5p),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 断么九
@@ -1337,6 +1903,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1s),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1355,6 +1922,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1372,9 +1940,517 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(C),
             is_ron: true,
+            ..Default::default()
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻, 対々和, 混一色, 混老頭, 小三元, double 南, 白, 中
         assert!(matches!(yaku, Agari::Normal { han: 15, .. }));
     }
+
+    #[test]
+    fn yaku_list() {
+        let tehai = hand("123m 123p 123s 456m 99s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(9s),
+            is_ron: true,
+            ..Default::default()
+        };
+        assert_eq!(calc.yaku_list(0), [(YakuName::SanshokuDoujun, 2)]);
+
+        let tehai = hand("123p 123s 456m 99s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: false,
+            chis: &[tu8!(1m)],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(9s),
+            is_ron: true,
+            ..Default::default()
+        };
+        assert_eq!(calc.yaku_list(0), [(YakuName::SanshokuDoujun, 1)]);
+
+        let tehai = hand("111s 2225556677z 7z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(S),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(C),
+            is_ron: true,
+            ..Default::default()
+        };
+        let yakus = calc.yaku_list(0);
+        assert_eq!(yakus.iter().map(|&(_, n)| n).sum::<u8>(), 15);
+        assert!(yakus.contains(&(YakuName::Shousangen, 2)));
+
+        let tehai = hand("111m 999p 111999s 11z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(E),
+            winning_tile: tu8!(E),
+            is_ron: false,
+            ..Default::default()
+        };
+        // 四暗刻, with 対々和 and 混老頭 omitted
+        assert_eq!(calc.yaku_list(0), [(YakuName::Suuankou, 1)]);
+    }
+
+    #[test]
+    fn contains_yaku() {
+        // Also a valid 七対子, but 二盃口 scores higher.
+        let tehai = hand("112233m 445566p 77s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(6p),
+            is_ron: true,
+            ..Default::default()
+        };
+        assert!(calc.contains_yaku(YakuName::Ryanpeikou, 0));
+        assert!(!calc.contains_yaku(YakuName::Iipeikou, 0));
+        assert!(!calc.contains_yaku(YakuName::Chiitoitsu, 0));
+
+        let tehai = hand("112233m 789s 777z 55s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(9s),
+            ..calc
+        };
+        assert!(calc.contains_yaku(YakuName::Iipeikou, 0));
+        assert!(!calc.contains_yaku(YakuName::Ryanpeikou, 0));
+        assert!(calc.contains_yaku(YakuName::Chun, 0));
+
+        // The same shape with an open 789s can't be 一盃口.
+        let tehai = hand("112233m 777z 55s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: false,
+            chis: &[tu8!(7s)],
+            winning_tile: tu8!(5s),
+            ..calc
+        };
+        assert!(!calc.contains_yaku(YakuName::Iipeikou, 0));
+        assert!(calc.contains_yaku(YakuName::Chun, 0));
+        assert!(!calc.contains_yaku(YakuName::Riichi, 1));
+    }
+
+    #[test]
+    fn best_decomposition() {
+        let tehai = hand("222333444m 456p 55s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(4m),
+            is_ron: false,
+            ..Default::default()
+        };
+        // 門前清自摸和 断幺九 三暗刻 (40 fu) beats 門前清自摸和 断幺九 平和 一盃口
+        // (20 fu).
+        let d = calc.best_decomposition(1, 0).unwrap();
+        assert_eq!(d.agari, Agari::Normal { fu: 40, han: 4 });
+        assert_eq!(Some(d.agari), calc.agari(1, 0));
+        assert_eq!(d.pairs.as_slice(), [tu8!(5s)]);
+        assert_eq!(d.kotsu.as_slice(), [tu8!(2m), tu8!(3m), tu8!(4m)]);
+        assert_eq!(d.shuntsu.as_slice(), [tu8!(4p)]);
+
+        let tehai = hand("1133m 2255p 4477s 11z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(E),
+            is_ron: true,
+            ..calc
+        };
+        let d = calc.best_decomposition(0, 0).unwrap();
+        assert_eq!(d.agari, Agari::Normal { fu: 25, han: 2 });
+        assert_eq!(d.pairs.len(), 7);
+        assert!(d.kotsu.is_empty() && d.shuntsu.is_empty());
+
+        // No yaku at all.
+        let tehai = hand("123m 456p 789s 11z 567s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(5s),
+            ..calc
+        };
+        assert_eq!(calc.best_decomposition(0, 3), None);
+        assert_eq!(
+            calc.best_decomposition(1, 0).unwrap().agari,
+            calc.agari(1, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn all_agari() {
+        let tehai = hand("223344m 556677p 99s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(2m),
+            is_ron: true,
+            ..Default::default()
+        };
+        // 平和 二盃口 (30 fu), and 七対子 (25 fu).
+        let mut all = calc.all_agari(0, 0);
+        all.sort();
+        assert_eq!(
+            all,
+            [
+                Agari::Normal { fu: 25, han: 2 },
+                Agari::Normal { fu: 30, han: 4 },
+            ],
+        );
+        assert_eq!(all.iter().max().copied(), calc.agari(0, 0));
+
+        let mut all = calc.all_agari(1, 2);
+        all.sort();
+        assert_eq!(
+            all,
+            [
+                Agari::Normal { fu: 25, han: 5 },
+                Agari::Normal { fu: 30, han: 7 },
+            ],
+        );
+
+        // No yaku at all.
+        let tehai = hand("123m 456p 789s 11z 567s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(5s),
+            ..calc
+        };
+        assert!(calc.all_agari(0, 3).is_empty());
+        assert_eq!(calc.all_agari(1, 0).len(), 1);
+    }
+
+    #[test]
+    fn menzen_only_yakus_on_open_hand() {
+        let open = |tehai, winning_tile| AgariCalculator {
+            tehai,
+            is_menzen: false,
+            chis: &[tu8!(2m)],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile,
+            is_ron: true,
+            ..Default::default()
+        };
+
+        // 断幺九 only, without 平和.
+        let tehai = hand("567m 234p 678s 55s").unwrap();
+        let calc = open(&tehai, tu8!(8s));
+        assert_eq!(calc.search_yakus(), Some(Agari::Normal { fu: 30, han: 1 }));
+        assert!(!calc.contains_yaku(YakuName::Pinfu, 0));
+        assert!(calc.contains_yaku(YakuName::Tanyao, 0));
+
+        // 断幺九 only, without 一盃口.
+        let tehai = hand("223344m 567p 88s").unwrap();
+        let calc = open(&tehai, tu8!(8s));
+        assert_eq!(calc.yaku_list(0), [(YakuName::Tanyao, 1)]);
+    }
+
+    #[test]
+    fn ryanpeikou_over_chiitoitsu() {
+        let tehai = hand("112233m 445566p 77s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(7s),
+            is_ron: true,
+            ..Default::default()
+        };
+        // 二盃口 with a tanki wait (40 fu) over 七対子 (25 fu).
+        let ryanpeikou = Agari::Normal { fu: 40, han: 3 };
+        assert_eq!(calc.search_yakus(), Some(ryanpeikou));
+        assert_eq!(calc.agari(0, 0), Some(ryanpeikou));
+        assert!(
+            calc.all_agari(0, 0)
+                .contains(&Agari::Normal { fu: 25, han: 2 })
+        );
+        let d = calc.best_decomposition(0, 0).unwrap();
+        assert_eq!(d.agari, ryanpeikou);
+        assert_eq!(d.pairs.as_slice(), [tu8!(7s)]);
+        assert_eq!(d.shuntsu.len(), 4);
+
+        // 門前清自摸和 二盃口 (30 fu) over 門前清自摸和 七対子 (25 fu).
+        let calc = AgariCalculator {
+            is_ron: false,
+            ..calc
+        };
+        assert_eq!(calc.agari(1, 0), Some(Agari::Normal { fu: 30, han: 4 }));
+
+        // 平和 二盃口 on 23m.
+        let calc = AgariCalculator {
+            winning_tile: tu8!(1m),
+            is_ron: true,
+            ..calc
+        };
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 30, han: 4 }));
+    }
+
+    #[test]
+    fn local_yaku() {
+        let tehai = hand("22334455667788p").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(5p),
+            is_ron: false,
+            ..Default::default()
+        };
+        // 門前清自摸和 平和 断幺九 二盃口 清一色
+        assert_eq!(calc.agari(1, 0), Some(Agari::Normal { fu: 0, han: 12 }));
+        assert!(!calc.contains_yaku(YakuName::Daisharin, 1));
+
+        let calc = AgariCalculator {
+            local_yaku: LocalYaku {
+                daisharin: true,
+                ..Default::default()
+            },
+            ..calc
+        };
+        assert_eq!(calc.agari(1, 0), Some(Agari::Yakuman(1)));
+        assert_eq!(calc.yaku_list(1), [(YakuName::Daisharin, 1)]);
+
+        // 大数隣 only counts with `daisharin_any_suit`.
+        let tehai = hand("22334455667788m").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(5m),
+            ..calc
+        };
+        assert_eq!(calc.agari(1, 0), Some(Agari::Normal { fu: 0, han: 12 }));
+        let calc = AgariCalculator {
+            local_yaku: LocalYaku {
+                daisharin: true,
+                daisharin_any_suit: true,
+                ..Default::default()
+            },
+            ..calc
+        };
+        assert_eq!(calc.agari(1, 0), Some(Agari::Yakuman(1)));
+
+        let tehai = hand("678p 55s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: false,
+            pons: &[tu8!(2m), tu8!(3m), tu8!(4m)],
+            winning_tile: tu8!(5s),
+            is_ron: true,
+            local_yaku: LocalYaku::default(),
+            ..calc
+        };
+        // 断幺九
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 30, han: 1 }));
+        let calc = AgariCalculator {
+            local_yaku: LocalYaku {
+                sanrenkou: true,
+                ..Default::default()
+            },
+            ..calc
+        };
+        // 断幺九 三連刻
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 30, han: 3 }));
+        assert!(calc.contains_yaku(YakuName::Sanrenkou, 0));
+
+        let calc = AgariCalculator {
+            pons: &[tu8!(2m), tu8!(3m), tu8!(5m)],
+            ..calc
+        };
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 30, han: 1 }));
+
+        // The kotsu division 222333444m wins over 234m x3 and picks it up.
+        let tehai = hand("222333444m 678p 55s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            pons: &[],
+            winning_tile: tu8!(5s),
+            ..calc
+        };
+        // 断幺九 三暗刻 三連刻
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 0, han: 5 }));
+    }
+
+    #[test]
+    fn fu_detail() {
+        use FuComponent::*;
+
+        // 平和 ツモ
+        let tehai = hand("234m 567p 345s 678s 99p").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(2m),
+            is_ron: false,
+            ..Default::default()
+        };
+        let d = calc.fu_detail(1).unwrap();
+        assert_eq!(d.components, [(Base, 20)]);
+        assert_eq!((d.raw, d.total), (20, 20));
+        assert_eq!(calc.agari(1, 0), Some(Agari::Normal { fu: 20, han: 2 }));
+
+        // 七対子
+        let tehai = hand("1133m 2255p 4477s 11z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(E),
+            is_ron: true,
+            ..calc
+        };
+        let d = calc.fu_detail(0).unwrap();
+        assert_eq!(d.components, [(Chiitoitsu, 25)]);
+        assert_eq!((d.raw, d.total), (25, 25));
+
+        // Closed ron on 5p kanchan with an ankan of the bakaze.
+        let tehai = hand("222m 456p 789s 77z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            ankans: &[tu8!(E)],
+            winning_tile: tu8!(5p),
+            ..calc
+        };
+        let d = calc.fu_detail(0).unwrap();
+        assert_eq!(
+            d.components,
+            [
+                (Base, 20),
+                (Ankou(tu8!(2m)), 4),
+                (Ankan(tu8!(E)), 32),
+                (YakuhaiPair(tu8!(C)), 2),
+                (MenzenRon, 10),
+                (Kanchan, 2),
+            ],
+        );
+        assert_eq!((d.raw, d.total), (70, 70));
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 70, han: 1 }));
+
+        // Open ryanmen ron without any other fu.
+        let tehai = hand("456p 678s 345s 55m").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: false,
+            chis: &[tu8!(2m)],
+            ankans: &[],
+            winning_tile: tu8!(3s),
+            ..calc
+        };
+        let d = calc.fu_detail(0).unwrap();
+        assert_eq!(d.components, [(Base, 20), (OpenPinfu, 10)]);
+        assert_eq!((d.raw, d.total), (30, 30));
+        assert_eq!(calc.agari(0, 0), Some(Agari::Normal { fu: 30, han: 1 }));
+
+        // No fu for yakumans nor hands without yaku.
+        let tehai = hand("19m 19p 19s 12345677z").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: true,
+            chis: &[],
+            winning_tile: tu8!(C),
+            ..calc
+        };
+        assert_eq!(calc.fu_detail(0), None);
+        let tehai = hand("123m 456p 789s 11z 567s").unwrap();
+        let calc = AgariCalculator {
+            tehai: &tehai,
+            winning_tile: tu8!(5s),
+            ..calc
+        };
+        assert_eq!(calc.fu_detail(0), None);
+        assert_eq!(calc.fu_detail(1).unwrap().total, 40);
+        assert_eq!(calc.agari(1, 0), Some(Agari::Normal { fu: 40, han: 1 }));
+    }
+
+    #[test]
+    fn point_for_oya_and_ko() {
+        let agari = Agari::Normal { fu: 30, han: 2 };
+        let oya = agari.point_for(true);
+        let ko = agari.point_for(false);
+        assert_eq!(
+            oya,
+            Point {
+                ron: 2900,
+                tsumo_ko: 1000,
+                tsumo_oya: 0,
+            },
+        );
+        assert_eq!(
+            ko,
+            Point {
+                ron: 2000,
+                tsumo_ko: 500,
+                tsumo_oya: 1000,
+            },
+        );
+        assert_eq!(oya.tsumo_total(true), 3000);
+        assert_eq!(ko.tsumo_total(false), 2000);
+        assert_eq!(agari.point(true), oya);
+        assert_eq!(agari.point(false), ko);
+
+        let yakuman = Agari::Yakuman(1);
+        assert_eq!(yakuman.point(true).ron, 48000);
+        assert_eq!(yakuman.point(true).tsumo_ko, 16000);
+        assert_eq!(yakuman.point(false).ron, 32000);
+        assert_eq!(yakuman.point(false).tsumo_oya, 16000);
+    }
 }
@@ -1,7 +1,8 @@
 //! This module includes essential mahjong algorithms including agari, shanten,
-//! single-player calculators and score lookups.
+//! single-player calculators, score lookups and score bookkeeping.
 
 pub mod agari;
 pub mod point;
+pub mod scoreboard;
 pub mod shanten;
 pub mod sp;
@@ -1,10 +1,52 @@
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+use serde::Serialize;
+
+/// The points of a win, for a winner who is either the dealer (oya) or not
+/// (ko), which is decided once when calculating it.
+///
+/// On a tsumo, each non-dealer pays `tsumo_ko` and the dealer pays
+/// `tsumo_oya`. For a dealer's win there is no dealer to pay, so `tsumo_oya`
+/// is 0 and everyone pays `tsumo_ko`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub struct Point {
     pub ron: i32,
     pub tsumo_ko: i32,
     pub tsumo_oya: i32,
 }
 
+/// The limit tier of a `Point`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Below,
+    Mangan,
+    Haneman,
+    Baiman,
+    Sanbaiman,
+    /// With the multiplier, where kazoe yakuman counts as 1.
+    Yakuman(u8),
+}
+
+/// How the payments of a ko's tsumo are rounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TsumoRounding {
+    /// Each payer's share of the base points is rounded up to 100 on its own,
+    /// as in `Point::calc`, so the total can exceed the ron value, e.g. 30 fu
+    /// 1 han is 300/500 for 1100 in total.
+    #[default]
+    PerPayer,
+    /// The total is the ron value, of which each ko pays 1/4 rounded up to
+    /// 100 and the oya pays the rest, e.g. 30 fu 1 han is 300/400. A dealer's
+    /// tsumo is always split equally, so it is the same as `PerPayer` there.
+    Total,
+}
+
+/// Rounds `points` up to the nearest 100. Every payment is rounded this way
+/// from the base points (基本点) before any honba is added.
+#[inline]
+#[must_use]
+pub const fn round_to_hundred(points: i32) -> i32 {
+    (points + 99) / 100 * 100
+}
+
 impl Point {
     /// Panics if the combinition is not possible.
     ///
@@ -83,6 +125,61 @@ impl Point {
         }
     }
 
+    /// Same as `calc`, but with 切り上げ満貫 when `kiriage_mangan` holds, where
+    /// 4 han 30 fu and 3 han 60 fu are rounded up to mangan.
+    #[must_use]
+    pub fn calc_with_kiriage(is_oya: bool, fu: u8, han: u8, kiriage_mangan: bool) -> Self {
+        if kiriage_mangan && matches!((fu, han), (30, 4) | (60, 3)) {
+            Self::mangan(is_oya)
+        } else {
+            Self::calc(is_oya, fu, han)
+        }
+    }
+
+    /// Same as `calc_with_kiriage`, but when `kazoe_yakuman` is false, 13 han
+    /// or more is capped at sanbaiman instead of being 数え役満, and with the
+    /// tsumo payments rounded by `tsumo_rounding`, see `with_tsumo_rounding`.
+    ///
+    /// The cap is decided first on the han alone, and 切り上げ満貫 then only
+    /// rounds 4 han 30 fu and 3 han 60 fu, so the two never touch the same
+    /// hands and compose in either order: with both on, 4 han 30 fu is a
+    /// mangan and 13 han a yakuman, and with `kazoe_yakuman` off, 13 han is
+    /// a sanbaiman regardless of the fu.
+    #[must_use]
+    pub fn calc_with_rules(
+        is_oya: bool,
+        fu: u8,
+        han: u8,
+        kiriage_mangan: bool,
+        kazoe_yakuman: bool,
+        tsumo_rounding: TsumoRounding,
+    ) -> Self {
+        let point = if !kazoe_yakuman && han >= 13 {
+            Self::calc(is_oya, fu, 12)
+        } else {
+            Self::calc_with_kiriage(is_oya, fu, han, kiriage_mangan)
+        };
+        point.with_tsumo_rounding(is_oya, tsumo_rounding)
+    }
+
+    /// Re-splits the tsumo payments of `self`, which are rounded as with
+    /// `TsumoRounding::PerPayer`, by `rounding`. `ron` is the same either
+    /// way, and so is every payment of a limit hand.
+    #[must_use]
+    pub const fn with_tsumo_rounding(self, is_oya: bool, rounding: TsumoRounding) -> Self {
+        match rounding {
+            TsumoRounding::Total if !is_oya => {
+                let tsumo_ko = round_to_hundred(self.ron / 4);
+                Self {
+                    tsumo_ko,
+                    tsumo_oya: self.ron - tsumo_ko * 2,
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
     #[inline]
     #[must_use]
     pub const fn yakuman(is_oya: bool, count: i32) -> Self {
@@ -101,6 +198,25 @@ impl Point {
         }
     }
 
+    /// Same as `calc` with 5 hans, also used for nagashi mangan.
+    #[inline]
+    #[must_use]
+    pub const fn mangan(is_oya: bool) -> Self {
+        if is_oya {
+            Self {
+                ron: 12000,
+                tsumo_ko: 4000,
+                tsumo_oya: 0,
+            }
+        } else {
+            Self {
+                ron: 8000,
+                tsumo_ko: 2000,
+                tsumo_oya: 4000,
+            }
+        }
+    }
+
     #[inline]
     #[must_use]
     pub const fn tsumo_total(self, is_oya: bool) -> i32 {
@@ -110,6 +226,129 @@ impl Point {
             self.tsumo_ko * 2 + self.tsumo_oya
         }
     }
+
+    /// Returns the limit tier, derived from the ron points so that it agrees
+    /// with any rounding such as 切り上げ満貫 already applied to `self`.
+    /// `is_oya` must be the one `self` was calculated for.
+    #[must_use]
+    pub const fn limit(self, is_oya: bool) -> Limit {
+        let mangan = Self::mangan(is_oya).ron;
+        match self.ron {
+            r if r < mangan => Limit::Below,
+            r if r < mangan * 3 / 2 => Limit::Mangan,
+            r if r < mangan * 2 => Limit::Haneman,
+            r if r < mangan * 3 => Limit::Baiman,
+            r if r < mangan * 4 => Limit::Sanbaiman,
+            r => Limit::Yakuman((r / (mangan * 4)) as u8),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_mangan_or_above(self, is_oya: bool) -> bool {
+        !matches!(self.limit(is_oya), Limit::Below)
+    }
+
+    /// Returns the score deltas of each seat after the agari, including honba
+    /// and kyotaku. All seats are relative, and `target_rel` is ignored for
+    /// tsumo.
+    #[must_use]
+    pub const fn payment_vector(
+        self,
+        winner_rel: usize,
+        is_ron: bool,
+        target_rel: usize,
+        oya_rel: usize,
+        honba: u8,
+        kyotaku: u8,
+    ) -> [i32; 4] {
+        let honba = honba as i32;
+        let mut deltas = [0; 4];
+        if is_ron {
+            deltas[target_rel] -= self.ron + honba * 300;
+            deltas[winner_rel] += self.ron + honba * 300;
+        } else {
+            let is_oya = winner_rel == oya_rel;
+            let mut i = 0;
+            while i < 4 {
+                if i != winner_rel {
+                    let pay = if is_oya || i != oya_rel {
+                        self.tsumo_ko
+                    } else {
+                        self.tsumo_oya
+                    };
+                    deltas[i] -= pay + honba * 100;
+                }
+                i += 1;
+            }
+            deltas[winner_rel] += self.tsumo_total(is_oya) + honba * 300;
+        }
+        deltas[winner_rel] += kyotaku as i32 * 1000;
+        deltas
+    }
+
+    /// Same as `payment_vector`, but with `liable_rel` being the player liable
+    /// (包) for the win. As per [Tenhou's rule](https://tenhou.net/man/#RULE):
+    ///
+    /// > 複合役満を含む得点を、ツモ＝全額・ロン＝折半で支払う。積み棒は包。
+    ///
+    /// The liable player may be the discarder of a ron as well, who then pays
+    /// it all.
+    #[must_use]
+    pub const fn pao_payment_vector(
+        self,
+        winner_rel: usize,
+        is_ron: bool,
+        target_rel: usize,
+        liable_rel: usize,
+        honba: u8,
+        kyotaku: u8,
+    ) -> [i32; 4] {
+        // For pao to happen, the agari must have at least 1 yakuman, so the
+        // ron points and the sum of the tsumo points are equal.
+        let honba = honba as i32;
+        let mut deltas = [0; 4];
+        if is_ron {
+            deltas[liable_rel] -= self.ron / 2 + honba * 300;
+            deltas[target_rel] -= self.ron / 2;
+        } else {
+            deltas[liable_rel] -= self.ron + honba * 300;
+        }
+        deltas[winner_rel] += self.ron + honba * 300 + kyotaku as i32 * 1000;
+        deltas
+    }
+}
+
+/// Returns the score deltas of the noten payments (不聴罰符) at an exhaustive
+/// ryukyoku, where 3000 points in total are paid evenly by the noten players
+/// to the tenpai players.
+#[must_use]
+pub const fn noten_payments(tenpai: [bool; 4]) -> [i32; 4] {
+    let mut count = 0;
+    let mut i = 0;
+    while i < 4 {
+        if tenpai[i] {
+            count += 1;
+        }
+        i += 1;
+    }
+    let (plus, minus) = match count {
+        1 => (3000, -1000),
+        2 => (1500, -1500),
+        3 => (1000, -3000),
+        // 0 | 4
+        _ => return [0; 4],
+    };
+
+    let mut deltas = [minus; 4];
+    i = 0;
+    while i < 4 {
+        if tenpai[i] {
+            deltas[i] = plus;
+        }
+        i += 1;
+    }
+    deltas
 }
 
 #[cfg(test)]
@@ -151,4 +390,236 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn kiriage_mangan() {
+        for (fu, han) in [(30, 4), (60, 3)] {
+            assert_eq!(Point::calc_with_kiriage(false, fu, han, false).ron, 7700);
+            assert_eq!(Point::calc_with_kiriage(true, fu, han, false).ron, 11600);
+            assert_eq!(
+                Point::calc_with_kiriage(false, fu, han, true),
+                Point::mangan(false),
+            );
+            assert_eq!(
+                Point::calc_with_kiriage(true, fu, han, true),
+                Point::mangan(true),
+            );
+        }
+        for (fu, han) in [(30, 3), (40, 3), (25, 4), (110, 2)] {
+            assert_eq!(
+                Point::calc_with_kiriage(false, fu, han, true),
+                Point::calc(false, fu, han),
+            );
+        }
+
+        // Honba is added after the rounding.
+        assert_eq!(
+            Point::calc_with_kiriage(false, 30, 4, true).payment_vector(0, true, 1, 2, 1, 0),
+            [8300, -8300, 0, 0],
+        );
+    }
+
+    #[test]
+    fn tsumo_rounding() {
+        let rules = |is_oya, fu, han, rounding| {
+            Point::calc_with_rules(is_oya, fu, han, false, true, rounding)
+        };
+        for fu in [20, 25, 30, 40, 50, 60, 70, 80, 90, 100, 110] {
+            for han in 1..=13 {
+                if matches!((fu, han), (20 | 25, 1)) {
+                    continue;
+                }
+                for is_oya in [false, true] {
+                    let per_payer = rules(is_oya, fu, han, TsumoRounding::PerPayer);
+                    assert_eq!(per_payer, Point::calc(is_oya, fu, han));
+
+                    let total = rules(is_oya, fu, han, TsumoRounding::Total);
+                    assert_eq!(total.ron, per_payer.ron);
+                    assert_eq!(total.tsumo_ko % 100, 0, "{is_oya} {fu}/{han}");
+                    assert_eq!(total.tsumo_oya % 100, 0, "{is_oya} {fu}/{han}");
+                    if is_oya || per_payer.is_mangan_or_above(is_oya) {
+                        assert_eq!(total, per_payer, "{is_oya} {fu}/{han}");
+                    } else {
+                        assert_eq!(total.tsumo_total(false), total.ron);
+                    }
+                }
+            }
+        }
+
+        // 40 fu 3 han is 1300/2600 both ways, which adds up to the ron value.
+        for rounding in [TsumoRounding::PerPayer, TsumoRounding::Total] {
+            let point = rules(false, 40, 3, rounding);
+            assert_eq!((point.tsumo_ko, point.tsumo_oya), (1300, 2600));
+        }
+
+        // 30 fu 1 han is 300/500 per payer, but 300/400 from the total of 1000.
+        let per_payer = rules(false, 30, 1, TsumoRounding::PerPayer);
+        let total = rules(false, 30, 1, TsumoRounding::Total);
+        assert_eq!((per_payer.tsumo_ko, per_payer.tsumo_oya), (300, 500));
+        assert_eq!(per_payer.tsumo_total(false), 1100);
+        assert_eq!((total.tsumo_ko, total.tsumo_oya), (300, 400));
+
+        // 20 fu 3 han is 700/1300 per payer, but 700/1200 from 2600. With
+        // 切り上げ満貫, 30 fu 4 han is a mangan either way.
+        let total = rules(false, 20, 3, TsumoRounding::Total);
+        assert_eq!((total.tsumo_ko, total.tsumo_oya), (700, 1200));
+        assert_eq!(
+            Point::calc_with_rules(false, 30, 4, true, true, TsumoRounding::Total),
+            Point::mangan(false),
+        );
+
+        // Honba is added after the rounding either way.
+        assert_eq!(
+            total.payment_vector(0, false, 0, 1, 1, 0),
+            [2600 + 300, -1300, -800, -800],
+        );
+        assert_eq!(round_to_hundred(1), 100);
+        assert_eq!(round_to_hundred(100), 100);
+        assert_eq!(round_to_hundred(0), 0);
+    }
+
+    #[test]
+    fn kazoe_yakuman() {
+        for is_oya in [false, true] {
+            for han in [13, 14, 20] {
+                let point =
+                    Point::calc_with_rules(is_oya, 30, han, false, true, TsumoRounding::PerPayer);
+                assert_eq!(point, Point::yakuman(is_oya, 1));
+                assert_eq!(point.limit(is_oya), Limit::Yakuman(1));
+
+                let point =
+                    Point::calc_with_rules(is_oya, 30, han, false, false, TsumoRounding::PerPayer);
+                assert_eq!(point, Point::calc(is_oya, 30, 12));
+                assert_eq!(point.limit(is_oya), Limit::Sanbaiman);
+            }
+            assert_eq!(
+                Point::calc_with_rules(is_oya, 30, 12, false, false, TsumoRounding::PerPayer),
+                Point::calc(is_oya, 30, 12),
+            );
+            assert_eq!(
+                Point::calc_with_rules(is_oya, 30, 4, true, false, TsumoRounding::PerPayer),
+                Point::mangan(is_oya),
+            );
+        }
+    }
+
+    #[test]
+    fn kiriage_with_kazoe() {
+        for is_oya in [false, true] {
+            let rules = |fu, han, kazoe_yakuman| {
+                Point::calc_with_rules(
+                    is_oya,
+                    fu,
+                    han,
+                    true,
+                    kazoe_yakuman,
+                    TsumoRounding::PerPayer,
+                )
+                .limit(is_oya)
+            };
+            for kazoe_yakuman in [false, true] {
+                assert_eq!(rules(30, 4, kazoe_yakuman), Limit::Mangan);
+                assert_eq!(rules(60, 3, kazoe_yakuman), Limit::Mangan);
+                assert_eq!(rules(30, 3, kazoe_yakuman), Limit::Below);
+                assert_eq!(rules(50, 3, kazoe_yakuman), Limit::Below);
+                assert_eq!(rules(20, 4, kazoe_yakuman), Limit::Below);
+                assert_eq!(rules(30, 12, kazoe_yakuman), Limit::Sanbaiman);
+            }
+            assert_eq!(rules(30, 13, true), Limit::Yakuman(1));
+            assert_eq!(rules(30, 13, false), Limit::Sanbaiman);
+            assert_eq!(rules(110, 26, false), Limit::Sanbaiman);
+        }
+    }
+
+    #[test]
+    fn limit() {
+        for is_oya in [false, true] {
+            for (fu, han, expected) in [
+                (110, 2, Limit::Below),
+                (30, 4, Limit::Below),
+                (60, 3, Limit::Below),
+                (40, 4, Limit::Mangan),
+                (70, 3, Limit::Mangan),
+                (30, 5, Limit::Mangan),
+                (30, 6, Limit::Haneman),
+                (30, 7, Limit::Haneman),
+                (30, 8, Limit::Baiman),
+                (30, 10, Limit::Baiman),
+                (30, 11, Limit::Sanbaiman),
+                (30, 12, Limit::Sanbaiman),
+                // kazoe
+                (30, 13, Limit::Yakuman(1)),
+                (30, 14, Limit::Yakuman(1)),
+            ] {
+                let point = Point::calc(is_oya, fu, han);
+                assert_eq!(point.limit(is_oya), expected, "{is_oya} {fu}/{han}");
+                assert_eq!(
+                    point.is_mangan_or_above(is_oya),
+                    expected != Limit::Below,
+                    "{is_oya} {fu}/{han}",
+                );
+            }
+            assert_eq!(
+                Point::calc_with_kiriage(is_oya, 30, 4, true).limit(is_oya),
+                Limit::Mangan,
+            );
+            assert_eq!(Point::yakuman(is_oya, 1).limit(is_oya), Limit::Yakuman(1));
+            assert_eq!(Point::yakuman(is_oya, 2).limit(is_oya), Limit::Yakuman(2));
+            assert_eq!(Point::yakuman(is_oya, 6).limit(is_oya), Limit::Yakuman(6));
+        }
+
+        // Only the ron points are known, e.g. for a ron-only hand.
+        let ron_only = Point {
+            ron: 8000,
+            tsumo_ko: 0,
+            tsumo_oya: 0,
+        };
+        assert_eq!(ron_only.limit(false), Limit::Mangan);
+        assert_eq!(ron_only.limit(true), Limit::Below);
+    }
+
+    #[test]
+    fn payment_vector() {
+        let point = Point::calc(false, 30, 3);
+        let (honba, kyotaku) = (2, 1);
+
+        let mut expected = [0; 4];
+        expected[0] += point.ron + kyotaku * 1000 + honba * 300;
+        expected[2] -= point.ron + honba * 300;
+        assert_eq!(
+            point.payment_vector(0, true, 2, 1, honba as u8, kyotaku as u8),
+            expected,
+        );
+
+        let mut expected = [0; 4];
+        expected[0] += point.tsumo_total(false) + kyotaku * 1000 + honba * 300;
+        for (idx, s) in expected.iter_mut().enumerate().skip(1) {
+            if idx == 1 {
+                *s -= point.tsumo_oya + honba * 100;
+            } else {
+                *s -= point.tsumo_ko + honba * 100;
+            }
+        }
+        assert_eq!(
+            point.payment_vector(0, false, 0, 1, honba as u8, kyotaku as u8),
+            expected,
+        );
+
+        let point = Point::calc(true, 40, 2);
+        assert_eq!(
+            point.payment_vector(3, false, 3, 3, 1, 0),
+            [-1400, -1400, -1400, 4200]
+        );
+
+        assert_eq!(Point::mangan(false), Point::calc(false, 30, 5));
+        assert_eq!(Point::mangan(true), Point::calc(true, 30, 5));
+        assert_eq!(
+            Point::mangan(false).payment_vector(1, false, 1, 0, 0, 0),
+            [-4000, 8000, -2000, -2000]
+        );
+        assert_eq!(
+            point.payment_vector(3, true, 1, 3, 0, 2),
+            [0, -3900, 0, 5900]
+        );
+    }
 }
@@ -0,0 +1,263 @@
+//! Score bookkeeping across kyokus.
+
+use super::point::{self, Point};
+use crate::vec_ops::vec_add_assign;
+
+use tinyvec::ArrayVec;
+
+/// The scores, honba, kyotaku and oya of a game, updated by the result of
+/// each kyoku.
+///
+/// Seats can be either absolute or relative as long as they are consistent.
+/// Nothing here decides when the game ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scoreboard {
+    pub scores: [i32; 4],
+    pub honba: u8,
+    pub kyotaku: u8,
+    pub oya: u8,
+}
+
+/// A winner of a hora, along with the player liable (包) for it, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Winner {
+    pub seat: usize,
+    pub point: Point,
+    pub liable: Option<usize>,
+}
+
+impl Scoreboard {
+    /// Takes 1000 points from `seat` into the kyotaku for an accepted riichi.
+    pub const fn apply_riichi(&mut self, seat: usize) {
+        self.scores[seat] -= 1000;
+        self.kyotaku += 1;
+    }
+
+    /// Returns the score deltas of each of `winners`, in the same order,
+    /// without applying them. `target` is ignored for tsumo, which must have
+    /// exactly one winner.
+    ///
+    /// In a multi-ron, only the winner closest to `target` in turn order (the
+    /// head-bump winner) takes the honba and all of the kyotaku.
+    pub fn agari_deltas(
+        &self,
+        winners: &[Winner],
+        is_ron: bool,
+        target: usize,
+    ) -> ArrayVec<[[i32; 4]; 3]> {
+        assert!(
+            is_ron && !winners.is_empty() || winners.len() == 1,
+            "invalid number of winners: {}",
+            winners.len(),
+        );
+        let head_bump = winners
+            .iter()
+            .map(|w| (w.seat + 4 - target) % 4)
+            .min()
+            .unwrap_or_default();
+
+        winners
+            .iter()
+            .map(|w| {
+                let (honba, kyotaku) = if !is_ron || (w.seat + 4 - target) % 4 == head_bump {
+                    (self.honba, self.kyotaku)
+                } else {
+                    (0, 0)
+                };
+                if let Some(liable) = w.liable {
+                    w.point
+                        .pao_payment_vector(w.seat, is_ron, target, liable, honba, kyotaku)
+                } else {
+                    w.point.payment_vector(
+                        w.seat,
+                        is_ron,
+                        target,
+                        self.oya as usize,
+                        honba,
+                        kyotaku,
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Pays each of `winners` as in `agari_deltas`, then settles the honba and
+    /// kyotaku once for the whole hora.
+    ///
+    /// The dealership is kept with the honba incremented if the oya is among
+    /// `winners`, or otherwise passed on with the honba reset. Returns the
+    /// total score deltas.
+    pub fn apply_agari(&mut self, winners: &[Winner], is_ron: bool, target: usize) -> [i32; 4] {
+        let mut deltas = [0; 4];
+        for d in self.agari_deltas(winners, is_ron, target) {
+            vec_add_assign(&mut deltas, &d);
+        }
+        vec_add_assign(&mut self.scores, &deltas);
+        self.kyotaku = 0;
+
+        if winners.iter().any(|w| w.seat == self.oya as usize) {
+            self.honba += 1;
+        } else {
+            self.oya = (self.oya + 1) % 4;
+            self.honba = 0;
+        }
+        deltas
+    }
+
+    /// Settles an exhaustive ryukyoku with the noten payments. The kyotaku
+    /// carries over and the honba is incremented, and the dealership is kept
+    /// iff the oya is tenpai. Returns the score deltas.
+    pub fn apply_ryukyoku(&mut self, tenpai_mask: [bool; 4]) -> [i32; 4] {
+        let deltas = point::noten_payments(tenpai_mask);
+        vec_add_assign(&mut self.scores, &deltas);
+
+        self.honba += 1;
+        if !tenpai_mask[self.oya as usize] {
+            self.oya = (self.oya + 1) % 4;
+        }
+        deltas
+    }
+
+    /// Settles an abortive ryukyoku (途中流局), where no one pays, the kyotaku
+    /// carries over, the honba is incremented and the dealership is kept.
+    pub const fn apply_abortive_ryukyoku(&mut self) {
+        self.honba += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const fn winner(seat: usize, point: Point) -> Winner {
+        Winner {
+            seat,
+            point,
+            liable: None,
+        }
+    }
+
+    #[test]
+    fn agari() {
+        let mut board = Scoreboard {
+            scores: [25000; 4],
+            oya: 1,
+            ..Default::default()
+        };
+
+        // Oya tsumo 2600 all with a riichi stick on the table.
+        board.apply_riichi(2);
+        let deltas = board.apply_agari(&[winner(1, Point::calc(true, 40, 2))], false, 0);
+        assert_eq!(deltas, [-1300, 4900, -1300, -1300]);
+        assert_eq!(board.scores, [23700, 29900, 22700, 23700]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (1, 0, 1));
+
+        // Dealer repeat, then a ko ron with 1 honba.
+        let deltas = board.apply_agari(&[winner(3, Point::calc(false, 30, 3))], true, 1);
+        assert_eq!(deltas, [0, -4200, 0, 4200]);
+        assert_eq!(board.scores, [23700, 25700, 22700, 27900]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (0, 0, 2));
+        assert_eq!(board.scores.iter().sum::<i32>(), 100000);
+    }
+
+    #[test]
+    fn ryukyoku() {
+        let mut board = Scoreboard {
+            scores: [25000; 4],
+            ..Default::default()
+        };
+
+        board.apply_riichi(3);
+        let deltas = board.apply_ryukyoku([true, false, false, true]);
+        assert_eq!(deltas, [1500, -1500, -1500, 1500]);
+        assert_eq!(board.scores, [26500, 23500, 23500, 25500]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (1, 1, 0));
+
+        // Oya noten, the kyotaku keeps piling up.
+        board.apply_riichi(1);
+        board.apply_ryukyoku([false, true, false, false]);
+        assert_eq!(board.scores, [25500, 25500, 22500, 24500]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (2, 2, 1));
+
+        // Collected by the next win, along with the honba.
+        let deltas = board.apply_agari(&[winner(2, Point::calc(false, 30, 1))], true, 0);
+        assert_eq!(deltas, [-1600, 0, 3600, 0]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (0, 0, 2));
+        assert_eq!(board.scores.iter().sum::<i32>(), 100000);
+    }
+
+    #[test]
+    fn multi_ron() {
+        let mut board = Scoreboard {
+            scores: [25000; 4],
+            honba: 2,
+            kyotaku: 1,
+            oya: 3,
+        };
+
+        // Player 2 deals in to both 0 and 3, the oya. Player 3 is the
+        // head-bump winner, so they take the honba and kyotaku, which are
+        // only settled once.
+        let winners = [
+            winner(0, Point::calc(false, 30, 2)),
+            winner(3, Point::calc(true, 40, 3)),
+        ];
+        let per_winner = board.agari_deltas(&winners, true, 2);
+        assert_eq!(per_winner[0], [2000, 0, -2000, 0]);
+        assert_eq!(per_winner[1], [0, 0, -8300, 9300]);
+
+        let deltas = board.apply_agari(&winners, true, 2);
+        assert_eq!(deltas, [2000, 0, -10300, 9300]);
+        assert_eq!(board.scores, [27000, 25000, 14700, 34300]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (3, 0, 3));
+
+        // A double ron without the oya passes the dealership on just once.
+        let winners = [
+            winner(1, Point::calc(false, 30, 1)),
+            winner(2, Point::calc(false, 30, 1)),
+        ];
+        let deltas = board.apply_agari(&winners, true, 3);
+        assert_eq!(deltas, [0, 1900, 1000, -2900]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (0, 0, 0));
+    }
+
+    #[test]
+    fn pao() {
+        let mut board = Scoreboard {
+            scores: [25000; 4],
+            honba: 1,
+            ..Default::default()
+        };
+
+        // Daisangen tsumo by player 1 with player 2 liable, who pays it all.
+        let daisangen = Winner {
+            seat: 1,
+            point: Point::yakuman(false, 1),
+            liable: Some(2),
+        };
+        let deltas = board.apply_agari(&[daisangen], false, 1);
+        assert_eq!(deltas, [0, 32300, -32300, 0]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (0, 0, 1));
+
+        // Ron from player 3, who splits it with the liable player, while the
+        // honba is paid by the liable player alone.
+        board.honba = 1;
+        let deltas = board.apply_agari(&[daisangen], true, 3);
+        assert_eq!(deltas, [0, 32300, -16300, -16000]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (2, 0, 1));
+    }
+
+    #[test]
+    fn abortive_ryukyoku() {
+        let mut board = Scoreboard {
+            scores: [25000; 4],
+            oya: 2,
+            ..Default::default()
+        };
+
+        board.apply_riichi(0);
+        board.apply_abortive_ryukyoku();
+        assert_eq!(board.scores, [24000, 25000, 25000, 25000]);
+        assert_eq!((board.honba, board.kyotaku, board.oya), (1, 1, 2));
+    }
+}
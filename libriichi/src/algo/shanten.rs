@@ -1,15 +1,31 @@
 //! Rust port of tomohxx's C++ implementation of Shanten Number Calculator.
 //!
 //! Source: <https://github.com/tomohxx/shanten-number-calculator/>
+//!
+//! The calculations are split in two layers. `Tables` and `Incremental`
+//! take the tables as `&'static` slices and never allocate, and neither do
+//! the table-free `calc_chitoi`, `calc_kokushi` and `calc_toward`. The free
+//! `calc_normal`, `calc_all` and the like are built on top of them with the
+//! tables embedded in this crate, which are decompressed into `Vec`s behind
+//! `LazyLock`s on first use (or by `ensure_init`), and add the thread-local
+//! cache of `calc_all_cached` and the errors of `calc_all_checked`.
+//!
+//! None of it is `no_std`, as the crate depends on std throughout.
 
 use crate::tuz;
+use std::array;
+use std::cell::RefCell;
 use std::io::prelude::*;
 use std::sync::LazyLock;
 
+use anyhow::{Result, ensure};
 use flate2::read::GzDecoder;
+use tinyvec::ArrayVec;
 
 const JIHAI_TABLE_SIZE: usize = 78_032;
 const SUHAI_TABLE_SIZE: usize = 1_940_777;
+/// Must be a power of 2.
+const CACHE_SIZE: usize = 1 << 10;
 
 static JIHAI_TABLE: LazyLock<Vec<[u8; 10]>> = LazyLock::new(|| {
     read_table(
@@ -17,6 +33,11 @@ static JIHAI_TABLE: LazyLock<Vec<[u8; 10]>> = LazyLock::new(|| {
         JIHAI_TABLE_SIZE,
     )
 });
+thread_local! {
+    /// Direct-mapped, so a new entry simply replaces the one in its slot.
+    /// Being thread-local, there is no locking at all.
+    static CACHE: RefCell<Vec<(u128, i8)>> = RefCell::new(vec![(u128::MAX, 0); CACHE_SIZE]);
+}
 static SUHAI_TABLE: LazyLock<Vec<[u8; 10]>> = LazyLock::new(|| {
     read_table(
         include_bytes!("data/shanten_suhai.bin.gz"),
@@ -48,9 +69,238 @@ pub fn ensure_init() {
     assert_eq!(SUHAI_TABLE.len(), SUHAI_TABLE_SIZE);
 }
 
-fn add_suhai(lhs: &mut [u8; 10], index: usize, m: usize) {
-    let tab = SUHAI_TABLE.get(index).copied().unwrap_or_default();
+/// The decompressed lookup tables of the normal form, with the core of the
+/// calculations on top of them.
+///
+/// Each entry holds the distances of a group of tiles as in `merge`, indexed
+/// by the counts of the group in base 5, i.e. `jihai` is indexed by the 7
+/// honors and must have 5^7 = 78032 entries, and `suhai` by the 9 tiles of a
+/// suit and must have 1940777 entries, since a suit can't have more than 14
+/// tiles.
+#[derive(Clone, Copy)]
+pub struct Tables {
+    jihai: &'static [[u8; 10]],
+    suhai: &'static [[u8; 10]],
+}
+
+impl Tables {
+    /// Panics if either table does not have the size described above.
+    #[must_use]
+    pub const fn new(jihai: &'static [[u8; 10]], suhai: &'static [[u8; 10]]) -> Self {
+        assert!(jihai.len() == JIHAI_TABLE_SIZE, "invalid jihai table size");
+        assert!(suhai.len() == SUHAI_TABLE_SIZE, "invalid suhai table size");
+        Self { jihai, suhai }
+    }
+
+    /// The tables embedded in this crate, decompressed on first use.
+    #[must_use]
+    pub fn embedded() -> Self {
+        Self {
+            jihai: JIHAI_TABLE.as_slice(),
+            suhai: SUHAI_TABLE.as_slice(),
+        }
+    }
+
+    /// See `calc_normal`.
+    #[must_use]
+    pub fn calc_normal(self, tiles: &[u8; 34], len_div3: u8) -> i8 {
+        let entries = array::from_fn(|group| self.entry(group, group_index(tiles, group)));
+        normal_of(&entries, len_div3)
+    }
+
+    /// See `calc_all_with`.
+    #[must_use]
+    pub fn calc_all_with(self, tiles: &[u8; 34], len_div3: u8, chitoi_rule: ChitoiRule) -> i8 {
+        with_chitoi_and_kokushi(
+            self.calc_normal(tiles, len_div3),
+            tiles,
+            len_div3,
+            chitoi_rule,
+        )
+    }
+
+    /// Starts an `Incremental` of `tiles` on these tables.
+    #[must_use]
+    pub fn incremental(self, tiles: &[u8; 34], len_div3: u8) -> Incremental {
+        let indices: [_; 4] = array::from_fn(|group| group_index(tiles, group));
+        let mut ret = Incremental {
+            tables: self,
+            tiles: [0; 34],
+            len_div3,
+            indices,
+            entries: array::from_fn(|group| self.entry(group, indices[group])),
+            kinds: [0; 2],
+            pairs: [0; 2],
+        };
+        for (tid, &count) in tiles.iter().enumerate() {
+            for _ in 0..count {
+                ret.count_added(tid);
+            }
+        }
+        ret
+    }
+
+    /// The entry of the `group`-th group of tiles, where 0 to 2 are the suits
+    /// and 3 is the honors, at `index` in base 5.
+    fn entry(self, group: usize, index: usize) -> [u8; 10] {
+        let table = if group < 3 { self.suhai } else { self.jihai };
+        table.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// The shanten of a hand kept up to date tile by tile, for hot loops of
+/// draws and discards.
+///
+/// Only the index of the group of the changed tile is updated, in O(1), so a
+/// change costs a single table lookup, instead of summing up all the 34 kinds
+/// and looking up all the 4 groups as `calc_all` does. The kinds and pairs
+/// that 七対子 and 国士無双 need are kept up to date as well, with
+/// `ChitoiRule::DistinctPairs`.
+#[derive(Clone, Copy)]
+pub struct Incremental {
+    tables: Tables,
+    tiles: [u8; 34],
+    len_div3: u8,
+    indices: [usize; 4],
+    entries: [[u8; 10]; 4],
+    /// Of all the tiles in `[0]` and of only the yaokyuu tiles in `[1]`.
+    kinds: [i8; 2],
+    pairs: [i8; 2],
+}
+
+impl Incremental {
+    /// Same as `Tables::incremental` on the embedded tables.
+    #[must_use]
+    pub fn new(tiles: &[u8; 34], len_div3: u8) -> Self {
+        Tables::embedded().incremental(tiles, len_div3)
+    }
+
+    #[must_use]
+    pub const fn tiles(&self) -> &[u8; 34] {
+        &self.tiles
+    }
+
+    /// Adds a tile, e.g. a tsumo. Panics if all 4 copies are already there.
+    pub fn add(&mut self, tid: usize) {
+        assert!(self.tiles[tid] < 4, "a fifth tile {tid}");
+        self.count_added(tid);
+        let (group, weight) = group_weight(tid);
+        self.indices[group] += weight;
+        self.entries[group] = self.tables.entry(group, self.indices[group]);
+    }
+
+    /// Removes a tile, e.g. a discard. Panics if there is none.
+    pub fn remove(&mut self, tid: usize) {
+        assert!(self.tiles[tid] > 0, "no tile {tid} to remove");
+        self.tiles[tid] -= 1;
+        let delta = match self.tiles[tid] {
+            0 => (1, 0),
+            1 => (0, 1),
+            _ => (0, 0),
+        };
+        self.apply_counts(tid, delta, -1);
+        let (group, weight) = group_weight(tid);
+        self.indices[group] -= weight;
+        self.entries[group] = self.tables.entry(group, self.indices[group]);
+    }
+
+    /// Sets `len_div3`, e.g. after a chi, pon or kan, whose tiles are then
+    /// `remove`d.
+    pub const fn set_len_div3(&mut self, len_div3: u8) {
+        self.len_div3 = len_div3;
+    }
 
+    /// The same as `calc_all` of `tiles`.
+    #[must_use]
+    pub fn shanten(&self) -> i8 {
+        let normal = normal_of(&self.entries, self.len_div3);
+        if normal <= 0 || self.len_div3 < 4 {
+            return normal;
+        }
+
+        // Same as `calc_chitoi` and `calc_kokushi`.
+        let chitoi = 7 - self.pairs[0] + (7 - self.kinds[0]).max(0) - 1;
+        let shanten = normal.min(chitoi);
+        if shanten > 0 {
+            let kokushi = 14 - self.kinds[1] - (self.pairs[1] > 0) as i8 - 1;
+            shanten.min(kokushi)
+        } else {
+            shanten
+        }
+    }
+
+    /// Counts a tile added to `tiles` in `kinds` and `pairs`.
+    const fn count_added(&mut self, tid: usize) {
+        let delta = match self.tiles[tid] {
+            0 => (1, 0),
+            1 => (0, 1),
+            _ => (0, 0),
+        };
+        self.tiles[tid] += 1;
+        self.apply_counts(tid, delta, 1);
+    }
+
+    const fn apply_counts(&mut self, tid: usize, (kinds, pairs): (i8, i8), sign: i8) {
+        let is_yaokyuu = tid >= 3 * 9 || matches!(tid % 9, 0 | 8);
+        self.kinds[0] += kinds * sign;
+        self.pairs[0] += pairs * sign;
+        if is_yaokyuu {
+            self.kinds[1] += kinds * sign;
+            self.pairs[1] += pairs * sign;
+        }
+    }
+}
+
+/// The index in base 5 of the `group`-th group of `tiles`, see
+/// `Tables::entry`.
+fn group_index(tiles: &[u8; 34], group: usize) -> usize {
+    sum_tiles(&tiles[group * 9..(group * 9 + 9).min(34)])
+}
+
+/// The group of `tid` and the weight of each of its copies in the index of
+/// the group, such that the first tile of a group is the most significant
+/// digit, as in `sum_tiles`.
+const fn group_weight(tid: usize) -> (usize, usize) {
+    let group = tid / 9;
+    let len = if group < 3 { 9 } else { 7 };
+    (group, 5_usize.pow((len - 1 - tid % 9) as u32))
+}
+
+fn normal_of(entries: &[[u8; 10]; 4], len_div3: u8) -> i8 {
+    let len_div3 = len_div3 as usize;
+
+    let mut ret = entries[0];
+    merge(&mut ret, &entries[1], len_div3);
+    merge(&mut ret, &entries[2], len_div3);
+    add_jihai(&mut ret, &entries[3], len_div3);
+
+    (ret[5 + len_div3] as i8) - 1
+}
+
+/// Takes the 七対子 and 国士無双 forms into account on top of the `normal`
+/// shanten, as in `calc_all_with`.
+fn with_chitoi_and_kokushi(
+    normal: i8,
+    tiles: &[u8; 34],
+    len_div3: u8,
+    chitoi_rule: ChitoiRule,
+) -> i8 {
+    if normal <= 0 || len_div3 < 4 {
+        return normal;
+    }
+
+    let shanten = normal.min(calc_chitoi_with(tiles, chitoi_rule));
+    if shanten > 0 {
+        shanten.min(calc_kokushi(tiles))
+    } else {
+        shanten
+    }
+}
+
+/// Combines `lhs` with the distances `tab` of another group of tiles, where
+/// `[j]` is the number of tiles to add for `j` mentsu and `[5 + j]` for `j`
+/// mentsu and a pair.
+fn merge(lhs: &mut [u8; 10], tab: &[u8; 10], m: usize) {
     for j in (5..=(5 + m)).rev() {
         let mut sht = (lhs[j] + tab[0]).min(lhs[0] + tab[j]);
         for k in 5..j {
@@ -68,9 +318,8 @@ fn add_suhai(lhs: &mut [u8; 10], index: usize, m: usize) {
     }
 }
 
-fn add_jihai(lhs: &mut [u8; 10], index: usize, m: usize) {
-    let tab = JIHAI_TABLE.get(index).copied().unwrap_or_default();
-
+/// Same as `merge` for honors, where only the entry with a pair is needed.
+fn add_jihai(lhs: &mut [u8; 10], tab: &[u8; 10], m: usize) {
     let j = m + 5;
     let mut sht = (lhs[j] + tab[0]).min(lhs[0] + tab[j]);
     for k in 5..j {
@@ -86,21 +335,161 @@ fn sum_tiles(tiles: &[u8]) -> usize {
 /// `len_div3` must be within [0, 4].
 #[must_use]
 pub fn calc_normal(tiles: &[u8; 34], len_div3: u8) -> i8 {
-    let len_div3 = len_div3 as usize;
+    Tables::embedded().calc_normal(tiles, len_div3)
+}
 
-    let mut ret = SUHAI_TABLE
-        .get(sum_tiles(&tiles[..9]))
-        .copied()
-        .unwrap_or_default();
-    add_suhai(&mut ret, sum_tiles(&tiles[9..2 * 9]), len_div3);
-    add_suhai(&mut ret, sum_tiles(&tiles[2 * 9..3 * 9]), len_div3);
-    add_jihai(&mut ret, sum_tiles(&tiles[3 * 9..]), len_div3);
+/// A restriction of the hands that count as complete in `calc_toward`.
+///
+/// A suit is 0 for manzu, 1 for pinzu and 2 for souzu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandConstraint {
+    /// 清一色, all tiles of the suit.
+    Chinitsu(u8),
+    /// 混一色, tiles of the suit and honors. A hand of only honors counts too.
+    Honitsu(u8),
+    /// 断幺九, no terminals or honors.
+    Tanyao,
+}
 
-    (ret[5 + len_div3] as i8) - 1
+impl HandConstraint {
+    const fn allows(self, tid: usize) -> bool {
+        match self {
+            Self::Chinitsu(suit) => tid / 9 == suit as usize,
+            Self::Honitsu(suit) => tid / 9 == suit as usize || tid >= 3 * 9,
+            Self::Tanyao => tid < 3 * 9 && !matches!(tid % 9, 0 | 8),
+        }
+    }
+}
+
+/// Same as `calc_all`, but only the complete hands that satisfy `constraint`
+/// count, so that it is the shanten toward e.g. a 清一色 tenpai. Tiles in
+/// `tiles` that the constraint does not allow need to be discarded, and
+/// drawn tiles can only be allowed ones. 七対子 counts when `len_div3` is
+/// 4, with `ChitoiRule::DistinctPairs`, while 国士無双 never does.
+///
+/// Only the concealed `tiles` are considered, so whether the existing fuuro
+/// satisfy the constraint is up to the caller.
+///
+/// Unlike `calc_all`, this does not use the tables, which cannot tell apart
+/// the allowed tiles, and is somewhat slower.
+#[must_use]
+pub fn calc_toward(tiles: &[u8; 34], len_div3: u8, constraint: HandConstraint) -> i8 {
+    let mut allowed = [false; 34];
+    for (tid, a) in allowed.iter_mut().enumerate() {
+        *a = constraint.allows(tid);
+    }
+
+    let m = len_div3 as usize;
+    let mut ret = group_distances(&tiles[..9], &allowed[..9], true);
+    for (range, is_suhai) in [(9..2 * 9, true), (2 * 9..3 * 9, true), (3 * 9..34, false)] {
+        let tab = group_distances(&tiles[range.clone()], &allowed[range], is_suhai);
+        merge(&mut ret, &tab, m);
+    }
+    let mut shanten = ret[5 + m] as i8 - 1;
+
+    if len_div3 == 4 {
+        let mut masked = *tiles;
+        for (c, &a) in masked.iter_mut().zip(&allowed) {
+            if !a {
+                *c = 0;
+            }
+        }
+        // Disallowed tiles are gone from `masked`, yet they still take up
+        // slots in the hand, which costs as much as for the missing ones.
+        shanten = shanten.min(calc_chitoi(&masked));
+    }
+    shanten
+}
+
+/// The distances of a group of tiles in the format of the tables, i.e. the
+/// minimum numbers of tiles to add for `j` mentsu in `[j]`, and for `j`
+/// mentsu and a pair in `[5 + j]`, only using the `allowed` tiles, searched
+/// exhaustively. Sequences are only formed if `is_suhai`.
+fn group_distances(counts: &[u8], allowed: &[bool], is_suhai: bool) -> [u8; 10] {
+    // Sets are (first tile, is sequence).
+    let mut sets = ArrayVec::<[_; 16]>::new();
+    for t in 0..counts.len() {
+        if !allowed[t] {
+            continue;
+        }
+        sets.push((t, false));
+        if is_suhai && t + 2 < counts.len() && allowed[t + 1] && allowed[t + 2] {
+            sets.push((t, true));
+        }
+    }
+
+    // Large enough to never be picked, yet far from overflowing when summed.
+    let mut ret = [30; 10];
+    let mut need = [0; 9];
+    search_sets(counts, allowed, &sets, 0, 0, &mut need, &mut ret);
+    ret
+}
+
+fn search_sets(
+    counts: &[u8],
+    allowed: &[bool],
+    sets: &[(usize, bool)],
+    from: usize,
+    n_sets: usize,
+    need: &mut [u8; 9],
+    ret: &mut [u8; 10],
+) {
+    let cost = |need: &[u8; 9]| -> u8 {
+        counts
+            .iter()
+            .zip(need)
+            .map(|(&c, &n)| n.saturating_sub(c))
+            .sum()
+    };
+    ret[n_sets] = ret[n_sets].min(cost(need));
+    for t in 0..counts.len() {
+        if allowed[t] && need[t] + 2 <= 4 {
+            need[t] += 2;
+            ret[5 + n_sets] = ret[5 + n_sets].min(cost(need));
+            need[t] -= 2;
+        }
+    }
+    if n_sets == 4 {
+        return;
+    }
+
+    for (i, &(t, is_seq)) in sets.iter().enumerate().skip(from) {
+        let tiles = if is_seq { t..t + 3 } else { t..t + 1 };
+        let add = if is_seq { 1 } else { 3 };
+        if tiles.clone().any(|u| need[u] + add > 4) {
+            continue;
+        }
+        tiles.clone().for_each(|u| need[u] += add);
+        search_sets(counts, allowed, sets, i, n_sets + 1, need, ret);
+        tiles.for_each(|u| need[u] -= add);
+    }
+}
+
+/// How 七対子 shanten treats 4 copies of a tile in hand.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChitoiRule {
+    /// 4 copies of a tile make only one pair, so all 7 pairs must be of
+    /// different kinds, as in tenhou and most rulesets, e.g. 1111m 2233p 4455s
+    /// 6z is 2-shanten.
+    #[default]
+    DistinctPairs,
+    /// 4 copies of a tile make two pairs, as in some tools and datasets, e.g.
+    /// 1111m 2233p 4455s 6z is tenpai. Triplets still make only one pair.
+    QuadAsTwoPairs,
 }
 
 #[must_use]
 pub fn calc_chitoi(tiles: &[u8; 34]) -> i8 {
+    calc_chitoi_with(tiles, ChitoiRule::DistinctPairs)
+}
+
+#[must_use]
+pub fn calc_chitoi_with(tiles: &[u8; 34], rule: ChitoiRule) -> i8 {
+    if rule == ChitoiRule::QuadAsTwoPairs {
+        let pairs: u8 = tiles.iter().map(|&c| c / 2).sum();
+        return 7 - pairs.min(7) as i8 - 1;
+    }
+
     let mut pairs = 0;
     let mut kinds = 0;
     tiles.iter().filter(|&&c| c > 0).for_each(|&c| {
@@ -114,6 +503,11 @@ pub fn calc_chitoi(tiles: &[u8; 34]) -> i8 {
     7 - pairs + redunct - 1
 }
 
+/// The shanten of 国士無双 alone, which is `13 - kinds - has_pair` over the
+/// 13 kinds of yaokyuu tiles, regardless of the other tiles in `tiles`. So
+/// the 13-sided wait with all 13 kinds and the single wait with 12 kinds and
+/// a pair are both tenpai (0), and a complete hand is -1, the same as on
+/// Tenhou.
 #[must_use]
 pub fn calc_kokushi(tiles: &[u8; 34]) -> i8 {
     let mut pairs = 0;
@@ -136,23 +530,83 @@ pub fn calc_kokushi(tiles: &[u8; 34]) -> i8 {
 
 #[must_use]
 pub fn calc_all(tiles: &[u8; 34], len_div3: u8) -> i8 {
-    let mut shanten = calc_normal(tiles, len_div3);
-    if shanten <= 0 || len_div3 < 4 {
-        return shanten;
-    }
+    calc_all_with(tiles, len_div3, ChitoiRule::DistinctPairs)
+}
 
-    shanten = shanten.min(calc_chitoi(tiles));
-    if shanten > 0 {
-        shanten.min(calc_kokushi(tiles))
-    } else {
-        shanten
-    }
+/// Same as `calc_all`, but returns an error instead of a meaningless result
+/// if `tiles` does not have `len_div3 * 3 + 1` or `len_div3 * 3 + 2` tiles,
+/// which is usually a sign of the called sets being counted wrong.
+pub fn calc_all_checked(tiles: &[u8; 34], len_div3: u8) -> Result<i8> {
+    ensure!(len_div3 <= 4, "invalid len_div3 {len_div3}");
+    ensure!(
+        is_len_consistent(tiles, len_div3),
+        "{} tiles in hand do not match len_div3 {len_div3}",
+        tiles.iter().map(|&c| c as u32).sum::<u32>(),
+    );
+    Ok(calc_all(tiles, len_div3))
+}
+
+pub(crate) fn is_len_consistent(tiles: &[u8; 34], len_div3: u8) -> bool {
+    let len: u32 = tiles.iter().map(|&c| c as u32).sum();
+    let base = len_div3 as u32 * 3;
+    len == base + 1 || len == base + 2
+}
+
+/// Same as `calc_all`, but with the given convention of 七対子, see
+/// `ChitoiRule`. `calc_all` and all the other functions in this mod use
+/// `ChitoiRule::DistinctPairs`.
+#[must_use]
+pub fn calc_all_with(tiles: &[u8; 34], len_div3: u8, chitoi_rule: ChitoiRule) -> i8 {
+    Tables::embedded().calc_all_with(tiles, len_div3, chitoi_rule)
+}
+
+/// Same as `calc_all`, but memoized in a small thread-local cache, for hot
+/// loops that test lots of hands differing by one or two tiles.
+#[must_use]
+pub fn calc_all_cached(tiles: &[u8; 34], len_div3: u8) -> i8 {
+    // 3 bits for each kind, 102 bits in total, and `len_div3` on top of that.
+    let key = tiles
+        .iter()
+        .fold(len_div3 as u128, |acc, &c| (acc << 3) | c as u128);
+    let folded = (key as u64) ^ ((key >> 64) as u64);
+    let idx = (folded.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 54) as usize & (CACHE_SIZE - 1);
+
+    CACHE.with_borrow_mut(|cache| {
+        let (k, v) = &mut cache[idx];
+        if *k != key {
+            *k = key;
+            *v = calc_all(tiles, len_div3);
+        }
+        *v
+    })
+}
+
+/// Calculates the shanten of a 3n+2 hand as the shanten after its best
+/// discard, or -1 if the hand is already complete, which follows the
+/// definition of shanten most people acknowledge.
+///
+/// This is only `calc_all` with the hand length checked in debug builds,
+/// since the tables already give exactly that for a 3n+2 hand, so callers
+/// that already have `len_div3` right can use `calc_all` directly.
+///
+/// `len_div3` is the same as in `calc_all`, i.e. `tiles` must have
+/// `len_div3 * 3 + 2` tiles.
+#[must_use]
+pub fn calc_3n2(tiles: &[u8; 34], len_div3: u8) -> i8 {
+    debug_assert_eq!(
+        tiles.iter().map(|&c| c as u32).sum::<u32>(),
+        len_div3 as u32 * 3 + 2,
+        "not a 3n+2 hand",
+    );
+    calc_all(tiles, len_div3)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::hand::hand;
+    use rand::prelude::*;
+    use rand_chacha::ChaCha12Rng;
 
     #[test]
     fn calc_3n_plus_1() {
@@ -199,4 +653,283 @@ mod test {
         let tehai = hand("55m").unwrap();
         assert_eq!(calc_all(&tehai, 0), -1);
     }
+
+    #[test]
+    fn kokushi() {
+        // (hand, calc_kokushi, calc_all), where calc_all matches Tenhou's 牌理.
+        let cases = [
+            ("19m 19p 19s 1234567z", 0, 0),
+            ("19m 19p 19s 1234567z 9s", -1, -1),
+            ("119m 19p 19s 123456z", 0, 0),
+            ("119m 19p 19s 123456z 7z", -1, -1),
+            ("119m 19p 19s 123456z 5p", 0, 0),
+            ("19m 19p 19s 123456z 5m", 1, 1),
+            ("1199m 19p 19s 12345z", 1, 1),
+            ("19m 19p 19s 11223z 58m", 3, 3),
+            ("111m 19p 19s 123456z", 1, 1),
+            // Far from 国士無双, where the normal form wins.
+            ("123m 456p 789s 1122z", 8, 0),
+        ];
+        for (hand_str, kokushi, all) in cases {
+            let tehai = hand(hand_str).unwrap();
+            assert_eq!(calc_kokushi(&tehai), kokushi, "{hand_str}");
+            assert_eq!(calc_all(&tehai, 4), all, "{hand_str}");
+        }
+    }
+
+    #[test]
+    fn chitoi_rule() {
+        // (hand, DistinctPairs, QuadAsTwoPairs)
+        let cases = [
+            ("1111m 2233p 4455s 6z", 2, 0),
+            ("1111m 2222p 3344s 5z", 4, 0),
+            ("1111m 2233p 4455s 66z", 1, -1),
+            ("1111m 2233p 4455s 67z", 1, 0),
+            // A triplet makes only one pair either way, but waiting on its 4th
+            // copy is tenpai only if quads make two pairs.
+            ("11m 22m 33p 44p 555s 67z", 1, 1),
+            ("11m 22m 33p 44p 555s 66z", 1, 0),
+            ("1133m 2255p 4477s 1z", 0, 0),
+        ];
+        for (s, distinct, quad) in cases {
+            let tehai = hand(s).unwrap();
+            assert_eq!(calc_chitoi(&tehai), distinct, "{s}");
+            assert_eq!(
+                calc_chitoi_with(&tehai, ChitoiRule::DistinctPairs),
+                distinct,
+                "{s}",
+            );
+            assert_eq!(
+                calc_chitoi_with(&tehai, ChitoiRule::QuadAsTwoPairs),
+                quad,
+                "{s}",
+            );
+        }
+
+        let tehai = hand("1111m 2233p 4455s 66z").unwrap();
+        assert_eq!(
+            calc_all(&tehai, 4),
+            calc_all_with(&tehai, 4, ChitoiRule::default())
+        );
+        assert!(calc_all(&tehai, 4) > 0);
+        assert_eq!(calc_all_with(&tehai, 4, ChitoiRule::QuadAsTwoPairs), -1);
+    }
+
+    #[test]
+    fn checked() {
+        let tehai = hand("468m 33346p 7s").unwrap();
+        calc_all_checked(&tehai, 3).unwrap_err();
+        let tehai = hand("468m 33346p 7s 11z").unwrap();
+        assert_eq!(calc_all_checked(&tehai, 3).unwrap(), calc_all(&tehai, 3));
+        calc_all_checked(&tehai, 4).unwrap_err();
+        let tehai = hand("468m 33346p 7s 1112z").unwrap();
+        assert_eq!(calc_all_checked(&tehai, 4).unwrap(), calc_all(&tehai, 4));
+        calc_all_checked(&tehai, 5).unwrap_err();
+        calc_all_checked(&[0; 34], 0).unwrap_err();
+    }
+
+    #[test]
+    fn cached() {
+        let tehai = hand("2344456m 14p 127s 2z").unwrap();
+        for _ in 0..2 {
+            for tsumo in 0..34 {
+                let mut tehai = tehai;
+                tehai[tsumo] += 1;
+                assert_eq!(calc_all_cached(&tehai, 4), calc_all(&tehai, 4));
+                assert_eq!(calc_all_cached(&tehai, 3), calc_all(&tehai, 3));
+            }
+        }
+    }
+
+    #[test]
+    fn tables() {
+        let tables = Tables::new(JIHAI_TABLE.as_slice(), SUHAI_TABLE.as_slice());
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut wall: Vec<_> = (0..136).map(|i| i / 4).collect();
+        for len_div3 in [4, 3, 1] {
+            for _ in 0..100 {
+                wall.shuffle(&mut rng);
+                let mut tehai = [0; 34];
+                wall[..len_div3 as usize * 3 + 2]
+                    .iter()
+                    .for_each(|&t| tehai[t] += 1);
+                assert_eq!(
+                    tables.calc_normal(&tehai, len_div3),
+                    calc_normal(&tehai, len_div3),
+                );
+                assert_eq!(
+                    tables.calc_all_with(&tehai, len_div3, ChitoiRule::QuadAsTwoPairs),
+                    calc_all_with(&tehai, len_div3, ChitoiRule::QuadAsTwoPairs),
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid suhai table size")]
+    fn tables_size() {
+        let _ = Tables::new(JIHAI_TABLE.as_slice(), &SUHAI_TABLE[1..]);
+    }
+
+    #[test]
+    fn calc_3n2_matches_best_discard() {
+        let tehai = hand("2344456m 14p 127s 2z 7p").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), 3);
+        let tehai = hand("344455667p 1139s 9p").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), 1);
+        let tehai = hand("122334m 678p 37s 22z 5s").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), 0);
+        let tehai = hand("12223456m 78889p 2m").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), -1);
+        // Tenpai after chi, where the called set is no longer in `tiles`.
+        let tehai = hand("223m 55p").unwrap();
+        assert_eq!(calc_3n2(&tehai, 1), 0);
+        let tehai = hand("123m 55p").unwrap();
+        assert_eq!(calc_3n2(&tehai, 1), -1);
+        let tehai = hand("55m").unwrap();
+        assert_eq!(calc_3n2(&tehai, 0), -1);
+        let tehai = hand("19m 19p 19s 1234567z 1m").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), -1);
+        let tehai = hand("19m 19p 19s 1234566z 1m").unwrap();
+        assert_eq!(calc_3n2(&tehai, 4), 0);
+
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut wall: Vec<_> = (0..136).map(|i| i / 4).collect();
+        for len_div3 in [4, 3, 1] {
+            for _ in 0..100 {
+                wall.shuffle(&mut rng);
+                let mut tehai = [0; 34];
+                wall[..len_div3 as usize * 3 + 2]
+                    .iter()
+                    .for_each(|&t| tehai[t] += 1);
+
+                let complete = calc_all(&tehai, len_div3) == -1;
+                let best_discard = (0..34)
+                    .filter(|&t| tehai[t] > 0)
+                    .map(|t| {
+                        let mut after = tehai;
+                        after[t] -= 1;
+                        calc_all(&after, len_div3)
+                    })
+                    .min()
+                    .unwrap();
+                let expected = if complete { -1 } else { best_discard };
+                assert_eq!(calc_3n2(&tehai, len_div3), expected, "{tehai:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn group_distances_match_tables() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut wall: Vec<_> = (0..36).map(|i| i / 4).collect();
+        for _ in 0..500 {
+            wall.shuffle(&mut rng);
+            let len = rng.random_range(0..=14);
+            let mut counts = [0; 9];
+            wall[..len].iter().for_each(|&t| counts[t] += 1);
+
+            let expected = SUHAI_TABLE[sum_tiles(&counts)];
+            assert_eq!(
+                group_distances(&counts, &[true; 9], true),
+                expected,
+                "{counts:?}"
+            );
+            let expected = JIHAI_TABLE[sum_tiles(&counts[..7])];
+            let tab = group_distances(&counts[..7], &[true; 7], false);
+            // Only the last entry of each half is used for honors.
+            assert_eq!(tab[4], expected[4], "{counts:?}");
+            assert_eq!(tab[9], expected[9], "{counts:?}");
+        }
+    }
+
+    #[test]
+    fn toward() {
+        let tehai = hand("1234567m 23p 789s 1z").unwrap();
+        assert_eq!(calc_all(&tehai, 4), 1);
+        // Only 1234567m can be kept, which needs 7 more tiles.
+        assert!(calc_toward(&tehai, 4, HandConstraint::Chinitsu(0)) > calc_all(&tehai, 4));
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Chinitsu(0)), 6);
+        // 1z can be the pair.
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Honitsu(0)), 5);
+        // 234m 567m 23p 78s are kept.
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Tanyao), 3);
+
+        // A 清一色 tenpai is a plain tenpai.
+        let tehai = hand("1112345678999p").unwrap();
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Chinitsu(1)), 0);
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Honitsu(1)), 0);
+        // Every tile has to go.
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Chinitsu(0)), 13);
+        // Only 234p 567p 8p can be kept.
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Tanyao), 6);
+
+        // 七対子 of simples.
+        let tehai = hand("223344m 5566p 778s").unwrap();
+        assert_eq!(calc_toward(&tehai, 4, HandConstraint::Tanyao), 0);
+        // Honors in a 混一色 with two calls.
+        let tehai = hand("5p 11222z 9s").unwrap();
+        assert_eq!(calc_toward(&tehai, 2, HandConstraint::Honitsu(1)), 1);
+        assert_eq!(calc_toward(&tehai, 2, HandConstraint::Honitsu(2)), 1);
+        assert_eq!(calc_toward(&tehai, 2, HandConstraint::Chinitsu(1)), 6);
+    }
+
+    #[test]
+    fn incremental() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut wall: Vec<_> = (0..136).map(|i| i / 4).collect();
+        for len_div3 in [4, 4, 4, 3, 1] {
+            for _ in 0..200 {
+                wall.shuffle(&mut rng);
+                let len = len_div3 as usize * 3 + 1;
+                let mut tehai = [0; 34];
+                wall[..len].iter().for_each(|&t| tehai[t] += 1);
+                let mut inc = Incremental::new(&tehai, len_div3);
+                assert_eq!(inc.shanten(), calc_all(&tehai, len_div3));
+
+                for &tsumo in &wall[len..len + 18] {
+                    inc.add(tsumo);
+                    assert_eq!(inc.shanten(), calc_all(inc.tiles(), len_div3));
+
+                    let discard = *(0..34)
+                        .filter(|&t| inc.tiles()[t] > 0)
+                        .collect::<Vec<_>>()
+                        .choose(&mut rng)
+                        .unwrap();
+                    inc.remove(discard);
+                    assert_eq!(inc.shanten(), calc_all(inc.tiles(), len_div3));
+                }
+            }
+        }
+
+        // 七対子 tenpai after 1s, then a pon of 2z instead.
+        let tehai = hand("1199m 1199p 1s 2234z").unwrap();
+        let mut inc = Incremental::new(&tehai, 4);
+        inc.add(tuz!(1s));
+        assert_eq!(inc.shanten(), calc_all(inc.tiles(), 4));
+        assert_eq!(inc.shanten(), 0);
+        inc.remove(tuz!(1s));
+        inc.remove(tuz!(S));
+        inc.remove(tuz!(S));
+        inc.set_len_div3(3);
+        assert_eq!(inc.shanten(), calc_all(inc.tiles(), 3));
+
+        // 国士無双 from 1-shanten to complete.
+        let tehai = hand("19m 19p 19s 123456z 5m").unwrap();
+        let mut inc = Incremental::new(&tehai, 4);
+        for (tid, is_add, expected) in [
+            (tuz!(C), true, 0),
+            (tuz!(5m), false, 0),
+            (tuz!(1m), true, -1),
+            (tuz!(C), false, 0),
+        ] {
+            if is_add {
+                inc.add(tid);
+            } else {
+                inc.remove(tid);
+            }
+            assert_eq!(inc.shanten(), calc_all(inc.tiles(), 4));
+            assert_eq!(inc.shanten(), expected);
+        }
+    }
 }
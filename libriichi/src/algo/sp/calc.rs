@@ -1,10 +1,11 @@
 use super::candidate::RawCandidate;
 use super::state::{InitState, State};
 use super::tile::{DiscardTile, DrawTile};
-use super::{Candidate, CandidateColumn, MAX_TSUMOS_LEFT};
+use super::{Candidate, CandidateColumn, max_tsumos_left};
 use crate::algo::agari::{Agari, AgariCalculator};
 use crate::tile::Tile;
 use crate::{must_tile, t, tu8};
+use std::iter;
 use std::rc::Rc;
 
 use ahash::AHashMap;
@@ -23,11 +24,12 @@ struct Values<const MAX_TSUMO: usize> {
     tenpai_probs: [f32; MAX_TSUMO],
     win_probs: [f32; MAX_TSUMO],
     exp_values: [f32; MAX_TSUMO],
+    yakuman_probs: [f32; MAX_TSUMO],
 }
 
 enum ScoresOrValues<const MAX_TSUMO: usize> {
-    // shanten == 0, and has yaku
-    Scores([f32; 4]),
+    // shanten == 0, and has yaku, along with whether it is a yakuman
+    Scores([f32; 4], bool),
     // shanten > 0
     Values(Rc<Values<MAX_TSUMO>>),
 }
@@ -48,8 +50,14 @@ pub struct SPCalculator<'a> {
     pub num_doras_in_fuuro: u8,
     pub dora_indicators: &'a [Tile],
     pub calc_double_riichi: bool,
+    /// Add the han of 海底摸月 to the wins on the last of the tsumos. 河底撈魚
+    /// is not modeled at all, since only our own tsumos are.
     pub calc_haitei: bool,
     pub prefer_riichi: bool,
+    /// For a closed hand without riichi assumed, only count the wins with a
+    /// yaku other than 門前清自摸和, i.e. the ones that can be won as dama
+    /// from a discard too.
+    pub dama_needs_yaku: bool,
     pub sort_result: bool,
 
     /// 和了確率を最大化
@@ -58,6 +66,26 @@ pub struct SPCalculator<'a> {
     pub calc_tegawari: bool,
     /// 向聴落とし考慮
     pub calc_shanten_down: bool,
+    /// For a closed hand, also calculate a second table assuming the hand
+    /// will be opened by calling, see `calc_with_open_table`.
+    pub compare_open_vs_closed: bool,
+    /// 役満確率計算
+    pub calc_yakuman_prob: bool,
+    /// With `calc_yakuman_prob`, also count the wins of 13 han or more as
+    /// yakuman (数え役満). The han are counted without the ura doras and the
+    /// extra hans of `scores_with_uradora`. The scores are not affected.
+    pub kazoe_yakuman: bool,
+    /// Add a rough bonus for concealed triplets in the winning hand whose
+    /// last copy is still in the wall, for the kan dora (and the 4th tile
+    /// itself if it is a dora) that an ankan would bring. Rinshan kaihou and
+    /// the change of fu and waits are not considered.
+    pub calc_kan_upgrade: bool,
+    /// Also fill `Candidate::win_curve`, where `win_curve[n]` is the win
+    /// probability with `n` tsumos left, from `win_curve[0] == 0` up to
+    /// `win_curve[tsumos_left] == win_probs[0]`, so it approximates the
+    /// chance to win within the next `n` tsumos. It is `win_probs` reversed,
+    /// which the DP already has, so it costs nothing but the allocation.
+    pub calc_win_curve: bool,
 }
 
 struct SPCalculatorState<'a, const MAX_TSUMO: usize> {
@@ -87,10 +115,88 @@ impl SPCalculator<'_> {
         can_discard: bool,
         tsumos_left: u8,
         cur_shanten: i8,
+    ) -> Result<Vec<Candidate>> {
+        self.calc_table(init_state, can_discard, tsumos_left, cur_shanten)
+    }
+
+    /// Same as `calc`, but when `compare_open_vs_closed` is set and the hand
+    /// is menzen, also returns a second table assuming the hand will be
+    /// opened by calling on the wait tiles, so that the loss of riichi and
+    /// menzen yakus can be weighed against the call.
+    ///
+    /// In the open table, riichi related flags are dropped and each win is
+    /// scored with one of its sets as an actual chi or pon, whichever scores
+    /// the best, so the open-hand han reductions and fu apply. Doras in the
+    /// called set are still counted from tehai the same way as in the closed
+    /// one, and `num_doras_in_fuuro` only covers the existing fuuro.
+    pub fn calc_with_open_table(
+        &self,
+        init_state: InitState,
+        can_discard: bool,
+        tsumos_left: u8,
+        cur_shanten: i8,
+    ) -> Result<(Vec<Candidate>, Option<Vec<Candidate>>)> {
+        let open_table = if self.compare_open_vs_closed && self.is_menzen {
+            let open_calc = SPCalculator {
+                is_menzen: false,
+                prefer_riichi: false,
+                calc_double_riichi: false,
+                ..*self
+            };
+            Some(open_calc.calc_table(init_state.clone(), can_discard, tsumos_left, cur_shanten)?)
+        } else {
+            None
+        };
+        let closed_table = self.calc_table(init_state, can_discard, tsumos_left, cur_shanten)?;
+        Ok((closed_table, open_table))
+    }
+
+    /// Same as `calc`, but when the hand is menzen and `prefer_riichi` is set,
+    /// also returns a second table for playing the hand dama (黙聴) instead.
+    ///
+    /// Compared to the riichi table, the dama table has no 立直, 一発, ダブル
+    /// 立直 or ura doras, and `dama_needs_yaku` is set, so a closed tenpai
+    /// without a yaku has no win value at all. Neither table accounts for
+    /// the other players folding against a declared riichi, so the EV gap
+    /// between them is the raw value of the riichi hans, which is the upper
+    /// bound of what a riichi can gain. The cost of the stick itself is not
+    /// in either, see `Candidate::riichi_stick_ev`.
+    pub fn calc_with_dama_table(
+        &self,
+        init_state: InitState,
+        can_discard: bool,
+        tsumos_left: u8,
+        cur_shanten: i8,
+    ) -> Result<(Vec<Candidate>, Option<Vec<Candidate>>)> {
+        let dama_table = if self.is_menzen && self.prefer_riichi {
+            let dama_calc = SPCalculator {
+                prefer_riichi: false,
+                dama_needs_yaku: true,
+                calc_double_riichi: false,
+                ..*self
+            };
+            Some(dama_calc.calc_table(init_state.clone(), can_discard, tsumos_left, cur_shanten)?)
+        } else {
+            None
+        };
+        let riichi_table = self.calc_table(init_state, can_discard, tsumos_left, cur_shanten)?;
+        Ok((riichi_table, dama_table))
+    }
+
+    fn calc_table(
+        &self,
+        init_state: InitState,
+        can_discard: bool,
+        tsumos_left: u8,
+        cur_shanten: i8,
     ) -> Result<Vec<Candidate>> {
         ensure!(cur_shanten >= 0, "can't calculate an agari hand");
         ensure!(tsumos_left >= 1, "need at least one more tsumo");
-        ensure!(tsumos_left <= MAX_TSUMOS_LEFT as u8);
+        ensure!(
+            tsumos_left <= max_tsumos_left(can_discard),
+            "at most {} tsumos left can be calculated",
+            max_tsumos_left(can_discard),
+        );
 
         #[cfg(feature = "sp_reproduce_cpp_ver")]
         let max_tsumo = if can_discard { 17 } else { 18 };
@@ -126,9 +232,18 @@ impl SPCalculator<'_> {
             }
         }
         #[cfg(feature = "sp_reproduce_cpp_ver")]
-        let candidates = static_expand!(17, 18);
+        let mut candidates = static_expand!(17, 18);
         #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
-        let candidates = static_expand!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17);
+        let mut candidates =
+            static_expand!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17);
+
+        if self.calc_win_curve {
+            for c in candidates.iter_mut().filter(|c| !c.win_probs.is_empty()) {
+                c.win_curve = iter::once(0.)
+                    .chain(c.win_probs.iter().rev().copied())
+                    .collect();
+            }
+        }
         Ok(candidates)
     }
 }
@@ -220,16 +335,19 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                     tenpai_probs.fill(1.);
                 }
 
-                let candidate = Candidate::from(RawCandidate {
+                let candidate = RawCandidate {
                     tile,
                     tenpai_probs: &tenpai_probs,
                     win_probs: &values.win_probs,
                     exp_values: &values.exp_values,
+                    yakuman_probs: &values.yakuman_probs,
                     required_tiles,
                     shanten_down: false,
-                });
+                };
                 #[cfg(feature = "sp_reproduce_cpp_ver")]
                 let candidate = candidate.calibrate(self.real_max_tsumo);
+                #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
+                let candidate = Candidate::from(candidate);
                 candidates.push(candidate);
             } else if self.sup.calc_shanten_down && shanten_diff == 1 && shanten < SHANTEN_THRES {
                 self.state.discard(tile);
@@ -239,16 +357,19 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                 self.state.n_extra_tsumo -= 1;
                 self.state.undo_discard(tile);
 
-                let candidate = Candidate::from(RawCandidate {
+                let candidate = RawCandidate {
                     tile,
                     tenpai_probs: &values.tenpai_probs,
                     win_probs: &values.win_probs,
                     exp_values: &values.exp_values,
+                    yakuman_probs: &values.yakuman_probs,
                     required_tiles,
                     shanten_down: true,
-                });
+                };
                 #[cfg(feature = "sp_reproduce_cpp_ver")]
                 let candidate = candidate.calibrate(self.real_max_tsumo);
+                #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
+                let candidate = Candidate::from(candidate);
                 candidates.push(candidate);
             }
         }
@@ -265,16 +386,19 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             tenpai_probs.fill(1.);
         }
 
-        let candidate = Candidate::from(RawCandidate {
+        let candidate = RawCandidate {
             tile: t!(?),
             tenpai_probs: &tenpai_probs,
             win_probs: &values.win_probs,
             exp_values: &values.exp_values,
+            yakuman_probs: &values.yakuman_probs,
             required_tiles,
             shanten_down: false,
-        });
+        };
         #[cfg(feature = "sp_reproduce_cpp_ver")]
         let candidate = candidate.calibrate(self.real_max_tsumo);
+        #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
+        let candidate = Candidate::from(candidate);
         vec![candidate]
     }
 
@@ -330,6 +454,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
         let mut tenpai_probs = [0.; MAX_TSUMO];
         let mut win_probs = [0.; MAX_TSUMO];
         let mut exp_values = [0.; MAX_TSUMO];
+        let mut yakuman_probs = [0.; MAX_TSUMO];
 
         // 自摸候補を取得する。
         let draw_tiles = self.state.get_draw_tiles(shanten, self.sup.tehai_len_div3);
@@ -351,8 +476,8 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             self.state.deal(tile);
             let scores_or_values = if shanten > 0 {
                 ScoresOrValues::Values(self.discard(shanten - 1))
-            } else if let Some(scores) = self.get_score(tile) {
-                ScoresOrValues::Scores(scores)
+            } else if let Some((scores, is_yakuman)) = self.get_score(tile) {
+                ScoresOrValues::Scores(scores, is_yakuman)
             } else {
                 self.state.undo_deal(tile);
                 continue;
@@ -373,7 +498,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                 // let tump_prob = &TSUMO_PROB_TABLE[count as usize - 1][i];
 
                 match &scores_or_values {
-                    ScoresOrValues::Scores(scores) => {
+                    ScoresOrValues::Scores(scores, is_yakuman) => {
                         let assume_riichi = self.sup.is_menzen && self.sup.prefer_riichi;
                         // 聴牌の場合は次で和了
                         // i 巡目で聴牌の場合はダブル立直成立
@@ -388,6 +513,9 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
 
                         win_probs[i] += tump_prob;
                         exp_values[i] += tump_prob * scores[han_plus];
+                        if *is_yakuman {
+                            yakuman_probs[i] += tump_prob;
+                        }
                     }
                     ScoresOrValues::Values(next_values) => {
                         if shanten == 1 {
@@ -400,6 +528,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                             }
                             win_probs[i] += tump_prob * next_values.win_probs[i + 1];
                             exp_values[i] += tump_prob * next_values.exp_values[i + 1];
+                            yakuman_probs[i] += tump_prob * next_values.yakuman_probs[i + 1];
                         }
                     }
                 }
@@ -431,6 +560,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                 tenpai_probs[i] += tump_prob * next_values.tenpai_probs[i + 1];
                 win_probs[i] += tump_prob * next_values.win_probs[i + 1];
                 exp_values[i] += tump_prob * next_values.exp_values[i + 1];
+                yakuman_probs[i] += tump_prob * next_values.yakuman_probs[i + 1];
             }
         }
 
@@ -438,6 +568,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             tenpai_probs,
             win_probs,
             exp_values,
+            yakuman_probs,
         });
         self.draw_cache[shanten as usize].insert(self.state.clone(), Rc::clone(&values));
 
@@ -455,6 +586,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
         let mut tenpai_probs = [0.; MAX_TSUMO];
         let mut win_probs = [0.; MAX_TSUMO];
         let mut exp_values = [0.; MAX_TSUMO];
+        let mut yakuman_probs = [0.; MAX_TSUMO];
 
         // 自摸候補を取得する。
         let draw_tiles = self.state.get_draw_tiles(shanten, self.sup.tehai_len_div3);
@@ -481,8 +613,8 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             self.state.deal(tile);
             let scores_or_values = if shanten > 0 {
                 ScoresOrValues::Values(self.discard(shanten - 1))
-            } else if let Some(scores) = self.get_score(tile) {
-                ScoresOrValues::Scores(scores)
+            } else if let Some((scores, is_yakuman)) = self.get_score(tile) {
+                ScoresOrValues::Scores(scores, is_yakuman)
             } else {
                 self.state.undo_deal(tile);
                 continue;
@@ -513,7 +645,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                     let prob = tsumo_probs[j] * n / m;
 
                     match &scores_or_values {
-                        ScoresOrValues::Scores(scores) => {
+                        ScoresOrValues::Scores(scores, is_yakuman) => {
                             let assume_riichi = self.sup.is_menzen && self.sup.prefer_riichi;
                             // 聴牌の場合は次で和了
                             // i 巡目で聴牌の場合はダブル立直成立
@@ -529,6 +661,9 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
 
                             win_probs[i] += prob;
                             exp_values[i] += prob * scores[han_plus];
+                            if *is_yakuman {
+                                yakuman_probs[i] += prob;
+                            }
                         }
                         ScoresOrValues::Values(next_values) => {
                             if shanten == 1 {
@@ -543,6 +678,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                                 // 聴牌以上で max_tsumo_ - 1 巡目以下の場合
                                 win_probs[i] += prob * next_values.win_probs[j + 1];
                                 exp_values[i] += prob * next_values.exp_values[j + 1];
+                                yakuman_probs[i] += prob * next_values.yakuman_probs[j + 1];
                             }
                         }
                     }
@@ -554,6 +690,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             tenpai_probs,
             win_probs,
             exp_values,
+            yakuman_probs,
         });
         self.draw_cache[shanten as usize].insert(self.state.clone(), Rc::clone(&values));
 
@@ -577,6 +714,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
         let mut max_tenpai_probs = [f32::MIN; MAX_TSUMO];
         let mut max_win_probs = [f32::MIN; MAX_TSUMO];
         let mut max_exp_values = [f32::MIN; MAX_TSUMO];
+        let mut max_yakuman_probs = [f32::MIN; MAX_TSUMO];
         let mut max_tiles = [t!(?); MAX_TSUMO];
         let mut max_values = [i32::MIN; MAX_TSUMO];
 
@@ -620,6 +758,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                     max_tenpai_probs[i] = values.tenpai_probs[i];
                     max_win_probs[i] = values.win_probs[i];
                     max_exp_values[i] = values.exp_values[i];
+                    max_yakuman_probs[i] = values.yakuman_probs[i];
                     max_values[i] = value;
                     max_tiles[i] = tile;
                 }
@@ -630,6 +769,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             tenpai_probs: max_tenpai_probs,
             win_probs: max_win_probs,
             exp_values: max_exp_values,
+            yakuman_probs: max_yakuman_probs,
         });
         self.discard_cache[shanten as usize].insert(self.state.clone(), Rc::clone(&values));
 
@@ -637,7 +777,10 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
     }
 
     /// None: no yaku
-    fn get_score(&self, win_tile: Tile) -> Option<[f32; 4]> {
+    ///
+    /// The bool is whether it is a yakuman, which is always false unless
+    /// `calc_yakuman_prob` is set.
+    fn get_score(&self, win_tile: Tile) -> Option<([f32; 4], bool)> {
         let calc = AgariCalculator {
             tehai: &self.state.tehai,
             is_menzen: self.sup.is_menzen,
@@ -649,9 +792,18 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             jikaze: self.sup.jikaze,
             winning_tile: win_tile.deaka().as_u8(),
             is_ron: false,
+            ..Default::default()
         };
         let is_oya = self.sup.jikaze == tu8!(E);
 
+        if self.sup.dama_needs_yaku
+            && self.sup.is_menzen
+            && !self.sup.prefer_riichi
+            && !calc.has_yaku()
+        {
+            return None;
+        }
+
         let additional_yakus = match (self.sup.is_menzen, self.sup.prefer_riichi) {
             (true, true) => 2,
             (true, false) => 1,
@@ -661,23 +813,102 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             .sup
             .dora_indicators
             .iter()
-            .map(|ind| self.state.tehai[ind.next().as_usize()])
+            .map(|ind| self.state.tehai[ind.dora_from_indicator().as_usize()])
             .sum::<u8>()
-            + self.state.akas_in_hand.iter().filter(|&&b| b).count() as u8
+            + self.state.akas_in_hand.iter().sum::<u8>()
             + self.sup.num_doras_in_fuuro;
 
         // Although you can technically win the base hand with just 海底, the
         // original C++ version didn't take this into account and I also agree
         // with that.
-        let (fu, han) = match calc.agari(additional_yakus, num_doras)? {
+        let (fu, han) = match self.agari(&calc, additional_yakus, num_doras)? {
             Agari::Normal { fu, han } => (fu, han),
             a @ Agari::Yakuman(_) => {
-                return Some([a.point(is_oya).tsumo_total(is_oya) as f32; 4]);
+                let score = a.point(is_oya).tsumo_total(is_oya) as f32;
+                return Some(([score; 4], self.sup.calc_yakuman_prob));
             }
         };
 
         // 役ありの場合
+        let mut scores = self.scores_with_uradora(fu, han, is_oya);
+
+        if self.sup.calc_kan_upgrade {
+            let (kan_prob, kan_dora_probs) = self.kan_dora_probs(win_tile);
+            if kan_prob > 0. {
+                for (i, s) in scores.iter_mut().enumerate() {
+                    let mut with_kan = 0.;
+                    for (j, &p) in kan_dora_probs.iter().enumerate() {
+                        if p == 0. {
+                            continue;
+                        }
+                        with_kan += p * self.scores_with_uradora(fu, han + j as u8, is_oya)[i];
+                    }
+                    *s = s.mul_add(1. - kan_prob, with_kan * kan_prob);
+                }
+            }
+        }
 
+        let is_kazoe = self.sup.calc_yakuman_prob && self.sup.kazoe_yakuman && han >= 13;
+        Some((scores, is_kazoe))
+    }
+
+    /// `calc.agari`, except for the open table of a hand without any fuuro
+    /// yet, where the best of the wins with one of the sets in tehai called
+    /// is returned instead.
+    fn agari(
+        &self,
+        calc: &AgariCalculator<'_>,
+        additional_yakus: u8,
+        num_doras: u8,
+    ) -> Option<Agari> {
+        let has_fuuro =
+            !self.sup.chis.is_empty() || !self.sup.pons.is_empty() || !self.sup.minkans.is_empty();
+        if self.sup.is_menzen || has_fuuro {
+            return calc.agari(additional_yakus, num_doras);
+        }
+
+        let win_tile = calc.winning_tile as usize;
+        let mut best = None;
+        for tid in 0..34 {
+            let mut tehai = self.state.tehai;
+            if tehai[tid] >= 3 {
+                tehai[tid] -= 3;
+                // The winning tile is drawn, so it can't be in the call.
+                if tehai[win_tile] > 0 {
+                    let pon = [tid as u8];
+                    let agari = AgariCalculator {
+                        tehai: &tehai,
+                        is_menzen: false,
+                        pons: &pon,
+                        ..*calc
+                    }
+                    .agari(additional_yakus, num_doras);
+                    best = best.max(agari);
+                }
+            }
+
+            let mut tehai = self.state.tehai;
+            if tid < 3 * 9 && tid % 9 < 7 && tehai[tid..tid + 3].iter().all(|&c| c > 0) {
+                tehai[tid..tid + 3].iter_mut().for_each(|c| *c -= 1);
+                if tehai[win_tile] > 0 {
+                    let chi = [tid as u8];
+                    let agari = AgariCalculator {
+                        tehai: &tehai,
+                        is_menzen: false,
+                        chis: &chi,
+                        ..*calc
+                    }
+                    .agari(additional_yakus, num_doras);
+                    best = best.max(agari);
+                }
+            }
+        }
+        best
+    }
+
+    /// Scores with 0 to 3 extra hans from ダブル立直, 一発 and 海底撈月, with
+    /// ura doras taken into account if riichi is assumed.
+    fn scores_with_uradora(&self, fu: u8, han: u8, is_oya: bool) -> [f32; 4] {
         // ダブル立直、一発、海底撈月で最大3翻まで増加するので、
         // ベースとなる点数、+1翻の点数、+2翻の点数、+3翻の点数も計算しておく。
         let mut scores = [0.; 4];
@@ -693,7 +924,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
                 }
                 // ドラ表示牌の枚数を数える。
                 let tile = must_tile!(tid);
-                let ind_count = self.state.tiles_in_wall[tile.prev().as_usize()];
+                let ind_count = self.state.tiles_in_wall[tile.dora_indicator_for().as_usize()];
                 n_indicators[count as usize] += ind_count;
                 sum_indicators += ind_count;
             }
@@ -754,7 +985,54 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             }
         }
 
-        Some(scores)
+        scores
+    }
+
+    /// Returns the chance that a concealed triplet in the winning hand has
+    /// been made an ankan by the time of agari, and the distribution of the
+    /// number of doras the kan brings, indexed by the number of doras.
+    ///
+    /// Only one kan is considered. Its chance is roughly estimated as drawing
+    /// the last copy within `MAX_TSUMO` tsumos, and the kan dora indicator is
+    /// assumed to be any of the tiles left in the wall.
+    fn kan_dora_probs(&self, win_tile: Tile) -> (f32, [f32; 10]) {
+        let win_tid = win_tile.deaka().as_usize();
+        let n_left_tiles = self.state.sum_left_tiles() as f32;
+
+        let Some(kan_tid) = (0..34).find(|&tid| {
+            self.state.tehai[tid] == 3 && tid != win_tid && self.state.tiles_in_wall[tid] > 0
+        }) else {
+            return (0., [0.; 10]);
+        };
+        let kan_prob = (MAX_TSUMO as f32 / n_left_tiles).min(1.);
+
+        let mut tehai_after = self.state.tehai;
+        tehai_after[kan_tid] += 1;
+        // The 4th tile itself is counted by the existing indicators.
+        let extra = self
+            .sup
+            .dora_indicators
+            .iter()
+            .filter(|ind| ind.dora_from_indicator().as_usize() == kan_tid)
+            .count();
+
+        let mut probs = [0.; 10];
+        let mut sum = 0.;
+        for (tid, &count) in tehai_after.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let ind = must_tile!(tid).dora_indicator_for().as_usize();
+            let mut ind_count = self.state.tiles_in_wall[ind];
+            if ind == kan_tid {
+                ind_count -= 1;
+            }
+            let p = ind_count as f32 / n_left_tiles;
+            probs[count as usize + extra] += p;
+            sum += p;
+        }
+        probs[extra] += 1. - sum;
+        (kan_prob, probs)
     }
 }
 
@@ -763,12 +1041,42 @@ mod test {
     use super::*;
     use crate::algo::sp::CALC_SHANTEN_FN;
     use crate::hand::hand;
+    use crate::tile::AkaConfig;
     use crate::tuz;
 
     fn feq(a: f32, b: f32) -> bool {
         (a - b).abs() <= f32::EPSILON
     }
 
+    #[test]
+    fn init_state_from_tehai_str() {
+        let state = InitState::from_tehai_str("123m456p789s11z05m", None).unwrap();
+        let mut tehai = hand("123m456p789s11z55m").unwrap();
+        assert_eq!(state.tehai, tehai);
+        assert_eq!(state.akas_in_hand, [1, 0, 0]);
+        assert_eq!(state.tiles_seen, tehai);
+        assert_eq!(state.akas_seen, [1, 0, 0]);
+
+        let state = InitState::from_tehai_str("123m456p789s11z55m", Some(&t![E, 5sr, 1m])).unwrap();
+        assert_eq!(state.tehai, tehai);
+        assert_eq!(state.akas_in_hand, [0; 3]);
+        tehai[tuz!(E)] += 1;
+        tehai[tuz!(5s)] += 1;
+        tehai[tuz!(1m)] += 1;
+        assert_eq!(state.tiles_seen, tehai);
+        assert_eq!(state.akas_seen, [0, 0, 1]);
+
+        // 3n tiles, or too many tiles
+        InitState::from_tehai_str("123m456p789s111z", None).unwrap_err();
+        InitState::from_tehai_str("123m456p789s11z12345p", None).unwrap_err();
+        // 5 copies
+        InitState::from_tehai_str("11111m", None).unwrap_err();
+        InitState::from_tehai_str("111m 2p", Some(&t![1m, 1m])).unwrap_err();
+        // 2 aka 5m
+        InitState::from_tehai_str("0m 2p", Some(&t![5mr,])).unwrap_err();
+        InitState::from_tehai_str("12x", None).unwrap_err();
+    }
+
     #[test]
     fn nanikiru() {
         let mut calc = SPCalculator {
@@ -780,6 +1088,7 @@ mod test {
             bakaze: tu8!(E),
             jikaze: tu8!(N),
             prefer_riichi: true,
+            dama_needs_yaku: false,
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![P,],
@@ -789,6 +1098,11 @@ mod test {
             maximize_win_prob: false,
             calc_tegawari: true,
             calc_shanten_down: true,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
         };
 
         let tehai = hand("45678m 34789p 3344z").unwrap();
@@ -798,9 +1112,10 @@ mod test {
         }
         let state = InitState {
             tehai,
-            akas_in_hand: [false; 3],
+            akas_in_hand: [0; 3],
             tiles_seen,
-            akas_seen: [false; 3],
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
         };
         let can_discard = true;
         let tsumos_left = 8;
@@ -821,9 +1136,10 @@ mod test {
         }
         let state = InitState {
             tehai,
-            akas_in_hand: [false; 3],
+            akas_in_hand: [0; 3],
             tiles_seen,
-            akas_seen: [false; 3],
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
         };
         let can_discard = true;
         let tsumos_left = 15;
@@ -852,6 +1168,7 @@ mod test {
             bakaze: tu8!(E),
             jikaze: tu8!(E),
             prefer_riichi: true,
+            dama_needs_yaku: false,
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![6m,],
@@ -861,6 +1178,11 @@ mod test {
             maximize_win_prob: false,
             calc_tegawari: true,
             calc_shanten_down: true,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
         };
 
         let tehai = hand("45677m 456778p 248s").unwrap();
@@ -870,9 +1192,10 @@ mod test {
         }
         let state = InitState {
             tehai,
-            akas_in_hand: [false; 3],
+            akas_in_hand: [0; 3],
             tiles_seen,
-            akas_seen: [false; 3],
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
         };
         let can_discard = true;
         let tsumos_left = 15;
@@ -910,6 +1233,7 @@ mod test {
             bakaze: tu8!(E),
             jikaze: tu8!(W),
             prefer_riichi: true,
+            dama_needs_yaku: false,
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![1m,],
@@ -919,6 +1243,11 @@ mod test {
             maximize_win_prob: false,
             calc_tegawari: true,
             calc_shanten_down: true,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
         };
         let tehai = hand("9999m 6677p 88s 335z 1m").unwrap();
         let mut tiles_seen = tehai;
@@ -927,9 +1256,10 @@ mod test {
         }
         let state = InitState {
             tehai,
-            akas_in_hand: [false; 3],
+            akas_in_hand: [0; 3],
             tiles_seen,
-            akas_seen: [false; 3],
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
         };
         let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
         let can_discard = true;
@@ -960,6 +1290,7 @@ mod test {
             bakaze: tu8!(E),
             jikaze: tu8!(W),
             prefer_riichi: true,
+            dama_needs_yaku: false,
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![6m,],
@@ -969,6 +1300,11 @@ mod test {
             maximize_win_prob: true,
             calc_tegawari: true,
             calc_shanten_down: true,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
         };
 
         let tehai = hand("45677m 456778p 48s").unwrap();
@@ -980,9 +1316,10 @@ mod test {
 
         let state = InitState {
             tehai,
-            akas_in_hand: [false; 3],
+            akas_in_hand: [0; 3],
             tiles_seen,
-            akas_seen: [false, false, true],
+            akas_seen: [0, 0, 1],
+            aka_config: AkaConfig::default(),
         };
         let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
         let can_discard = false;
@@ -1005,4 +1342,321 @@ mod test {
             assert!(feq(c.exp_values[0], 432.26678));
         }
     }
+
+    #[test]
+    fn open_vs_closed() {
+        let mut calc = SPCalculator {
+            tehai_len_div3: 4,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(W),
+            prefer_riichi: true,
+            dama_needs_yaku: false,
+            is_menzen: true,
+            num_doras_in_fuuro: 0,
+            dora_indicators: &t![9m,],
+            calc_double_riichi: false,
+            calc_haitei: false,
+            sort_result: true,
+            maximize_win_prob: false,
+            calc_tegawari: false,
+            calc_shanten_down: false,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
+        };
+
+        // Only riichi and menzen tsumo can make it a yaku hand.
+        let tehai = hand("123m 567p 789s 23p 11z").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        let can_discard = false;
+        let tsumos_left = 8;
+
+        let (_, open_table) = calc
+            .calc_with_open_table(state.clone(), can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(open_table.is_none());
+
+        calc.compare_open_vs_closed = true;
+        let (closed_table, open_table) = calc
+            .calc_with_open_table(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        let closed = &closed_table[0];
+        let open = &open_table.unwrap()[0];
+        assert_eq!(closed.num_required_tiles, open.num_required_tiles);
+        assert!(closed.win_probs[0] > 0.);
+        assert!(closed.exp_values[0] > 0.);
+        assert!(feq(open.win_probs[0], 0.));
+        assert!(feq(open.exp_values[0], 0.));
+
+        // The called set is scored as open, so an open 対々和 shanpon wait is
+        // at most 三暗刻 instead of 四暗刻 on a tsumo.
+        let tehai = hand("222m 444p 666s 33m 88s").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        let (closed_table, open_table) = calc
+            .calc_with_open_table(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        let closed = &closed_table[0];
+        let open = &open_table.unwrap()[0];
+        assert!(feq(closed.win_probs[0], open.win_probs[0]));
+        assert!((closed.exp_values[0] / closed.win_probs[0] - 32000.).abs() < 1.);
+        // 三暗刻, 対々和 and 断幺九 for a mangan.
+        assert!((open.exp_values[0] / open.win_probs[0] - 8000.).abs() < 1.);
+    }
+
+    #[test]
+    fn yakuman_prob() {
+        let mut calc = SPCalculator {
+            tehai_len_div3: 4,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            prefer_riichi: true,
+            dama_needs_yaku: false,
+            is_menzen: true,
+            num_doras_in_fuuro: 0,
+            dora_indicators: &t![9m,],
+            calc_double_riichi: false,
+            calc_haitei: false,
+            sort_result: true,
+            maximize_win_prob: false,
+            calc_tegawari: false,
+            calc_shanten_down: false,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
+        };
+
+        // 四暗刻単騎
+        let tehai = hand("111m 222p 333s 444z 5z").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        let can_discard = false;
+        let tsumos_left = 8;
+
+        let candidates = calc
+            .calc(state.clone(), can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(feq(candidates[0].yakuman_prob, 0.));
+
+        calc.calc_yakuman_prob = true;
+        let candidates = calc
+            .calc(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        let c = &candidates[0];
+        assert!(c.yakuman_prob > 0.);
+        assert!(feq(c.yakuman_prob, c.win_probs[0]));
+
+        // 清一色 三暗刻 with 5 doras, a 数え役満 with riichi and tsumo.
+        let dora_indicators = t![9m, 6m];
+        calc.dora_indicators = &dora_indicators;
+        let tehai = hand("1112223334567m").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let candidates = calc
+            .calc(state.clone(), can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(feq(candidates[0].yakuman_prob, 0.));
+
+        calc.kazoe_yakuman = true;
+        let candidates = calc
+            .calc(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        let c = &candidates[0];
+        assert!(c.yakuman_prob > 0.);
+        assert!(c.yakuman_prob <= c.win_probs[0]);
+    }
+
+    #[test]
+    fn kan_upgrade() {
+        let mut calc = SPCalculator {
+            tehai_len_div3: 4,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            prefer_riichi: false,
+            dama_needs_yaku: false,
+            is_menzen: true,
+            num_doras_in_fuuro: 0,
+            dora_indicators: &t![1m,],
+            calc_double_riichi: false,
+            calc_haitei: false,
+            sort_result: true,
+            maximize_win_prob: false,
+            calc_tegawari: false,
+            calc_shanten_down: false,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
+        };
+
+        // The 2m triplet is dora, and the last copy is still in the wall.
+        let tehai = hand("222m 456p 678s 234s 5p").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        let can_discard = false;
+        let tsumos_left = 8;
+
+        let plain = calc
+            .calc(state.clone(), can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        calc.calc_kan_upgrade = true;
+        let with_kan = calc
+            .calc(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(feq(plain[0].win_probs[0], with_kan[0].win_probs[0]));
+        assert!(with_kan[0].exp_values[0] > plain[0].exp_values[0]);
+
+        // No triplet, no change.
+        let tehai = hand("123m 456p 678s 234s 5p").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let with_kan = calc
+            .calc(state.clone(), can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        calc.calc_kan_upgrade = false;
+        let plain = calc
+            .calc(state, can_discard, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(feq(plain[0].exp_values[0], with_kan[0].exp_values[0]));
+    }
+
+    #[test]
+    fn win_curve() {
+        let mut calc = SPCalculator {
+            tehai_len_div3: 4,
+            chis: &[],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            prefer_riichi: true,
+            dama_needs_yaku: false,
+            is_menzen: true,
+            num_doras_in_fuuro: 0,
+            dora_indicators: &t![1m,],
+            calc_double_riichi: false,
+            calc_haitei: false,
+            sort_result: true,
+            maximize_win_prob: false,
+            calc_tegawari: false,
+            calc_shanten_down: false,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: false,
+            kazoe_yakuman: false,
+            calc_kan_upgrade: false,
+            calc_win_curve: false,
+        };
+
+        let tehai = hand("123m 456p 68s 2344s 79p").unwrap();
+        let mut tiles_seen = tehai;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [0; 3],
+            tiles_seen,
+            akas_seen: [0; 3],
+            aka_config: AkaConfig::default(),
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        let tsumos_left = 10;
+
+        let plain = calc
+            .calc(state.clone(), true, tsumos_left, cur_shanten)
+            .unwrap();
+        assert!(plain.iter().all(|c| c.win_curve.is_empty()));
+
+        calc.calc_win_curve = true;
+        let with_curve = calc.calc(state, true, tsumos_left, cur_shanten).unwrap();
+        assert_eq!(plain.len(), with_curve.len());
+        for (p, c) in plain.iter().zip(&with_curve) {
+            assert_eq!(p.tile, c.tile);
+            assert_eq!(p.win_probs, c.win_probs);
+            assert_eq!(c.win_curve.len(), tsumos_left as usize + 1);
+            assert!(c.win_curve[0] == 0.);
+            assert!(feq(c.win_curve[tsumos_left as usize], c.win_probs[0]));
+            assert!(c.win_curve.windows(2).all(|w| w[0] <= w[1]));
+        }
+        // From 1-shanten, a win takes at least 2 tsumos.
+        assert!(with_curve[0].win_curve[1] == 0.);
+        assert!(with_curve[0].win_curve[2] > 0.);
+    }
 }
@@ -3,9 +3,10 @@ use super::tile::RequiredTile;
 use crate::tile::Tile;
 use std::cmp::Ordering;
 
+use serde::Serialize;
 use tinyvec::ArrayVec;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Candidate {
     /// 打牌
     pub tile: Tile,
@@ -15,11 +16,21 @@ pub struct Candidate {
     pub win_probs: ArrayVec<[f32; MAX_TSUMOS_LEFT]>,
     /// 巡目ごとの期待値
     pub exp_values: ArrayVec<[f32; MAX_TSUMOS_LEFT]>,
+    /// 現巡目での役満和了確率, only calculated with `calc_yakuman_prob`.
+    pub yakuman_prob: f32,
+    /// 残りツモ数ごとの累積和了確率, only calculated with `calc_win_curve`,
+    /// see `SPCalculator::calc_win_curve` for the indexing.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub win_curve: Vec<f32>,
     /// 有効牌及び枚数の一覧
     pub required_tiles: ArrayVec<[RequiredTile; 34]>,
     pub num_required_tiles: u8,
     /// 向聴戻しになるかどうか
     pub shanten_down: bool,
+    /// 立直棒の期待収支, not included in `exp_values`. Only filled by
+    /// `PlayerState::single_player_tables` for the riichi table, see
+    /// `PlayerState::riichi_stick_ev_adjustment`.
+    pub riichi_stick_ev: f32,
 }
 
 #[derive(Default)]
@@ -28,6 +39,7 @@ pub(super) struct RawCandidate<'a> {
     pub(super) tenpai_probs: &'a [f32],
     pub(super) win_probs: &'a [f32],
     pub(super) exp_values: &'a [f32],
+    pub(super) yakuman_probs: &'a [f32],
     pub(super) required_tiles: ArrayVec<[RequiredTile; 34]>,
     pub(super) shanten_down: bool,
 }
@@ -49,6 +61,7 @@ impl From<RawCandidate<'_>> for Candidate {
             tenpai_probs,
             win_probs,
             exp_values,
+            yakuman_probs,
             required_tiles,
             shanten_down,
         }: RawCandidate<'_>,
@@ -57,15 +70,19 @@ impl From<RawCandidate<'_>> for Candidate {
         let tenpai_probs = tenpai_probs.iter().map(|p| p.clamp(0., 1.)).collect();
         let win_probs = win_probs.iter().map(|p| p.clamp(0., 1.)).collect();
         let exp_values = exp_values.iter().map(|v| v.max(0.)).collect();
+        let yakuman_prob = yakuman_probs.first().map_or(0., |p| p.clamp(0., 1.));
 
         Self {
             tile,
             tenpai_probs,
             win_probs,
             exp_values,
+            yakuman_prob,
+            win_curve: vec![],
             required_tiles,
             num_required_tiles,
             shanten_down,
+            riichi_stick_ev: 0.,
         }
     }
 }
@@ -158,25 +175,36 @@ impl Candidate {
             ]
         }
     }
+}
 
-    #[cfg(feature = "sp_reproduce_cpp_ver")]
-    pub(super) fn calibrate(mut self, real_max_tsumo: usize) -> Self {
-        if self.shanten_down {
-            // 向聴戻しをしない場合のパターンの確率が過小に算出されているような気がするため、
-            // 帳尻をあわせるために1巡ずらしている → 本来必要ない処理なので、あとで消す
-            self.tenpai_probs[0] = 0.;
-            self.tenpai_probs.rotate_left(1);
-            self.win_probs[0] = 0.;
-            self.win_probs.rotate_left(1);
-            self.exp_values[0] = 0.;
-            self.exp_values.rotate_left(1);
-        }
-        self.tenpai_probs.rotate_right(real_max_tsumo);
-        self.tenpai_probs.truncate(real_max_tsumo);
-        self.win_probs.rotate_right(real_max_tsumo);
-        self.win_probs.truncate(real_max_tsumo);
-        self.exp_values.rotate_right(real_max_tsumo);
-        self.exp_values.truncate(real_max_tsumo);
-        self
+#[cfg(feature = "sp_reproduce_cpp_ver")]
+impl RawCandidate<'_> {
+    /// Converts into a `Candidate` with each of the columns cut down to the
+    /// last `real_max_tsumo` tsumos.
+    pub(super) fn calibrate(self, real_max_tsumo: usize) -> Candidate {
+        let calibrate = |column: &[f32]| {
+            let mut column: ArrayVec<[f32; MAX_TSUMOS_LEFT]> = column.iter().copied().collect();
+            if self.shanten_down {
+                // 向聴戻しをしない場合のパターンの確率が過小に算出されているような気がするため、
+                // 帳尻をあわせるために1巡ずらしている → 本来必要ない処理なので、あとで消す
+                column[0] = 0.;
+                column.rotate_left(1);
+            }
+            column.rotate_right(real_max_tsumo);
+            column.truncate(real_max_tsumo);
+            column
+        };
+        let tenpai_probs = calibrate(self.tenpai_probs);
+        let win_probs = calibrate(self.win_probs);
+        let exp_values = calibrate(self.exp_values);
+        let yakuman_probs = calibrate(self.yakuman_probs);
+
+        Candidate::from(RawCandidate {
+            tenpai_probs: &tenpai_probs,
+            win_probs: &win_probs,
+            exp_values: &exp_values,
+            yakuman_probs: &yakuman_probs,
+            ..self
+        })
     }
 }
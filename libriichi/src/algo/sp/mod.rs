@@ -41,6 +41,17 @@ pub const MAX_TSUMOS_LEFT: usize = 18;
 #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
 pub const MAX_TSUMOS_LEFT: usize = 17;
 
+/// The max `tsumos_left` that `SPCalculator::calc` takes, which is 1 less than
+/// `MAX_TSUMOS_LEFT` at 3n+2 with `sp_reproduce_cpp_ver`.
+#[must_use]
+pub const fn max_tsumos_left(can_discard: bool) -> u8 {
+    if cfg!(feature = "sp_reproduce_cpp_ver") && can_discard {
+        17
+    } else {
+        MAX_TSUMOS_LEFT as u8
+    }
+}
+
 #[cfg(feature = "sp_reproduce_cpp_ver")]
 const CALC_SHANTEN_FN: fn(&[u8; 34], u8) -> i8 = super::shanten::calc_normal;
 #[cfg(not(feature = "sp_reproduce_cpp_ver"))]
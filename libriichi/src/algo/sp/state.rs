@@ -1,8 +1,10 @@
 use super::CALC_SHANTEN_FN;
 use super::tile::{DiscardTile, DrawTile, RequiredTile};
-use crate::tile::Tile;
-use crate::{must_tile, t, tu8};
+use crate::hand::hand_with_aka;
+use crate::tile::{AkaConfig, Tile};
+use crate::{must_tile, t, tu8, tuz};
 
+use anyhow::{Result, ensure};
 use tinyvec::ArrayVec;
 
 /// Mutable state of both the hand and the board.
@@ -10,24 +12,25 @@ use tinyvec::ArrayVec;
 pub(super) struct State {
     // hand
     pub(super) tehai: [u8; 34],
-    pub(super) akas_in_hand: [bool; 3],
+    pub(super) akas_in_hand: [u8; 3],
 
     // global
     pub(super) tiles_in_wall: [u8; 34],
-    pub(super) akas_in_wall: [bool; 3],
+    pub(super) akas_in_wall: [u8; 3],
     pub(super) n_extra_tsumo: u8,
 }
 
 /// Mutable state of both the hand and the board.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct InitState {
     // hand
     pub tehai: [u8; 34],
-    pub akas_in_hand: [bool; 3],
+    pub akas_in_hand: [u8; 3],
 
     // global
     pub tiles_seen: [u8; 34],
-    pub akas_seen: [bool; 3],
+    pub akas_seen: [u8; 3],
+    pub aka_config: AkaConfig,
 }
 
 impl From<InitState> for State {
@@ -37,12 +40,16 @@ impl From<InitState> for State {
             akas_in_hand,
             tiles_seen,
             akas_seen,
+            aka_config,
         }: InitState,
     ) -> Self {
         let mut tiles_in_wall = tiles_seen;
         let mut akas_in_wall = akas_seen;
         tiles_in_wall.iter_mut().for_each(|v| *v = 4 - *v);
-        akas_in_wall.iter_mut().for_each(|v| *v = !*v);
+        akas_in_wall
+            .iter_mut()
+            .zip(aka_config.counts)
+            .for_each(|(v, n)| *v = n - *v);
         Self {
             tehai,
             akas_in_hand,
@@ -53,13 +60,65 @@ impl From<InitState> for State {
     }
 }
 
+impl InitState {
+    /// Builds an `InitState` from a hand in tenhou.net/2 format like
+    /// `123m456p789s11z5m`, where 0m, 0p and 0s are akas, with
+    /// `AkaConfig::STANDARD`.
+    ///
+    /// `seen` is the tiles visible elsewhere, such as kawa, fuuro and dora
+    /// indicators. `tiles_seen` and `akas_seen` are the hand plus `seen`, or
+    /// the hand only if `seen` is None.
+    pub fn from_tehai_str(hand: &str, seen: Option<&[Tile]>) -> Result<Self> {
+        let tiles = hand_with_aka(hand)?;
+        let len: u8 = tiles.iter().sum();
+        ensure!(
+            !len.is_multiple_of(3) && len <= 14,
+            "hand {hand} has {len} tiles, which is neither 3n+1 nor 3n+2",
+        );
+
+        let mut tehai = [0; 34];
+        tehai.copy_from_slice(&tiles[..34]);
+        let mut akas_in_hand = [0; 3];
+        for (i, five) in tuz![5m, 5p, 5s].into_iter().enumerate() {
+            akas_in_hand[i] = tiles[tuz!(5mr) + i];
+            tehai[five] += akas_in_hand[i];
+        }
+
+        let mut tiles_seen = tehai;
+        let mut akas_seen = akas_in_hand;
+        for tile in seen.unwrap_or_default() {
+            ensure!(!tile.is_unknown(), "unknown tile in seen");
+            tiles_seen[tile.deaka().as_usize()] += 1;
+            if tile.is_aka() {
+                akas_seen[tile.as_usize() - tuz!(5mr)] += 1;
+            }
+        }
+
+        let aka_config = AkaConfig::STANDARD;
+        for (tid, &count) in tiles_seen.iter().enumerate() {
+            ensure!(count <= 4, "{} is seen {count} times", must_tile!(tid),);
+        }
+        for (&count, max) in akas_seen.iter().zip(aka_config.counts) {
+            ensure!(count <= max, "more akas than {aka_config:?} are seen");
+        }
+
+        Ok(Self {
+            tehai,
+            akas_in_hand,
+            tiles_seen,
+            akas_seen,
+            aka_config,
+        })
+    }
+}
+
 impl State {
     pub(super) fn discard(&mut self, tile: Tile) {
         self.tehai[tile.deaka().as_usize()] -= 1;
         match tile.as_u8() {
-            tu8!(5mr) => self.akas_in_hand[0] = false,
-            tu8!(5pr) => self.akas_in_hand[1] = false,
-            tu8!(5sr) => self.akas_in_hand[2] = false,
+            tu8!(5mr) => self.akas_in_hand[0] -= 1,
+            tu8!(5pr) => self.akas_in_hand[1] -= 1,
+            tu8!(5sr) => self.akas_in_hand[2] -= 1,
             _ => (),
         }
     }
@@ -67,9 +126,9 @@ impl State {
     pub(super) fn undo_discard(&mut self, tile: Tile) {
         self.tehai[tile.deaka().as_usize()] += 1;
         match tile.as_u8() {
-            tu8!(5mr) => self.akas_in_hand[0] = true,
-            tu8!(5pr) => self.akas_in_hand[1] = true,
-            tu8!(5sr) => self.akas_in_hand[2] = true,
+            tu8!(5mr) => self.akas_in_hand[0] += 1,
+            tu8!(5pr) => self.akas_in_hand[1] += 1,
+            tu8!(5sr) => self.akas_in_hand[2] += 1,
             _ => (),
         }
     }
@@ -77,9 +136,9 @@ impl State {
     pub(super) fn deal(&mut self, tile: Tile) {
         self.tiles_in_wall[tile.deaka().as_usize()] -= 1;
         match tile.as_u8() {
-            tu8!(5mr) => self.akas_in_wall[0] = false,
-            tu8!(5pr) => self.akas_in_wall[1] = false,
-            tu8!(5sr) => self.akas_in_wall[2] = false,
+            tu8!(5mr) => self.akas_in_wall[0] -= 1,
+            tu8!(5pr) => self.akas_in_wall[1] -= 1,
+            tu8!(5sr) => self.akas_in_wall[2] -= 1,
             _ => (),
         }
         self.undo_discard(tile);
@@ -89,9 +148,9 @@ impl State {
         self.discard(tile);
         self.tiles_in_wall[tile.deaka().as_usize()] += 1;
         match tile.as_u8() {
-            tu8!(5mr) => self.akas_in_wall[0] = true,
-            tu8!(5pr) => self.akas_in_wall[1] = true,
-            tu8!(5sr) => self.akas_in_wall[2] = true,
+            tu8!(5mr) => self.akas_in_wall[0] += 1,
+            tu8!(5pr) => self.akas_in_wall[1] += 1,
+            tu8!(5sr) => self.akas_in_wall[2] += 1,
             _ => (),
         }
     }
@@ -116,9 +175,9 @@ impl State {
             let shanten_diff = shanten_after - shanten;
 
             let tile = match tid as u8 {
-                tu8!(5m) if self.akas_in_hand[0] && tehai[tid] == 1 => t!(5mr),
-                tu8!(5p) if self.akas_in_hand[1] && tehai[tid] == 1 => t!(5pr),
-                tu8!(5s) if self.akas_in_hand[2] && tehai[tid] == 1 => t!(5sr),
+                tu8!(5m) if tehai[tid] == self.akas_in_hand[0] => t!(5mr),
+                tu8!(5p) if tehai[tid] == self.akas_in_hand[1] => t!(5pr),
+                tu8!(5s) if tehai[tid] == self.akas_in_hand[2] => t!(5sr),
                 _ => must_tile!(tid),
             };
 
@@ -148,26 +207,31 @@ impl State {
             let shanten_diff = shanten_after - shanten;
 
             let tile = must_tile!(tid);
-            match (tid as u8, self.akas_in_wall) {
-                (tu8!(5m), [true, _, _]) | (tu8!(5p), [_, true, _]) | (tu8!(5s), [_, _, true]) => {
-                    if count >= 2 {
-                        draw_tiles.push(DrawTile {
-                            tile,
-                            count: count - 1,
-                            shanten_diff,
-                        });
-                    }
+            let akas = match tid as u8 {
+                tu8!(5m) => self.akas_in_wall[0],
+                tu8!(5p) => self.akas_in_wall[1],
+                tu8!(5s) => self.akas_in_wall[2],
+                _ => 0,
+            };
+            if akas > 0 {
+                if count > akas {
                     draw_tiles.push(DrawTile {
-                        tile: tile.akaize(),
-                        count: 1,
+                        tile,
+                        count: count - akas,
                         shanten_diff,
                     });
                 }
-                _ => draw_tiles.push(DrawTile {
+                draw_tiles.push(DrawTile {
+                    tile: tile.akaize(),
+                    count: akas,
+                    shanten_diff,
+                });
+            } else {
+                draw_tiles.push(DrawTile {
                     tile,
                     count,
                     shanten_diff,
-                }),
+                });
             }
         }
 
@@ -1,5 +1,7 @@
 use crate::tile::Tile;
 
+use serde::Serialize;
+
 #[derive(Debug, Default, Clone, Copy)]
 pub(super) struct DiscardTile {
     pub(super) tile: Tile,
@@ -13,7 +15,7 @@ pub(super) struct DrawTile {
     pub(super) shanten_diff: i8,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize)]
 pub struct RequiredTile {
     pub tile: Tile,
     pub count: u8,
@@ -1,21 +1,24 @@
-use super::result::KyokuResult;
+use super::result::{GameResult, KyokuResult};
+use crate::algo::point::{self, Point};
+use crate::algo::scoreboard::{Scoreboard, Winner};
 use crate::array::Simple2DArray;
 use crate::consts::oracle_obs_shape;
 use crate::mjai::{Event, EventExt};
 use crate::state::PlayerState;
 use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
-use crate::{matches_tu8, must_tile, t, tu8};
+use crate::wall::Wall;
+use crate::{must_tile, t, tu8};
 use std::convert::TryInto;
 use std::{array, mem};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, bail, ensure};
 use derivative::Derivative;
 use ndarray::prelude::*;
 use rand::prelude::*;
 use rand_chacha::ChaCha12Rng;
+use serde_json as json;
 use sha3::{Digest, Sha3_256};
-use tinyvec::ArrayVec;
 
 /// The fields are all pub on purpose so the caller will be able to set the
 /// yama, doras, scores directly.
@@ -74,9 +77,7 @@ pub struct BoardState {
     can_four_wind: bool,
     four_wind_tile: Option<Tile>,
     accepted_riichis: u8,
-    kans: u8,
     check_four_kan: bool,
-    paos: [Option<u8>; 4],
 
     log: Vec<EventExt>,
 
@@ -122,6 +123,19 @@ impl Board {
         assert_eq!(idx, seq.len());
     }
 
+    /// Same as `init_from_seed`, but lays out the board from `wall`, which
+    /// may come from `Wall::for_kyoku` so that the game can be replayed from a
+    /// single seed.
+    pub fn init_from_wall(&mut self, wall: &Wall) {
+        let rev = |tiles: &[Tile]| tiles.iter().rev().copied().collect();
+
+        self.haipai = wall.haipai(self.kyoku % 4);
+        self.yama = rev(wall.live_wall());
+        self.rinshan = rev(&wall.rinshan());
+        self.dora_indicators = rev(&wall.dora_indicators());
+        self.ura_indicators = wall.ura_indicators().to_vec();
+    }
+
     pub fn into_state(self) -> BoardState {
         let oya = self.kyoku % 4;
         let dora_indicators_full = self.dora_indicators.clone();
@@ -186,6 +200,163 @@ impl BoardState {
         mem::take(&mut self.log)
     }
 
+    /// Replays a whole game log of mjai events, checking every action against
+    /// the `last_cans` of its actor and every `deltas` given in the log
+    /// against the ones calculated here, as well as the scores at each
+    /// `start_kyoku`. Errors mention the index of the offending event in
+    /// `events`.
+    ///
+    /// The returned `GameResult` has the scores calculated here, and its
+    /// `game_log` has the `deltas` of hora and ryukyoku filled in.
+    pub fn replay(events: &[json::Value]) -> Result<GameResult> {
+        let mut result = GameResult::default();
+        let mut state = Self::default();
+        for (idx, value) in events.iter().enumerate() {
+            json::from_value(value.clone())
+                .map_err(Into::into)
+                .and_then(|ev| state.replay_event(&mut result, ev))
+                .with_context(|| format!("at event {idx}: {value}"))?;
+        }
+        Ok(result)
+    }
+
+    fn replay_event(&mut self, result: &mut GameResult, ev: Event) -> Result<()> {
+        if let Some(actor) = ev.actor()
+            && !matches!(ev, Event::Tsumo { .. } | Event::ReachAccepted { .. })
+        {
+            self.player_states[actor as usize]
+                .validate_reaction(&ev)
+                .context("invalid action")?;
+        }
+
+        match ev {
+            Event::StartGame { names, seed } => {
+                result.names = names;
+                result.seed = seed.unwrap_or_default();
+                return Ok(());
+            }
+            Event::EndGame => return Ok(()),
+
+            Event::StartKyoku {
+                bakaze,
+                kyoku,
+                honba,
+                kyotaku,
+                oya,
+                scores,
+                ..
+            } => {
+                if result.game_log.is_empty() {
+                    result.scores = scores;
+                } else {
+                    ensure!(
+                        scores == result.scores,
+                        "expected scores {:?}, got {scores:?}",
+                        result.scores,
+                    );
+                }
+                let board = Board {
+                    kyoku: (bakaze.as_u8() - tu8!(E)) * 4 + kyoku - 1,
+                    honba,
+                    kyotaku,
+                    scores,
+                    ..Default::default()
+                };
+                *self = board.into_state();
+                ensure!(self.oya == oya, "expected oya {}, got {oya}", self.oya);
+            }
+
+            Event::Tsumo { .. } => {
+                self.tiles_left = self
+                    .tiles_left
+                    .checked_sub(1)
+                    .context("tsumo from exhausted yama")?;
+            }
+
+            Event::ReachAccepted { actor } => {
+                self.board.scores[actor as usize] -= 1000;
+                self.board.kyotaku += 1;
+                self.accepted_riichis += 1;
+            }
+
+            Event::Hora {
+                actor,
+                target,
+                deltas,
+                ref ura_markers,
+            } => {
+                // Only the head-bump winner, who comes first in the log,
+                // takes the honba and kyotaku in a multi-ron.
+                let honba = if self.has_hora { 0 } else { self.board.honba };
+                let scoreboard = Scoreboard {
+                    honba,
+                    ..self.scoreboard()
+                };
+                self.board.kyotaku = 0;
+                self.has_hora = true;
+                self.can_renchan |= actor == self.oya;
+
+                let is_ron = actor != target;
+                let winner =
+                    self.winner(actor, is_ron, ura_markers.as_deref().unwrap_or_default())?;
+                let expected = scoreboard.agari_deltas(&[winner], is_ron, target as usize)[0];
+                if let Some(deltas) = deltas {
+                    ensure!(
+                        deltas == expected,
+                        "expected deltas {expected:?}, got {deltas:?}",
+                    );
+                }
+                vec_add_assign(&mut self.kyoku_deltas, &expected);
+                self.add_log_no_meta(Event::Hora {
+                    actor,
+                    target,
+                    deltas: Some(expected),
+                    ura_markers: ura_markers.clone(),
+                });
+                return Ok(());
+            }
+
+            Event::Ryukyoku { deltas } => {
+                if self.tiles_left == 0 {
+                    self.exhaustive_ryukyoku();
+                } else {
+                    self.abortive_ryukyoku();
+                }
+                let Some(Event::Ryukyoku {
+                    deltas: Some(expected),
+                }) = self.log.last().map(|ev| &ev.event)
+                else {
+                    unreachable!()
+                };
+                if let Some(deltas) = deltas {
+                    ensure!(
+                        deltas == *expected,
+                        "expected deltas {expected:?}, got {deltas:?}",
+                    );
+                }
+                return Ok(());
+            }
+
+            Event::EndKyoku => {
+                vec_add_assign(&mut self.board.scores, &self.kyoku_deltas);
+                self.add_log_no_meta(ev);
+                result.game_log.push(self.take_log());
+                result.scores = self.board.scores;
+                return Ok(());
+            }
+
+            Event::None => bail!("unexpected event"),
+
+            _ => self.update_nagashi_mangan_and_four_wind(&ev),
+        };
+
+        for s in &mut self.player_states {
+            s.update(&ev)?;
+        }
+        self.add_log_no_meta(ev);
+        Ok(())
+    }
+
     #[inline]
     fn add_log(&mut self, ev: EventExt) {
         self.log.push(ev);
@@ -250,39 +421,14 @@ impl BoardState {
             .map(|(i, _)| i)
             .for_each(|i| {
                 has_nagashi_mangan = true;
-                if i as u8 == self.oya {
-                    let mut dod = [-4000; 4];
-                    dod[i] = 12000;
-                    vec_add_assign(&mut deltas, &dod);
-                } else {
-                    let mut dod = [-2000; 4];
-                    dod[i] = 8000;
-                    dod[self.oya as usize] = -4000;
-                    vec_add_assign(&mut deltas, &dod);
-                };
+                let oya = self.oya as usize;
+                let dod = Point::mangan(i == oya).payment_vector(i, false, i, oya, 0, 0);
+                vec_add_assign(&mut deltas, &dod);
             });
 
         if !has_nagashi_mangan {
-            let tenpai_actors: ArrayVec<[_; 4]> = self
-                .player_states
-                .iter()
-                .enumerate()
-                .filter(|&(_, s)| s.shanten() == 0)
-                .map(|(i, _)| i)
-                .collect();
-
-            let (plus, minus) = match tenpai_actors.len() {
-                1 => (3000, -1000),
-                2 => (1500, -1500),
-                3 => (1000, -3000),
-                // 0 | 4
-                _ => (0, 0),
-            };
-            if plus > 0 {
-                let mut dod = [minus; 4];
-                tenpai_actors.into_iter().for_each(|i| dod[i] = plus);
-                vec_add_assign(&mut deltas, &dod);
-            }
+            let tenpai = array::from_fn(|i| self.player_states[i].shanten() == 0);
+            vec_add_assign(&mut deltas, &point::noten_payments(tenpai));
         }
 
         vec_add_assign(&mut self.kyoku_deltas, &deltas);
@@ -312,7 +458,7 @@ impl BoardState {
     }
 
     fn check_four_wind(&mut self, pai: Tile) -> Result<bool> {
-        if !matches_tu8!(pai.as_u8(), E | S | W | N) {
+        if !pai.is_wind() {
             self.can_four_wind = false;
         } else if self.player_states[self.tsumo_actor as usize].can_w_riichi() {
             if let Some(tile) = self.four_wind_tile {
@@ -372,132 +518,66 @@ impl BoardState {
         self.has_hora = true;
 
         let is_ron = single_actor != single_target;
-        let mut honba_left = self.board.honba as i32; // mut in case of multi-ron
-        let mut kyotaku_point = self.board.kyotaku as i32 * 1000; // ditto
+        let scoreboard = self.scoreboard();
         self.board.kyotaku = 0; // Unlike honba, kyotaku in self will be cleared
 
         // Let the states get their agari points provided with our ura
-        // indicators.
+        // indicators. Multi-ron winners are logged in turn order from the
+        // target, starting with the head-bump winner.
         let ura_indicators =
             self.board.ura_indicators[..5 - self.board.dora_indicators.len()].to_vec();
-        let points = reactions
-            .iter()
-            .map(|ev| match ev.event {
-                Event::Hora { actor, .. } => {
-                    self.can_renchan |= actor == self.oya;
-                    let point =
-                        self.player_states[actor as usize].agari_points(is_ron, &ura_indicators);
-                    Some(point).transpose()
-                }
-                _ => Ok(None),
+        let winners = (1..=4)
+            .map(|i| (single_target + i) % 4)
+            .filter(|&actor| matches!(reactions[actor as usize].event, Event::Hora { .. }))
+            .map(|actor| {
+                self.can_renchan |= actor == self.oya;
+                self.winner(actor, is_ron, &ura_indicators)
             })
             .collect::<Result<Vec<_>>>()?;
-
-        if is_ron {
-            // Multi-ron will be handled
-            points
-                .into_iter()
-                .enumerate()
-                .cycle()
-                .skip(single_target as usize + 1)
-                .take(3)
-                .filter_map(|(actor, v)| v.map(|point| (actor, point)))
-                .for_each(|(actor, point)| {
-                    let mut deltas = [0; 4];
-                    if let Some(pao_target) = self.paos[actor] {
-                        // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
-                        //
-                        // > 複合役満を含む得点を、ツモ＝全額・ロン＝折半で支払
-                        // > う。積み棒は包。
-                        deltas[pao_target as usize] = -point.ron / 2 - honba_left * 300;
-                        deltas[single_target as usize] -= point.ron / 2; // they may be the same person
-                    } else {
-                        deltas[single_target as usize] = -point.ron - honba_left * 300;
-                    }
-                    deltas[actor] = point.ron + kyotaku_point + honba_left * 300;
-
-                    kyotaku_point = 0;
-                    honba_left = 0;
-
-                    vec_add_assign(&mut self.kyoku_deltas, &deltas);
-                    let ura_markers = self.player_states[actor]
-                        .self_riichi_accepted()
-                        .then(|| ura_indicators.clone())
-                        .unwrap_or_default();
-
-                    let hora = Event::Hora {
-                        actor: actor as u8,
-                        target: single_target,
-                        deltas: Some(deltas),
-                        ura_markers: Some(ura_markers),
-                    };
-                    self.add_log_no_meta(hora);
-                    // No need to broadcast
-                });
-            return Ok(());
+        let deltas = scoreboard.agari_deltas(&winners, is_ron, single_target as usize);
+
+        for (winner, deltas) in winners.iter().zip(deltas) {
+            vec_add_assign(&mut self.kyoku_deltas, &deltas);
+            let ura_markers = self.player_states[winner.seat]
+                .self_riichi_accepted()
+                .then(|| ura_indicators.clone())
+                .unwrap_or_default();
+
+            let hora = Event::Hora {
+                actor: winner.seat as u8,
+                target: single_target,
+                deltas: Some(deltas),
+                ura_markers: Some(ura_markers),
+            };
+            self.add_log_no_meta(hora);
+            // No need to broadcast
         }
 
-        let point = points[single_actor as usize].unwrap();
-        let mut deltas = [0; 4];
-        if let Some(pao_target) = self.paos[single_actor as usize] {
-            // For pao to happen, the agari must have at least 1 yakuman so ron
-            // point and sum of tsumo point should be equal.
-            deltas[pao_target as usize] = -point.ron - honba_left * 300;
-        } else {
-            deltas.fill(-point.tsumo_ko - honba_left * 100);
-            if single_actor != self.oya {
-                deltas[self.oya as usize] = -point.tsumo_oya - honba_left * 100;
-            }
-        };
-        deltas[single_actor as usize] =
-            point.tsumo_total(single_actor == self.oya) + kyotaku_point + honba_left * 300;
-
-        vec_add_assign(&mut self.kyoku_deltas, &deltas);
-        let ura_markers = self.player_states[single_actor as usize]
-            .self_riichi_accepted()
-            .then_some(ura_indicators)
-            .unwrap_or_default();
-
-        let hora = Event::Hora {
-            actor: single_actor,
-            target: single_target,
-            deltas: Some(deltas),
-            ura_markers: Some(ura_markers),
-        };
-        self.add_log_no_meta(hora);
-        // No need to broadcast
-
         Ok(())
     }
 
-    fn update_paos(&mut self, ev: &Event) {
-        match *ev {
-            Event::Pon {
-                target, actor, pai, ..
-            }
-            | Event::Daiminkan {
-                target, actor, pai, ..
-            } if pai.is_jihai() => {
-                let mut jihais = 0_u8;
-                self.player_states[actor as usize]
-                    .pons()
-                    .iter()
-                    .chain(self.player_states[actor as usize].minkans())
-                    .copied()
-                    .filter(|&t| t >= tu8!(E))
-                    .for_each(|t| jihais |= 1 << (t - tu8!(E)));
-                let daisanein_confirmed = (jihais & 0b1110000) == 0b1110000;
-                let daisuushi_confirmed = (jihais & 0b0001111) == 0b0001111;
-                if daisanein_confirmed && matches_tu8!(pai.as_u8(), P | F | C)
-                    || daisuushi_confirmed && matches_tu8!(pai.as_u8(), E | S | W | N)
-                {
-                    self.paos[actor as usize] = Some(target);
-                }
-            }
-            _ => (),
+    /// The scores, honba and kyotaku at this point of the kyoku, that is, with
+    /// the riichi sticks accepted so far already taken, but none of the
+    /// `kyoku_deltas` applied.
+    const fn scoreboard(&self) -> Scoreboard {
+        Scoreboard {
+            scores: self.board.scores,
+            honba: self.board.honba,
+            kyotaku: self.board.kyotaku,
+            oya: self.oya,
         }
     }
 
+    fn winner(&self, actor: u8, is_ron: bool, ura_indicators: &[Tile]) -> Result<Winner> {
+        let (point, liable) =
+            self.player_states[actor as usize].agari_points_with_liable(is_ron, ura_indicators)?;
+        Ok(Winner {
+            seat: actor as usize,
+            point,
+            liable: liable.map(usize::from),
+        })
+    }
+
     #[inline]
     fn abortive_ryukyoku(&mut self) {
         let ryukyoku = Event::Ryukyoku {
@@ -599,8 +679,7 @@ impl BoardState {
                     return Ok(Poll::End);
                 }
 
-                if self.kans == 4 && self.player_states.iter().all(|s| s.kans_count() < 4) {
-                    // 四槓散了
+                if is_suukaikan(array::from_fn(|i| self.player_states[i].kans_count())) {
                     self.check_four_kan = true;
                 }
             }
@@ -625,7 +704,6 @@ impl BoardState {
 
                 self.tsumo_actor = actor;
                 self.deal_from_rinshan = Some(());
-                self.kans += 1;
             }
 
             Event::Daiminkan { actor, .. } | Event::Kakan { actor, .. } => {
@@ -644,7 +722,6 @@ impl BoardState {
 
                 self.tsumo_actor = actor;
                 self.deal_from_rinshan = Some(());
-                self.kans += 1;
             }
 
             Event::Reach { actor } => {
@@ -669,11 +746,6 @@ impl BoardState {
             }
         };
 
-        // The pao check cannot be done before the current event (Pon or
-        // Daiminkan) gets processed because it requires to read `.pons()` and
-        // `.minkans()`.
-        self.update_paos(&ev.event);
-
         Ok(Poll::InGame)
     }
 
@@ -782,6 +854,19 @@ impl BoardState {
     }
 }
 
+/// Whether the kyoku should be aborted by 四槓散了, given the number of kans
+/// made by each player.
+///
+/// The variant implemented is the one of tenhou: it holds when there are 4
+/// kans in total made by 2 or more players, and the kyoku is aborted right
+/// after the discard following the 4th kan, unless the discard is ronned. If
+/// all 4 kans belong to one player, the kyoku goes on so that player can aim
+/// for 四槓子, and no one can make a 5th kan.
+#[must_use]
+pub fn is_suukaikan(kans_counts: [usize; 4]) -> bool {
+    kans_counts.iter().sum::<usize>() == 4 && kans_counts.iter().all(|&c| c < 4)
+}
+
 #[rustfmt::skip]
 const UNSHUFFLED: [Tile; 136] = [
     t!(1m),  t!(1m), t!(1m), t!(1m),
@@ -822,3 +907,153 @@ const UNSHUFFLED: [Tile; 136] = [
     t!(F), t!(F), t!(F), t!(F),
     t!(C), t!(C), t!(C), t!(C),
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suukaikan() {
+        assert!(is_suukaikan([3, 1, 0, 0]));
+        assert!(is_suukaikan([0, 1, 0, 3]));
+        assert!(is_suukaikan([2, 0, 2, 0]));
+        assert!(is_suukaikan([1, 1, 1, 1]));
+        assert!(!is_suukaikan([4, 0, 0, 0]));
+        assert!(!is_suukaikan([0, 0, 4, 0]));
+        assert!(!is_suukaikan([3, 0, 0, 0]));
+        assert!(!is_suukaikan([2, 1, 0, 0]));
+        assert!(!is_suukaikan([0; 4]));
+    }
+
+    const HANCHAN: &str = include_str!("data/hanchan.json");
+
+    fn hanchan_events() -> Vec<json::Value> {
+        HANCHAN
+            .lines()
+            .map(|l| json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn replay() {
+        let result = BoardState::replay(&hanchan_events()).unwrap();
+        assert_eq!(result.scores, [19200, 48700, 21900, 10200]);
+        assert_eq!(result.game_log.len(), 8);
+        assert_eq!(result.dump_json_log().unwrap(), HANCHAN);
+    }
+
+    /// A kyoku where the oya pons all three dragons, the last of them from
+    /// player 3, who is then liable (包) for the daisangen, followed by
+    /// `ending`.
+    fn pao_events(ending: &str) -> Vec<json::Value> {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":1,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["P","P","F","F","C","C","1m","2m","3m","4s","5s","6s","9p"],["1p","1p","1p","2p","2p","2p","3p","3p","3p","7m","7m","7m","8m"],["4p","4p","4p","5p","5p","5p","6p","6p","6p","8m","8m","9m","9m"],["1s","1s","1s","2s","2s","2s","3s","3s","3s","7s","7s","7s","8s"]]}
+            {"type":"tsumo","actor":0,"pai":"N"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"P"}
+            {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+            {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+            {"type":"dahai","actor":0,"pai":"9p","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"W"}
+            {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"F"}
+            {"type":"dahai","actor":2,"pai":"F","tsumogiri":true}
+            {"type":"pon","actor":0,"target":2,"pai":"F","consumed":["F","F"]}
+            {"type":"dahai","actor":0,"pai":"6s","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"W"}
+            {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"S"}
+            {"type":"dahai","actor":2,"pai":"S","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"C"}
+            {"type":"dahai","actor":3,"pai":"C","tsumogiri":true}
+            {"type":"pon","actor":0,"target":3,"pai":"C","consumed":["C","C"]}
+            {"type":"dahai","actor":0,"pai":"5s","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"S"}
+            {"type":"dahai","actor":1,"pai":"S","tsumogiri":true}
+        "#;
+        log.lines()
+            .chain(ending.lines())
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| json::from_str(l).unwrap())
+            .collect()
+    }
+
+    fn pao_hora_deltas(ending: &str) -> ([i32; 4], [i32; 4]) {
+        let result = BoardState::replay(&pao_events(ending)).unwrap();
+        let deltas = result.game_log[0]
+            .iter()
+            .find_map(|ev| match ev.event {
+                Event::Hora { deltas, .. } => deltas,
+                _ => None,
+            })
+            .unwrap();
+        (deltas, result.scores)
+    }
+
+    #[test]
+    fn pao_tsumo() {
+        // Player 3 pays the whole yakuman and the honba.
+        let (deltas, scores) = pao_hora_deltas(
+            r#"
+            {"type":"tsumo","actor":2,"pai":"E"}
+            {"type":"dahai","actor":2,"pai":"E","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"E"}
+            {"type":"dahai","actor":3,"pai":"E","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"4s"}
+            {"type":"hora","actor":0,"target":0}
+            {"type":"end_kyoku"}
+            "#,
+        );
+        assert_eq!(deltas, [48300, 0, 0, -48300]);
+        assert_eq!(scores, [73300, 25000, 25000, -23300]);
+    }
+
+    #[test]
+    fn pao_ron() {
+        // Player 3 and the discarder split the yakuman, and player 3 pays the
+        // honba.
+        let (deltas, _) = pao_hora_deltas(
+            r#"
+            {"type":"tsumo","actor":2,"pai":"4s"}
+            {"type":"dahai","actor":2,"pai":"4s","tsumogiri":true}
+            {"type":"hora","actor":0,"target":2}
+            {"type":"end_kyoku"}
+            "#,
+        );
+        assert_eq!(deltas, [48300, 0, -24000, -24300]);
+
+        // Dealt in by the liable player, who pays it all.
+        let (deltas, _) = pao_hora_deltas(
+            r#"
+            {"type":"tsumo","actor":2,"pai":"E"}
+            {"type":"dahai","actor":2,"pai":"E","tsumogiri":true}
+            {"type":"tsumo","actor":3,"pai":"4s"}
+            {"type":"dahai","actor":3,"pai":"4s","tsumogiri":true}
+            {"type":"hora","actor":0,"target":3}
+            {"type":"end_kyoku"}
+            "#,
+        );
+        assert_eq!(deltas, [48300, 0, 0, -48300]);
+    }
+
+    #[test]
+    fn replay_invalid() {
+        // The first discard of seat 0 is replaced with a tile not in hand.
+        let mut events = hanchan_events();
+        assert_eq!(events[3]["type"], "dahai");
+        events[3]["pai"] = "1s".into();
+        let err = BoardState::replay(&events).unwrap_err();
+        assert!(format!("{err:?}").contains("at event 3"), "{err:?}");
+
+        // The deltas of the first hora are off by 100.
+        let mut events = hanchan_events();
+        let idx = events.iter().position(|ev| ev["type"] == "hora").unwrap();
+        events[idx]["deltas"][3] = (-5300).into();
+        let err = BoardState::replay(&events).unwrap_err();
+        assert!(
+            format!("{err:?}").contains(&format!("at event {idx}")),
+            "{err:?}"
+        );
+    }
+}
@@ -2,6 +2,8 @@ use super::board::{Board, BoardState, Poll};
 use super::result::GameResult;
 use crate::agent::BatchAgent;
 use crate::mjai::EventExt;
+use crate::tile::AkaConfig;
+use crate::wall::Wall;
 use std::time::Duration;
 use std::{array, mem};
 
@@ -14,6 +16,9 @@ pub struct BatchGame {
     pub length: u8,
     pub init_scores: [i32; 4],
     pub disable_progress_bar: bool,
+    /// Lay out every kyoku with `Wall::for_kyoku(seed.0, ..)`, so that a game
+    /// can be replayed from a single `u64`. `seed.1` is ignored.
+    pub wall_seed: bool,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -28,6 +33,9 @@ pub struct Index {
 struct Game {
     length: u8,
     seed: (u64, u64),
+    /// Lay out every kyoku with `Wall::for_kyoku(seed.0, ..)` instead of
+    /// `Board::init_from_seed`.
+    from_wall: bool,
     indexes: [Index; 4],
 
     oracle_obs_versions: [Option<u32>; 4],
@@ -82,7 +90,13 @@ impl Game {
                 scores: self.scores,
                 ..Default::default()
             };
-            next_board.init_from_seed(self.seed);
+            if self.from_wall {
+                let wall =
+                    Wall::for_kyoku(self.seed.0, self.kyoku, self.honba, AkaConfig::STANDARD);
+                next_board.init_from_wall(&wall);
+            } else {
+                next_board.init_from_seed(self.seed);
+            }
             self.board = next_board.into_state();
             self.kyoku_started = true;
         }
@@ -224,6 +238,7 @@ impl BatchGame {
             length: 8,
             init_scores: [25000; 4],
             disable_progress_bar,
+            wall_seed: false,
         }
     }
 
@@ -256,6 +271,7 @@ impl BatchGame {
                 let game = Box::new(Game {
                     length: self.length,
                     seed,
+                    from_wall: self.wall_seed,
                     indexes: *idxs,
                     scores: self.init_scores,
                     oracle_obs_versions,
@@ -370,4 +386,27 @@ mod test {
         g.run(&mut agents, indexes, &[(1009, 0), (1021, 0)])
             .unwrap();
     }
+
+    #[test]
+    fn replay_from_wall_seed() {
+        let g = BatchGame {
+            wall_seed: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i,
+        })];
+        let play = || {
+            let mut agents = [Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap()) as _];
+            let mut results = g.run(&mut agents, indexes, &[(42, 0)]).unwrap();
+            results.pop().unwrap()
+        };
+
+        let a = play();
+        let b = play();
+        assert_eq!(a.seed, (42, 0));
+        assert_eq!(a.scores, b.scores);
+        assert_eq!(a.dump_json_log().unwrap(), b.dump_json_log().unwrap());
+    }
 }
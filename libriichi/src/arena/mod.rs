@@ -1,18 +1,27 @@
 mod board;
 mod game;
+#[cfg(feature = "python")]
 mod one_vs_three;
 mod result;
+#[cfg(feature = "python")]
 mod two_vs_two;
 
-pub use board::Board;
+pub mod selfplay;
+
+pub use board::{Board, BoardState, Poll};
 pub use result::GameResult;
 
+#[cfg(feature = "python")]
 use crate::py_helper::add_submodule;
+#[cfg(feature = "python")]
 use one_vs_three::OneVsThree;
+#[cfg(feature = "python")]
 use two_vs_two::TwoVsTwo;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+#[cfg(feature = "python")]
 pub(crate) fn register_module(
     py: Python<'_>,
     prefix: &str,
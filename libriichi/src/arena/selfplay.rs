@@ -0,0 +1,71 @@
+//! Self-play of a single `Agent` against itself.
+
+use super::game::{BatchGame, Index};
+use crate::agent::{Agent, BatchAgent, BatchifiedAgent};
+use std::array;
+
+use anyhow::{Context, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Plays `n_games` hanchans, in parallel under the `parallel` feature, with
+/// `new_agent(player_id)` at every seat and returns the mjai log of each
+/// game, in order. Agents are built per seat rather than cloned because most
+/// of them, like `Tsumogiri`, need to know their player ID.
+///
+/// Game `i` lays out its walls with
+/// `Wall::for_kyoku(seed.wrapping_add(i as u64), ..)`, so the same `seed`
+/// yields identical logs as long as the agent is deterministic, regardless
+/// of the number of threads. The logs can be written to disk as is.
+pub fn run<A, F>(n_games: usize, seed: u64, new_agent: F) -> Result<Vec<String>>
+where
+    A: Agent + 'static,
+    F: Fn(u8) -> Result<A> + Sync,
+{
+    let batch_game = BatchGame {
+        wall_seed: true,
+        ..BatchGame::tenhou_hanchan(true)
+    };
+    let indexes = [array::from_fn(|i| Index {
+        agent_idx: 0,
+        player_id_idx: i,
+    })];
+
+    #[cfg(feature = "parallel")]
+    let games = (0..n_games).into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let games = 0..n_games;
+
+    games
+        .map(|i| {
+            let agent = BatchifiedAgent::new(&new_agent, &[0, 1, 2, 3])?;
+            let mut agents = [Box::new(agent) as Box<dyn BatchAgent>];
+            let game_seed = (seed.wrapping_add(i as u64), 0);
+            let mut results = batch_game.run(&mut agents, &indexes, &[game_seed])?;
+            results
+                .pop()
+                .with_context(|| format!("game {i} returned no result"))?
+                .dump_json_log()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Tsumogiri;
+
+    #[test]
+    fn deterministic() {
+        let play = || run(2, 1009, |id| Ok(Tsumogiri(id))).unwrap();
+
+        let logs = play();
+        assert_eq!(logs.len(), 2);
+        assert!(
+            logs.iter()
+                .all(|log| log.starts_with(r#"{"type":"start_game""#))
+        );
+        assert_ne!(logs[0], logs[1]);
+        assert_eq!(logs, play());
+    }
+}
@@ -0,0 +1,70 @@
+//! Batched queries over many independent player states, parallelized with
+//! rayon under the `parallel` feature, and run sequentially otherwise.
+
+use crate::state::PlayerState;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// `PlayerState::discard_candidates_with_unconditional_tenpai` over `states`,
+/// in the same order.
+///
+/// Panics if any of the states is not at 3n+2.
+#[must_use]
+pub fn unconditional_tenpai(states: &[PlayerState]) -> Vec<[bool; 34]> {
+    #[cfg(feature = "parallel")]
+    let iter = states.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let iter = states.iter();
+
+    iter.map(PlayerState::discard_candidates_with_unconditional_tenpai)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mjai::Event;
+    use crate::t;
+    use crate::wall::Wall;
+
+    #[test]
+    fn matches_per_hand() {
+        // Only first draws at most 1 shanten away, since anything further is
+        // trivially all false.
+        let states: Vec<_> = (0..)
+            .map(|seed| {
+                let wall = Wall::from_seed(seed);
+                let mut tehais = [[t!(?); 13]; 4];
+                tehais[0] = wall.haipai(0)[0];
+                let mut ps = PlayerState::new(0);
+                ps.update(&Event::StartKyoku {
+                    bakaze: t!(E),
+                    dora_marker: wall.dora_indicators()[0],
+                    kyoku: 1,
+                    honba: 0,
+                    kyotaku: 0,
+                    oya: 0,
+                    scores: [25000; 4],
+                    tehais,
+                })
+                .unwrap();
+                ps.update(&Event::Tsumo {
+                    actor: 0,
+                    pai: wall.live_wall()[0],
+                })
+                .unwrap();
+                ps
+            })
+            .filter(|ps| ps.shanten() <= 1)
+            .take(50)
+            .collect();
+
+        let actual = unconditional_tenpai(&states);
+        assert_eq!(actual.len(), states.len());
+        assert!(actual.iter().any(|r| r.contains(&true)));
+        for (ps, r) in states.iter().zip(&actual) {
+            assert_eq!(*r, ps.discard_candidates_with_unconditional_tenpai());
+        }
+    }
+}
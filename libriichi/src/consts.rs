@@ -1,5 +1,7 @@
+#[cfg(feature = "python")]
 use crate::py_helper::add_submodule;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 pub const MAX_VERSION: u32 = 4;
@@ -13,9 +15,10 @@ pub const ACTION_SPACE: usize = 37 // discard | kan (choice)
                               + 1  // ryukyoku
                               + 1; // pass
 // = 46
+#[cfg(feature = "python")]
 pub const GRP_SIZE: usize = 7;
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 #[inline]
 pub const fn obs_shape(version: u32) -> (usize, usize) {
     match version {
@@ -27,7 +30,7 @@ pub const fn obs_shape(version: u32) -> (usize, usize) {
     }
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 #[inline]
 pub const fn oracle_obs_shape(version: u32) -> (usize, usize) {
     match version {
@@ -37,6 +40,7 @@ pub const fn oracle_obs_shape(version: u32) -> (usize, usize) {
     }
 }
 
+#[cfg(feature = "python")]
 pub(crate) fn register_module(
     py: Python<'_>,
     prefix: &str,
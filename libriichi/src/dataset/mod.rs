@@ -4,13 +4,16 @@ mod gameplay;
 mod grp;
 mod invisible;
 
+#[cfg(feature = "python")]
 use crate::py_helper::add_submodule;
 pub use gameplay::{Gameplay, GameplayLoader};
 pub use grp::Grp;
 pub use invisible::Invisible;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+#[cfg(feature = "python")]
 pub(crate) fn register_module(
     py: Python<'_>,
     prefix: &str,
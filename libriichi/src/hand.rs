@@ -93,8 +93,25 @@ pub fn tile34_to_vec(tiles: &[u8; 34]) -> Vec<Tile> {
     ret
 }
 
+/// `aka` marks, for each suit, whether one of its fives is an aka.
 #[must_use]
 pub fn tiles_to_string(tiles: &[u8; 34], aka: [bool; 3]) -> String {
+    tiles_to_string_inner(tiles, aka.map(u8::from))
+}
+
+/// The inverse of `hand_with_aka`, with akas as "0".
+#[must_use]
+pub fn tiles37_to_string(tiles: &[u8; 37]) -> String {
+    let mut tiles34 = [0; 34];
+    tiles34.copy_from_slice(&tiles[..34]);
+    tiles34[tuz!(5m)] += tiles[tuz!(5mr)];
+    tiles34[tuz!(5p)] += tiles[tuz!(5pr)];
+    tiles34[tuz!(5s)] += tiles[tuz!(5sr)];
+    tiles_to_string_inner(&tiles34, [tiles[34], tiles[35], tiles[36]])
+}
+
+/// `akas` are the numbers of akas among the fives in `tiles` of each suit.
+fn tiles_to_string_inner(tiles: &[u8; 34], akas: [u8; 3]) -> String {
     let suhai = tiles[..3 * 9]
         .chunks_exact(9)
         .enumerate()
@@ -107,9 +124,10 @@ pub fn tiles_to_string(tiles: &[u8; 34], aka: [bool; 3]) -> String {
                 .filter(|&(_, &count)| count > 0)
                 .for_each(|(num, &count)| {
                     let literal_num = num + 1;
-                    if literal_num == 5 && aka[kind] {
-                        partial.push('0');
-                        partial += &literal_num.to_string().repeat(count as usize - 1);
+                    if literal_num == 5 {
+                        let num_akas = akas[kind].min(count) as usize;
+                        partial += &"0".repeat(num_akas);
+                        partial += &"5".repeat(count as usize - num_akas);
                     } else {
                         partial += &literal_num.to_string().repeat(count as usize);
                     }
@@ -140,6 +158,8 @@ pub fn tiles_to_string(tiles: &[u8; 34], aka: [bool; 3]) -> String {
 
     if jihai.is_empty() {
         suhai
+    } else if suhai.is_empty() {
+        format!("{jihai}z")
     } else {
         format!("{suhai} {jihai}z")
     }
@@ -197,5 +217,15 @@ mod test {
             ),
             "33067m 345678p 678s"
         );
+
+        for s in [
+            "123m 456p 789s 11z",
+            "00555m 0p 05s 1234567z",
+            "19m 19p 19s 1234567z",
+            "1112z",
+            "22334405m 234p 234s",
+        ] {
+            assert_eq!(tiles37_to_string(&hand_with_aka(s).unwrap()), s);
+        }
     }
 }
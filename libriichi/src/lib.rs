@@ -93,29 +93,39 @@
     clippy::useless_let_if_seq
 )]
 
-mod arena;
 mod array;
 mod consts;
+#[cfg(feature = "python")]
 mod dataset;
 mod macros;
+#[cfg(feature = "python")]
 mod py_helper;
 mod rankings;
 mod vec_ops;
 
 // pub for bins
+pub mod batch;
 pub mod chi_type;
 pub mod mjai;
+#[cfg(feature = "python")]
 pub mod stat;
 pub mod state;
+pub mod tenhou;
+pub mod wall;
 
 // pub for non-cfg(test) tests
 pub mod agent;
+pub mod arena;
 pub mod tile;
 
 // pub for benchmarks
 pub mod algo;
 pub mod hand;
 
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 #[cfg(feature = "mimalloc")]
@@ -133,6 +143,7 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 /// - Definitions of observation and action space for Mortal (via `consts`).
 /// - Statistical works on mjai logs (via `stat.Stat`).
 /// - mjai interface (via `mjai.Bot`).
+#[cfg(feature = "python")]
 #[pymodule]
 fn libriichi(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     pyo3_log::init();
@@ -1,13 +1,20 @@
+#[cfg(feature = "python")]
 mod bot;
 mod event;
+mod reader;
 
 pub use event::{Event, EventExt, EventWithCanAct, Metadata, OutOfBoundError};
+pub use reader::EventReader;
 
+#[cfg(feature = "python")]
 use crate::py_helper::add_submodule;
+#[cfg(feature = "python")]
 use bot::Bot;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+#[cfg(feature = "python")]
 pub(crate) fn register_module(
     py: Python<'_>,
     prefix: &str,
@@ -0,0 +1,122 @@
+use super::Event;
+use std::io::BufRead;
+
+use anyhow::{Context, Result};
+use serde_json as json;
+
+/// Lazily parses a stream of newline-delimited mjai events into `Event`s,
+/// without going through `serde_json::Value` and with a single line buffer
+/// reused across lines. Blank lines are skipped.
+///
+/// The iteration stops at EOF, and yields an error for an I/O failure or a
+/// line that is not a valid event, after which it can still be resumed from
+/// the next line.
+pub struct EventReader<R> {
+    reader: R,
+    buf: String,
+    line_no: usize,
+}
+
+impl<R: BufRead> EventReader<R> {
+    pub const fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: String::new(),
+            line_no: 0,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead> Iterator for EventReader<R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            self.line_no += 1;
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(err) => {
+                    let ret =
+                        Err(err).with_context(|| format!("failed to read line {}", self.line_no));
+                    return Some(ret);
+                }
+            }
+
+            let line = self.buf.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let ret = json::from_str(line)
+                .with_context(|| format!("failed to parse event at line {}: {line}", self.line_no));
+            return Some(ret);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_stream() {
+        let stream = r#"
+            {"type":"start_game","names":["Equim","Mortal","akochan","NoName"],"seed":[123,456]}
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
+            {"type":"tsumo","actor":0,"pai":"1m"}
+
+            {"type":"dahai","actor":0,"pai":"2m","tsumogiri":false}
+            {"type":"pon","actor":2,"target":0,"pai":"2m","consumed":["2m","2m"]}
+            {"type":"reach","actor":3}
+            not an event
+            {"type":"hora","actor":1,"target":0,"deltas":[-8000,8000,0,0]}
+            {"type":"end_kyoku"}
+        "#;
+        let mut reader = EventReader::new(Cursor::new(stream));
+
+        assert!(matches!(reader.next(), Some(Ok(Event::StartGame { .. }))));
+        assert!(matches!(reader.next(), Some(Ok(Event::StartKyoku { .. }))));
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Event::Tsumo { actor: 0, .. }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Event::Dahai {
+                actor: 0,
+                tsumogiri: false,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Event::Pon {
+                actor: 2,
+                target: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(reader.next(), Some(Ok(Event::Reach { actor: 3 }))));
+
+        let err = reader.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("line 9"));
+
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Event::Hora {
+                actor: 1,
+                target: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(reader.next(), Some(Ok(Event::EndKyoku))));
+        assert!(reader.next().is_none());
+    }
+}
@@ -5,72 +5,88 @@ use crate::tile::Tile;
 use crate::tuz;
 
 use anyhow::{Result, bail, ensure};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use serde::Serialize;
 
-#[pyclass]
-#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct ActionCandidate {
-    #[pyo3(get)]
     pub can_discard: bool,
-    #[pyo3(get)]
     pub can_chi_low: bool,
-    #[pyo3(get)]
     pub can_chi_mid: bool,
-    #[pyo3(get)]
     pub can_chi_high: bool,
-    #[pyo3(get)]
     pub can_pon: bool,
-    #[pyo3(get)]
     pub can_daiminkan: bool,
-    #[pyo3(get)]
     pub can_kakan: bool,
-    #[pyo3(get)]
     pub can_ankan: bool,
-    #[pyo3(get)]
     pub can_riichi: bool,
-    #[pyo3(get)]
     pub can_tsumo_agari: bool,
-    #[pyo3(get)]
     pub can_ron_agari: bool,
-    #[pyo3(get)]
     pub can_ryukyoku: bool,
 
-    #[pyo3(get)]
     pub target_actor: u8,
 }
 
-#[pymethods]
+/// All the legal actions of a player at once, with the tiles involved, see
+/// `PlayerState::legal_actions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegalActions {
+    /// Tiles that can be discarded, where akas are distinguished. All false
+    /// if the player can't discard.
+    pub discards: [bool; 37],
+    pub can_riichi: bool,
+    /// Tiles to consume for each possible chi.
+    pub chis: Vec<[Tile; 2]>,
+    /// Tiles to consume for pon.
+    pub pon: Option<[Tile; 2]>,
+    /// The tile to daiminkan, deaka'd.
+    pub daiminkan: Option<Tile>,
+    /// Deaka'd.
+    pub ankans: Vec<Tile>,
+    /// Deaka'd.
+    pub kakans: Vec<Tile>,
+    pub can_ron_agari: bool,
+    pub can_tsumo_agari: bool,
+    /// 九種九牌
+    pub can_ryukyoku: bool,
+    /// The absolute seat of the discarder and the discarded tile, if they can
+    /// be called or ronned.
+    pub target: Option<(u8, Tile)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KanType {
+    Daiminkan,
+    Ankan,
+    Kakan,
+}
+
 impl ActionCandidate {
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn can_chi(&self) -> bool {
         self.can_chi_low || self.can_chi_mid || self.can_chi_high
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn can_kan(&self) -> bool {
         self.can_daiminkan || self.can_kakan || self.can_ankan
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn can_agari(&self) -> bool {
         self.can_tsumo_agari || self.can_ron_agari
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn can_pass(&self) -> bool {
         self.can_chi() || self.can_pon || self.can_daiminkan || self.can_ron_agari
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn can_act(&self) -> bool {
@@ -82,6 +98,31 @@ impl ActionCandidate {
             || self.can_agari()
             || self.can_ryukyoku
     }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ActionCandidate {
+    #[getter(can_chi)]
+    const fn can_chi_py(&self) -> bool {
+        self.can_chi()
+    }
+    #[getter(can_kan)]
+    const fn can_kan_py(&self) -> bool {
+        self.can_kan()
+    }
+    #[getter(can_agari)]
+    const fn can_agari_py(&self) -> bool {
+        self.can_agari()
+    }
+    #[getter(can_pass)]
+    const fn can_pass_py(&self) -> bool {
+        self.can_pass()
+    }
+    #[getter(can_act)]
+    const fn can_act_py(&self) -> bool {
+        self.can_act()
+    }
 
     fn __repr__(&self) -> String {
         format!("{self:?}")
@@ -210,6 +251,47 @@ impl PlayerState {
         Ok(())
     }
 
+    /// Collects `last_cans` together with the tiles of each action.
+    #[must_use]
+    pub fn legal_actions(&self) -> LegalActions {
+        let cans = self.last_cans;
+
+        let discards = if cans.can_discard {
+            self.discard_candidates_aka()
+        } else {
+            [false; 37]
+        };
+        let mut daiminkan = None;
+        let mut ankans = vec![];
+        let mut kakans = vec![];
+        for (kan_type, tile) in self.kan_candidates() {
+            match kan_type {
+                KanType::Daiminkan => daiminkan = Some(tile),
+                KanType::Ankan => ankans.push(tile),
+                KanType::Kakan => kakans.push(tile),
+            }
+        }
+        let target = if cans.can_pass() {
+            self.last_kawa_tile.map(|pai| (cans.target_actor, pai))
+        } else {
+            None
+        };
+
+        LegalActions {
+            discards,
+            can_riichi: cans.can_riichi,
+            chis: self.chi_candidates(),
+            pon: self.pon_candidates(),
+            daiminkan,
+            ankans,
+            kakans,
+            can_ron_agari: cans.can_ron_agari,
+            can_tsumo_agari: cans.can_tsumo_agari,
+            can_ryukyoku: cans.can_ryukyoku,
+            target,
+        }
+    }
+
     fn ensure_tiles_in_hand(&self, tiles: &[Tile]) -> Result<()> {
         for &tile in tiles {
             ensure!(
@@ -218,7 +300,7 @@ impl PlayerState {
             );
             if tile.is_aka() {
                 ensure!(
-                    self.akas_in_hand[tile.as_usize() - tuz!(5mr)],
+                    self.akas_in_hand[tile.as_usize() - tuz!(5mr)] > 0,
                     "{tile} is not in hand",
                 );
             }
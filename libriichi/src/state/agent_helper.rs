@@ -1,14 +1,21 @@
-use super::{PlayerState, SinglePlayerTables};
-use crate::algo::agari::AgariCalculator;
-use crate::algo::point::Point;
+use super::{
+    DiscardExplanation, DoraDetail, KanType, KanUraRule, PlayerState, PushFoldAdvice,
+    PushFoldCandidate, RenchanRule, RenhouRule, SinglePlayerOptions, SinglePlayerTables, SuitRead,
+    WaitKind, WaitShape,
+};
+use crate::algo::agari::{self, AgariCalculator};
+use crate::algo::point::{self, Point};
+use crate::algo::scoreboard::{Scoreboard, Winner};
 use crate::algo::shanten;
-use crate::algo::sp::{InitState, SPCalculator};
+use crate::algo::sp::{self, Candidate, InitState, SPCalculator};
+use crate::mjai::Event;
 use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
 use crate::{must_tile, t, tu8, tuz};
+use std::{array, iter};
 
 use anyhow::{Context, Result, ensure};
-use tinyvec::array_vec;
+use tinyvec::{ArrayVec, array_vec};
 
 impl PlayerState {
     /// Used by `BoardState` to check if a player is making 4 kans on his own.
@@ -62,20 +69,40 @@ impl PlayerState {
             };
         }
 
-        if ret[tuz!(5m)] && self.akas_in_hand[0] {
-            ret[tuz!(5mr)] = true;
-            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > 1;
-        }
-        if ret[tuz!(5p)] && self.akas_in_hand[1] {
-            ret[tuz!(5pr)] = true;
-            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > 1;
+        self.split_akas(&mut ret);
+        ret
+    }
+
+    /// The discards that a riichi can be declared with, which are the ones
+    /// that keep the hand tenpai. All false if riichi is not legal.
+    #[must_use]
+    pub const fn riichi_declare_candidates(&self) -> [bool; 34] {
+        if !self.last_cans.can_riichi {
+            return [false; 34];
         }
-        if ret[tuz!(5s)] && self.akas_in_hand[2] {
-            ret[tuz!(5sr)] = true;
-            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > 1;
+        if self.shanten == 1 {
+            self.next_shanten_discards
+        } else {
+            self.keep_shanten_discards
         }
+    }
 
-        ret
+    /// Marks the aka variant of each 5 in `candidates` if there is any in
+    /// hand, and keeps the normal one only if not all of them are aka.
+    fn split_akas(&self, candidates: &mut [bool; 37]) {
+        for (i, (five, aka)) in [
+            (tuz!(5m), tuz!(5mr)),
+            (tuz!(5p), tuz!(5pr)),
+            (tuz!(5s), tuz!(5sr)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if candidates[five] && self.akas_in_hand[i] > 0 {
+                candidates[aka] = true;
+                candidates[five] = self.tehai[five] > self.akas_in_hand[i];
+            }
+        }
     }
 
     /// Must be called at 3n+2.
@@ -132,24 +159,14 @@ impl PlayerState {
             self.keep_shanten_discards
         };
 
-        // Replace and test
         tenpai_discards
             .iter()
             .copied()
             .enumerate()
             .filter(|&(tid, b)| b && !self.forbidden_tiles[tid])
             .for_each(|(discard, _)| {
-                let mut tehai_3n1 = self.tehai;
-                tehai_3n1[discard] -= 1;
-
-                for (tsumo, seen) in self.tiles_seen.iter().copied().enumerate() {
-                    if tsumo == discard || tehai_3n1[tsumo] == 4 {
-                        continue;
-                    }
-
-                    let mut tehai_3n2 = tehai_3n1;
-                    tehai_3n2[tsumo] += 1;
-                    if shanten::calc_all(&tehai_3n2, self.tehai_len_div3) > -1 {
+                for (tsumo, tehai_3n2, shanten) in self.replace_and_test(discard) {
+                    if tsumo == discard || shanten > -1 {
                         continue;
                     }
 
@@ -160,51 +177,325 @@ impl PlayerState {
                     }
 
                     // Must be placed after the furiten check above
-                    if seen == 4 || ret[discard] {
+                    if self.tiles_seen[tsumo] == 4 || ret[discard] {
                         continue;
                     }
 
-                    let agari_calc = AgariCalculator {
-                        tehai: &tehai_3n2,
-                        is_menzen: self.is_menzen,
-                        chis: &self.chis,
-                        pons: &self.pons,
-                        minkans: &self.minkans,
-                        ankans: &self.ankans,
-                        bakaze: self.bakaze.as_u8(),
-                        jikaze: self.jikaze.as_u8(),
-                        winning_tile: tsumo as u8,
-                        is_ron: true,
-                    };
-                    ret[discard] = agari_calc.has_yaku();
+                    ret[discard] = self.has_yaku_on(&tehai_3n2, tsumo, true);
                 }
             });
 
-        if ret[tuz!(5m)] && self.akas_in_hand[0] {
-            ret[tuz!(5mr)] = true;
-            ret[tuz!(5m)] = self.tehai[tuz!(5m)] > 1;
-        }
-        if ret[tuz!(5p)] && self.akas_in_hand[1] {
-            ret[tuz!(5pr)] = true;
-            ret[tuz!(5p)] = self.tehai[tuz!(5p)] > 1;
+        self.split_akas(&mut ret);
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// The legal discards after which the hand is tenpai with at least one
+    /// live wait that can be ronned with a yaku, so that a dama hand can avoid
+    /// discarding into a yakuless wait. Riichi counts as a yaku once declared,
+    /// and a tenpai that is furiten on our discards does not count.
+    #[must_use]
+    pub fn discard_candidates_preserving_yaku(&self) -> [bool; 34] {
+        let legal = self.discard_candidates();
+
+        let mut ret = [false; 34];
+        if self.shanten > 1 // impossible to discard-to-tenpai
+            || self.shanten == 1 && !self.has_next_shanten_discard
+            || self.riichi_accepted[0] && self.at_furiten
+        {
+            return ret;
         }
-        if ret[tuz!(5s)] && self.akas_in_hand[2] {
-            ret[tuz!(5sr)] = true;
-            ret[tuz!(5s)] = self.tehai[tuz!(5s)] > 1;
+
+        for discard in (0..34).filter(|&t| legal[t]) {
+            for (tsumo, tehai_3n2, shanten) in self.replace_and_test(discard) {
+                if shanten > -1 {
+                    continue;
+                }
+
+                // Furiten
+                if tsumo == discard || self.discarded_tiles[tsumo] {
+                    ret[discard] = false;
+                    break;
+                }
+
+                if self.tiles_seen[tsumo] == 4 || ret[discard] {
+                    continue;
+                }
+
+                ret[discard] = self.riichi_declared[0] || self.has_yaku_on(&tehai_3n2, tsumo, true);
+            }
         }
+        ret
+    }
 
+    /// Must be called at 3n+1.
+    ///
+    /// Marks the waits of the hand that we can ron on, i.e. those with a yaku,
+    /// or all of them after riichi, and none of them when furiten. A tsumo on
+    /// an unmarked wait may still be a win by 門前清自摸和 or a yaku that only
+    /// a tsumo makes.
+    #[must_use]
+    pub fn yaku_bearing_waits(&self) -> [bool; 34] {
+        assert!(!self.last_cans.can_discard, "tehai is not 3n+1");
+
+        let mut ret = [false; 34];
+        if self.at_furiten {
+            return ret;
+        }
+        for (tid, &is_wait) in self.waits.iter().enumerate() {
+            if !is_wait {
+                continue;
+            }
+            let mut tehai = self.tehai;
+            tehai[tid] += 1;
+            ret[tid] = self.riichi_accepted[0] || self.has_yaku_on(&tehai, tid, true);
+        }
         ret
     }
 
+    /// Must be called at 3n+1.
+    ///
+    /// The probability that our very next draw is a tsumo agari, i.e. the
+    /// number of unseen copies of the waits we can tsumo on over the number
+    /// of all unseen tiles, according to `tiles_seen`. Furiten does not
+    /// matter for a tsumo, and a closed hand can always tsumo on its waits
+    /// with 門前清自摸和. It is 0 when not tenpai or when there is no draw
+    /// left.
+    ///
+    /// It ignores ron chances and any later draw, see `single_player_tables`
+    /// for those.
+    #[must_use]
+    pub fn next_draw_win_prob(&self) -> f32 {
+        assert!(!self.last_cans.can_discard, "tehai is not 3n+1");
+
+        if self.shanten > 0 || self.tiles_left == 0 {
+            return 0.;
+        }
+        let unseen: u32 = self.tiles_seen.iter().map(|&c| 4 - c as u32).sum();
+        if unseen == 0 {
+            return 0.;
+        }
+        let live: u32 = self
+            .waits
+            .iter()
+            .enumerate()
+            .filter(|&(tid, &is_wait)| {
+                if !is_wait {
+                    return false;
+                }
+                if self.is_menzen || self.riichi_accepted[0] {
+                    return true;
+                }
+                let mut tehai = self.tehai;
+                tehai[tid] += 1;
+                self.has_yaku_on(&tehai, tid, false)
+            })
+            .map(|(tid, _)| 4 - self.tiles_seen[tid] as u32)
+            .sum();
+        live as f32 / unseen as f32
+    }
+
+    /// Whether the 3n+2 `tehai` won on `winning_tile` has a pattern-based yaku.
+    /// Without `self.atozuke`, every other wait of the hand must have one as
+    /// well.
+    pub(super) fn has_yaku_on(&self, tehai: &[u8; 34], winning_tile: usize, is_ron: bool) -> bool {
+        let has_yaku = |tehai: &[u8; 34], winning_tile: usize| {
+            AgariCalculator {
+                tehai,
+                is_menzen: self.is_menzen,
+                chis: &self.chis,
+                pons: &self.pons,
+                minkans: &self.minkans,
+                ankans: &self.ankans,
+                bakaze: self.bakaze.as_u8(),
+                jikaze: self.jikaze.as_u8(),
+                winning_tile: winning_tile as u8,
+                is_ron,
+                local_yaku: self.local_yaku,
+            }
+            .has_yaku()
+        };
+        if !has_yaku(tehai, winning_tile) {
+            return false;
+        }
+        if self.atozuke {
+            return true;
+        }
+
+        let mut tehai_3n1 = *tehai;
+        tehai_3n1[winning_tile] -= 1;
+        (0..34)
+            .filter(|&t| t != winning_tile && tehai_3n1[t] < 4)
+            .all(|t| {
+                let mut tehai_3n2 = tehai_3n1;
+                tehai_3n2[t] += 1;
+                shanten::calc_all_cached(&tehai_3n2, self.tehai_len_div3) != -1
+                    || has_yaku(&tehai_3n2, t)
+            })
+    }
+
+    /// Must be called at 3n+2 with a tile in hand.
+    ///
+    /// Returns the tiles that decrease the shanten after `discard` is
+    /// discarded (ukeire), excluding those with no copy left to draw, and the
+    /// total number of copies left of them, according to `tiles_seen`.
+    ///
+    /// Unlike `discard_candidates_with_unconditional_tenpai`, the shanten can
+    /// be anything, and yaku and furiten are not taken into account.
+    #[must_use]
+    pub fn ukeire_after_discard(&self, discard: Tile) -> ([bool; 34], u8) {
+        assert!(self.last_cans.can_discard, "tehai is not 3n+2");
+        let discard = discard.deaka().as_usize();
+        assert!(self.tehai[discard] > 0, "discard is not in hand");
+
+        let mut tehai_3n1 = self.tehai;
+        tehai_3n1[discard] -= 1;
+        let shanten_3n1 = shanten::calc_all(&tehai_3n1, self.tehai_len_div3);
+
+        let mut tiles = [false; 34];
+        let mut count = 0;
+        for (tsumo, _, shanten) in self.replace_and_test(discard) {
+            let left = 4 - self.tiles_seen[tsumo];
+            if shanten < shanten_3n1 && left > 0 {
+                tiles[tsumo] = true;
+                count += left;
+            }
+        }
+        (tiles, count)
+    }
+
+    /// Must be called at 3n+1.
+    ///
+    /// Returns the tiles that decrease the current shanten (ukeire),
+    /// excluding those with no copy left to draw, and the total number of
+    /// copies left of them, according to `tiles_seen`. The shanten can be
+    /// anything, and yaku and furiten are not taken into account.
+    #[must_use]
+    pub fn acceptance_at_current_shanten(&self) -> ([bool; 34], u8) {
+        assert!(!self.last_cans.can_discard, "tehai is not 3n+1");
+        let shanten_3n1 = shanten::calc_all(&self.tehai, self.tehai_len_div3);
+
+        let mut tiles = [false; 34];
+        let mut count = 0;
+        for tsumo in (0..34).filter(|&t| self.tehai[t] < 4) {
+            let left = 4 - self.tiles_seen[tsumo];
+            if left == 0 {
+                continue;
+            }
+            let mut tehai_3n2 = self.tehai;
+            tehai_3n2[tsumo] += 1;
+            if shanten::calc_all_cached(&tehai_3n2, self.tehai_len_div3) < shanten_3n1 {
+                tiles[tsumo] = true;
+                count += left;
+            }
+        }
+        (tiles, count)
+    }
+
+    /// Discards `discard` and then draws every tile that is not all in hand,
+    /// yielding the drawn tile, the resulting 3n+2 tehai and its shanten.
+    fn replace_and_test(&self, discard: usize) -> impl Iterator<Item = (usize, [u8; 34], i8)> + '_ {
+        let mut tehai_3n1 = self.tehai;
+        tehai_3n1[discard] -= 1;
+
+        (0..34)
+            .filter(move |&tsumo| tehai_3n1[tsumo] < 4)
+            .map(move |tsumo| {
+                let mut tehai_3n2 = tehai_3n1;
+                tehai_3n2[tsumo] += 1;
+                let shanten = shanten::calc_all_cached(&tehai_3n2, self.tehai_len_div3);
+                (tsumo, tehai_3n2, shanten)
+            })
+    }
+
     #[inline]
     #[must_use]
     pub fn yaokyuu_kind_count(&self) -> u8 {
-        tuz![1m, 9m, 1p, 9p, 1s, 9s, E, S, W, N, P, F, C]
+        self.tehai
             .iter()
-            .map(|&i| self.tehai[i].min(1))
+            .enumerate()
+            .filter(|&(tid, _)| must_tile!(tid).is_yaokyuu())
+            .map(|(_, &c)| c.min(1))
             .sum()
     }
 
+    /// Whether 九種九牌 can be declared, which requires our first draw to be
+    /// uninterrupted by any call, and at least 9 kinds of yaokyuu tiles in
+    /// hand. Same as `last_cans.can_ryukyoku`.
+    #[inline]
+    #[must_use]
+    pub const fn can_kyushu_kyuhai(&self) -> bool {
+        self.last_cans.can_ryukyoku
+    }
+
+    /// Whether an ankan of `tile` is legal right after our tsumo with an
+    /// accepted riichi, which requires `tile` to be the tsumo (送り槓不可) and
+    /// the waits not to change, under the rule set by
+    /// `with_strict_riichi_ankan`.
+    #[must_use]
+    pub fn can_ankan_after_riichi(&self, tile: Tile) -> bool {
+        self.riichi_accepted[0]
+            && self.last_cans.can_discard
+            && self.tiles_left > 0
+            && self.kans_on_board < 4
+            && self
+                .last_self_tsumo
+                .is_some_and(|t| t.deaka() == tile.deaka())
+            && agari::check_ankan_after_riichi(
+                &self.tehai,
+                self.tehai_len_div3,
+                tile,
+                self.strict_riichi_ankan,
+            )
+    }
+
+    /// Whether we can rob (槍槓) someone else's kan of `tile` with a ron, at
+    /// 3n+1. Only a kakan can be robbed by any hand waiting on `tile`, while an
+    /// ankan can only be robbed by kokushi, and only under the rule set by
+    /// `with_kokushi_ankan_chankan`. A daiminkan can never be robbed.
+    ///
+    /// Furiten is respected, but yaku is not checked, as 槍槓 is a yaku on its
+    /// own.
+    #[must_use]
+    pub fn can_chankan(&self, kan_type: KanType, tile: Tile) -> bool {
+        if self.at_furiten {
+            return false;
+        }
+        let tid = tile.deaka().as_usize();
+        match kan_type {
+            KanType::Kakan => self.waits[tid],
+            KanType::Ankan => self.kokushi_ankan_chankan && self.kokushi_waits()[tid],
+            KanType::Daiminkan => false,
+        }
+    }
+
+    /// Whether we are furiten only because a winning tile has been passed up
+    /// since our last discard (同巡内フリテン, including the no-yaku case), so
+    /// that ron is not allowed now but will be again from our next draw, while
+    /// tsumo is still allowed.
+    ///
+    /// It is false from our draw on, even though `at_furiten` is only reset by
+    /// our discard, as nobody else can discard in between. Riichi furiten and
+    /// furiten on our own discards (捨て牌フリテン) are permanent and never
+    /// count.
+    #[must_use]
+    pub fn temporary_furiten(&self) -> bool {
+        if !self.at_furiten || self.riichi_accepted[0] || self.last_cans.can_discard {
+            return false;
+        }
+        let mut tehai = self.tehai;
+        !(0..34).any(|t| {
+            if !self.discarded_tiles[t] || tehai[t] == 4 {
+                return false;
+            }
+            tehai[t] += 1;
+            let is_wait = shanten::calc_all(&tehai, self.tehai_len_div3) == -1;
+            tehai[t] -= 1;
+            is_wait
+        })
+    }
+
     #[inline]
     #[must_use]
     pub fn rule_based_ryukyoku(&self) -> bool {
@@ -227,13 +518,18 @@ impl PlayerState {
         }
 
         if self.is_all_last {
-            // Ryukyoku if it is all-last and we are oya or we are not the last,
-            // because it is hard to decide whether it is appropriate to not
-            // ryukyoku.
-            if self.oya == 0 || self.rank < 3 {
+            // Ryukyoku if it is all-last and we are not the last, because it is
+            // hard to decide whether it is appropriate to not ryukyoku.
+            if self.rank < 3 {
                 return true;
             }
 
+            // As the last place oya, ryukyoku keeps the dealership under
+            // テンパイ連荘, but ends the game under アガリ連荘.
+            if self.oya == 0 {
+                return self.renchan_rule == RenchanRule::Tenpai;
+            }
+
             // At all-last, we are the last and we are not oya. If even a
             // haneman tsumo cannot let us avoid the last, then do not ryukyoku.
             let mut scores = [-3000 - self.honba as i32 * 300; 4];
@@ -272,6 +568,11 @@ impl PlayerState {
     fn rule_based_agari_slow(&self, is_ron: bool, target_rel: usize) -> bool {
         // Agari if it is not yet all-last, or we are oya ourselves, or we are
         // not the last place at all.
+        //
+        // As oya, the win always keeps the dealership (連荘) in all-last
+        // unless it makes us the top (和了り止め), under any `RenchanRule`, so
+        // it can never leave us worse off than passing it, and there is
+        // nothing left to weigh.
         if !self.is_all_last || self.oya == 0 || self.rank < 3 {
             return true;
         }
@@ -291,7 +592,7 @@ impl PlayerState {
         // Calculate the max theoretical score we can achieve through this agari.
         let max_win_point = if self.riichi_accepted[0] {
             let mut tehai_full = self.tehai;
-            for t in &self.ankan_overview[0] {
+            for t in self.ankan_tiles(0) {
                 tehai_full[t.as_usize()] += 4;
             }
 
@@ -306,9 +607,9 @@ impl PlayerState {
             let mut tiles_seen = self.tiles_seen;
             let mut ura_indicators = array_vec!([_; 5]);
             'outer: for (t, _) in tehai_ordered_by_count {
-                let ura_ind = must_tile!(t).prev();
+                let ura_ind = must_tile!(t).dora_indicator_for();
                 loop {
-                    if ura_indicators.len() >= self.dora_indicators.len() {
+                    if ura_indicators.len() >= self.num_ura_indicators(is_ron) {
                         // Break out of all loops.
                         break 'outer;
                     }
@@ -321,6 +622,19 @@ impl PlayerState {
                 }
             }
 
+            // Place them at the indices that apply, the others are ignored by
+            // `agari_points` anyways.
+            let mut best_uras = ura_indicators.into_iter();
+            let ura_indicators: ArrayVec<[_; 5]> = (0..self.dora_indicators.len())
+                .map_while(|i| {
+                    if self.ura_applies(i, is_ron) {
+                        best_uras.next()
+                    } else {
+                        Some(Tile::default())
+                    }
+                })
+                .collect();
+
             // `unwrap` is safe because there is a condition guard in
             // `rule_based_agari`.
             self.agari_points(is_ron, &ura_indicators).unwrap()
@@ -329,29 +643,21 @@ impl PlayerState {
             self.agari_points(is_ron, &[]).unwrap()
         };
 
-        // Calculate the best post-hora situation for us.
-        let mut exp_scores = self.scores;
-        if is_ron {
-            exp_scores[0] +=
-                max_win_point.ron + self.kyotaku as i32 * 1000 + self.honba as i32 * 300;
-            exp_scores[target_rel] -= max_win_point.ron + self.honba as i32 * 300;
-        } else {
-            // The player must be ko here.
-            exp_scores[0] += max_win_point.tsumo_total(false)
-                + self.kyotaku as i32 * 1000
-                + self.honba as i32 * 300;
-            exp_scores
-                .iter_mut()
-                .enumerate()
-                .skip(1)
-                .for_each(|(idx, s)| {
-                    if idx as u8 == self.oya {
-                        *s -= max_win_point.tsumo_oya + self.honba as i32 * 100;
-                    } else {
-                        *s -= max_win_point.tsumo_ko + self.honba as i32 * 100;
-                    }
-                });
-        }
+        // Calculate the best post-hora situation for us. The player must be ko
+        // here if it is tsumo.
+        let mut board = Scoreboard {
+            scores: self.scores,
+            honba: self.honba,
+            kyotaku: self.kyotaku,
+            oya: self.oya,
+        };
+        let winner = Winner {
+            seat: 0,
+            point: max_win_point,
+            liable: self.pao_actor.map(|a| self.rel(a)),
+        };
+        board.apply_agari(&[winner], is_ron, target_rel);
+        let exp_scores = board.scores;
 
         // The prerequisite `!(self.bakaze == t!(W) && self.kyoku == 3)` has
         // already been checked at the beginning.
@@ -367,6 +673,92 @@ impl PlayerState {
         self.get_rank(exp_scores) < 3
     }
 
+    /// Whether `ura_indicators[idx]` applies to the win under
+    /// `self.kan_ura_rule`, where `idx` 0 is the ura of the initial dora
+    /// indicator.
+    fn ura_applies(&self, idx: usize, is_ron: bool) -> bool {
+        if idx == 0 {
+            return true;
+        }
+        match self.kan_ura_rule {
+            KanUraRule::All => true,
+            KanUraRule::OwnKanOrTsumo => {
+                !is_ron
+                    || self
+                        .kan_dora_actors
+                        .get(idx - 1)
+                        .is_some_and(|&a| a == Some(0))
+            }
+        }
+    }
+
+    /// Whether a ron now would be 人和 under `self.renhou_rule`, i.e. we have
+    /// not drawn yet and no call has been made, which would have cleared
+    /// `can_w_riichi`.
+    pub(super) fn at_renhou(&self) -> bool {
+        self.renhou_rule != RenhouRule::None && self.at_turn == 0 && self.can_w_riichi
+    }
+
+    /// The number of ura indicators that apply to the win.
+    #[must_use]
+    pub fn num_ura_indicators(&self, is_ron: bool) -> usize {
+        (0..self.dora_indicators.len())
+            .filter(|&i| self.ura_applies(i, is_ron))
+            .count()
+    }
+
+    /// Counts the doras of the hand as it would win now, split by kind. Tiles
+    /// in fuuro and ankans are included. At 3n+1, the discard to ron is
+    /// included if `last_cans.can_ron_agari` holds.
+    ///
+    /// Like in `agari_points`, `ura_indicators` is used only when the actor
+    /// has an accepted riichi, and only those that apply under
+    /// `self.kan_ura_rule` are counted.
+    #[must_use]
+    pub fn dora_detail(&self, ura_indicators: &[Tile]) -> DoraDetail {
+        let mut tiles = self.tehai;
+        for t in self.melds[0].iter().flat_map(|m| m.tiles()) {
+            tiles[t.deaka().as_usize()] += 1;
+        }
+
+        let (omote, kan) = self
+            .dora_indicators
+            .split_at(self.dora_indicators.len().min(1));
+        let doras_by = |indicators: &[Tile], tiles: &[u8; 34]| -> u8 {
+            indicators
+                .iter()
+                .map(|ind| tiles[ind.dora_from_indicator().as_usize()])
+                .sum()
+        };
+        // Akas in fuuro and ankans are not tracked on their own, but are
+        // included in `doras_owned`.
+        let mut aka = self.doras_owned[0] - doras_by(omote, &tiles) - doras_by(kan, &tiles);
+
+        let is_ron = !self.last_cans.can_discard && self.last_cans.can_ron_agari;
+        if is_ron && let Some(tile) = self.last_kawa_tile {
+            tiles[tile.deaka().as_usize()] += 1;
+            aka += tile.is_aka() as u8;
+        }
+
+        let ura = if self.riichi_accepted[0] {
+            ura_indicators
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| self.ura_applies(i, is_ron))
+                .map(|(_, &ura)| tiles[ura.dora_from_indicator().as_usize()])
+                .sum()
+        } else {
+            0
+        };
+
+        DoraDetail {
+            dora: doras_by(omote, &tiles),
+            kan_dora: doras_by(kan, &tiles),
+            aka,
+            ura,
+        }
+    }
+
     /// Err is returned if the hand cannot agari, or cannot retrieve the winning
     /// tile.
     ///
@@ -380,10 +772,16 @@ impl PlayerState {
             "cannot agari"
         );
 
-        // Here, 天和 and 地和 are handled individually as special cases, and
-        // there is no multi yakuman for these two.
+        let is_oya = self.is_oya();
+
+        // Here, 天和, 地和 and 人和 as a yakuman are handled individually as
+        // special cases, and there is no multi yakuman for these.
         if !is_ron && self.can_w_riichi {
-            return Ok(Point::yakuman(self.oya == 0, 1));
+            return Ok(Point::yakuman(is_oya, 1));
+        }
+        let is_renhou = is_ron && self.at_renhou();
+        if is_renhou && self.renhou_rule == RenhouRule::Yakuman {
+            return Ok(Point::yakuman(is_oya, 1));
         }
 
         let winning_tile = if is_ron {
@@ -393,10 +791,27 @@ impl PlayerState {
         }
         .context("cannot find the winning tile")?;
 
+        // Any chi, pon or kan after our riichi breaks ippatsu, which must have
+        // already cleared `at_ippatsu`.
+        debug_assert!(
+            !self.at_ippatsu
+                || self.riichi_accepted[0] && self.calls_on_board() == self.calls_at_riichi,
+            "ippatsu is broken by a call after riichi",
+        );
+
+        // `self.tiles_left` counts rinshan draws as well, so a rinshan tsumo of
+        // the last tile is 嶺上開花 only, while the discard after it is still
+        // 河底撈魚 if it's ron'd. No kan can be declared on the haitei tile, so
+        // 槍槓 and 河底撈魚 never overlap.
+        debug_assert!(
+            self.chankan_chance.is_none() || self.tiles_left > 0,
+            "chankan on the haitei tile",
+        );
         let additional_hans = if is_ron {
             [
                 self.riichi_accepted[0],       // 立直
                 self.is_w_riichi,              // 両立直
+                self.is_open_riichi,           // オープン立直
                 self.at_ippatsu,               // 一发
                 self.tiles_left == 0,          // 河底撈魚
                 self.chankan_chance.is_some(), // 槍槓
@@ -408,6 +823,7 @@ impl PlayerState {
             [
                 self.riichi_accepted[0],                  // 立直
                 self.is_w_riichi,                         // 両立直
+                self.is_open_riichi,                      // オープン立直
                 self.at_ippatsu,                          // 一发
                 self.is_menzen,                           // 門前清自摸和
                 self.tiles_left == 0 && !self.at_rinshan, // 海底摸月
@@ -431,10 +847,12 @@ impl PlayerState {
         if self.riichi_accepted[0] {
             final_doras_owned += ura_indicators
                 .iter()
-                .map(|&ura| {
-                    let next = ura.next();
+                .enumerate()
+                .filter(|&(i, _)| self.ura_applies(i, is_ron))
+                .map(|(_, &ura)| {
+                    let next = ura.dora_from_indicator();
                     let mut count = tehai[next.as_usize()];
-                    if self.ankan_overview[0].contains(&next) {
+                    if self.ankan_tiles(0).any(|t| t == next) {
                         count += 4;
                     }
                     count
@@ -453,12 +871,64 @@ impl PlayerState {
             jikaze: self.jikaze.as_u8(),
             winning_tile: winning_tile.deaka().as_u8(),
             is_ron,
+            local_yaku: self.local_yaku,
+        };
+        let agari = agari_calc.agari(additional_hans, final_doras_owned);
+        if is_renhou {
+            let mangan = Point::mangan(is_oya);
+            return Ok(agari
+                .map(|a| {
+                    a.point_with_rules(
+                        is_oya,
+                        self.kiriage_mangan,
+                        self.kazoe_yakuman,
+                        self.tsumo_rounding,
+                    )
+                })
+                .filter(|p| p.ron > mangan.ron)
+                .unwrap_or(mangan));
+        }
+        let agari = agari.context("not a hora hand")?;
+
+        Ok(agari.point_with_rules(
+            is_oya,
+            self.kiriage_mangan,
+            self.kazoe_yakuman,
+            self.tsumo_rounding,
+        ))
+    }
+
+    /// Same as `agari_points`, but also returns the honba and kyotaku bonus
+    /// this player will receive, which `Point` never includes.
+    ///
+    /// In a multi-ron, only the winner closest to the discarder in turn order
+    /// (the head-bump winner) takes the honba and the kyotaku pot, and the
+    /// others get a bonus of `0`. `is_head_bump_winner` is ignored for tsumo.
+    pub fn agari_points_multi_ron(
+        &self,
+        is_ron: bool,
+        ura_indicators: &[Tile],
+        is_head_bump_winner: bool,
+    ) -> Result<(Point, i32)> {
+        let point = self.agari_points(is_ron, ura_indicators)?;
+        let bonus = if !is_ron || is_head_bump_winner {
+            self.kyotaku as i32 * 1000 + self.honba as i32 * 300
+        } else {
+            0
         };
-        let agari = agari_calc
-            .agari(additional_hans, final_doras_owned)
-            .context("not a hora hand")?;
+        Ok((point, bonus))
+    }
 
-        Ok(agari.point(self.oya == 0))
+    /// Same as `agari_points`, but also returns the absolute ID of the player
+    /// liable (包) for the win, if any, which is to be settled with
+    /// `Point::pao_payment_vector`.
+    pub fn agari_points_with_liable(
+        &self,
+        is_ron: bool,
+        ura_indicators: &[Tile],
+    ) -> Result<(Point, Option<u8>)> {
+        let point = self.agari_points(is_ron, ura_indicators)?;
+        Ok((point, self.pao_actor))
     }
 
     /// Calculate the actual shanten at this point. Unlike `self.shanten`, this
@@ -502,40 +972,797 @@ impl PlayerState {
         shanten::calc_all(&self.tehai, self.tehai_len_div3)
     }
 
+    /// Whether the hand is tenpai at 3n+1, or can be tenpai after the best
+    /// discard at 3n+2. Furiten and `tiles_seen` are not taken into account.
+    #[inline]
+    #[must_use]
+    pub fn is_tenpai(&self) -> bool {
+        self.real_time_shanten() <= 0
+    }
+
+    /// Whether the hand would be declared tenpai at ryukyoku, which requires
+    /// at least one wait that is not fully held in our own tehai. Furiten and
+    /// waits whose remaining copies are all visible elsewhere still count.
+    ///
+    /// Must be called at 3n+1, returns false otherwise.
+    #[must_use]
+    pub fn tenpai_for_opponents(&self) -> bool {
+        if self.last_cans.can_discard || self.shanten > 0 {
+            return false;
+        }
+
+        (0..34).any(|t| {
+            if self.tehai[t] == 4 {
+                return false;
+            }
+            let mut tehai_after = self.tehai;
+            tehai_after[t] += 1;
+            shanten::calc_all_cached(&tehai_after, self.tehai_len_div3) == -1
+        })
+    }
+
+    /// Whether the hand is 形式聴牌 at ryukyoku. This is the same as
+    /// `tenpai_for_opponents`, unless `require_live_tile` is set, in which
+    /// case at least one wait must still have a copy that is not visible to
+    /// us according to `tiles_seen`, as some rulesets require.
+    ///
+    /// Must be called at 3n+1, returns false otherwise.
+    #[must_use]
+    pub fn formal_tenpai(&self, require_live_tile: bool) -> bool {
+        if !require_live_tile {
+            return self.tenpai_for_opponents();
+        }
+        if self.last_cans.can_discard || self.shanten > 0 {
+            return false;
+        }
+
+        // `self.waits` is only updated on our own discards, so it can miss the
+        // copies that have become visible since then.
+        (0..34).any(|t| {
+            if self.tiles_seen[t] >= 4 {
+                return false;
+            }
+            let mut tehai_after = self.tehai;
+            tehai_after[t] += 1;
+            shanten::calc_all_cached(&tehai_after, self.tehai_len_div3) == -1
+        })
+    }
+
+    /// Returns the minimum number of points we need to gain to be at
+    /// `target_rank` (0-based) or better, assuming the others' scores stay the
+    /// same, or 0 if we already are. Ties are broken by seat as in the final
+    /// ranking, so passing a player who wins ties against us takes one more
+    /// point. Points taken from the player to pass narrow the gap twice as
+    /// fast, which is not taken into account.
+    #[must_use]
+    pub fn points_to_rank(&self, target_rank: usize) -> i32 {
+        assert!(target_rank < 4, "invalid rank {target_rank}");
+        let ours = self.scores[0];
+        self.scores[1..]
+            .iter()
+            .flat_map(|&s| [s - ours, s - ours + 1])
+            .chain(iter::once(0))
+            .filter(|&delta| delta >= 0)
+            .filter(|&delta| {
+                let mut scores = self.scores;
+                scores[0] += delta;
+                self.get_rank(scores) as usize <= target_rank
+            })
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Returns our current rank (0-based), with ties broken by seat as in the
+    /// final ranking.
+    #[inline]
+    #[must_use]
+    pub const fn current_rank(&self) -> usize {
+        self.rank as usize
+    }
+
+    /// Returns a rough estimate of the probabilities of finishing the game at
+    /// each rank (0-based), assuming a hanchan.
+    ///
+    /// This is not a proper model of the game. The final score of each player
+    /// is treated as an independent normal variable around the current score,
+    /// with a fixed standard deviation per remaining kyoku (including the
+    /// current one). Renchan, busting and 西入 are not considered. It is only
+    /// meant for display.
+    #[must_use]
+    pub fn placement_probabilities(&self) -> [f32; 4] {
+        const STDDEV_PER_KYOKU: f32 = 4000.;
+        const STEPS: usize = 256;
+        const RANGE: f32 = 6.;
+
+        let kyokus_left = if self.is_all_last {
+            1
+        } else {
+            match self.bakaze.as_u8() {
+                tu8!(E) => 8 - self.kyoku,
+                _ => 4 - self.kyoku,
+            }
+        };
+        let stddev = STDDEV_PER_KYOKU * (kyokus_left as f32).sqrt();
+
+        // Scores move in steps of 100, so ties are resolved halfway.
+        let ours = self.scores[0];
+        let gaps: [f32; 3] = std::array::from_fn(|i| {
+            let wins_ties = (self.player_id as usize + i + 1) % 4 < self.player_id as usize;
+            (self.scores[i + 1] - ours) as f32 + if wins_ties { 50. } else { -50. }
+        });
+
+        // Integrate over our final score `x` (in units of `stddev`) the
+        // distribution of the number of players above us.
+        let dx = 2. * RANGE / STEPS as f32;
+        let mut ret = [0.; 4];
+        for step in 0..=STEPS {
+            let x = (step as f32).mul_add(dx, -RANGE);
+            let mut by_rank = [1., 0., 0., 0.];
+            for gap in gaps {
+                let p_above = 1. - normal_cdf(x - gap / stddev);
+                for rank in (0..4).rev() {
+                    let stay = by_rank[rank] * (1. - p_above);
+                    let from_above = if rank > 0 {
+                        by_rank[rank - 1] * p_above
+                    } else {
+                        0.
+                    };
+                    by_rank[rank] = stay + from_above;
+                }
+            }
+            let weight = (-0.5 * x * x).exp();
+            for (r, p) in ret.iter_mut().zip(by_rank) {
+                *r = p.mul_add(weight, *r);
+            }
+        }
+
+        let sum: f32 = ret.iter().sum();
+        ret.map(|r| r / sum)
+    }
+
+    /// Returns the relative score deltas of the noten payments if the kyoku
+    /// ended in an exhaustive draw now. Our own tenpai status comes from
+    /// `is_tenpai`, and `opponents_tenpai[i]` is the assumed status of the
+    /// player at relative seat `i + 1`. Pass all false to only count ours.
+    ///
+    /// Nagashi mangan is not taken into account.
+    #[must_use]
+    pub fn ryukyoku_payment_delta(&self, opponents_tenpai: [bool; 3]) -> [i32; 4] {
+        let [a, b, c] = opponents_tenpai;
+        point::noten_payments([self.is_tenpai(), a, b, c])
+    }
+
+    /// Whether we would get nagashi mangan (流し満貫) if the kyoku ended in an
+    /// exhaustive draw now, i.e. all of our discards are yaokyuu and none of
+    /// them has been called. It is paid as a mangan tsumo, which can be
+    /// produced by `Point::mangan`, instead of the noten payments.
+    #[must_use]
+    pub fn is_nagashi_mangan(&self) -> bool {
+        !self.own_discard_called
+            && !self.kawa_overview[0].is_empty()
+            && self
+                .discarded_tiles
+                .iter()
+                .enumerate()
+                .all(|(tid, &discarded)| !discarded || must_tile!(tid).is_yaokyuu())
+    }
+
+    /// Returns the tiles that can't deal into the player at `opponent_rel`
+    /// right now, which are the tiles they have discarded, the tiles discarded
+    /// by anyone after their riichi, and the tiles discarded by others since
+    /// their last discard, as they are furiten on all of them. The last kind
+    /// is only safe until their next discard.
+    ///
+    /// Tiles passed by kakan are not included.
+    #[must_use]
+    pub fn genbutsu(&self, opponent_rel: usize) -> [bool; 34] {
+        assert!(
+            (1..4).contains(&opponent_rel),
+            "invalid opponent {opponent_rel}"
+        );
+        let mut ret = self.safe_tiles[opponent_rel];
+        ret.iter_mut()
+            .zip(self.safe_tiles_this_cycle[opponent_rel])
+            .for_each(|(s, t)| *s |= t);
+        ret
+    }
+
+    /// A rough estimate of the probability that the player at `opponent_rel`
+    /// is tenpai, which is 1 once they declared riichi, and otherwise grows
+    /// with the number of their discards and open melds as a logistic curve.
+    #[must_use]
+    pub fn estimated_tenpai_prob(&self, opponent_rel: usize) -> f32 {
+        assert!(
+            (1..4).contains(&opponent_rel),
+            "invalid opponent {opponent_rel}"
+        );
+        if self.riichi_declared[opponent_rel] {
+            return 1.;
+        }
+        let discards = self.kawa_overview[opponent_rel].len() as f32;
+        let melds = self.fuuro_tiles(opponent_rel).count() as f32;
+        let logit = 0.25_f32.mul_add(discards, 0.8_f32.mul_add(melds, -4.5));
+        1. / (1. + (-logit).exp())
+    }
+
+    /// Reads whether the player at `opponent_rel` is going for a flush in
+    /// each suit from their discards and melds, with a simple heuristic:
+    ///
+    /// - A suit is ruled out if any of their melds, including ankans, has a
+    ///   tile of another suit.
+    /// - Otherwise, the purity is the share of their number tile discards that
+    ///   are not of the suit, and the likelihood is its square scaled by the
+    ///   evidence, which saturates at 9 discards of the other suits, with each
+    ///   meld of the suit counting as 3.
+    ///
+    /// Jihai discards and melds count for nothing.
+    #[must_use]
+    pub fn opponent_suit_read(&self, opponent_rel: usize) -> SuitRead {
+        assert!(
+            (1..4).contains(&opponent_rel),
+            "invalid opponent {opponent_rel}"
+        );
+
+        let mut discards = [0; 3];
+        for t in &self.kawa_overview[opponent_rel] {
+            if !t.is_jihai() {
+                discards[t.deaka().as_usize() / 9] += 1;
+            }
+        }
+        let mut melds = [0; 3];
+        let mut ruled_out = [false; 3];
+        for meld in &self.melds[opponent_rel] {
+            let tile = meld.tiles()[0].deaka();
+            if tile.is_jihai() {
+                continue;
+            }
+            let suit = tile.as_usize() / 9;
+            melds[suit] += 1;
+            for (other, out) in ruled_out.iter_mut().enumerate() {
+                *out |= other != suit;
+            }
+        }
+
+        let likelihood = array::from_fn(|suit| {
+            if ruled_out[suit] {
+                return 0.;
+            }
+            let own = discards[suit];
+            let other: i32 = discards.iter().sum::<i32>() - own;
+            let purity = if other + own == 0 {
+                1.
+            } else {
+                other as f32 / (other + own) as f32
+            };
+            let evidence = ((other + 3 * melds[suit]) as f32 / 9.).min(1.);
+            purity * purity * evidence
+        });
+        let likely_flush = (0..3)
+            .filter(|&suit| likelihood[suit] >= 0.5)
+            .max_by(|&a, &b| likelihood[a].total_cmp(&likelihood[b]));
+
+        SuitRead {
+            likelihood,
+            likely_flush,
+        }
+    }
+
+    /// Returns a classical estimate of the probability of dealing into the
+    /// player at `opponent_rel` by discarding each tile, whether or not they
+    /// have declared riichi. It is `estimated_tenpai_prob` times the danger of
+    /// the tile given they are tenpai, which is built from these features:
+    ///
+    /// - `genbutsu`, which are never dangerous.
+    /// - Suji (筋): each of the ryanmen waits on the tile is ruled out if the
+    ///   other tile of that wait is in `genbutsu`, as they would be furiten.
+    /// - Kabe (壁): a ryanmen, kanchan or penchan wait on the tile is ruled out
+    ///   if all 4 copies of a tile it is made of are visible to us.
+    /// - The number of unseen copies of the tile, which bounds the tanki and
+    ///   shanpon waits on it, and is the only thing that matters for jihai.
+    ///
+    /// The rates by wait shape are rough figures of the deal-in rates against
+    /// riichi, not fitted to any data.
+    #[must_use]
+    pub fn estimated_deal_in_risk(&self, opponent_rel: usize) -> [f32; 34] {
+        let tenpai_prob = self.estimated_tenpai_prob(opponent_rel);
+        let genbutsu = self.genbutsu(opponent_rel);
+        let visible_all = |tid: usize| self.tiles_seen[tid] >= 4;
+
+        let mut ret = [0.; 34];
+        for (tid, risk) in ret.iter_mut().enumerate() {
+            if genbutsu[tid] {
+                continue;
+            }
+
+            // tanki and shanpon
+            let unseen = 4 - self.tiles_seen[tid];
+            let mut danger = match unseen {
+                0 => 0.,
+                1 => 0.8,
+                _ if tid >= tuz!(E) => 1.5_f32.mul_add(unseen as f32, -0.5),
+                _ => 1.5,
+            };
+
+            if tid < tuz!(E) {
+                let num = tid % 9 + 1;
+                // ryanmen on the upper side (n+1, n+2) and the lower side
+                // (n-2, n-1), each of which is ruled out by its suji partner
+                // being genbutsu, or by either of its tiles being gone (壁).
+                for partner in must_tile!(tid).suji_partners() {
+                    let partner = partner.as_usize();
+                    let (near, far) = if partner > tid {
+                        (tid + 1, tid + 2)
+                    } else {
+                        (tid - 1, tid - 2)
+                    };
+                    if !genbutsu[partner] && !visible_all(near) && !visible_all(far) {
+                        danger += 4.;
+                    }
+                }
+                // kanchan
+                if (2..=8).contains(&num) && !visible_all(tid - 1) && !visible_all(tid + 1) {
+                    danger += 1.;
+                }
+                // penchan
+                if num == 3 && !visible_all(tid - 1) && !visible_all(tid - 2)
+                    || num == 7 && !visible_all(tid + 1) && !visible_all(tid + 2)
+                {
+                    danger += 1.;
+                }
+            }
+
+            *risk = tenpai_prob * danger / 100.;
+        }
+        ret
+    }
+
+    /// Returns the waits if the hand is a kokushi tenpai shape, which is
+    /// either a single wait on the missing kind or the thirteen-sided wait.
+    /// All false if it's not.
+    ///
+    /// Must be called at 3n+1, returns all false otherwise.
+    #[must_use]
+    pub fn kokushi_waits(&self) -> [bool; 34] {
+        if self.last_cans.can_discard || !self.is_menzen {
+            return [false; 34];
+        }
+        kokushi_waits_of(&self.tehai)
+    }
+
+    /// Whether `winning_tile` completes kokushi. At 3n+2, `winning_tile` is
+    /// taken as already in hand.
+    #[must_use]
+    pub fn is_kokushi_agari(&self, winning_tile: Tile) -> bool {
+        if !self.is_menzen {
+            return false;
+        }
+        let tid = winning_tile.deaka().as_usize();
+        if !self.last_cans.can_discard {
+            return kokushi_waits_of(&self.tehai)[tid];
+        }
+        if self.tehai[tid] == 0 {
+            return false;
+        }
+        let mut tehai = self.tehai;
+        tehai[tid] -= 1;
+        kokushi_waits_of(&tehai)[tid]
+    }
+
+    /// The pair in hand to pon `last_kawa_tile` with, akas first.
+    #[must_use]
+    pub fn pon_candidates(&self) -> Option<[Tile; 2]> {
+        if !self.last_cans.can_pon {
+            return None;
+        }
+        self.last_kawa_tile.map(|pai| self.tiles_in_hand(pai))
+    }
+
+    /// All the pairs in hand to chi `last_kawa_tile` with. A pair including a
+    /// 5 is listed twice if both the aka and the normal one are in hand.
+    #[must_use]
+    pub fn chi_candidates(&self) -> Vec<[Tile; 2]> {
+        let Some(pai) = self.last_kawa_tile else {
+            return vec![];
+        };
+        let pai = pai.deaka();
+
+        let mut ret = vec![];
+        for (can, a, b) in [
+            (self.last_cans.can_chi_low, pai.next(), pai.next().next()),
+            (self.last_cans.can_chi_mid, pai.prev(), pai.next()),
+            (self.last_cans.can_chi_high, pai.prev().prev(), pai.prev()),
+        ] {
+            if !can {
+                continue;
+            }
+            for a in self.variants_in_hand(a) {
+                for b in self.variants_in_hand(b) {
+                    ret.push([a, b]);
+                }
+            }
+        }
+        ret
+    }
+
+    /// All the kans that can be declared right now, with the deaka'd tile
+    /// being called for daiminkan, or the tile to ankan or kakan.
+    #[must_use]
+    pub fn kan_candidates(&self) -> Vec<(KanType, Tile)> {
+        let mut ret = vec![];
+        if self.last_cans.can_daiminkan
+            && let Some(pai) = self.last_kawa_tile
+        {
+            ret.push((KanType::Daiminkan, pai.deaka()));
+        }
+        if self.last_cans.can_ankan {
+            ret.extend(self.ankan_candidates.iter().map(|&t| (KanType::Ankan, t)));
+        }
+        if self.last_cans.can_kakan {
+            ret.extend(self.kakan_candidates.iter().map(|&t| (KanType::Kakan, t)));
+        }
+        ret
+    }
+
+    /// The aka and the normal one of `tile` that are in hand respectively.
+    fn variants_in_hand(&self, tile: Tile) -> ArrayVec<[Tile; 2]> {
+        let tile = tile.deaka();
+        let akas = self.akas_in_hand_of(tile);
+        let mut ret = ArrayVec::new();
+        if akas > 0 {
+            ret.push(tile.akaize());
+        }
+        if self.tehai[tile.as_usize()] > akas {
+            ret.push(tile);
+        }
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// Returns the shanten of the 3n+1 hand after discarding each tile, or
+    /// `i8::MAX` for tiles not in hand.
+    #[must_use]
+    pub fn shanten_after_each_discard(&self) -> [i8; 34] {
+        assert!(self.last_cans.can_discard, "tehai is not 3n+2");
+
+        let mut ret = [i8::MAX; 34];
+        let mut tehai = self.tehai;
+        for (tid, &count) in self.tehai.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            tehai[tid] -= 1;
+            ret[tid] = shanten::calc_all_cached(&tehai, self.tehai_len_div3);
+            tehai[tid] += 1;
+        }
+        ret
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// Returns a copy of the state after we discard `tile`, where akas are
+    /// distinguished, as if the `dahai` event had arrived, with `tehai`,
+    /// `akas_in_hand`, shanten, waits and furiten updated accordingly. This is
+    /// heavier than the other helpers but the result can be analyzed in the
+    /// same way, for lookahead. Returns an error if `tile` is not a legal
+    /// discard.
+    pub fn hypothetical_after_discard(&self, tile: Tile) -> Result<Self> {
+        ensure!(self.last_cans.can_discard, "tehai is not 3n+2");
+        ensure!(
+            self.discard_candidates_aka()[tile.as_usize()],
+            "{tile} is not a legal discard",
+        );
+
+        let mut state = self.clone();
+        state.update(&Event::Dahai {
+            actor: self.player_id,
+            pai: tile,
+            tsumogiri: self.last_self_tsumo == Some(tile),
+        })?;
+        Ok(state)
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// Collects the legality, shanten, ukeire and furiten status of
+    /// discarding `tile`, for analysis and coaching. Returns an error if
+    /// `tile` is not in hand, where akas are distinguished.
+    pub fn explain_discard(&self, tile: Tile) -> Result<DiscardExplanation> {
+        ensure!(self.last_cans.can_discard, "tehai is not 3n+2");
+        ensure!(
+            self.variants_in_hand(tile).contains(&tile),
+            "{tile} is not in hand",
+        );
+
+        let tid = tile.deaka().as_usize();
+        let is_legal = self.discard_candidates_aka()[tile.as_usize()];
+        let shanten = self.shanten_after_each_discard()[tid];
+        let (ukeire_tiles, ukeire) = self.ukeire_after_discard(tile);
+        let keeps_tenpai = shanten == 0;
+
+        let is_furiten = keeps_tenpai && {
+            let mut tehai = self.tehai;
+            tehai[tid] -= 1;
+            (0..34).any(|t| {
+                if tehai[t] == 4 || !self.discarded_tiles[t] && t != tid {
+                    return false;
+                }
+                tehai[t] += 1;
+                let is_wait = shanten::calc_all_cached(&tehai, self.tehai_len_div3) == -1;
+                tehai[t] -= 1;
+                is_wait
+            })
+        };
+
+        Ok(DiscardExplanation {
+            tile,
+            is_legal,
+            shanten,
+            ukeire_tiles,
+            ukeire,
+            keeps_tenpai,
+            is_furiten,
+        })
+    }
+
+    /// Must be called at 3n+1 while tenpai.
+    ///
+    /// Classifies the wait for every tile that completes the hand, over all
+    /// of its decompositions, which also tells the 2 fu of kanchan, penchan
+    /// and tanki and whether 平和 is possible. Unlike `waits`, tiles with all
+    /// 4 copies visible are still included.
+    pub fn wait_shape(&self) -> Result<WaitShape> {
+        ensure!(!self.last_cans.can_discard, "tehai is not 3n+1");
+        ensure!(self.shanten == 0, "not tenpai");
+
+        let kokushi_waits = kokushi_waits_of(&self.tehai);
+        let mut waits = vec![];
+        for tid in 0..34 {
+            if self.tehai[tid] == 4 {
+                continue;
+            }
+            let mut tehai = self.tehai;
+            tehai[tid] += 1;
+            if shanten::calc_all(&tehai, self.tehai_len_div3) != -1 {
+                continue;
+            }
+
+            let mut kinds = vec![];
+            let is_chiitoi = self.tehai_len_div3 == 4 && tehai.iter().all(|&c| c == 0 || c == 2);
+            if kokushi_waits[tid] || is_chiitoi {
+                kinds.push(WaitKind::Tanki);
+            }
+            collect_wait_kinds(&mut tehai, 0, false, &mut vec![], tid, &mut kinds);
+            kinds.sort_unstable();
+            kinds.dedup();
+            waits.push((must_tile!(tid), kinds));
+        }
+        Ok(WaitShape { waits })
+    }
+
+    /// Must be called at 3n+2.
+    ///
+    /// Pairs the EV of each legal discard from `single_player_tables` with its
+    /// deal-in risk, where `opponent_risk` holds the per-tile deal-in
+    /// probabilities of the three opponents in relative order, such as those
+    /// from `estimated_deal_in_risk`. The risks of the opponents are assumed
+    /// to be independent.
+    pub fn push_fold(&self, opponent_risk: &[[f32; 34]; 3]) -> Result<PushFoldAdvice> {
+        ensure!(self.last_cans.can_discard, "tehai is not 3n+2");
+        let tables = self.single_player_tables()?;
+
+        let mut candidates: Vec<_> = self
+            .discard_candidates_aka()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &legal)| legal)
+            .map(|(tid, _)| {
+                let tile = must_tile!(tid);
+                let ev = tables
+                    .max_ev_table
+                    .iter()
+                    .find(|c| c.tile == tile)
+                    .and_then(|c| c.exp_values.first().copied())
+                    .unwrap_or(0.);
+                let safe = opponent_risk
+                    .iter()
+                    .map(|risk| 1. - risk[tile.deaka().as_usize()])
+                    .product::<f32>();
+                PushFoldCandidate {
+                    tile,
+                    ev,
+                    risk: 1. - safe,
+                    recommended: false,
+                }
+            })
+            .collect();
+
+        for i in 0..candidates.len() {
+            let c = candidates[i];
+            candidates[i].recommended = !candidates
+                .iter()
+                .any(|o| o.ev >= c.ev && o.risk <= c.risk && (o.ev > c.ev || o.risk < c.risk));
+        }
+        Ok(PushFoldAdvice { candidates })
+    }
+
     /// Can be called at both 3n+1 and 3n+2, but `self.real_time_shanten` must
     /// be >= 0 and `self.tiles_left` must be >= 4.
     ///
     /// This function is currently highly internal.
     pub(super) fn single_player_tables(&self) -> Result<SinglePlayerTables> {
+        self.single_player_tables_with(self.tiles_seen, &self.dora_indicators)
+    }
+
+    /// Same as `single_player_tables`, but calculated with hypothetical
+    /// `tiles_seen` and dora indicators instead of the real ones, for
+    /// counterfactual EVs. `override_tiles_seen` must include the tehai and
+    /// `override_dora`.
+    pub(super) fn single_player_tables_with(
+        &self,
+        override_tiles_seen: [u8; 34],
+        override_dora: &[Tile],
+    ) -> Result<SinglePlayerTables> {
+        self.calc_single_player_tables(
+            override_tiles_seen,
+            override_dora,
+            SinglePlayerOptions::default(),
+        )
+    }
+
+    /// Same as `single_player_tables`, but for a closed hand that has not
+    /// declared riichi yet and can afford it, also calculates the table for
+    /// playing it dama in `SinglePlayerTables::dama_table`, so that riichi and
+    /// dama can be compared, see `SPCalculator::calc_with_dama_table`.
+    pub fn single_player_tables_with_dama(&self) -> Result<SinglePlayerTables> {
+        self.single_player_tables_opts(SinglePlayerOptions {
+            calc_dama_table: true,
+            ..Default::default()
+        })
+    }
+
+    /// Same as `single_player_tables`, but with the extras of `opts`, such as
+    /// 手変わり and 向聴落とし, so that discards that temporarily drop a
+    /// shanten or wait for a better shape can be evaluated properly.
+    ///
+    /// Each of `calc_tegawari` and `calc_shanten_down` can make the
+    /// calculation several times slower, especially at high shanten, so
+    /// `single_player_tables` keeps both off.
+    pub fn single_player_tables_opts(
+        &self,
+        opts: SinglePlayerOptions,
+    ) -> Result<SinglePlayerTables> {
+        self.calc_single_player_tables(self.tiles_seen, &self.dora_indicators, opts)
+    }
+
+    /// The net EV of the 1000 points stick for the riichi that `candidate`
+    /// assumes, in points, to be added to its `exp_values[0]` when comparing
+    /// it against dama.
+    ///
+    /// The stick is only paid if the hand reaches tenpai, and it is assumed
+    /// that we get our own stick back if and only if we win, so it comes to
+    /// `1000 * (win_probs[0] - tenpai_probs[0])`, which is never positive.
+    /// A stick left on the table by a ryukyoku counts as lost. The kyotaku
+    /// already on the table are not included, since a dama win collects them
+    /// too.
+    ///
+    /// It is 0 when riichi is not assumed, i.e. for an open hand, a hand that
+    /// has already declared riichi, which has paid for it, or with less than
+    /// 1000 points.
+    pub fn riichi_stick_ev_adjustment(&self, candidate: &Candidate) -> f32 {
+        if !self.is_menzen || self.riichi_declared[0] || self.scores[0] < 1000 {
+            return 0.;
+        }
+        let tenpai_prob = candidate.tenpai_probs.first().copied().unwrap_or(0.);
+        let win_prob = candidate.win_probs.first().copied().unwrap_or(0.);
+        1000. * (win_prob - tenpai_prob)
+    }
+
+    /// Whether declaring riichi now is worth it, by the EVs of
+    /// `single_player_tables_with_dama`, or `None` if riichi is not legal or
+    /// the tables can't be made.
+    ///
+    /// Riichi is taken as worth it if the best riichi EV among the discards
+    /// that keep tenpai, with its `riichi_stick_ev` added, is strictly higher
+    /// than the best dama EV, both at the current turn. Like the tables
+    /// themselves, it does not account for other players folding against the
+    /// riichi, nor for giving up the chance of folding ourselves.
+    #[must_use]
+    pub fn should_riichi(&self) -> Option<bool> {
+        if !self.last_cans.can_riichi {
+            return None;
+        }
+        let tables = self.single_player_tables_with_dama().ok()?;
+        let ev_now = |c: &Candidate| c.exp_values.first().copied().unwrap_or(0.);
+
+        let mut tehai = self.tehai;
+        let riichi_ev = tables
+            .max_ev_table
+            .iter()
+            .filter(|c| {
+                let tid = c.tile.deaka().as_usize();
+                tehai[tid] -= 1;
+                let keeps_tenpai = shanten::calc_all(&tehai, self.tehai_len_div3) == 0;
+                tehai[tid] += 1;
+                keeps_tenpai
+            })
+            .map(|c| ev_now(c) + c.riichi_stick_ev)
+            .reduce(f32::max)?;
+        let dama_ev = tables.dama_table?.iter().map(ev_now).reduce(f32::max)?;
+        Some(riichi_ev > dama_ev)
+    }
+
+    fn calc_single_player_tables(
+        &self,
+        override_tiles_seen: [u8; 34],
+        override_dora: &[Tile],
+        opts: SinglePlayerOptions,
+    ) -> Result<SinglePlayerTables> {
         ensure!(self.tiles_left >= 4, "need at least one more tsumo");
+        ensure!(
+            !override_dora.is_empty(),
+            "need at least one dora indicator"
+        );
+        ensure!(
+            override_tiles_seen
+                .iter()
+                .zip(self.tehai)
+                .all(|(&seen, held)| (held..=4).contains(&seen)),
+            "invalid tiles_seen {override_tiles_seen:?}",
+        );
 
         let cur_shanten = self.real_time_shanten();
         ensure!(cur_shanten >= 0, "can't calculate an agari hand");
 
         let mut can_discard = self.last_cans.can_discard;
-        let (tsumos_left, calc_haitei) = if can_discard {
-            (self.tiles_left / 4, self.tiles_left % 4 == 0)
+        let draws_before_ours = if can_discard {
+            0
         } else {
-            let target = self.rel(self.last_cans.target_actor) as u8;
             // Let's just ignore chankan here.
-            let tiles_left_at_next_tsumo = self.tiles_left.saturating_sub(4 - target);
-            (
-                tiles_left_at_next_tsumo / 4,
-                tiles_left_at_next_tsumo % 4 == 0,
-            )
+            self.wall_rule
+                .draws_until(self.last_cans.target_actor, self.player_id)
         };
+        let (mut estimated_tsumos_left, mut calc_haitei) = self
+            .wall_rule
+            .estimate_tsumos_left(self.tiles_left, draws_before_ours);
+        // Early in sanma, there can be more tsumos left than the calculator
+        // takes, with the haitei out of reach anyway.
+        let max_tsumos_left = sp::max_tsumos_left(can_discard);
+        if estimated_tsumos_left > max_tsumos_left {
+            estimated_tsumos_left = max_tsumos_left;
+            calc_haitei = false;
+        }
+        let tsumos_left = opts.tsumos_left_override.unwrap_or(estimated_tsumos_left);
         ensure!(tsumos_left >= 1, "need at least one more tsumo");
+        ensure!(
+            tsumos_left <= max_tsumos_left,
+            "at most {max_tsumos_left} tsumos left can be calculated, got {tsumos_left}",
+        );
 
-        let num_doras_in_fuuro = if self.is_menzen && self.ankan_overview[0].is_empty() {
+        let num_doras_in_fuuro = if self.melds[0].is_empty() {
             0
         } else {
             let num_doras_in_tehai: u8 = self
                 .dora_indicators
                 .iter()
-                .map(|ind| self.tehai[ind.next().as_usize()])
+                .map(|ind| self.tehai[ind.dora_from_indicator().as_usize()])
                 .sum();
-            let num_akas = self.akas_in_hand.iter().filter(|&&b| b).count() as u8;
-            self.doras_owned[0] - num_doras_in_tehai - num_akas
+            let num_akas = self.akas_in_hand.iter().sum::<u8>();
+            let real = self.doras_owned[0] - num_doras_in_tehai - num_akas;
+
+            // Swap the doras counted by the real indicators for the overridden
+            // ones, while akas in fuuro stay the same.
+            let mut fuuro_tiles = [0; 34];
+            for t in self.melds[0].iter().flat_map(|m| m.tiles()) {
+                fuuro_tiles[t.deaka().as_usize()] += 1;
+            }
+            let doras_by = |indicators: &[Tile]| -> u8 {
+                indicators
+                    .iter()
+                    .map(|ind| fuuro_tiles[ind.dora_from_indicator().as_usize()])
+                    .sum()
+            };
+            real - doras_by(&self.dora_indicators) + doras_by(override_dora)
         };
         let prefer_riichi = self.scores[0] >= 1000;
         let calc_double_riichi = can_discard && self.can_w_riichi;
@@ -550,9 +1777,9 @@ impl PlayerState {
             let last_tsumo = self.last_self_tsumo.unwrap();
             tehai[last_tsumo.deaka().as_usize()] -= 1;
             match last_tsumo.as_u8() {
-                tu8!(5mr) => akas_in_hand[0] = false,
-                tu8!(5pr) => akas_in_hand[1] = false,
-                tu8!(5sr) => akas_in_hand[2] = false,
+                tu8!(5mr) => akas_in_hand[0] -= 1,
+                tu8!(5pr) => akas_in_hand[1] -= 1,
+                tu8!(5sr) => akas_in_hand[2] -= 1,
                 _ => (),
             }
             can_discard = false;
@@ -561,8 +1788,9 @@ impl PlayerState {
         let init_state = InitState {
             tehai,
             akas_in_hand,
-            tiles_seen: self.tiles_seen,
+            tiles_seen: override_tiles_seen,
             akas_seen: self.akas_seen,
+            aka_config: self.aka_config,
         };
         let sp_calc = SPCalculator {
             tehai_len_div3: self.tehai_len_div3,
@@ -575,20 +1803,121 @@ impl PlayerState {
             jikaze: self.jikaze.as_u8(),
             num_doras_in_fuuro,
             prefer_riichi,
-            dora_indicators: &self.dora_indicators,
+            dama_needs_yaku: false,
+            dora_indicators: override_dora,
             calc_double_riichi,
             calc_haitei,
             sort_result: true,
             maximize_win_prob: false,
-            calc_tegawari: false,
-            calc_shanten_down: false,
+            calc_tegawari: opts.calc_tegawari,
+            calc_shanten_down: opts.calc_shanten_down,
+            compare_open_vs_closed: false,
+            calc_yakuman_prob: opts.calc_yakuman_prob,
+            kazoe_yakuman: self.kazoe_yakuman,
+            calc_kan_upgrade: opts.calc_kan_upgrade,
+            calc_win_curve: false,
         };
 
-        let mut max_ev_table = sp_calc.calc(init_state, can_discard, tsumos_left, cur_shanten)?;
+        let (mut max_ev_table, dama_table) = if opts.calc_dama_table && !self.riichi_accepted[0] {
+            sp_calc.calc_with_dama_table(init_state, can_discard, tsumos_left, cur_shanten)?
+        } else {
+            let table = sp_calc.calc(init_state, can_discard, tsumos_left, cur_shanten)?;
+            (table, None)
+        };
         if is_discard_after_riichi {
             max_ev_table[0].tile = self.last_self_tsumo.unwrap();
         }
+        for c in &mut max_ev_table {
+            c.riichi_stick_ev = self.riichi_stick_ev_adjustment(c);
+        }
+
+        Ok(SinglePlayerTables {
+            max_ev_table,
+            dama_table,
+        })
+    }
+}
+
+/// Searches every decomposition of the 3n+2 `tiles` into mentsu and a pair,
+/// where each of `groups` is `(first tile, size)` with size 2 for the pair, 3
+/// for a kotsu and 0 for a shuntsu, and pushes the kind of every group
+/// `winning` can complete into `kinds`.
+fn collect_wait_kinds(
+    tiles: &mut [u8; 34],
+    from: usize,
+    has_pair: bool,
+    groups: &mut Vec<(usize, u8)>,
+    winning: usize,
+    kinds: &mut Vec<WaitKind>,
+) {
+    let Some(tid) = (from..34).find(|&t| tiles[t] > 0) else {
+        for &(first, size) in &*groups {
+            let kind = match size {
+                2 if first == winning => WaitKind::Tanki,
+                3 if first == winning => WaitKind::Shanpon,
+                0 if first + 1 == winning => WaitKind::Kanchan,
+                0 if first == winning && first % 9 == 6 => WaitKind::Penchan,
+                0 if first + 2 == winning && first % 9 == 0 => WaitKind::Penchan,
+                0 if first == winning || first + 2 == winning => WaitKind::Ryanmen,
+                _ => continue,
+            };
+            kinds.push(kind);
+        }
+        return;
+    };
+
+    if tiles[tid] >= 3 {
+        tiles[tid] -= 3;
+        groups.push((tid, 3));
+        collect_wait_kinds(tiles, tid, has_pair, groups, winning, kinds);
+        groups.pop();
+        tiles[tid] += 3;
+    }
+    if !has_pair && tiles[tid] >= 2 {
+        tiles[tid] -= 2;
+        groups.push((tid, 2));
+        collect_wait_kinds(tiles, tid, true, groups, winning, kinds);
+        groups.pop();
+        tiles[tid] += 2;
+    }
+    if tid < 3 * 9 && tid % 9 <= 6 && tiles[tid + 1] > 0 && tiles[tid + 2] > 0 {
+        tiles[tid..tid + 3].iter_mut().for_each(|c| *c -= 1);
+        groups.push((tid, 0));
+        collect_wait_kinds(tiles, tid, has_pair, groups, winning, kinds);
+        groups.pop();
+        tiles[tid..tid + 3].iter_mut().for_each(|c| *c += 1);
+    }
+}
+
+fn kokushi_waits_of(tehai: &[u8; 34]) -> [bool; 34] {
+    let mut waits = [false; 34];
+    if tehai.iter().sum::<u8>() != 13 || shanten::calc_kokushi(tehai) != 0 {
+        return waits;
+    }
+
+    let yaokyuu = tuz![1m, 9m, 1p, 9p, 1s, 9s, E, S, W, N, P, F, C];
+    let has_pair = yaokyuu.iter().any(|&t| tehai[t] >= 2);
+    for t in yaokyuu {
+        // Without a pair, all 13 kinds are in hand and any of them is a wait.
+        waits[t] = !has_pair || tehai[t] == 0;
+    }
+    waits
+}
 
-        Ok(SinglePlayerTables { max_ev_table })
+/// Approximation of the standard normal CDF (Abramowitz and Stegun 7.1.26),
+/// with an absolute error below 1.5e-7 for erf.
+fn normal_cdf(x: f32) -> f32 {
+    let z = x.abs() / std::f32::consts::SQRT_2;
+    let t = 1. / 0.327_591_1_f32.mul_add(z, 1.);
+    let poly = t * 1.061_405_4_f32
+        .mul_add(t, -1.453_152_1)
+        .mul_add(t, 1.421_413_7)
+        .mul_add(t, -0.284_496_74)
+        .mul_add(t, 0.254_829_6);
+    let erf = 1. - poly * (-z * z).exp();
+    if x >= 0. {
+        0.5 * (1. + erf)
+    } else {
+        0.5 * (1. - erf)
     }
 }
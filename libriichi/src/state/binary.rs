@@ -0,0 +1,698 @@
+use super::item::{ChiPon, KawaItem, Sutehai};
+use super::meld::Meld;
+use super::{ActionCandidate, KanUraRule, PlayerState, RenchanRule, RenhouRule, WallRule};
+use crate::algo::agari::LocalYaku;
+use crate::algo::point::TsumoRounding;
+use crate::tile::{AkaConfig, Tile};
+
+use anyhow::{Context, Result, bail, ensure};
+use tinyvec::{Array, ArrayVec, TinyVec};
+
+/// The leading byte of every encoded `PlayerState`.
+const MAGIC: u8 = 0xb5;
+/// Bumped on every change of the layout.
+const VERSION: u8 = 10;
+
+/// Used for `None` of `Option<Tile>` and `Option<u8>`, which is never a valid
+/// tile ID or seat.
+const NONE: u8 = u8::MAX;
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn opt_u8(&mut self, v: Option<u8>) {
+        self.u8(v.unwrap_or(NONE));
+    }
+
+    fn opt_tile(&mut self, v: Option<Tile>) {
+        self.opt_u8(v.map(Tile::as_u8));
+    }
+
+    fn tiles(&mut self, v: &[Tile]) {
+        self.u8(v.len() as u8);
+        for t in v {
+            self.u8(t.as_u8());
+        }
+    }
+
+    fn u8s(&mut self, v: &[u8]) {
+        self.u8(v.len() as u8);
+        self.bytes(v);
+    }
+
+    /// Packed into bits, LSB first.
+    fn bits(&mut self, v: &[bool]) {
+        for chunk in v.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &b)| acc | (b as u8) << i);
+            self.u8(byte);
+        }
+    }
+
+    /// Tile counts of at most 15, packed into nibbles.
+    fn counts(&mut self, v: &[u8; 34]) {
+        for pair in v.chunks(2) {
+            self.u8(pair[0] | pair[1] << 4);
+        }
+    }
+
+    fn sutehai(&mut self, v: Sutehai) {
+        self.u8(v.tile.as_u8());
+        self.bits(&[v.is_dora, v.is_tedashi, v.is_riichi]);
+    }
+
+    fn opt_sutehai(&mut self, v: Option<Sutehai>) {
+        self.bits(&[v.is_some()]);
+        if let Some(v) = v {
+            self.sutehai(v);
+        }
+    }
+
+    fn opt_chi_pon(&mut self, v: Option<&ChiPon>) {
+        self.bits(&[v.is_some()]);
+        if let Some(v) = v {
+            self.u8(v.consumed[0].as_u8());
+            self.u8(v.consumed[1].as_u8());
+            self.u8(v.target_tile.as_u8());
+        }
+    }
+
+    fn meld(&mut self, v: Meld) {
+        let kind = match v {
+            Meld::Chi { .. } => 0,
+            Meld::Pon { .. } => 1,
+            Meld::Minkan { .. } => 2,
+            Meld::Ankan { .. } => 3,
+            Meld::Shouminkan { .. } => 4,
+        };
+        self.u8(kind);
+        self.tiles(&v.tiles());
+        self.opt_u8(v.target());
+    }
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8> {
+        let v = *self
+            .buf
+            .get(self.pos)
+            .with_context(|| format!("unexpected end of input at byte {}", self.pos))?;
+        self.pos += 1;
+        Ok(v)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut ret = [0; N];
+        for v in &mut ret {
+            *v = self.u8()?;
+        }
+        Ok(ret)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn opt_u8(&mut self) -> Result<Option<u8>> {
+        Ok(Some(self.u8()?).filter(|&v| v != NONE))
+    }
+
+    fn tile(&mut self) -> Result<Tile> {
+        Ok(Tile::try_from(self.u8()?)?)
+    }
+
+    fn opt_tile(&mut self) -> Result<Option<Tile>> {
+        self.opt_u8()?
+            .map(Tile::try_from)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    fn len(&mut self, cap: usize) -> Result<usize> {
+        let len = self.u8()? as usize;
+        ensure!(len <= cap, "length {len} exceeds capacity {cap}");
+        Ok(len)
+    }
+
+    fn tiles<A: Array<Item = Tile>>(&mut self) -> Result<ArrayVec<A>> {
+        let mut ret = ArrayVec::new();
+        for _ in 0..self.len(A::CAPACITY)? {
+            ret.push(self.tile()?);
+        }
+        Ok(ret)
+    }
+
+    fn u8s<A: Array<Item = u8>>(&mut self) -> Result<ArrayVec<A>> {
+        let mut ret = ArrayVec::new();
+        for _ in 0..self.len(A::CAPACITY)? {
+            ret.push(self.u8()?);
+        }
+        Ok(ret)
+    }
+
+    fn bits<const N: usize>(&mut self) -> Result<[bool; N]> {
+        let mut ret = [false; N];
+        for chunk in ret.chunks_mut(8) {
+            let byte = self.u8()?;
+            ensure!(
+                byte.checked_shr(chunk.len() as u32).unwrap_or(0) == 0,
+                "invalid bit field {byte:#x}",
+            );
+            for (i, b) in chunk.iter_mut().enumerate() {
+                *b = byte >> i & 1 == 1;
+            }
+        }
+        Ok(ret)
+    }
+
+    fn bit(&mut self) -> Result<bool> {
+        Ok(self.bits::<1>()?[0])
+    }
+
+    fn counts(&mut self) -> Result<[u8; 34]> {
+        let mut ret = [0; 34];
+        for pair in ret.chunks_mut(2) {
+            let byte = self.u8()?;
+            pair[0] = byte & 0xf;
+            pair[1] = byte >> 4;
+        }
+        Ok(ret)
+    }
+
+    fn sutehai(&mut self) -> Result<Sutehai> {
+        let tile = self.tile()?;
+        let [is_dora, is_tedashi, is_riichi] = self.bits()?;
+        Ok(Sutehai {
+            tile,
+            is_dora,
+            is_tedashi,
+            is_riichi,
+        })
+    }
+
+    fn opt_sutehai(&mut self) -> Result<Option<Sutehai>> {
+        self.bit()?.then(|| self.sutehai()).transpose()
+    }
+
+    fn opt_chi_pon(&mut self) -> Result<Option<ChiPon>> {
+        self.bit()?
+            .then(|| {
+                Ok(ChiPon {
+                    consumed: [self.tile()?, self.tile()?],
+                    target_tile: self.tile()?,
+                })
+            })
+            .transpose()
+    }
+
+    fn meld(&mut self) -> Result<Meld> {
+        let kind = self.u8()?;
+        let tiles: ArrayVec<[_; 4]> = self.tiles()?;
+        let target = self.opt_u8()?;
+        let expected_len = match kind {
+            0 | 1 => 3,
+            2..=4 => 4,
+            _ => bail!("invalid meld kind {kind}"),
+        };
+        ensure!(
+            tiles.len() == expected_len,
+            "expected {expected_len} tiles in meld kind {kind}, got {}",
+            tiles.len(),
+        );
+        ensure!(
+            target.is_some() == (kind != 3),
+            "unexpected target {target:?} for meld kind {kind}",
+        );
+
+        let target = target.unwrap_or_default();
+        let meld = match kind {
+            0 => Meld::Chi {
+                pai: tiles[2],
+                consumed: [tiles[0], tiles[1]],
+                target,
+            },
+            1 => Meld::Pon {
+                pai: tiles[2],
+                consumed: [tiles[0], tiles[1]],
+                target,
+            },
+            2 => Meld::Minkan {
+                pai: tiles[3],
+                consumed: [tiles[0], tiles[1], tiles[2]],
+                target,
+            },
+            3 => Meld::Ankan {
+                consumed: [tiles[0], tiles[1], tiles[2], tiles[3]],
+            },
+            _ => Meld::Shouminkan {
+                pai: tiles[2],
+                consumed: [tiles[0], tiles[1]],
+                added: tiles[3],
+                target,
+            },
+        };
+        Ok(meld)
+    }
+}
+
+impl PlayerState {
+    /// Encodes the state into a compact binary layout for dataset storage,
+    /// which `decode` turns back into an identical state. Fields are written
+    /// in a fixed order with fixed widths, except for lists, which are
+    /// prefixed with their lengths, after a magic byte and a layout version.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer {
+            buf: Vec::with_capacity(512),
+        };
+        w.u8(MAGIC);
+        w.u8(VERSION);
+
+        w.bits(&[
+            self.own_discard_called,
+            self.is_all_last,
+            self.kiriage_mangan,
+            self.kazoe_yakuman,
+            self.atozuke,
+            self.strict_riichi_ankan,
+            self.kokushi_ankan_chankan,
+            self.local_yaku.daisharin,
+            self.local_yaku.daisharin_any_suit,
+            self.local_yaku.sanrenkou,
+            self.chankan_chance.is_some(),
+            self.can_w_riichi,
+            self.is_w_riichi,
+            self.is_open_riichi,
+            self.at_rinshan,
+            self.at_ippatsu,
+            self.at_furiten,
+            self.to_mark_same_cycle_furiten.is_some(),
+            self.is_menzen,
+            self.has_next_shanten_discard,
+        ]);
+        w.u8(self.player_id);
+        w.u8(self.kan_ura_rule as u8);
+        w.u8(self.renhou_rule as u8);
+        w.u8(self.renchan_rule as u8);
+        w.u8(self.tsumo_rounding as u8);
+        w.u8(self.wall_rule.num_players);
+        w.u8(self.wall_rule.num_tiles);
+        w.u8(self.wall_rule.dead_wall_size);
+        w.bytes(&self.aka_config.counts);
+
+        w.counts(&self.tehai);
+        w.counts(&self.dora_factor);
+        w.counts(&self.tiles_seen);
+        w.bytes(&self.akas_seen);
+        w.bytes(&self.akas_in_hand);
+        w.u8(self.tehai_len_div3);
+        w.u8(self.shanten as u8);
+
+        w.bits(&self.waits);
+        w.bits(&self.keep_shanten_discards);
+        w.bits(&self.next_shanten_discards);
+        w.bits(&self.forbidden_tiles);
+        w.bits(&self.discarded_tiles);
+        for safe in self.safe_tiles.iter().chain(&self.safe_tiles_this_cycle) {
+            w.bits(safe);
+        }
+
+        w.u8(self.bakaze.as_u8());
+        w.u8(self.jikaze.as_u8());
+        w.u8(self.kyoku);
+        w.u8(self.honba);
+        w.u8(self.kyotaku);
+        for &score in &self.scores {
+            w.i32(score);
+        }
+        w.u8(self.rank);
+        w.u8(self.oya);
+        w.u8(self.at_turn);
+        w.u8(self.tiles_left);
+
+        w.tiles(&self.dora_indicators);
+        w.u8s(&self.pending_kan_actors);
+        w.u8(self.kan_dora_actors.len() as u8);
+        for &actor in &self.kan_dora_actors {
+            w.opt_u8(actor);
+        }
+
+        for kawa in &self.kawa {
+            w.u8(kawa.len() as u8);
+            for item in kawa {
+                w.bits(&[item.is_some()]);
+                if let Some(item) = item {
+                    w.opt_chi_pon(item.chi_pon.as_ref());
+                    w.tiles(&item.kan);
+                    w.sutehai(item.sutehai);
+                }
+            }
+        }
+        for &sutehai in self.last_tedashis.iter().chain(&self.riichi_sutehais) {
+            w.opt_sutehai(sutehai);
+        }
+        for kawa in &self.kawa_overview {
+            w.tiles(kawa);
+        }
+        for melds in &self.melds {
+            w.u8(melds.len() as u8);
+            for &meld in melds {
+                w.meld(meld);
+            }
+        }
+        w.bits(&self.riichi_declared);
+        w.bits(&self.riichi_accepted);
+
+        w.tiles(&self.intermediate_kan);
+        w.opt_chi_pon(self.intermediate_chi_pon.as_ref());
+        w.opt_tile(self.last_self_tsumo);
+        w.opt_tile(self.last_kawa_tile);
+        let cans = self.last_cans;
+        w.bits(&[
+            cans.can_discard,
+            cans.can_chi_low,
+            cans.can_chi_mid,
+            cans.can_chi_high,
+            cans.can_pon,
+            cans.can_daiminkan,
+            cans.can_kakan,
+            cans.can_ankan,
+            cans.can_riichi,
+            cans.can_tsumo_agari,
+            cans.can_ron_agari,
+            cans.can_ryukyoku,
+        ]);
+        w.u8(cans.target_actor);
+        w.tiles(&self.ankan_candidates);
+        w.tiles(&self.kakan_candidates);
+
+        w.bytes(&(self.calls_at_riichi as u32).to_le_bytes());
+        w.u8(self.kans_on_board);
+        w.opt_u8(self.pao_actor);
+        w.u8s(&self.chis);
+        w.u8s(&self.pons);
+        w.u8s(&self.minkans);
+        w.u8s(&self.ankans);
+        w.bytes(&self.doras_owned);
+        w.u8(self.doras_seen);
+
+        w.buf
+    }
+
+    /// Decodes a state encoded by `encode`, rejecting anything of another
+    /// version or with trailing bytes.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { buf: bytes, pos: 0 };
+        let magic = r.u8()?;
+        ensure!(magic == MAGIC, "not an encoded PlayerState");
+        let version = r.u8()?;
+        ensure!(
+            version == VERSION,
+            "unsupported version {version}, expected {VERSION}",
+        );
+
+        let [
+            own_discard_called,
+            is_all_last,
+            kiriage_mangan,
+            kazoe_yakuman,
+            atozuke,
+            strict_riichi_ankan,
+            kokushi_ankan_chankan,
+            daisharin,
+            daisharin_any_suit,
+            sanrenkou,
+            chankan_chance,
+            can_w_riichi,
+            is_w_riichi,
+            is_open_riichi,
+            at_rinshan,
+            at_ippatsu,
+            at_furiten,
+            to_mark_same_cycle_furiten,
+            is_menzen,
+            has_next_shanten_discard,
+        ] = r.bits()?;
+        let player_id = r.u8()?;
+        let kan_ura_rule = match r.u8()? {
+            0 => KanUraRule::All,
+            1 => KanUraRule::OwnKanOrTsumo,
+            v => bail!("invalid kan_ura_rule {v}"),
+        };
+        let renhou_rule = match r.u8()? {
+            0 => RenhouRule::None,
+            1 => RenhouRule::Mangan,
+            2 => RenhouRule::Yakuman,
+            v => bail!("invalid renhou_rule {v}"),
+        };
+        let renchan_rule = match r.u8()? {
+            0 => RenchanRule::Tenpai,
+            1 => RenchanRule::Agari,
+            v => bail!("invalid renchan_rule {v}"),
+        };
+        let tsumo_rounding = match r.u8()? {
+            0 => TsumoRounding::PerPayer,
+            1 => TsumoRounding::Total,
+            v => bail!("invalid tsumo_rounding {v}"),
+        };
+        let wall_rule = WallRule {
+            num_players: r.u8()?,
+            num_tiles: r.u8()?,
+            dead_wall_size: r.u8()?,
+        };
+        ensure!(
+            (3..=4).contains(&wall_rule.num_players)
+                && wall_rule.num_tiles >= wall_rule.dead_wall_size + 13 * wall_rule.num_players,
+            "invalid wall_rule {wall_rule:?}",
+        );
+        let aka_config = AkaConfig { counts: r.array()? };
+
+        let tehai = r.counts()?;
+        let dora_factor = r.counts()?;
+        let tiles_seen = r.counts()?;
+        let akas_seen = r.array()?;
+        let akas_in_hand = r.array()?;
+        let tehai_len_div3 = r.u8()?;
+        let shanten = r.u8()? as i8;
+
+        let waits = r.bits()?;
+        let keep_shanten_discards = r.bits()?;
+        let next_shanten_discards = r.bits()?;
+        let forbidden_tiles = r.bits()?;
+        let discarded_tiles = r.bits()?;
+        let mut safe_tiles = [[false; 34]; 4];
+        let mut safe_tiles_this_cycle = [[false; 34]; 4];
+        for safe in safe_tiles.iter_mut().chain(&mut safe_tiles_this_cycle) {
+            *safe = r.bits()?;
+        }
+
+        let bakaze = r.tile()?;
+        let jikaze = r.tile()?;
+        let kyoku = r.u8()?;
+        let honba = r.u8()?;
+        let kyotaku = r.u8()?;
+        let mut scores = [0; 4];
+        for score in &mut scores {
+            *score = r.i32()?;
+        }
+        let rank = r.u8()?;
+        let oya = r.u8()?;
+        let at_turn = r.u8()?;
+        let tiles_left = r.u8()?;
+
+        let dora_indicators = r.tiles()?;
+        let pending_kan_actors = r.u8s()?;
+        let mut kan_dora_actors = ArrayVec::new();
+        for _ in 0..r.len(4)? {
+            kan_dora_actors.push(r.opt_u8()?);
+        }
+
+        let mut kawa: [TinyVec<_>; 4] = Default::default();
+        for kawa in &mut kawa {
+            for _ in 0..r.u8()? {
+                let item = if r.bit()? {
+                    Some(KawaItem {
+                        chi_pon: r.opt_chi_pon()?,
+                        kan: r.tiles()?,
+                        sutehai: r.sutehai()?,
+                    })
+                } else {
+                    None
+                };
+                kawa.push(item);
+            }
+        }
+        let mut last_tedashis = [None; 4];
+        let mut riichi_sutehais = [None; 4];
+        for sutehai in last_tedashis.iter_mut().chain(&mut riichi_sutehais) {
+            *sutehai = r.opt_sutehai()?;
+        }
+        let mut kawa_overview: [ArrayVec<_>; 4] = Default::default();
+        for kawa in &mut kawa_overview {
+            *kawa = r.tiles()?;
+        }
+        let mut melds: [ArrayVec<_>; 4] = Default::default();
+        for melds in &mut melds {
+            for _ in 0..r.len(4)? {
+                melds.push(r.meld()?);
+            }
+        }
+        let riichi_declared = r.bits()?;
+        let riichi_accepted = r.bits()?;
+
+        let intermediate_kan = r.tiles()?;
+        let intermediate_chi_pon = r.opt_chi_pon()?;
+        let last_self_tsumo = r.opt_tile()?;
+        let last_kawa_tile = r.opt_tile()?;
+        let [
+            can_discard,
+            can_chi_low,
+            can_chi_mid,
+            can_chi_high,
+            can_pon,
+            can_daiminkan,
+            can_kakan,
+            can_ankan,
+            can_riichi,
+            can_tsumo_agari,
+            can_ron_agari,
+            can_ryukyoku,
+        ] = r.bits()?;
+        let last_cans = ActionCandidate {
+            can_discard,
+            can_chi_low,
+            can_chi_mid,
+            can_chi_high,
+            can_pon,
+            can_daiminkan,
+            can_kakan,
+            can_ankan,
+            can_riichi,
+            can_tsumo_agari,
+            can_ron_agari,
+            can_ryukyoku,
+            target_actor: r.u8()?,
+        };
+        let ankan_candidates = r.tiles()?;
+        let kakan_candidates = r.tiles()?;
+
+        let calls_at_riichi = u32::from_le_bytes(r.array()?) as usize;
+        let kans_on_board = r.u8()?;
+        let pao_actor = r.opt_u8()?;
+        let chis = r.u8s()?;
+        let pons = r.u8s()?;
+        let minkans = r.u8s()?;
+        let ankans = r.u8s()?;
+        let doras_owned = r.array()?;
+        let doras_seen = r.u8()?;
+
+        ensure!(
+            r.pos == bytes.len(),
+            "{} trailing bytes",
+            bytes.len() - r.pos,
+        );
+
+        Ok(Self {
+            player_id,
+            tehai,
+            waits,
+            dora_factor,
+            tiles_seen,
+            akas_seen,
+            keep_shanten_discards,
+            next_shanten_discards,
+            forbidden_tiles,
+            discarded_tiles,
+            safe_tiles,
+            safe_tiles_this_cycle,
+            own_discard_called,
+            bakaze,
+            jikaze,
+            kyoku,
+            honba,
+            kyotaku,
+            scores,
+            rank,
+            oya,
+            is_all_last,
+            dora_indicators,
+            pending_kan_actors,
+            kan_dora_actors,
+            kan_ura_rule,
+            kiriage_mangan,
+            renhou_rule,
+            kazoe_yakuman,
+            tsumo_rounding,
+            atozuke,
+            strict_riichi_ankan,
+            kokushi_ankan_chankan,
+            local_yaku: LocalYaku {
+                daisharin,
+                daisharin_any_suit,
+                sanrenkou,
+            },
+            renchan_rule,
+            wall_rule,
+            kawa,
+            last_tedashis,
+            riichi_sutehais,
+            kawa_overview,
+            melds,
+            riichi_declared,
+            riichi_accepted,
+            at_turn,
+            tiles_left,
+            intermediate_kan,
+            intermediate_chi_pon,
+            shanten,
+            last_self_tsumo,
+            last_kawa_tile,
+            last_cans,
+            ankan_candidates,
+            kakan_candidates,
+            chankan_chance: chankan_chance.then_some(()),
+            can_w_riichi,
+            is_w_riichi,
+            is_open_riichi,
+            at_rinshan,
+            at_ippatsu,
+            calls_at_riichi,
+            at_furiten,
+            to_mark_same_cycle_furiten: to_mark_same_cycle_furiten.then_some(()),
+            kans_on_board,
+            is_menzen,
+            pao_actor,
+            chis,
+            pons,
+            minkans,
+            ankans,
+            doras_owned,
+            doras_seen,
+            akas_in_hand,
+            aka_config,
+            tehai_len_div3,
+            has_next_shanten_discard,
+        })
+    }
+}
@@ -0,0 +1,22 @@
+use crate::tile::Tile;
+
+/// Everything about a single discard candidate, see
+/// `PlayerState::explain_discard`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscardExplanation {
+    pub tile: Tile,
+    /// Whether `discard_candidates_aka` allows it. Illegal discards are still
+    /// explained.
+    pub is_legal: bool,
+    /// Shanten of the 3n+1 hand after the discard.
+    pub shanten: i8,
+    /// Tiles that decrease `shanten`, with at least one copy left to draw.
+    pub ukeire_tiles: [bool; 34],
+    /// The number of copies left of `ukeire_tiles`.
+    pub ukeire: u8,
+    /// Whether the hand is tenpai after the discard.
+    pub keeps_tenpai: bool,
+    /// Whether the hand is tenpai after the discard but furiten because one of
+    /// the waits has been discarded by us, including `tile` itself.
+    pub is_furiten: bool,
+}
@@ -0,0 +1,18 @@
+/// Doras of a hand by kind, see `PlayerState::dora_detail`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DoraDetail {
+    /// Doras of the initial indicator.
+    pub dora: u8,
+    /// Doras of the indicators revealed by kans.
+    pub kan_dora: u8,
+    pub aka: u8,
+    pub ura: u8,
+}
+
+impl DoraDetail {
+    #[inline]
+    #[must_use]
+    pub const fn total(self) -> u8 {
+        self.dora + self.kan_dora + self.aka + self.ura
+    }
+}
@@ -1,98 +1,197 @@
-use super::{ActionCandidate, PlayerState};
-use crate::tile::Tile;
+use super::{ActionCandidate, Meld, PlayerState};
+use crate::tile::{AkaConfig, Tile};
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
-#[pymethods]
 impl PlayerState {
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn player_id(&self) -> u8 {
         self.player_id
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn kyoku(&self) -> u8 {
         self.kyoku
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn honba(&self) -> u8 {
         self.honba
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn kyotaku(&self) -> u8 {
         self.kyotaku
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn is_oya(&self) -> bool {
         self.oya == 0
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn tehai(&self) -> [u8; 34] {
         self.tehai
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn akas_in_hand(&self) -> [bool; 3] {
-        self.akas_in_hand
+        [
+            self.akas_in_hand[0] > 0,
+            self.akas_in_hand[1] > 0,
+            self.akas_in_hand[2] > 0,
+        ]
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub fn chis(&self) -> &[u8] {
         &self.chis
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub fn pons(&self) -> &[u8] {
         &self.pons
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub fn minkans(&self) -> &[u8] {
         &self.minkans
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub fn ankans(&self) -> &[u8] {
         &self.ankans
     }
 
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn at_turn(&self) -> u8 {
         self.at_turn
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn shanten(&self) -> i8 {
         self.shanten
     }
-    #[getter]
     #[inline]
     #[must_use]
     pub const fn waits(&self) -> [bool; 34] {
         self.waits
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn last_cans(&self) -> ActionCandidate {
+        self.last_cans
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn can_w_riichi(&self) -> bool {
+        self.can_w_riichi
+    }
+    #[inline]
+    #[must_use]
+    pub const fn self_riichi_declared(&self) -> bool {
+        self.riichi_declared[0]
+    }
+    #[inline]
+    #[must_use]
+    pub const fn self_riichi_accepted(&self) -> bool {
+        self.riichi_accepted[0]
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn at_furiten(&self) -> bool {
+        self.at_furiten
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PlayerState {
+    #[getter(player_id)]
+    const fn player_id_py(&self) -> u8 {
+        self.player_id()
+    }
+    #[getter(kyoku)]
+    const fn kyoku_py(&self) -> u8 {
+        self.kyoku()
+    }
+    #[getter(honba)]
+    const fn honba_py(&self) -> u8 {
+        self.honba()
+    }
+    #[getter(kyotaku)]
+    const fn kyotaku_py(&self) -> u8 {
+        self.kyotaku()
+    }
+    #[getter(is_oya)]
+    const fn is_oya_py(&self) -> bool {
+        self.is_oya()
+    }
+    #[getter(tehai)]
+    const fn tehai_py(&self) -> [u8; 34] {
+        self.tehai()
+    }
+    #[getter(akas_in_hand)]
+    const fn akas_in_hand_py(&self) -> [bool; 3] {
+        self.akas_in_hand()
+    }
+    #[getter(chis)]
+    fn chis_py(&self) -> &[u8] {
+        self.chis()
+    }
+    #[getter(pons)]
+    fn pons_py(&self) -> &[u8] {
+        self.pons()
+    }
+    #[getter(minkans)]
+    fn minkans_py(&self) -> &[u8] {
+        self.minkans()
+    }
+    #[getter(ankans)]
+    fn ankans_py(&self) -> &[u8] {
+        self.ankans()
+    }
+    #[getter(at_turn)]
+    const fn at_turn_py(&self) -> u8 {
+        self.at_turn()
+    }
+    #[getter(shanten)]
+    const fn shanten_py(&self) -> i8 {
+        self.shanten()
+    }
+    #[getter(waits)]
+    const fn waits_py(&self) -> [bool; 34] {
+        self.waits()
+    }
+    #[getter(last_cans)]
+    const fn last_cans_py(&self) -> ActionCandidate {
+        self.last_cans()
+    }
+    #[getter(can_w_riichi)]
+    const fn can_w_riichi_py(&self) -> bool {
+        self.can_w_riichi()
+    }
+    #[getter(self_riichi_declared)]
+    const fn self_riichi_declared_py(&self) -> bool {
+        self.self_riichi_declared()
+    }
+    #[getter(self_riichi_accepted)]
+    const fn self_riichi_accepted_py(&self) -> bool {
+        self.self_riichi_accepted()
+    }
+    #[getter(at_furiten)]
+    const fn at_furiten_py(&self) -> bool {
+        self.at_furiten()
+    }
+
     #[inline]
     #[pyo3(name = "last_self_tsumo")]
     fn last_self_tsumo_py(&self) -> Option<String> {
@@ -103,14 +202,6 @@ impl PlayerState {
     fn last_kawa_tile_py(&self) -> Option<String> {
         self.last_kawa_tile.map(|t| t.to_string())
     }
-
-    #[getter]
-    #[inline]
-    #[must_use]
-    pub const fn last_cans(&self) -> ActionCandidate {
-        self.last_cans
-    }
-
     #[inline]
     #[pyo3(name = "ankan_candidates")]
     fn ankan_candidates_py(&self) -> Vec<String> {
@@ -127,54 +218,78 @@ impl PlayerState {
             .map(|t| t.to_string())
             .collect()
     }
+}
 
-    #[getter]
+impl PlayerState {
     #[inline]
     #[must_use]
-    pub const fn can_w_riichi(&self) -> bool {
-        self.can_w_riichi
+    pub const fn last_self_tsumo(&self) -> Option<Tile> {
+        self.last_self_tsumo
     }
-    #[getter]
     #[inline]
     #[must_use]
-    pub const fn self_riichi_declared(&self) -> bool {
-        self.riichi_declared[0]
+    pub const fn last_kawa_tile(&self) -> Option<Tile> {
+        self.last_kawa_tile
     }
-    #[getter]
+
     #[inline]
     #[must_use]
-    pub const fn self_riichi_accepted(&self) -> bool {
-        self.riichi_accepted[0]
+    pub fn ankan_candidates(&self) -> &[Tile] {
+        &self.ankan_candidates
     }
-
-    #[getter]
     #[inline]
     #[must_use]
-    pub const fn at_furiten(&self) -> bool {
-        self.at_furiten
+    pub fn kakan_candidates(&self) -> &[Tile] {
+        &self.kakan_candidates
     }
-}
 
-impl PlayerState {
     #[inline]
     #[must_use]
-    pub const fn last_self_tsumo(&self) -> Option<Tile> {
-        self.last_self_tsumo
+    pub const fn aka_config(&self) -> AkaConfig {
+        self.aka_config
     }
+    /// Number of each aka in hand, as `akas_in_hand` only tells whether there
+    /// is any.
     #[inline]
     #[must_use]
-    pub const fn last_kawa_tile(&self) -> Option<Tile> {
-        self.last_kawa_tile
+    pub const fn aka_counts_in_hand(&self) -> [u8; 3] {
+        self.akas_in_hand
     }
 
+    /// The absolute ID of the player liable (包) for our daisangen or
+    /// daisuushii, who pays the whole tsumo or half of the ron of our agari.
     #[inline]
     #[must_use]
-    pub fn ankan_candidates(&self) -> &[Tile] {
-        &self.ankan_candidates
+    pub const fn pao_actor(&self) -> Option<u8> {
+        self.pao_actor
     }
+
+    /// Number of each tile visible to us, which counts every tile that has
+    /// ever been in our hand (including the ones we have since discarded or
+    /// called with), every tile in the other players' rivers and melds, the
+    /// tile each of their kans is made of, and the dora indicators. Ura
+    /// indicators are never counted. Akas are counted as their normal
+    /// counterparts.
+    ///
+    /// Our own concealed tiles count as visible, as they can't be held by any
+    /// opponent either.
     #[inline]
     #[must_use]
-    pub fn kakan_candidates(&self) -> &[Tile] {
-        &self.kakan_candidates
+    pub const fn visible_tile_counts(&self) -> [u8; 34] {
+        self.tiles_seen
+    }
+
+    /// The fuuros and ankans of each player by relative seat, in the order
+    /// they are made. A pon is turned into a shouminkan in place.
+    #[must_use]
+    pub fn melds(&self) -> [Vec<Meld>; 4] {
+        self.melds.each_ref().map(|melds| melds.to_vec())
+    }
+
+    /// `4 - visible_tile_counts()`, i.e. the number of each tile that is
+    /// either in the wall or in opponents' concealed hands.
+    #[must_use]
+    pub fn remaining_counts(&self) -> [u8; 34] {
+        self.tiles_seen.map(|seen| 4 - seen)
     }
 }
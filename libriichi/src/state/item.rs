@@ -4,14 +4,14 @@ use std::fmt;
 use serde::Serialize;
 use tinyvec::ArrayVec;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub(super) struct KawaItem {
     pub(super) chi_pon: Option<ChiPon>,
     pub(super) kan: ArrayVec<[Tile; 4]>,
     pub(super) sutehai: Sutehai,
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub(super) struct Sutehai {
     pub(super) tile: Tile,
     // only for normal dora, aka is not included
@@ -20,7 +20,7 @@ pub(super) struct Sutehai {
     pub(super) is_riichi: bool,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub(super) struct ChiPon {
     pub(super) consumed: [Tile; 2],
     pub(super) target_tile: Tile,
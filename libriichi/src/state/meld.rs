@@ -0,0 +1,90 @@
+use crate::tile::Tile;
+
+use tinyvec::ArrayVec;
+
+/// A called set or ankan, see `PlayerState::melds`. `pai` is the
+/// called tile, and `target` is the relative seat of the player it is called
+/// from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Meld {
+    Chi {
+        pai: Tile,
+        consumed: [Tile; 2],
+        target: u8,
+    },
+    Pon {
+        pai: Tile,
+        consumed: [Tile; 2],
+        target: u8,
+    },
+    /// 大明槓.
+    Minkan {
+        pai: Tile,
+        consumed: [Tile; 3],
+        target: u8,
+    },
+    Ankan {
+        consumed: [Tile; 4],
+    },
+    /// 加槓, a `Pon` that `added` has been added to.
+    Shouminkan {
+        pai: Tile,
+        consumed: [Tile; 2],
+        added: Tile,
+        target: u8,
+    },
+}
+
+impl Default for Meld {
+    /// Only a placeholder for `ArrayVec`.
+    fn default() -> Self {
+        Self::Ankan {
+            consumed: [Tile::default(); 4],
+        }
+    }
+}
+
+impl Meld {
+    /// All tiles of the meld, with akas kept. The tiles taken from the hand
+    /// come first, then `pai`, and finally `added` for a shouminkan.
+    #[must_use]
+    pub fn tiles(self) -> ArrayVec<[Tile; 4]> {
+        let mut ret = ArrayVec::new();
+        match self {
+            Self::Chi { pai, consumed, .. } | Self::Pon { pai, consumed, .. } => {
+                ret.extend(consumed);
+                ret.push(pai);
+            }
+            Self::Minkan { pai, consumed, .. } => {
+                ret.extend(consumed);
+                ret.push(pai);
+            }
+            Self::Ankan { consumed } => ret.extend(consumed),
+            Self::Shouminkan {
+                pai,
+                consumed,
+                added,
+                ..
+            } => {
+                ret.extend(consumed);
+                ret.push(pai);
+                ret.push(added);
+            }
+        }
+        ret
+    }
+
+    /// The relative seat of the player the tile is called from, `None` for an
+    /// ankan.
+    #[inline]
+    #[must_use]
+    pub const fn target(self) -> Option<u8> {
+        match self {
+            Self::Chi { target, .. }
+            | Self::Pon { target, .. }
+            | Self::Minkan { target, .. }
+            | Self::Shouminkan { target, .. } => Some(target),
+            Self::Ankan { .. } => None,
+        }
+    }
+}
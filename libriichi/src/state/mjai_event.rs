@@ -0,0 +1,144 @@
+use super::{Meld, PlayerState};
+use crate::chi_type::ChiType;
+use crate::mjai::Event;
+use crate::tile::Tile;
+use crate::tu8;
+use std::array;
+
+use anyhow::{Context, Result, ensure};
+use serde_json as json;
+
+/// Builders of the mjai events of this player's own actions at the current
+/// decision point, for bridging agent outputs to an mjai server. Every event
+/// is checked by `validate_reaction` before being serialized.
+impl PlayerState {
+    pub fn to_mjai_dahai_event(&self, pai: Tile, tsumogiri: bool) -> Result<json::Value> {
+        if !tsumogiri && self.last_self_tsumo == Some(pai) {
+            let akas = self.akas_in_hand_of(pai);
+            let copies = if pai.is_aka() {
+                akas
+            } else {
+                self.tehai[pai.as_usize()] - akas
+            };
+            ensure!(
+                copies > 1,
+                "{pai} is the only one in hand, must be tsumogiri"
+            );
+        }
+
+        self.to_mjai_value(Event::Dahai {
+            actor: self.player_id,
+            pai,
+            tsumogiri,
+        })
+    }
+
+    pub fn to_mjai_reach_event(&self) -> Result<json::Value> {
+        self.to_mjai_value(Event::Reach {
+            actor: self.player_id,
+        })
+    }
+
+    /// Akas in hand are preferred for `consumed`.
+    pub fn to_mjai_chi_event(&self, chi_type: ChiType) -> Result<json::Value> {
+        let pai = self.last_kawa_tile.context("no last kawa tile")?;
+        let consumed = match chi_type {
+            ChiType::Low => [pai.next(), pai.next().next()],
+            ChiType::Mid => [pai.prev(), pai.next()],
+            ChiType::High => [pai.prev().prev(), pai.prev()],
+        }
+        .map(|t| {
+            let t = t.deaka();
+            if self.akas_in_hand_of(t) > 0 {
+                t.akaize()
+            } else {
+                t
+            }
+        });
+
+        self.to_mjai_value(Event::Chi {
+            actor: self.player_id,
+            target: self.last_cans.target_actor,
+            pai,
+            consumed,
+        })
+    }
+
+    /// Akas in hand are preferred for `consumed`.
+    pub fn to_mjai_pon_event(&self) -> Result<json::Value> {
+        let pai = self.last_kawa_tile.context("no last kawa tile")?;
+        self.to_mjai_value(Event::Pon {
+            actor: self.player_id,
+            target: self.last_cans.target_actor,
+            pai,
+            consumed: self.tiles_in_hand(pai),
+        })
+    }
+
+    /// Builds a daiminkan if it is possible, in which case `tile` is ignored,
+    /// otherwise an ankan or kakan of `tile`.
+    pub fn to_mjai_kan_event(&self, tile: Tile) -> Result<json::Value> {
+        let actor = self.player_id;
+        let event = if self.last_cans.can_daiminkan {
+            let pai = self.last_kawa_tile.context("no last kawa tile")?;
+            Event::Daiminkan {
+                actor,
+                target: self.last_cans.target_actor,
+                pai,
+                consumed: self.tiles_in_hand(pai),
+            }
+        } else if self.ankan_candidates.contains(&tile.deaka()) {
+            Event::Ankan {
+                actor,
+                consumed: self.tiles_in_hand(tile),
+            }
+        } else {
+            let consumed = self.melds[0]
+                .iter()
+                .find_map(|m| match *m {
+                    Meld::Pon { pai, consumed, .. } if pai.deaka() == tile.deaka() => {
+                        Some([consumed[0], consumed[1], pai])
+                    }
+                    _ => None,
+                })
+                .context("no pon to kakan")?;
+            let [pai] = self.tiles_in_hand(tile);
+            Event::Kakan {
+                actor,
+                pai,
+                consumed,
+            }
+        };
+        self.to_mjai_value(event)
+    }
+
+    pub fn to_mjai_hora_event(&self) -> Result<json::Value> {
+        self.to_mjai_value(Event::Hora {
+            actor: self.player_id,
+            target: self.last_cans.target_actor,
+            deltas: None,
+            ura_markers: None,
+        })
+    }
+
+    fn to_mjai_value(&self, event: Event) -> Result<json::Value> {
+        self.validate_reaction(&event)?;
+        Ok(json::to_value(event)?)
+    }
+
+    pub(super) const fn akas_in_hand_of(&self, tile: Tile) -> u8 {
+        match tile.deaka().as_u8() {
+            tu8!(5m) => self.akas_in_hand[0],
+            tu8!(5p) => self.akas_in_hand[1],
+            tu8!(5s) => self.akas_in_hand[2],
+            _ => 0,
+        }
+    }
+
+    /// Returns `N` tiles of the same kind as `tile` in hand, akas first.
+    pub(super) fn tiles_in_hand<const N: usize>(&self, tile: Tile) -> [Tile; N] {
+        let tile = tile.deaka();
+        let akas = self.akas_in_hand_of(tile) as usize;
+        array::from_fn(|i| if i < akas { tile.akaize() } else { tile })
+    }
+}
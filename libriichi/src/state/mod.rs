@@ -1,22 +1,39 @@
 mod action;
 mod agent_helper;
+mod binary;
+mod discard_explanation;
+mod dora_detail;
 mod getter;
 mod item;
+mod meld;
+mod mjai_event;
 mod obs_repr;
 mod player_state;
+mod push_fold;
 mod sp_tables;
+mod suit_read;
 mod update;
+mod wait_shape;
 
 #[cfg(test)]
 mod test;
 
+#[cfg(feature = "python")]
 use crate::py_helper::add_submodule;
-pub use action::ActionCandidate;
-pub use player_state::PlayerState;
-pub use sp_tables::SinglePlayerTables;
+pub use action::{ActionCandidate, KanType, LegalActions};
+pub use discard_explanation::DiscardExplanation;
+pub use dora_detail::DoraDetail;
+pub use meld::Meld;
+pub use player_state::{KanUraRule, PlayerState, RenchanRule, RenhouRule, WallRule};
+pub use push_fold::{PushFoldAdvice, PushFoldCandidate, estimate_deal_in_cost};
+pub use sp_tables::{SinglePlayerOptions, SinglePlayerTables};
+pub use suit_read::SuitRead;
+pub use wait_shape::{WaitKind, WaitShape};
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+#[cfg(feature = "python")]
 pub(crate) fn register_module(
     py: Python<'_>,
     prefix: &str,
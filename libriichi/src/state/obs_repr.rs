@@ -8,7 +8,9 @@ use crate::{tu8, tuz};
 use std::num::NonZeroUsize;
 
 use ndarray::prelude::*;
+#[cfg(feature = "python")]
 use numpy::{PyArray1, PyArray2};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 const SELF_KAWA_ITEM_CHANNELS: usize = 4;
@@ -142,7 +144,7 @@ impl<'a> ObsEncoderContext<'a> {
             .akas_in_hand
             .into_iter()
             .enumerate()
-            .filter(|&(_, has_it)| has_it)
+            .filter(|&(_, n)| n > 0)
             .for_each(|(i, _)| self.arr.fill(self.idx + i, 1.));
         self.idx += 3;
 
@@ -300,8 +302,9 @@ impl<'a> ObsEncoderContext<'a> {
             self.encode_tile_set(player_kawa_overview.iter().copied());
         }
 
-        for player_fuuro in &state.fuuro_overview {
-            for f in player_fuuro {
+        for rel in 0..4 {
+            let mut num_fuuros = 0;
+            for f in state.fuuro_tiles(rel) {
                 for tile in f {
                     let tile_id = tile.deaka().as_usize();
                     let i = (0..4)
@@ -316,12 +319,13 @@ impl<'a> ObsEncoderContext<'a> {
                     }
                 }
                 self.idx += 5;
+                num_fuuros += 1;
             }
-            self.idx += (4 - player_fuuro.len()) * 5;
+            self.idx += (4 - num_fuuros) * 5;
         }
 
-        for player_ankan in &state.ankan_overview {
-            for tile in player_ankan {
+        for rel in 0..4 {
+            for tile in state.ankan_tiles(rel) {
                 let tile_id = tile.as_usize();
                 self.arr.assign(self.idx, tile_id, 1.);
             }
@@ -562,7 +566,13 @@ impl<'a> ObsEncoderContext<'a> {
         self.idx += 1;
 
         if self.version == 4 {
-            if let Ok(SinglePlayerTables { max_ev_table }) = state.single_player_tables() {
+            // The table can be empty with `sp_reproduce_cpp_ver`, which only
+            // considers the normal shanten, e.g. for a kokushi hand.
+            if let Some(SinglePlayerTables { max_ev_table, .. }) = state
+                .single_player_tables()
+                .ok()
+                .filter(|tables| !tables.max_ev_table.is_empty())
+            {
                 // Get the max EV from the table that maximizes EV, which should
                 // be the global max EV.
                 //
@@ -737,7 +747,7 @@ impl<'a> ObsEncoderContext<'a> {
         if let Some(k) = item {
             if let Some(cp) = &k.chi_pon {
                 // Aka info of the chi/pon is not encoded in the kawa detail;
-                // they are included in fuuro_overview instead.
+                // they are included in the fuuros instead.
                 //
                 // This is one-hot.
                 let a = cp.consumed[0].deaka().as_usize();
@@ -773,6 +783,7 @@ impl<'a> ObsEncoderContext<'a> {
     }
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl PlayerState {
     /// Returns `(obs, mask)`
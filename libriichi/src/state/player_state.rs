@@ -1,25 +1,147 @@
 use super::action::ActionCandidate;
 use super::item::{ChiPon, KawaItem, Sutehai};
+use super::meld::Meld;
+use crate::algo::agari::LocalYaku;
+use crate::algo::point::TsumoRounding;
 use crate::algo::sp::Candidate;
-use crate::hand::tiles_to_string;
-use crate::must_tile;
-use crate::tile::Tile;
+use crate::hand::tiles37_to_string;
+use crate::tile::{AkaConfig, Tile};
+use crate::{must_tile, tuz};
 use std::iter;
 
-use anyhow::Result;
+use anyhow::{Result, ensure};
 use derivative::Derivative;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use serde_json as json;
 use tinyvec::{ArrayVec, TinyVec};
 
+/// Which kan-uras apply to a win, for rulesets that restrict them.
+///
+/// Kan doras are taken as revealed when the `dora` event arrives, which is
+/// right after an ankan, and after the next discard or before the next rinshan
+/// tsumo for daiminkan and kakan. A win before that, e.g. rinshan kaihou after
+/// a daiminkan, does not get the kan dora nor its kan-ura.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KanUraRule {
+    /// Every revealed kan dora has its kan-ura.
+    #[default]
+    All,
+    /// Kan-uras only apply to a win by tsumo, or to the ones revealed by our
+    /// own kans.
+    OwnKanOrTsumo,
+}
+
+/// How 人和, a ron before our first draw with no call in between, is scored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenhouRule {
+    /// Not a yaku.
+    #[default]
+    None,
+    /// At least a mangan, or the hand's own value if higher.
+    Mangan,
+    /// A single yakuman.
+    Yakuman,
+}
+
+/// What keeps the dealership besides a win of the oya, as assumed by the
+/// all-last heuristics in `rule_based_agari` and `rule_based_ryukyoku`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenchanRule {
+    /// テンパイ連荘: the oya also continues by being tenpai at an exhaustive
+    /// draw, and by abortive draws such as 九種九牌.
+    #[default]
+    Tenpai,
+    /// アガリ連荘: the oya continues only by winning. Every draw, including
+    /// abortive ones, passes the dealership.
+    Agari,
+}
+
+/// The wall of a game, for variants other than the standard 4-player one.
+///
+/// The live wall starts with `num_tiles - dead_wall_size - 13 * num_players`
+/// tiles, which is 70 for `STANDARD` and 55 for `SANMA`. Seats are still
+/// among 4 in `PlayerState`, where the absolute seats from 0 to
+/// `num_players - 1` play, as in mjai logs, so in a 3-player game seat 3
+/// never plays and is simply skipped by the events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallRule {
+    pub num_players: u8,
+    pub num_tiles: u8,
+    pub dead_wall_size: u8,
+}
+
+impl Default for WallRule {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+impl WallRule {
+    pub const STANDARD: Self = Self {
+        num_players: 4,
+        num_tiles: 136,
+        dead_wall_size: 14,
+    };
+    /// 三人麻雀, without 2m to 8m.
+    pub const SANMA: Self = Self {
+        num_players: 3,
+        num_tiles: 108,
+        dead_wall_size: 14,
+    };
+
+    #[must_use]
+    pub const fn initial_tiles_left(self) -> u8 {
+        self.num_tiles - self.dead_wall_size - 13 * self.num_players
+    }
+
+    /// Estimates how many more tsumos we get from a live wall of
+    /// `tiles_left`, where `draws_before_ours` is the number of tiles to be
+    /// drawn until and including our next tsumo, 0 if we have just drawn,
+    /// assuming no calls or kans. Every player draws once per turn, so it is
+    /// `(tiles_left - draws_before_ours) / num_players`.
+    ///
+    /// Also returns whether the last of them is the haitei, i.e. the wall is
+    /// exhausted right at it, which is the case if the division has no
+    /// remainder.
+    #[must_use]
+    pub const fn estimate_tsumos_left(self, tiles_left: u8, draws_before_ours: u8) -> (u8, bool) {
+        let tiles_left = tiles_left.saturating_sub(draws_before_ours);
+        (
+            tiles_left / self.num_players,
+            tiles_left % self.num_players == 0,
+        )
+    }
+
+    /// The number of tiles drawn from the turn after the absolute seat
+    /// `discarder` until and including the next tsumo of the absolute seat
+    /// `seat`, skipping the seats that do not play, for the
+    /// `draws_before_ours` of `estimate_tsumos_left`.
+    #[must_use]
+    pub const fn draws_until(self, discarder: u8, seat: u8) -> u8 {
+        let mut draws = 0;
+        let mut cur = discarder;
+        loop {
+            cur = (cur + 1) % 4;
+            if cur < self.num_players {
+                draws += 1;
+            }
+            if cur == seat {
+                return draws;
+            }
+        }
+    }
+}
+
 /// `PlayerState` is the core of the lib, which holds all the observable game
 /// state information from a specific seat's perspective with the ability to
 /// identify the legal actions the specified player can make upon an incoming
 /// mjai event, along with some helper functions to build an actual agent.
 /// Notably, `PlayerState` encodes observation features into numpy arrays which
 /// serve as inputs for deep learning model.
-#[pyclass]
-#[derive(Clone, Derivative)]
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, PartialEq, Eq, Derivative)]
 #[derivative(Default)]
 pub struct PlayerState {
     pub(super) player_id: u8,
@@ -33,15 +155,17 @@ pub struct PlayerState {
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) waits: [bool; 34],
 
+    /// Always `tile::doras_from_indicators(&dora_indicators)`.
     #[derivative(Default(value = "[0; 34]"))]
     pub(super) dora_factor: [u8; 34],
 
-    /// For calculating `waits` and `doras_seen`, also for SPCalculator.
+    /// For calculating `waits` and `doras_seen`, also for SPCalculator. See
+    /// `visible_tile_counts` for what counts.
     #[derivative(Default(value = "[0; 34]"))]
     pub(super) tiles_seen: [u8; 34],
 
     /// For SPCalculator.
-    pub(super) akas_seen: [bool; 3],
+    pub(super) akas_seen: [u8; 3],
 
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) keep_shanten_discards: [bool; 34],
@@ -55,6 +179,15 @@ pub struct PlayerState {
     /// Used for furiten check.
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) discarded_tiles: [bool; 34],
+    /// Tiles that can't deal into each player, see `genbutsu`. Deaka'd.
+    #[derivative(Default(value = "[[false; 34]; 4]"))]
+    pub(super) safe_tiles: [[bool; 34]; 4],
+    /// Tiles discarded by others since each player's last discard, which are
+    /// safe until that player discards again (同巡内フリテン).
+    #[derivative(Default(value = "[[false; 34]; 4]"))]
+    pub(super) safe_tiles_this_cycle: [[bool; 34]; 4],
+    /// Used for nagashi mangan check.
+    pub(super) own_discard_called: bool,
 
     pub(super) bakaze: Tile,
     pub(super) jikaze: Tile,
@@ -70,6 +203,35 @@ pub struct PlayerState {
     /// Including 西入 sudden death.
     pub(super) is_all_last: bool,
     pub(super) dora_indicators: ArrayVec<[Tile; 5]>,
+    /// Relative actors of the kans whose kan dora is not yet revealed.
+    pub(super) pending_kan_actors: ArrayVec<[u8; 4]>,
+    /// Relative actors of the kans that revealed `dora_indicators[1..]`, None
+    /// if the dora indicator was not preceded by a kan.
+    pub(super) kan_dora_actors: ArrayVec<[Option<u8>; 4]>,
+    pub(super) kan_ura_rule: KanUraRule,
+    /// Whether 切り上げ満貫 applies in `agari_points`.
+    pub(super) kiriage_mangan: bool,
+    pub(super) renhou_rule: RenhouRule,
+    /// Whether 13 han or more is 数え役満 rather than sanbaiman in
+    /// `agari_points`.
+    #[derivative(Default(value = "true"))]
+    pub(super) kazoe_yakuman: bool,
+    /// How the payments of a ko's tsumo are rounded in `agari_points`.
+    pub(super) tsumo_rounding: TsumoRounding,
+    /// Whether 後付け is allowed, i.e. a win is valid as long as it has a yaku
+    /// with the winning tile, even if some other wait of the hand does not.
+    #[derivative(Default(value = "true"))]
+    pub(super) atozuke: bool,
+    /// Whether an ankan after riichi must also keep the shapes of the tenpai
+    /// and the agari, see `agari::check_ankan_after_riichi`.
+    pub(super) strict_riichi_ankan: bool,
+    /// Whether kokushi can rob an ankan (暗槓の国士搶槓), see `can_chankan`.
+    #[derivative(Default(value = "true"))]
+    pub(super) kokushi_ankan_chankan: bool,
+    /// Local yakus that count in `agari_points` and `last_cans`.
+    pub(super) local_yaku: LocalYaku,
+    pub(super) renchan_rule: RenchanRule,
+    pub(super) wall_rule: WallRule,
 
     /// 24 is the theoretical max size of kawa, however, since None is included
     /// in the kawa, in some very rare cases (about one in a million hanchans),
@@ -84,9 +246,9 @@ pub struct PlayerState {
     /// Using 34-D arrays here may be more efficient, but I don't want to mess up
     /// with aka doras.
     pub(super) kawa_overview: [ArrayVec<[Tile; 24]>; 4],
-    pub(super) fuuro_overview: [ArrayVec<[ArrayVec<[Tile; 4]>; 4]>; 4],
-    /// In this field all `Tile` are deaka'd.
-    pub(super) ankan_overview: [ArrayVec<[Tile; 4]>; 4],
+    /// Both the fuuros and the ankans of each player in the order they are
+    /// made, see `melds()`, `fuuro_tiles` and `ankan_tiles`.
+    pub(super) melds: [ArrayVec<[Meld; 4]>; 4],
 
     pub(super) riichi_declared: [bool; 4],
     pub(super) riichi_accepted: [bool; 4],
@@ -109,8 +271,13 @@ pub struct PlayerState {
 
     pub(super) can_w_riichi: bool,
     pub(super) is_w_riichi: bool,
+    /// オープン立直, only set by `declare_open_riichi`.
+    pub(super) is_open_riichi: bool,
     pub(super) at_rinshan: bool,
     pub(super) at_ippatsu: bool,
+    /// Number of fuuros and ankans on board when our riichi is accepted, only
+    /// for checking that `at_ippatsu` has been cleared by any call since.
+    pub(super) calls_at_riichi: usize,
     pub(super) at_furiten: bool,
     pub(super) to_mark_same_cycle_furiten: Option<()>,
 
@@ -118,6 +285,8 @@ pub struct PlayerState {
     pub(super) kans_on_board: u8,
 
     pub(super) is_menzen: bool,
+    /// Absolute ID of the player who fed our third dragon or fourth wind set.
+    pub(super) pao_actor: Option<u8>,
     /// For agari calc, all deaka'd.
     pub(super) chis: ArrayVec<[u8; 4]>,
     pub(super) pons: ArrayVec<[u8; 4]>,
@@ -129,7 +298,8 @@ pub struct PlayerState {
     pub(super) doras_owned: [u8; 4],
     pub(super) doras_seen: u8,
 
-    pub(super) akas_in_hand: [bool; 3],
+    pub(super) akas_in_hand: [u8; 3],
+    pub(super) aka_config: AkaConfig,
 
     /// For shanten calc.
     pub(super) tehai_len_div3: u8,
@@ -139,33 +309,46 @@ pub struct PlayerState {
     pub(super) has_next_shanten_discard: bool,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl PlayerState {
     /// Panics if `player_id` is outside of range [0, 3].
     #[new]
-    #[must_use]
-    pub fn new(player_id: u8) -> Self {
-        assert!(player_id < 4, "{player_id} is not in range [0, 3]");
-        Self {
-            player_id,
-            ..Default::default()
-        }
+    fn new_py(player_id: u8) -> Self {
+        Self::new(player_id)
     }
 
     /// Returns an `ActionCandidate`.
     #[pyo3(name = "update")]
-    pub(super) fn update_json(&mut self, mjai_json: &str) -> Result<ActionCandidate> {
+    fn update_json(&mut self, mjai_json: &str) -> Result<ActionCandidate> {
         let event = json::from_str(mjai_json)?;
         self.update(&event)
     }
 
     /// Raises an exception if the action is not valid.
     #[pyo3(name = "validate_reaction")]
-    pub(super) fn validate_reaction_json(&self, mjai_json: &str) -> Result<()> {
+    fn validate_reaction_json(&self, mjai_json: &str) -> Result<()> {
         let action = json::from_str(mjai_json)?;
         self.validate_reaction(&action)
     }
 
+    #[pyo3(name = "brief_info")]
+    fn brief_info_py(&self) -> String {
+        self.brief_info()
+    }
+}
+
+impl PlayerState {
+    /// Panics if `player_id` is outside of range [0, 3].
+    #[must_use]
+    pub fn new(player_id: u8) -> Self {
+        assert!(player_id < 4, "{player_id} is not in range [0, 3]");
+        Self {
+            player_id,
+            ..Default::default()
+        }
+    }
+
     /// For debug only.
     ///
     /// Return a human readable description of the current state.
@@ -245,9 +428,9 @@ single player table (max EV):
             self.at_turn,
             self.jikaze,
             self.scores,
-            tiles_to_string(&self.tehai, self.akas_in_hand),
-            self.fuuro_overview[0],
-            self.ankan_overview[0],
+            self.tehai_str(),
+            self.fuuro_tiles(0).collect::<Vec<_>>(),
+            self.ankan_tiles(0).collect::<Vec<_>>(),
             self.tehai_len_div3,
             self.shanten,
             self.real_time_shanten(),
@@ -262,3 +445,203 @@ single player table (max EV):
         )
     }
 }
+
+impl PlayerState {
+    /// Same as `new`, but for rulesets with a different number of akas.
+    #[must_use]
+    pub fn with_aka_config(player_id: u8, aka_config: AkaConfig) -> Self {
+        Self {
+            aka_config,
+            ..Self::new(player_id)
+        }
+    }
+
+    /// Sets the rule of which kan-uras apply, see `KanUraRule`.
+    #[must_use]
+    pub fn with_kan_ura_rule(self, kan_ura_rule: KanUraRule) -> Self {
+        Self {
+            kan_ura_rule,
+            ..self
+        }
+    }
+
+    /// Enables 切り上げ満貫 in `agari_points`, see `Point::calc_with_kiriage`.
+    #[must_use]
+    pub fn with_kiriage_mangan(self, kiriage_mangan: bool) -> Self {
+        Self {
+            kiriage_mangan,
+            ..self
+        }
+    }
+
+    /// Sets whether 数え役満 applies in `agari_points`, see
+    /// `Point::calc_with_rules`. It does by default.
+    #[must_use]
+    pub fn with_kazoe_yakuman(self, kazoe_yakuman: bool) -> Self {
+        Self {
+            kazoe_yakuman,
+            ..self
+        }
+    }
+
+    /// Sets how the payments of a ko's tsumo are rounded in `agari_points`,
+    /// see `TsumoRounding`. They are rounded per payer by default.
+    #[must_use]
+    pub fn with_tsumo_rounding(self, tsumo_rounding: TsumoRounding) -> Self {
+        Self {
+            tsumo_rounding,
+            ..self
+        }
+    }
+
+    /// Sets whether 後付け is allowed. It is by default. When it is not (後付け
+    /// なし), a win that relies on a pattern-based yaku is only valid if every
+    /// wait of the hand has a yaku, which affects `last_cans` and
+    /// `discard_candidates_with_unconditional_tenpai`. Yakus from the way of
+    /// winning such as 立直 and 門前清自摸和 are not affected.
+    #[must_use]
+    pub fn with_atozuke(self, atozuke: bool) -> Self {
+        Self { atozuke, ..self }
+    }
+
+    /// Sets whether an ankan after riichi must also keep the shapes of the
+    /// tenpai and the agari, rather than only the waits as on Tenhou, which is
+    /// the default. It affects `last_cans` and `can_ankan_after_riichi`.
+    #[must_use]
+    pub fn with_strict_riichi_ankan(self, strict_riichi_ankan: bool) -> Self {
+        Self {
+            strict_riichi_ankan,
+            ..self
+        }
+    }
+
+    /// Sets whether kokushi can rob an ankan, which is the default as on
+    /// Tenhou. A normal hand can only ever rob a kakan. It affects
+    /// `last_cans` and `can_chankan`.
+    #[must_use]
+    pub fn with_kokushi_ankan_chankan(self, kokushi_ankan_chankan: bool) -> Self {
+        Self {
+            kokushi_ankan_chankan,
+            ..self
+        }
+    }
+
+    /// Sets the local yakus that count, see `LocalYaku`. None of them do by
+    /// default. It affects `agari_points`, as well as `last_cans` when
+    /// 後付け is not allowed.
+    #[must_use]
+    pub fn with_local_yaku(self, local_yaku: LocalYaku) -> Self {
+        Self { local_yaku, ..self }
+    }
+
+    /// Sets what keeps the dealership in the all-last heuristics, see
+    /// `RenchanRule`.
+    #[must_use]
+    pub fn with_renchan_rule(self, renchan_rule: RenchanRule) -> Self {
+        Self {
+            renchan_rule,
+            ..self
+        }
+    }
+
+    /// Sets the wall for non-standard games, see `WallRule`, which affects
+    /// `tiles_left` and the number of tsumos `single_player_tables` assumes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `wall_rule` is not for 3 or 4 players, or has too few tiles
+    /// to deal the hands from.
+    #[must_use]
+    pub fn with_wall_rule(self, wall_rule: WallRule) -> Self {
+        assert!(
+            (3..=4).contains(&wall_rule.num_players)
+                && wall_rule.num_tiles >= wall_rule.dead_wall_size + 13 * wall_rule.num_players,
+            "invalid wall_rule {wall_rule:?}",
+        );
+        Self { wall_rule, ..self }
+    }
+
+    /// Sets how 人和 is scored in `agari_points`, see `RenhouRule`.
+    #[must_use]
+    pub fn with_renhou_rule(self, renhou_rule: RenhouRule) -> Self {
+        Self {
+            renhou_rule,
+            ..self
+        }
+    }
+
+    /// Marks our riichi declared in this kyoku as an open riichi, which is
+    /// worth one more han in `agari_points`. mjai has no event for it, so it
+    /// must be called by the caller under a ruleset that allows it, any time
+    /// after our `reach` event.
+    ///
+    /// The yakuman paid by a player who deals into an open riichi is not
+    /// handled.
+    pub fn declare_open_riichi(&mut self) -> Result<()> {
+        ensure!(self.riichi_declared[0], "riichi is not declared");
+        self.is_open_riichi = true;
+        Ok(())
+    }
+
+    /// Takes the kyoku as past its first go-around, as if a call had been
+    /// made, so that 天和, 地和, 人和, ダブル立直 and 九種九牌 no longer apply.
+    /// This is for analyzing a standalone hand without feeding made-up events,
+    /// and must be called after `start_kyoku` and before our first tsumo.
+    pub fn pass_first_turn(&mut self) -> Result<()> {
+        ensure!(self.at_turn == 0, "already past the first turn");
+        self.can_w_riichi = false;
+        Ok(())
+    }
+
+    /// For debug only.
+    ///
+    /// The concealed hand in tenhou.net/2 format, grouped by suit, with akas
+    /// as "0", e.g. "3067m 345p 11z", which can be parsed back with
+    /// `hand::hand_with_aka`.
+    #[must_use]
+    pub fn tehai_str(&self) -> String {
+        let mut tiles = [0; 37];
+        tiles[..34].copy_from_slice(&self.tehai);
+        for (i, &n) in self.akas_in_hand.iter().enumerate() {
+            tiles[tuz!(5m) + 9 * i] -= n;
+            tiles[tuz!(5mr) + i] = n;
+        }
+        tiles37_to_string(&tiles)
+    }
+
+    /// The tiles of each fuuro of the player at `rel` in the order they are
+    /// made, i.e. `Meld::tiles` of every meld except ankans.
+    pub(super) fn fuuro_tiles(&self, rel: usize) -> impl Iterator<Item = ArrayVec<[Tile; 4]>> + '_ {
+        self.melds[rel]
+            .iter()
+            .filter(|m| m.target().is_some())
+            .map(|m| m.tiles())
+    }
+
+    /// The deaka'd tile of each ankan of the player at `rel`.
+    pub(super) fn ankan_tiles(&self, rel: usize) -> impl Iterator<Item = Tile> + '_ {
+        self.melds[rel].iter().filter_map(|m| match m {
+            Meld::Ankan { consumed } => Some(consumed[0].deaka()),
+            _ => None,
+        })
+    }
+
+    /// For debug only.
+    ///
+    /// Same as `tehai_str`, followed by our melds in the order they are made,
+    /// each in brackets, e.g. "3067m 11z [345p] [7777z]".
+    #[must_use]
+    pub fn to_hand_string(&self) -> String {
+        let mut ret = self.tehai_str();
+        for meld in &self.melds[0] {
+            let mut tiles = [0; 37];
+            for t in meld.tiles() {
+                tiles[t.as_usize()] += 1;
+            }
+            ret.push_str(" [");
+            ret.push_str(&tiles37_to_string(&tiles));
+            ret.push(']');
+        }
+        ret
+    }
+}
@@ -0,0 +1,65 @@
+use super::Meld;
+use crate::algo::agari::Agari;
+use crate::algo::point::Point;
+use crate::tile::Tile;
+
+/// The win-versus-deal-in tradeoff of every legal discard, see
+/// `PlayerState::push_fold`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushFoldAdvice {
+    /// In the order of `discard_candidates_aka`.
+    pub candidates: Vec<PushFoldCandidate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PushFoldCandidate {
+    pub tile: Tile,
+    /// Expected score of our hand after the discard, from
+    /// `single_player_tables` at the current tsumo. It is `0` for discards
+    /// the tables don't cover, such as at high shanten.
+    pub ev: f32,
+    /// The probability of dealing in to any of the opponents with the discard.
+    pub risk: f32,
+    /// Whether no other candidate is at least as good in both `ev` and `risk`
+    /// and strictly better in one of them. The final decision is left to the
+    /// caller.
+    pub recommended: bool,
+}
+
+/// A rough estimate of what dealing in to an opponent costs, from what can be
+/// seen of their hand, for weighing `PushFoldCandidate::risk` in points.
+///
+/// The model is a flat base plus the doras shown in the fuuro:
+///
+/// - A riichi is taken as 3 han 40 fu, i.e. 5200 from a ko, which is about
+///   the average value of a riichi win.
+/// - Any other hand is taken as 2 han 30 fu, i.e. 2000 from a ko.
+/// - Each dora in `opponent_fuuro` by `dora_indicators` adds a han, as does
+///   each aka.
+///
+/// The opponent is assumed to be a ko, and the concealed tiles, yakuhai and
+/// the fu of the melds are not considered.
+#[must_use]
+pub fn estimate_deal_in_cost(
+    opponent_fuuro: &[Meld],
+    opponent_riichi: bool,
+    dora_indicators: &[Tile],
+) -> Point {
+    let (fu, base_han) = if opponent_riichi { (40, 3) } else { (30, 2) };
+    let doras: u8 = opponent_fuuro
+        .iter()
+        .flat_map(|m| m.tiles())
+        .map(|t| {
+            let n = dora_indicators
+                .iter()
+                .filter(|ind| ind.dora_from_indicator() == t.deaka())
+                .count();
+            n as u8 + t.is_aka() as u8
+        })
+        .sum();
+    Agari::Normal {
+        fu,
+        han: base_han + doras,
+    }
+    .point(false)
+}
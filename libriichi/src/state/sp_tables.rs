@@ -1,5 +1,70 @@
 use crate::algo::sp::Candidate;
+use crate::tile::Tile;
 
+use serde::Serialize;
+use serde_json as json;
+
+/// Tiles are serialized as mjai tile strings, with akas such as "5mr" apart
+/// from their normal counterparts.
+#[derive(Serialize)]
 pub struct SinglePlayerTables {
     pub max_ev_table: Vec<Candidate>,
+    /// The same table for playing the hand dama, see
+    /// `SPCalculator::calc_with_dama_table`. Only calculated on request, for
+    /// a closed hand that has not declared riichi yet, in which case
+    /// `max_ev_table` is the riichi one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dama_table: Option<Vec<Candidate>>,
+}
+
+/// What `PlayerState::single_player_tables_opts` calculates on top of the
+/// plain `single_player_tables`, all off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinglePlayerOptions {
+    /// 手変わり, see `SPCalculator::calc_tegawari`.
+    pub calc_tegawari: bool,
+    /// 向聴落とし, see `SPCalculator::calc_shanten_down`.
+    pub calc_shanten_down: bool,
+    /// An approximate bonus for the kan dora of an ankan on a held triplet,
+    /// see `SPCalculator::calc_kan_upgrade`.
+    pub calc_kan_upgrade: bool,
+    /// Replaces the number of our remaining tsumos, which is otherwise
+    /// estimated from `tiles_left` assuming no calls or kans, for callers
+    /// with a better estimate. It affects the haitei probability too, since
+    /// the haitei bonus, if any, is then applied to the last of the
+    /// overridden tsumos. It must not exceed `algo::sp::max_tsumos_left`.
+    pub tsumos_left_override: Option<u8>,
+    /// Also calculates `SinglePlayerTables::dama_table`, see
+    /// `PlayerState::single_player_tables_with_dama`.
+    pub calc_dama_table: bool,
+    /// Fills `Candidate::yakuman_prob`, see `SPCalculator::calc_yakuman_prob`.
+    /// 数え役満 is counted as yakuman iff it applies, see
+    /// `PlayerState::with_kazoe_yakuman`.
+    pub calc_yakuman_prob: bool,
+}
+
+impl SinglePlayerTables {
+    /// The discard with the highest EV, or `None` if the tables were made at
+    /// 3n+1. After riichi, it is always the drawn tile, since a tsumogiri is
+    /// the only discard.
+    #[must_use]
+    pub fn best_discard(&self) -> Option<Tile> {
+        self.max_ev_table
+            .first()
+            .map(|c| c.tile)
+            .filter(|t| !t.is_unknown())
+    }
+
+    /// The `n` best entries of `max_ev_table`, or all of them if there are
+    /// fewer.
+    #[must_use]
+    pub fn best_n(&self, n: usize) -> &[Candidate] {
+        &self.max_ev_table[..n.min(self.max_ev_table.len())]
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        // There is no map with non-string keys, so this never fails.
+        json::to_string(self).unwrap()
+    }
 }
@@ -0,0 +1,11 @@
+/// How likely an opponent is going for 混一色 or 清一色, see
+/// `PlayerState::opponent_suit_read`. Suits are indexed as 0 for manzu, 1 for
+/// pinzu and 2 for souzu.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SuitRead {
+    /// A rough likelihood in `[0, 1]` of a flush in each suit.
+    pub likelihood: [f32; 3],
+    /// The suit of a likely flush, if any suit has a likelihood of at least
+    /// 0.5.
+    pub likely_flush: Option<usize>,
+}
@@ -1,10 +1,22 @@
-use super::{ActionCandidate, PlayerState};
+use super::{
+    ActionCandidate, DoraDetail, KanType, KanUraRule, LegalActions, Meld, PlayerState, RenchanRule,
+    RenhouRule, SinglePlayerOptions, SuitRead, WaitKind, WaitShape, WallRule,
+    estimate_deal_in_cost,
+};
+use crate::algo::agari::LocalYaku;
+use crate::algo::point::{Limit, Point, TsumoRounding};
 use crate::algo::shanten;
+use crate::arena::{Board, Poll};
 use crate::consts::MAX_VERSION;
-use crate::hand::{hand, hand_with_aka, tile37_to_vec};
-use crate::mjai::Event;
-use crate::{matches_tu8, must_tile, t, tuz};
-use std::mem;
+use crate::hand::{hand, hand_with_aka, tile37_to_vec, tiles37_to_string};
+use crate::mjai::{Event, EventExt};
+use crate::tile::{AkaConfig, Tile};
+use crate::{must_tile, t, tu8, tuz};
+use std::{array, mem};
+
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde_json::json;
 
 impl PlayerState {
     fn test_update(&mut self, event: &Event) -> ActionCandidate {
@@ -14,9 +26,8 @@ impl PlayerState {
     }
 
     fn test_update_json(&mut self, mjai_json: &str) -> ActionCandidate {
-        let cans = self.update_json(mjai_json).unwrap();
-        self.validate();
-        cans
+        let event = serde_json::from_str(mjai_json).unwrap();
+        self.test_update(&event)
     }
 
     fn from_log(player_id: u8, log: &str) -> Self {
@@ -32,17 +43,13 @@ impl PlayerState {
             .iter()
             .zip(self.dora_factor.iter())
             .map(|(&count, &f)| count * f)
-            .chain(self.akas_in_hand.iter().map(|&b| b as u8))
+            .chain(self.akas_in_hand.iter().copied())
             .chain(
-                self.fuuro_overview[0]
+                self.melds[0]
                     .iter()
-                    .flatten()
+                    .flat_map(|m| m.tiles())
                     .map(|t| self.dora_factor[t.deaka().as_usize()] + t.is_aka() as u8),
             )
-            .chain(self.ankan_overview[0].iter().map(|t| {
-                self.dora_factor[t.deaka().as_usize()] * 4
-                    + matches_tu8!(t.as_u8(), 5m | 5p | 5s) as u8
-            }))
             .sum()
     }
 
@@ -825,6 +832,50 @@ fn get_rank() {
     assert_eq!(rank, 1);
 }
 
+#[test]
+fn current_rank() {
+    // Relative scores, the players at relative seats 2 and 3 (absolute seats
+    // 0 and 1) win ties against us.
+    let mut ps = PlayerState::new(2);
+    for (scores, rank) in [
+        ([25000, 25000, 25000, 25000], 2),
+        ([30000, 25000, 25000, 20000], 0),
+        ([25000, 25000, 25000, 30000], 2),
+        ([32000, 18000, 32000, 18000], 1),
+        ([32000, 32000, 18000, 32000], 1),
+        ([18000, 32000, 18000, 32000], 3),
+        ([25000, 24000, 25000, 26000], 2),
+    ] {
+        ps.scores = scores;
+        ps.update_rank();
+        assert_eq!(ps.current_rank(), rank);
+    }
+}
+
+#[test]
+fn placement_probabilities() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","9m","1p","9p","1s","9s","E","S","W","2m","3m","4m","5m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let mut ps = PlayerState::from_log(1, log);
+    let probs = ps.placement_probabilities();
+    assert!((probs.iter().sum::<f32>() - 1.).abs() < 1e-5);
+    // Even scores, so only the seat tie-break matters.
+    assert!(probs.iter().all(|&p| (p - 0.25).abs() < 0.02));
+
+    ps.scores = [40000, 20000, 20000, 20000];
+    ps.update_rank();
+    let early = ps.placement_probabilities();
+    assert!(early[0] > 0.5);
+    assert!(early.windows(2).all(|w| w[0] > w[1]));
+
+    ps.is_all_last = true;
+    let last = ps.placement_probabilities();
+    assert!((last.iter().sum::<f32>() - 1.).abs() < 1e-5);
+    assert!(last[0] > early[0]);
+    assert!(last[0] > 0.99);
+}
+
 #[test]
 fn kakan_from_hand() {
     let log = r#"
@@ -1223,6 +1274,152 @@ fn discard_candidates_with_unconditional_tenpai() {
     assert_eq!(discard_candidates, [false; 34]);
 }
 
+#[test]
+fn ukeire_after_discard() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"P"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let (tiles, count) = ps.ukeire_after_discard(t!(P));
+    let expected = t![1s, 4s];
+    for (idx, &b) in tiles.iter().enumerate() {
+        assert_eq!(b, expected.contains(&must_tile!(idx)));
+    }
+    assert_eq!(count, 8);
+
+    let (tiles, count) = ps.ukeire_after_discard(t!(2s));
+    let expected = t![1s, 2s, 3s, 4s, 5s, E, P];
+    for (idx, &b) in tiles.iter().enumerate() {
+        assert_eq!(b, expected.contains(&must_tile!(idx)));
+    }
+    assert_eq!(count, 4 + 3 + 3 + 4 + 4 + 2 + 3);
+}
+
+#[test]
+fn acceptance_at_current_shanten() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","6p","8p","2s","4s","7s","9s","E","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.real_time_shanten(), 3);
+
+    // 4 kinds complete a taatsu, 5 kinds make the missing pair.
+    let (tiles, count) = ps.acceptance_at_current_shanten();
+    let expected = t![5p, 7p, 3s, 8s, 4p, 8p, E, W, N];
+    for (idx, &b) in tiles.iter().enumerate() {
+        assert_eq!(b, expected.contains(&must_tile!(idx)));
+    }
+    assert_eq!(count, 4 * 4 + 5 * 3);
+}
+
+#[test]
+fn hypothetical_after_discard() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5pr","6p","7s","8s","9s","E","S","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let shantens = ps.shanten_after_each_discard();
+    for (idx, &legal) in ps.discard_candidates_aka().iter().enumerate() {
+        let tile = must_tile!(idx);
+        if !legal {
+            ps.hypothetical_after_discard(tile).err().unwrap();
+            continue;
+        }
+        let after = ps.hypothetical_after_discard(tile).unwrap();
+        assert!(!after.last_cans.can_discard);
+        assert_eq!(after.real_time_shanten(), shantens[tile.deaka().as_usize()]);
+        assert_eq!(
+            after.tehai[tile.deaka().as_usize()] + 1,
+            ps.tehai[tile.deaka().as_usize()]
+        );
+    }
+
+    let after = ps.hypothetical_after_discard(t!(5pr)).unwrap();
+    assert_eq!(after.akas_in_hand, [0; 3]);
+    assert_eq!(after.tehai[tuz!(5p)], 0);
+    assert_eq!(ps.akas_in_hand, [0, 1, 0]);
+
+    // Tenpai on 1s or 4s after discarding S.
+    let after = ps.hypothetical_after_discard(t!(S)).unwrap();
+    assert!(after.waits[tuz!(1s)] && after.waits[tuz!(4s)]);
+    assert_eq!(after.waits.iter().filter(|&&b| b).count(), 2);
+    assert!(ps.waits.iter().all(|&b| !b));
+}
+
+#[test]
+fn shanten_after_each_discard() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.real_time_shanten(), 0);
+
+    let shantens = ps.shanten_after_each_discard();
+    for (tid, &s) in shantens.iter().enumerate() {
+        let expected = match (ps.tehai[tid], tid as u8) {
+            (0, _) => i8::MAX,
+            (_, tu8!(N)) => 0,
+            _ => 1,
+        };
+        assert_eq!(s, expected, "{}", must_tile!(tid));
+    }
+}
+
+#[test]
+fn no_aka() {
+    let mut ps = PlayerState::with_aka_config(0, AkaConfig::NONE);
+    ps.test_update_json(r#"{"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5m","5m","5p","6p","7p","5s","5s","E","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"N"}"#);
+
+    let full = ps.discard_candidates_aka();
+    assert_eq!(full[..34], ps.discard_candidates());
+    assert!(!full[tuz!(5mr)] && !full[tuz!(5pr)] && !full[tuz!(5sr)]);
+
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    let dahai = serde_json::from_str(r#"{"type":"dahai","actor":1,"pai":"5mr","tsumogiri":true}"#);
+    ps.update(&dahai.unwrap()).unwrap_err();
+}
+
+#[test]
+fn four_akas() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","5pr","5p","5p","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"5pr"}
+    "#;
+    let mut ps = PlayerState::with_aka_config(0, AkaConfig::FOUR);
+    for line in log.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.aka_counts_in_hand(), [0, 2, 0]);
+    assert_eq!(ps.doras_owned[0], 2);
+    ps.single_player_tables().unwrap();
+
+    let full = ps.discard_candidates_aka();
+    assert!(full[tuz!(5pr)] && full[tuz!(5p)]);
+
+    let log = r#"
+        {"type":"dahai","actor":0,"pai":"5p","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    for line in log.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert!(ps.last_cans.can_tsumo_agari);
+    // 門前清自摸和, 一気通貫, aka 2
+    assert_eq!(ps.agari_points(false, &[]).unwrap().tsumo_ko, 4000);
+}
+
 #[test]
 fn double_chankan_ron() {
     let log = r#"
@@ -1386,10 +1583,58 @@ fn double_chankan_ron() {
     assert!(cans.can_ron_agari);
     assert_eq!(ps_kakan.agari_points(true, &[]).unwrap().ron, 1000);
 
+    ps_kakan.honba = 2;
+    ps_kakan.kyotaku = 1;
+    let (point, bonus) = ps_kakan.agari_points_multi_ron(true, &[], true).unwrap();
+    assert_eq!(point.ron, 1000);
+    assert_eq!(bonus, 1600);
+    let (point, bonus) = ps_kakan.agari_points_multi_ron(true, &[], false).unwrap();
+    assert_eq!(point.ron, 1000);
+    assert_eq!(bonus, 0);
+
     let cans = ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"2m","tsumogiri":true}"#);
     assert!(!cans.can_ron_agari);
 }
 
+#[test]
+fn mjai_events() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5pr","6p","7s","8s","9s","E","E","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let ev = ps.to_mjai_dahai_event(t!(E), true).unwrap();
+    assert_eq!(
+        ev,
+        json!({"type":"dahai","actor":0,"pai":"E","tsumogiri":true}),
+    );
+    // There are other E in hand.
+    ps.to_mjai_dahai_event(t!(E), false).unwrap();
+    ps.to_mjai_dahai_event(t!(1m), true).unwrap_err();
+    ps.to_mjai_pon_event().unwrap_err();
+    let ev = ps.to_mjai_reach_event().unwrap();
+    assert_eq!(ev, json!({"type":"reach","actor":0}));
+
+    let log = r#"
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"E","tsumogiri":true}
+    "#;
+    let mut ps = ps;
+    for line in log.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    let ev = ps.to_mjai_pon_event().unwrap();
+    assert_eq!(
+        ev,
+        json!({"type":"pon","actor":0,"target":1,"pai":"E","consumed":["E","E"]}),
+    );
+    ps.to_mjai_kan_event(t!(E)).unwrap_err();
+
+    ps.to_mjai_hora_event().unwrap_err();
+}
+
 #[test]
 fn chi_at_0_shanten() {
     let log = r#"
@@ -1416,3 +1661,2363 @@ fn chi_at_0_shanten() {
     assert!(ps.at_furiten);
     assert!(!ps.has_next_shanten_discard);
 }
+
+#[test]
+fn tenpai_for_opponents() {
+    // furiten on 6p
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["4m","5m","6m","7p","8p","9p","9p","9p","7s","8s","9s","C","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"6p"}
+        {"type":"dahai","actor":0,"pai":"6p","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.at_furiten);
+    assert!(ps.is_tenpai());
+    assert!(ps.tenpai_for_opponents());
+
+    // all 4 of 3z in kawa, so the only wait is dead
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","1s","1s","1s","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.tiles_seen[tuz!(W)], 4);
+    assert!(ps.is_tenpai());
+    assert!(ps.tenpai_for_opponents());
+
+    // waiting only on the 5th 1s
+    let mut ps = PlayerState {
+        tehai: hand("123m 456p 789s 1111s").unwrap(),
+        tehai_len_div3: 4,
+        ..Default::default()
+    };
+    ps.update_shanten();
+    ps.update_waits_and_furiten();
+    assert!(!ps.tenpai_for_opponents());
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","S","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.is_tenpai());
+    assert!(!ps.tenpai_for_opponents());
+}
+
+#[test]
+fn formal_tenpai() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","1s","1s","1s","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert!(ps.formal_tenpai(false));
+    assert!(ps.formal_tenpai(true));
+
+    // The last W is gone as well.
+    ps.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"W","tsumogiri":true}"#);
+    assert!(ps.formal_tenpai(false));
+    assert!(!ps.formal_tenpai(true));
+
+    // Not at 3n+1.
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"N"}"#);
+    assert!(!ps.formal_tenpai(false));
+    assert!(!ps.formal_tenpai(true));
+}
+
+#[test]
+fn points_to_rank() {
+    let ps = PlayerState {
+        scores: [18000, 25000, 30000, 27000],
+        ..PlayerState::new(0)
+    };
+    assert_eq!(ps.points_to_rank(3), 0);
+    assert_eq!(ps.points_to_rank(2), 7000);
+    assert_eq!(ps.points_to_rank(1), 9000);
+    assert_eq!(ps.points_to_rank(0), 12000);
+
+    // Players at absolute seats 0 and 1 win the ties against us.
+    let ps = PlayerState {
+        scores: [18000, 30000, 25000, 27000],
+        ..PlayerState::new(2)
+    };
+    assert_eq!(ps.points_to_rank(2), 7001);
+    assert_eq!(ps.points_to_rank(1), 9001);
+    assert_eq!(ps.points_to_rank(0), 12000);
+
+    let ps = PlayerState {
+        scores: [25000; 4],
+        ..PlayerState::new(1)
+    };
+    assert_eq!(ps.points_to_rank(1), 0);
+    assert_eq!(ps.points_to_rank(0), 1);
+}
+
+#[test]
+fn kokushi_waits() {
+    let mut ps = PlayerState {
+        tehai: hand("19m 19p 19s 1234567z").unwrap(),
+        tehai_len_div3: 4,
+        is_menzen: true,
+        ..Default::default()
+    };
+    let waits = ps.kokushi_waits();
+    assert_eq!(waits.iter().filter(|&&b| b).count(), 13);
+    for (tid, &b) in waits.iter().enumerate() {
+        assert_eq!(b, must_tile!(tid).is_yaokyuu());
+    }
+    assert!(ps.is_kokushi_agari(t!(C)));
+    assert!(!ps.is_kokushi_agari(t!(5m)));
+
+    ps.tehai = hand("119m 19p 19s 123456z").unwrap();
+    let waits = ps.kokushi_waits();
+    for (tid, &b) in waits.iter().enumerate() {
+        assert_eq!(b, tid == tuz!(C));
+    }
+    assert!(ps.is_kokushi_agari(t!(C)));
+    assert!(!ps.is_kokushi_agari(t!(1m)));
+
+    ps.tehai = hand("119m 19p 19s 12345z 5m").unwrap();
+    assert!(ps.kokushi_waits().iter().all(|&b| !b));
+
+    ps.tehai = hand("119m 19p 19s 1234567z").unwrap();
+    ps.last_cans.can_discard = true;
+    assert!(ps.kokushi_waits().iter().all(|&b| !b));
+    assert!(ps.is_kokushi_agari(t!(1m)));
+    assert!(ps.is_kokushi_agari(t!(C)));
+    assert!(!ps.is_kokushi_agari(t!(5m)));
+}
+
+#[test]
+fn melds() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"3m"}
+        {"type":"dahai","actor":0,"pai":"3m","tsumogiri":true}
+        {"type":"chi","actor":1,"target":0,"pai":"3m","consumed":["4m","5mr"]}
+        {"type":"dahai","actor":1,"pai":"C","tsumogiri":false}
+        {"type":"pon","actor":2,"target":1,"pai":"C","consumed":["C","C"]}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"ankan","actor":3,"consumed":["S","S","S","S"]}
+        {"type":"dora","dora_marker":"1p"}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"F","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":true}
+        {"type":"daiminkan","actor":2,"target":0,"pai":"9p","consumed":["9p","9p","9p"]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"1s","tsumogiri":false}
+        {"type":"dora","dora_marker":"2p"}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":0,"pai":"7m"}
+        {"type":"dahai","actor":0,"pai":"7m","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"8p","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"kakan","actor":2,"pai":"C","consumed":["C","C","C"]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"2m","tsumogiri":false}
+        {"type":"dora","dora_marker":"3p"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let melds = ps.melds();
+    assert!(melds[0].is_empty());
+    assert_eq!(
+        melds[1],
+        [Meld::Chi {
+            pai: t!(3m),
+            consumed: t![4m, 5mr],
+            target: 0,
+        }],
+    );
+    assert_eq!(
+        melds[2],
+        [
+            Meld::Shouminkan {
+                pai: t!(C),
+                consumed: t![C, C],
+                added: t!(C),
+                target: 1,
+            },
+            Meld::Minkan {
+                pai: t!(9p),
+                consumed: t![9p, 9p, 9p],
+                target: 0,
+            },
+        ],
+    );
+    assert_eq!(
+        melds[3],
+        [Meld::Ankan {
+            consumed: t![S, S, S, S],
+        }],
+    );
+    assert_eq!(melds[3][0].target(), None);
+    assert_eq!(melds[2][0].target(), Some(1));
+
+    // The plain tiles, where the kakan stays in place of the pon.
+    let fuuros: Vec<_> = ps.fuuro_tiles(2).collect();
+    assert_eq!(fuuros[0].as_slice(), t![C, C, C, C]);
+    assert_eq!(fuuros[1].as_slice(), t![9p, 9p, 9p, 9p]);
+    assert_eq!(fuuros.len(), 2);
+    assert_eq!(ps.ankan_tiles(3).collect::<Vec<_>>(), t![S,]);
+    assert_eq!(ps.fuuro_tiles(3).count(), 0);
+}
+
+#[test]
+fn kan_ura_rule() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","2s","3s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    // 1m of the initial dora.
+    assert_eq!(ps.doras_owned[0], 1);
+    ps.test_update_json(r#"{"type":"ankan","actor":1,"consumed":["S","S","S","S"]}"#);
+    ps.test_update_json(r#"{"type":"dora","dora_marker":"3p"}"#);
+    assert_eq!(ps.kan_dora_actors.as_slice(), [Some(1)]);
+    assert_eq!(ps.num_ura_indicators(true), 2);
+    assert_eq!(ps.num_ura_indicators(false), 2);
+
+    // The kan dora of 4p counts no matter whose kan it is.
+    assert_eq!(ps.doras_owned[0], 2);
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":1,"pai":"4s","tsumogiri":true}"#);
+    assert!(ps.last_cans.can_ron_agari);
+
+    // 2 uras of E from the initial indicator, and 1 of 7s from the kan-ura.
+    let uras = t![N, 6s];
+    let all = ps.dora_detail(&uras);
+    assert_eq!((all.dora, all.kan_dora, all.ura), (1, 1, 3));
+
+    let ps = ps.with_kan_ura_rule(KanUraRule::OwnKanOrTsumo);
+    assert_eq!(ps.num_ura_indicators(true), 1);
+    assert_eq!(ps.num_ura_indicators(false), 2);
+    assert_eq!(ps.doras_owned[0], 2);
+    let own = ps.dora_detail(&uras);
+    assert_eq!((own.dora, own.kan_dora, own.ura), (1, 1, 2));
+}
+
+#[test]
+fn single_player_tables_with() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","2s","3s","E","E","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"E","tsumogiri":true}
+        {"type":"pon","actor":0,"target":3,"pai":"E","consumed":["E","E"]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let real = ps.single_player_tables().unwrap().max_ev_table;
+    let same = ps
+        .single_player_tables_with(ps.tiles_seen, &ps.dora_indicators)
+        .unwrap()
+        .max_ev_table;
+    assert_eq!(real.len(), same.len());
+    for (l, r) in real.iter().zip(&same) {
+        assert_eq!(l.tile, r.tile);
+        assert_eq!(l.exp_values, r.exp_values);
+    }
+
+    // With N as the indicator, the pon of E has 3 more doras.
+    let mut tiles_seen = ps.tiles_seen;
+    tiles_seen[tuz!(N)] += 1;
+    let hypo = ps
+        .single_player_tables_with(tiles_seen, &t![N,])
+        .unwrap()
+        .max_ev_table;
+    assert!(hypo[0].exp_values[0] > real[0].exp_values[0]);
+
+    let mut tiles_seen = ps.tiles_seen;
+    tiles_seen[tuz!(1m)] = 0;
+    assert!(ps.single_player_tables_with(tiles_seen, &t![N,]).is_err());
+}
+
+#[test]
+fn call_candidates() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4m","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.pon_candidates(), None);
+    assert!(ps.kan_candidates().is_empty());
+    assert_eq!(
+        ps.chi_candidates(),
+        [
+            [t!(5mr), t!(6m)],
+            [t!(5m), t!(6m)],
+            [t!(3m), t!(5mr)],
+            [t!(3m), t!(5m)],
+        ],
+    );
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":2,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"1p","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.pon_candidates(), Some([t!(1p), t!(1p)]));
+    assert!(ps.chi_candidates().is_empty());
+    assert_eq!(ps.kan_candidates(), [(KanType::Daiminkan, t!(1p))]);
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":2,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"5m","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.pon_candidates(), Some([t!(5mr), t!(5m)]));
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1p"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.kan_candidates(), [(KanType::Ankan, t!(1p))]);
+}
+
+#[test]
+fn nagashi_mangan() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","9p","1s","9s","E","S","W","N","2m","3m","4m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"P"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert!(!ps.is_nagashi_mangan());
+    // The SP tables of this kokushi hand are empty with
+    // `sp_reproduce_cpp_ver`, which must not break the obs.
+    let (_, mask) = ps.encode_obs(4, false);
+    assert!(mask[tuz!(P)]);
+
+    let next = r#"
+        {"type":"dahai","actor":0,"pai":"1m","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9m","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"6p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"F"}
+        {"type":"dahai","actor":0,"pai":"F","tsumogiri":true}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert!(ps.is_nagashi_mangan());
+
+    // The discarded 9p gets ponned.
+    let mut called = ps.clone();
+    let next = r#"
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":false}
+        {"type":"pon","actor":2,"target":0,"pai":"9p","consumed":["9p","9p"]}
+        {"type":"dahai","actor":2,"pai":"1s","tsumogiri":false}
+    "#;
+    for line in next.trim().split('\n') {
+        called.test_update_json(line);
+    }
+    assert!(!called.is_nagashi_mangan());
+
+    // A non-yaokyuu discard breaks it as well.
+    let next = r#"
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"7s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"2m","tsumogiri":false}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert!(!ps.is_nagashi_mangan());
+}
+
+#[test]
+fn single_player_tables_opts() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","7p","7s","8s","2s","3s","E","E","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let plain = ps.single_player_tables().unwrap().max_ev_table;
+    let same = ps
+        .single_player_tables_opts(SinglePlayerOptions::default())
+        .unwrap()
+        .max_ev_table;
+    assert_eq!(plain.len(), same.len());
+    for (l, r) in plain.iter().zip(&same) {
+        assert_eq!(l.tile, r.tile);
+        assert_eq!(l.exp_values, r.exp_values);
+    }
+    assert!(plain.iter().all(|c| !c.shanten_down));
+
+    let shanten_down = ps
+        .single_player_tables_opts(SinglePlayerOptions {
+            calc_shanten_down: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .max_ev_table;
+    assert!(shanten_down.len() > plain.len());
+    assert!(shanten_down.iter().any(|c| c.shanten_down));
+
+    let tegawari = ps
+        .single_player_tables_opts(SinglePlayerOptions {
+            calc_tegawari: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .max_ev_table;
+    assert_eq!(tegawari.len(), plain.len());
+    assert!(tegawari[0].exp_values[0] >= plain[0].exp_values[0]);
+
+    let fewer_tsumos = ps
+        .single_player_tables_opts(SinglePlayerOptions {
+            tsumos_left_override: Some(5),
+            ..Default::default()
+        })
+        .unwrap()
+        .max_ev_table;
+    assert_eq!(plain[0].exp_values.len(), 17);
+    assert_eq!(fewer_tsumos[0].exp_values.len(), 5);
+    assert!(fewer_tsumos[0].win_probs[0] < plain[0].win_probs[0]);
+    assert!(
+        ps.single_player_tables_opts(SinglePlayerOptions {
+            tsumos_left_override: Some(0),
+            ..Default::default()
+        })
+        .is_err()
+    );
+    assert!(
+        ps.single_player_tables_opts(SinglePlayerOptions {
+            tsumos_left_override: Some(18),
+            ..Default::default()
+        })
+        .is_err()
+    );
+}
+
+#[test]
+fn single_player_tables_yakuman_prob() {
+    // 四暗刻単騎
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","2p","2p","2p","3s","3s","3s","E","E","E","S"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let plain = ps.single_player_tables().unwrap().max_ev_table;
+    assert!(plain.iter().all(|c| c.yakuman_prob == 0.));
+
+    let tables = ps
+        .single_player_tables_opts(SinglePlayerOptions {
+            calc_yakuman_prob: true,
+            ..Default::default()
+        })
+        .unwrap();
+    let c = &tables.max_ev_table[0];
+    assert!(c.yakuman_prob > 0.);
+    assert!(c.yakuman_prob <= c.win_probs[0]);
+}
+
+#[test]
+fn single_player_tables_with_dama() {
+    // 5s kanchan without any yaku.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","5p","6p","7p","7s","8s","9s","4s","6s","N","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let tables = ps.single_player_tables_with_dama().unwrap();
+    let dama = tables.dama_table.unwrap();
+    assert!(tables.max_ev_table[0].exp_values[0] > 0.);
+    assert!(dama[0].win_probs.iter().all(|&p| p == 0.));
+    assert!(dama[0].exp_values.iter().all(|&v| v == 0.));
+    assert!(ps.single_player_tables().unwrap().dama_table.is_none());
+
+    // 断幺九 makes the dama wins count.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","6s","7s","8s","4s","6s","8p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let tables = ps.single_player_tables_with_dama().unwrap();
+    let riichi = &tables.max_ev_table[0];
+    let dama = &tables.dama_table.unwrap()[0];
+    assert!(dama.exp_values[0] > 0.);
+    assert!(riichi.exp_values[0] > dama.exp_values[0]);
+    assert!((riichi.win_probs[0] - dama.win_probs[0]).abs() < 1e-6);
+}
+
+#[test]
+fn riichi_stick_ev_adjustment() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","6s","7s","8s","4s","6s","8p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let tables = ps.single_player_tables_with_dama().unwrap();
+    let riichi = &tables.max_ev_table[0];
+    // Already tenpai, so the stick is lost unless we win.
+    let expected = -1000. * (1. - riichi.win_probs[0]);
+    assert!(expected < 0.);
+    assert!((riichi.riichi_stick_ev - expected).abs() < 1e-3);
+    assert!((ps.riichi_stick_ev_adjustment(riichi) - expected).abs() < 1e-3);
+    let dama = &tables.dama_table.unwrap()[0];
+    assert!(dama.riichi_stick_ev == 0.);
+
+    // Nothing more to pay once declared.
+    ps.riichi_declared[0] = true;
+    assert!(ps.riichi_stick_ev_adjustment(riichi) == 0.);
+    ps.riichi_declared[0] = false;
+    ps.scores[0] = 900;
+    assert!(ps.riichi_stick_ev_adjustment(riichi) == 0.);
+}
+
+#[test]
+fn single_player_tables_best_discard() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let tables = ps.single_player_tables().unwrap();
+    assert_eq!(tables.best_discard(), Some(t!(N)));
+    assert!(tables.best_n(0).is_empty());
+    assert_eq!(tables.best_n(1)[0].tile, t!(N));
+    assert_eq!(tables.best_n(100).len(), tables.max_ev_table.len());
+
+    ps.test_update_json(r#"{"type":"reach","actor":0}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"reach_accepted","actor":0}"#);
+    assert_eq!(ps.single_player_tables().unwrap().best_discard(), None);
+
+    // Only the drawn tile can go after riichi, even if keeping it would be
+    // better.
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"1s"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"1s","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"3s"}"#);
+    let tables = ps.single_player_tables().unwrap();
+    assert_eq!(tables.best_discard(), Some(t!(3s)));
+    assert_eq!(tables.best_n(3).len(), 1);
+}
+
+#[test]
+fn wall_rule() {
+    assert_eq!(WallRule::STANDARD.initial_tiles_left(), 70);
+    assert_eq!(WallRule::SANMA.initial_tiles_left(), 55);
+    assert_eq!(WallRule::STANDARD.estimate_tsumos_left(12, 0), (3, true));
+    assert_eq!(WallRule::SANMA.estimate_tsumos_left(12, 0), (4, true));
+    assert_eq!(WallRule::SANMA.estimate_tsumos_left(12, 1), (3, false));
+    assert_eq!(WallRule::STANDARD.draws_until(0, 1), 1);
+    assert_eq!(WallRule::STANDARD.draws_until(2, 1), 3);
+    assert_eq!(WallRule::STANDARD.draws_until(1, 1), 4);
+    assert_eq!(WallRule::SANMA.draws_until(0, 1), 1);
+    assert_eq!(WallRule::SANMA.draws_until(2, 1), 2);
+    assert_eq!(WallRule::SANMA.draws_until(1, 2), 1);
+    assert_eq!(WallRule::SANMA.draws_until(0, 2), 2);
+
+    // 1s-4s after discarding N.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[35000,35000,35000,0],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","9m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let standard = PlayerState::from_log(0, log);
+    let mut sanma = PlayerState::new(0).with_wall_rule(WallRule::SANMA);
+    for line in log.trim().split('\n') {
+        sanma.test_update_json(line);
+    }
+    assert_eq!(standard.tiles_left, 69);
+    assert_eq!(sanma.tiles_left, 54);
+
+    let tsumos_at = |ps: &PlayerState, tiles_left| {
+        let mut ps = ps.clone();
+        ps.tiles_left = tiles_left;
+        let table = ps.single_player_tables().unwrap().max_ev_table;
+        table[0].exp_values.len()
+    };
+    assert_eq!(tsumos_at(&standard, 12), 3);
+    assert_eq!(tsumos_at(&sanma, 12), 4);
+    // The first turn of sanma has 18 tsumos left, more than the calculator
+    // takes.
+    assert_eq!(WallRule::SANMA.estimate_tsumos_left(54, 0), (18, true));
+    assert_eq!(tsumos_at(&sanma, 54), 17);
+
+    // Seat 2 after a discard of seat 0 waits for seat 1 to draw first, and
+    // after one of seat 1 draws right away.
+    let seat_2_log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[35000,35000,35000,0],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","9m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":true}
+    "#;
+    let mut sanma = PlayerState::new(2).with_wall_rule(WallRule::SANMA);
+    for line in seat_2_log.trim().split('\n') {
+        sanma.test_update_json(line);
+    }
+    assert_eq!(tsumos_at(&sanma, 13), 3);
+    assert_eq!(tsumos_at(&sanma, 14), 4);
+    sanma.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    sanma.test_update_json(r#"{"type":"dahai","actor":1,"pai":"E","tsumogiri":true}"#);
+    assert_eq!(tsumos_at(&sanma, 13), 4);
+    assert_eq!(tsumos_at(&sanma, 12), 3);
+
+    // Riichi needs a tsumo left for everyone.
+    let start = log.trim().split('\n').next().unwrap();
+    for (rule, can_riichi) in [(WallRule::STANDARD, false), (WallRule::SANMA, true)] {
+        let mut ps = PlayerState::new(0).with_wall_rule(rule);
+        ps.test_update_json(start);
+        ps.tiles_left = 4;
+        ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"N"}"#);
+        assert_eq!(ps.last_cans.can_riichi, can_riichi);
+    }
+}
+
+#[test]
+fn single_player_tables_haitei() {
+    // 1s-4s after discarding N, 平和.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","9m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    // Not the first turn, so no 両立直.
+    ps.can_w_riichi = false;
+    let table_at = |ps: &mut PlayerState, tiles_left| {
+        ps.tiles_left = tiles_left;
+        let table = ps.single_player_tables().unwrap().max_ev_table;
+        let c = table.into_iter().find(|c| c.tile == t!(N)).unwrap();
+        assert_eq!(c.exp_values.len(), 1);
+        c
+    };
+    // Our tsumo after this discard is the last one either way, but only with
+    // 4 tiles left is it the haitei.
+    let haitei = table_at(&mut ps, 4);
+    let not_haitei = table_at(&mut ps, 5);
+
+    // The only draw is priced by the unseen copies of the waits over all the
+    // unseen tiles. With `sp_reproduce_cpp_ver`, it is taken as the last of
+    // the hardcoded 17 tsumos, after 16 other tiles are drawn.
+    let unseen: u8 = ps.tiles_seen.iter().map(|&c| 4 - c).sum();
+    let win_prob = if cfg!(feature = "sp_reproduce_cpp_ver") {
+        8. / f32::from(unseen - 16)
+    } else {
+        8. / f32::from(unseen)
+    };
+    assert!((haitei.win_probs[0] - win_prob).abs() < 1e-6);
+    assert!((not_haitei.win_probs[0] - win_prob).abs() < 1e-6);
+    assert!((haitei.tenpai_probs[0] - 1.).abs() < 1e-6);
+
+    // 立直 一発 門前清自摸和 平和 is 4 han, and the haitei makes it a mangan
+    // before any ura.
+    assert!(haitei.exp_values[0] > not_haitei.exp_values[0]);
+    assert!(not_haitei.exp_values[0] < win_prob * 12000.);
+    assert!(haitei.exp_values[0] >= win_prob * 12000.);
+}
+
+#[test]
+fn wait_shape() {
+    let shape_of = |tehai: &str| {
+        let ps = PlayerState {
+            tehai: hand(tehai).unwrap(),
+            tehai_len_div3: 4,
+            shanten: 0,
+            ..Default::default()
+        };
+        ps.wait_shape().unwrap()
+    };
+    use WaitKind::*;
+
+    // 4m is either shanpon with 99s or ryanmen of 23m with 444m.
+    let shape = shape_of("23444m 123p 567s 99s");
+    assert_eq!(
+        shape.waits,
+        [
+            (t!(1m), vec![Ryanmen]),
+            (t!(4m), vec![Ryanmen, Shanpon]),
+            (t!(9s), vec![Shanpon]),
+        ],
+    );
+    assert_eq!(shape.kinds(), [Ryanmen, Shanpon]);
+    assert!(shape.is_ryanmen_on(t!(4m)));
+    assert!(!shape.is_ryanmen_on(t!(9s)));
+
+    // 5m is either kanchan of 46m or tanki with 456m.
+    let shape = shape_of("4556m 123p 456s 789s");
+    assert_eq!(shape.waits, [(t!(5m), vec![Kanchan, Tanki])]);
+    assert_eq!(shape.kinds().iter().map(|k| k.fu()).max(), Some(2));
+
+    // 6s is both ryanmen and kanchan, and 9s is ryanmen.
+    let shape = shape_of("123m 456p 567s 78s 11z");
+    assert_eq!(
+        shape.waits,
+        [(t!(6s), vec![Ryanmen, Kanchan]), (t!(9s), vec![Ryanmen])],
+    );
+
+    assert_eq!(
+        shape_of("12m 456p 789p 123s 55s").waits,
+        [(t!(3m), vec![Penchan])],
+    );
+    assert_eq!(
+        shape_of("1133m 55p 77p 99s 11z 2z").waits,
+        [(t!(S), vec![Tanki])],
+    );
+    let shape = shape_of("19m 19p 19s 1234567z");
+    assert_eq!(shape.waits.len(), 13);
+    assert!(shape.waits.iter().all(|(_, kinds)| *kinds == [Tanki]));
+    assert_eq!(shape_of("1112345678999m").waits.len(), 9,);
+
+    let ps = PlayerState {
+        tehai: hand("1234m 123p 456s 789s").unwrap(),
+        tehai_len_div3: 4,
+        shanten: 1,
+        ..Default::default()
+    };
+    ps.wait_shape().unwrap_err();
+    assert_eq!(WaitShape::default().kinds(), []);
+}
+
+#[test]
+fn temporary_furiten() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5m","6m","7m","3p","4p","5p","6s","7s","8s","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"5s","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_ron_agari);
+    assert!(!ps.temporary_furiten());
+
+    // Passed up.
+    ps.test_update_json(r#"{"type":"tsumo","actor":2,"pai":"?"}"#);
+    assert!(ps.at_furiten);
+    assert!(ps.temporary_furiten());
+    ps.test_update_json(r#"{"type":"dahai","actor":2,"pai":"8s","tsumogiri":true}"#);
+    assert!(!ps.last_cans.can_ron_agari);
+    assert!(ps.temporary_furiten());
+    ps.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"N","tsumogiri":true}"#);
+    assert!(ps.temporary_furiten());
+
+    // Cleared from our draw on.
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"E"}"#);
+    assert!(ps.at_furiten);
+    assert!(!ps.temporary_furiten());
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"E","tsumogiri":true}"#);
+    assert!(!ps.at_furiten);
+    assert!(!ps.temporary_furiten());
+
+    // 捨て牌フリテン is permanent.
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":1,"pai":"1m","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":2,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":2,"pai":"9m","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"1p","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"W"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"5s","tsumogiri":false}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":1,"pai":"9p","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":2,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":2,"pai":"1s","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"9s","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"5s"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"W","tsumogiri":false}"#);
+    assert!(ps.at_furiten);
+    assert!(!ps.temporary_furiten());
+}
+
+#[test]
+fn visible_tile_counts() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","3m","5mr","6m","7m","2p","4p","6p","8p","E","E","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"8p","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"E","tsumogiri":false}
+        {"type":"pon","actor":0,"target":1,"pai":"E","consumed":["E","E"]}
+        {"type":"dahai","actor":0,"pai":"6p","tsumogiri":false}
+        {"type":"chi","actor":1,"target":0,"pai":"6p","consumed":["5pr","7p"]}
+        {"type":"dahai","actor":1,"pai":"1m","tsumogiri":false}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let visible = ps.visible_tile_counts();
+    let remaining = ps.remaining_counts();
+
+    // In hand, in our melds and in our river, all the same.
+    assert_eq!(visible[tuz!(1m)], 3);
+    assert_eq!(visible[tuz!(5m)], 1);
+    assert_eq!(visible[tuz!(8p)], 1);
+    assert_eq!(visible[tuz!(6p)], 1);
+    assert_eq!(visible[tuz!(E)], 3);
+    assert_eq!(visible[tuz!(C)], 2);
+    // The other tiles of their chi, and the dora indicator.
+    assert_eq!(visible[tuz!(5p)], 1);
+    assert_eq!(visible[tuz!(7p)], 1);
+    assert_eq!(visible[tuz!(9s)], 1);
+    assert_eq!(visible[tuz!(1s)], 0);
+    assert_eq!(visible.iter().map(|&c| c as u32).sum::<u32>(), 19);
+
+    for (v, r) in visible.iter().zip(remaining) {
+        assert_eq!(v + r, 4);
+    }
+    assert_eq!(remaining[tuz!(1m)], 1);
+    assert_eq!(remaining[tuz!(1s)], 4);
+}
+
+#[test]
+fn push_fold() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","7p","7s","8s","2s","3s","E","E","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let legal = ps.discard_candidates_aka();
+    let num_legal = legal.iter().filter(|&&b| b).count();
+
+    // Without any risk, only the max EV discards are recommended.
+    let advice = ps.push_fold(&[[0.; 34]; 3]).unwrap();
+    assert_eq!(advice.candidates.len(), num_legal);
+    assert!(advice.candidates.iter().all(|c| legal[c.tile.as_usize()]));
+    assert!(advice.candidates.iter().all(|c| c.risk == 0.));
+    let max_ev = advice
+        .candidates
+        .iter()
+        .map(|c| c.ev)
+        .fold(f32::MIN, f32::max);
+    for c in &advice.candidates {
+        assert_eq!(c.recommended, c.ev >= max_ev, "{}", c.tile);
+    }
+    let best = advice
+        .candidates
+        .iter()
+        .find(|c| c.recommended)
+        .unwrap()
+        .tile;
+
+    // Make the best one dangerous to two of the opponents.
+    let mut risk = [[0.; 34]; 3];
+    risk[0][best.as_usize()] = 0.5;
+    risk[2][best.as_usize()] = 0.5;
+    let advice = ps.push_fold(&risk).unwrap();
+    let risky = advice.candidates.iter().find(|c| c.tile == best).unwrap();
+    assert!((risky.risk - 0.75).abs() < f32::EPSILON);
+    assert!(risky.recommended);
+    let safe_max_ev = advice
+        .candidates
+        .iter()
+        .filter(|c| c.tile != best)
+        .map(|c| c.ev)
+        .fold(f32::MIN, f32::max);
+    for c in advice.candidates.iter().filter(|c| c.tile != best) {
+        assert!(c.risk == 0.);
+        assert_eq!(c.recommended, c.ev >= safe_max_ev, "{}", c.tile);
+    }
+
+    ps.last_cans.can_discard = false;
+    ps.push_fold(&risk).unwrap_err();
+}
+
+#[test]
+fn estimate_deal_in_cost_model() {
+    assert_eq!(estimate_deal_in_cost(&[], true, &t![1m,]).ron, 5200);
+    assert_eq!(estimate_deal_in_cost(&[], false, &t![1m,]).ron, 2000);
+
+    let pon = Meld::Pon {
+        pai: t!(5mr),
+        consumed: t![5m, 5m],
+        target: 1,
+    };
+    let chi = Meld::Chi {
+        pai: t!(3p),
+        consumed: t![4p, 5p],
+        target: 3,
+    };
+    // 3 doras and an aka make a haneman.
+    assert_eq!(
+        estimate_deal_in_cost(&[pon, chi], false, &t![4m,]).ron,
+        12000
+    );
+    // The aka alone.
+    assert_eq!(estimate_deal_in_cost(&[pon, chi], false, &t![E,]).ron, 3900);
+    // 2p makes 3p a dora, twice.
+    assert_eq!(estimate_deal_in_cost(&[chi], false, &t![2p, 2p]).ron, 7700,);
+
+    // Ankan doras count for a riichi too.
+    let ankan = Meld::Ankan {
+        consumed: t![S, S, S, S],
+    };
+    assert_eq!(estimate_deal_in_cost(&[ankan], true, &t![E,]).ron, 12000);
+}
+
+#[test]
+fn next_draw_win_prob() {
+    // 1s-4s after discarding N.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"4s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"1s","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    // 13 in hand, the dora marker, N and 1s are seen, so 136 - 16 = 120 are
+    // unseen, of which 3 1s and 3 4s.
+    assert!((ps.next_draw_win_prob() - 6. / 120.).abs() < 1e-6);
+    // Furiten on 1s, which is still fine for a tsumo.
+    assert!(ps.at_furiten);
+    assert!(ps.next_draw_win_prob() > 0.);
+
+    ps.tiles_left = 0;
+    assert!(ps.next_draw_win_prob() == 0.);
+
+    // 234m 567p 23s 66s with a pon of 8p, where only 4s gives 断幺九.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","8p","8p","2s","3s","6s","6s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"8p","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"8p","consumed":["8p","8p"]}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    // 13 in hand, the dora marker, W and 8p are seen, and 4s has 4 copies
+    // left.
+    assert!((ps.next_draw_win_prob() - 4. / 120.).abs() < 1e-6);
+
+    // Not tenpai.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.next_draw_win_prob() == 0.);
+}
+
+#[test]
+fn yaku_bearing_waits() {
+    // 234m 567p 23s 66s with a pon of 8p, where only 4s gives 断幺九.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","8p","8p","2s","3s","6s","6s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"8p","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"8p","consumed":["8p","8p"]}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let expected = t![4s,];
+    for (tid, &b) in ps.yaku_bearing_waits().iter().enumerate() {
+        assert_eq!(
+            b,
+            expected.contains(&must_tile!(tid)),
+            "{}",
+            must_tile!(tid)
+        );
+    }
+    assert!(ps.waits[tuz!(1s)]);
+
+    ps.at_furiten = true;
+    assert!(ps.yaku_bearing_waits().iter().all(|&b| !b));
+}
+
+#[test]
+fn can_ankan_after_riichi() {
+    // 23m 999p 33345666s, waiting on 1m and 4m.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","9p","9p","9p","3s","3s","3s","4s","5s","6s","6s","6s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+    "#;
+    let tenhou = PlayerState::from_log(0, log);
+    let mut strict = PlayerState::new(0).with_strict_riichi_ankan(true);
+    for line in log.trim().split('\n') {
+        strict.test_update_json(line);
+    }
+
+    // A pure triplet is legal either way.
+    for ps in [&tenhou, &strict] {
+        let mut ps = ps.clone();
+        ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"9p"}"#);
+        assert!(ps.last_cans.can_ankan);
+        assert_eq!(ps.ankan_candidates(), t![9p,]);
+        assert!(ps.can_ankan_after_riichi(t!(9p)));
+        // Not the tsumo.
+        assert!(!ps.can_ankan_after_riichi(t!(3s)));
+    }
+
+    // 6s is also part of 456s, which only changes the shape.
+    let mut ps = tenhou.clone();
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"6s"}"#);
+    assert!(ps.last_cans.can_ankan);
+    assert!(ps.can_ankan_after_riichi(t!(6s)));
+    let mut ps = strict.clone();
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"6s"}"#);
+    assert!(!ps.last_cans.can_ankan);
+    assert!(!ps.can_ankan_after_riichi(t!(6s)));
+
+    // 3s takes part in the wait shape 3334s, so that the kan changes the
+    // waits.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","3s","3s","3s","4s","E","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":0,"pai":"3s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(!ps.last_cans.can_ankan);
+    assert!(!ps.can_ankan_after_riichi(t!(3s)));
+    // 111z stays a triplet whatever the wait.
+    let mut ps = PlayerState::from_log(0, &log.replace(r#""pai":"3s"}"#, r#""pai":"E"}"#));
+    assert!(ps.can_ankan_after_riichi(t!(E)));
+    ps.kans_on_board = 4;
+    assert!(!ps.can_ankan_after_riichi(t!(E)));
+}
+
+#[test]
+fn kokushi_ankan_chankan() {
+    let kokushi = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["9m","1p","9p","1s","9s","E","S","W","N","P","F","C","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"5p"}
+        {"type":"dahai","actor":0,"pai":"5p","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+    "#;
+    let normal = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4p","5p","6p","7p","8p","9p","1s","2s","3s","5s","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+    "#;
+    let ankan = r#"{"type":"ankan","actor":1,"consumed":["1m","1m","1m","1m"]}"#;
+
+    let mut ps = PlayerState::from_log(0, kokushi);
+    assert!(ps.can_chankan(KanType::Ankan, t!(1m)));
+    assert!(ps.can_chankan(KanType::Kakan, t!(1m)));
+    assert!(!ps.can_chankan(KanType::Daiminkan, t!(1m)));
+    ps.test_update_json(ankan);
+    assert!(ps.last_cans.can_ron_agari);
+    assert_eq!(ps.agari_points(true, &[]).unwrap().ron, 48000);
+
+    // Passing it is furiten.
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    assert!(ps.at_furiten);
+
+    // Disabled by the rule.
+    let mut ps = PlayerState::new(0).with_kokushi_ankan_chankan(false);
+    for line in kokushi.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert!(!ps.can_chankan(KanType::Ankan, t!(1m)));
+    ps.test_update_json(ankan);
+    assert!(!ps.last_cans.can_ron_agari);
+
+    // A normal hand can only rob a kakan.
+    let mut ps = PlayerState::from_log(0, normal);
+    assert!(ps.waits[tuz!(1m)]);
+    assert!(ps.can_chankan(KanType::Kakan, t!(1m)));
+    assert!(!ps.can_chankan(KanType::Ankan, t!(1m)));
+    ps.test_update_json(ankan);
+    assert!(!ps.last_cans.can_ron_agari);
+}
+
+#[test]
+fn ippatsu_broken_by_call() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    let mut ippatsu = ps.clone();
+    let next = r#"
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4s","tsumogiri":true}
+    "#;
+    for line in next.trim().split('\n') {
+        ippatsu.test_update_json(line);
+    }
+    assert!(ippatsu.at_ippatsu);
+
+    let mut broken = ps;
+    let next = r#"
+        {"type":"pon","actor":2,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4s","tsumogiri":true}
+    "#;
+    for line in next.trim().split('\n') {
+        broken.test_update_json(line);
+    }
+    assert!(!broken.at_ippatsu);
+
+    // 両立直 一発 ドラ1 vs 両立直 ドラ1
+    let with_ippatsu = ippatsu.agari_points(true, &[]).unwrap();
+    let without_ippatsu = broken.agari_points(true, &[]).unwrap();
+    assert_eq!(with_ippatsu, Point::calc(true, 40, 4));
+    assert_eq!(without_ippatsu, Point::calc(true, 40, 3));
+}
+
+#[test]
+fn open_riichi() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"W","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","6s","7s","8s","2s","3s","9p","9p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+        {"type":"dahai","actor":0,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"reach","actor":0}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let mut open = ps.clone();
+    open.declare_open_riichi().unwrap();
+
+    let next = r#"
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"C"}
+        {"type":"dahai","actor":0,"pai":"C","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"4s"}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+        open.test_update_json(line);
+    }
+
+    // 立直 門前清自摸和 平和 (+ オープン立直)
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap(),
+        Point::calc(true, 20, 3)
+    );
+    assert_eq!(
+        open.agari_points(false, &[]).unwrap(),
+        Point::calc(true, 20, 4)
+    );
+
+    let mut not_declared = PlayerState::new(0);
+    not_declared.declare_open_riichi().unwrap_err();
+}
+
+#[test]
+fn genbutsu() {
+    fn assert_genbutsu(ps: &PlayerState, opponent_rel: usize, expected: &[Tile]) {
+        for (tid, &b) in ps.genbutsu(opponent_rel).iter().enumerate() {
+            let tile = must_tile!(tid);
+            assert_eq!(b, expected.contains(&tile), "{tile}");
+        }
+    }
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"4m","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"7p","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"C","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"P"}
+        {"type":"dahai","actor":0,"pai":"P","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"5sr","tsumogiri":false}
+        {"type":"reach_accepted","actor":1}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"2p","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    // Player 1 has discarded 4m and 5s, which make 1m, 7m, 2s and 8s suji.
+    // 7p is passed before the riichi, and 2p after it.
+    assert_genbutsu(&ps, 1, &t![4m, 5s, 2p]);
+
+    // Player 3 is furiten on what everyone else has discarded since their C,
+    // until they discard again.
+    assert_genbutsu(&ps, 3, &t![C, P, 5s, 2p]);
+
+    let mut ps = ps;
+    ps.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"1p","tsumogiri":true}"#);
+    assert_genbutsu(&ps, 3, &t![C, 1p]);
+    assert!(ps.genbutsu(1)[tuz!(1p)]);
+
+    // Player 2 is only furiten on 1p in this cycle.
+    assert_genbutsu(&ps, 2, &t![7p, 2p, 1p]);
+}
+
+#[test]
+fn kan_on_last_tile() {
+    // Rinshan tsumo of the last tile is 嶺上開花 but not 海底摸月.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","1m","4p","5p","6p","7s","8s","9s","2s","3s","4s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    ps.tiles_left = 2;
+    let next = r#"
+        {"type":"tsumo","actor":0,"pai":"E"}
+        {"type":"ankan","actor":0,"consumed":["1m","1m","1m","1m"]}
+        {"type":"dora","dora_marker":"9p"}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.tiles_left, 0);
+    assert!(ps.last_cans.can_tsumo_agari);
+    // 門前清自摸和 嶺上開花
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap(),
+        Point::calc(true, 60, 2),
+    );
+
+    // The discard after a rinshan tsumo of the last tile is still 河底撈魚.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    ps.tiles_left = 2;
+    let next = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"ankan","actor":3,"consumed":["S","S","S","S"]}
+        {"type":"dora","dora_marker":"9p"}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"4s","tsumogiri":true}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.tiles_left, 0);
+    assert!(ps.last_cans.can_ron_agari);
+    // 河底撈魚 only
+    assert_eq!(
+        ps.agari_points(true, &[]).unwrap(),
+        Point::calc(false, 40, 1),
+    );
+}
+
+#[test]
+fn haitei_and_rinshan_on_last_tile() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","1m","4p","5p","6p","7s","8s","9s","2s","3s","4s","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"N","tsumogiri":true}
+    "#;
+    let mut start = PlayerState::from_log(0, log);
+    start.tiles_left = 2;
+    start.test_update_json(r#"{"type":"tsumo","actor":3,"pai":"?"}"#);
+
+    // A regular tsumo of the last tile is 海底摸月, where no kan is allowed.
+    let mut ps = start.clone();
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"W","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"E"}"#);
+    assert_eq!(ps.tiles_left, 0);
+    assert!(ps.last_cans.can_tsumo_agari);
+    // 門前清自摸和 海底摸月
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap(),
+        Point::calc(true, 40, 2),
+    );
+    let mut ps = start.clone();
+    ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"W","tsumogiri":true}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"1m"}"#);
+    assert!(!ps.last_cans.can_ankan);
+
+    // The rinshan tsumo of a daiminkan on the last tile is 嶺上開花 only,
+    // even for an open hand.
+    let mut ps = start.clone();
+    let next = r#"
+        {"type":"dahai","actor":3,"pai":"1m","tsumogiri":true}
+        {"type":"daiminkan","actor":0,"target":3,"pai":"1m","consumed":["1m","1m","1m"]}
+        {"type":"tsumo","actor":0,"pai":"E"}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.tiles_left, 0);
+    assert!(ps.last_cans.can_tsumo_agari);
+    // 20 + 16 for the minkan + 4 for the double east pair + 2 for tanki + 2
+    // for tsumo.
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap(),
+        Point::calc(true, 50, 1),
+    );
+
+    // The discard right after a rinshan tsumo of the last tile is still
+    // 河底撈魚, which is the only yaku here and makes the ron legal.
+    let mut ps = start;
+    let next = r#"
+        {"type":"ankan","actor":3,"consumed":["S","S","S","S"]}
+        {"type":"dora","dora_marker":"1p"}
+        {"type":"tsumo","actor":3,"pai":"?"}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.tiles_left, 0);
+    let cans = ps.test_update_json(r#"{"type":"dahai","actor":3,"pai":"E","tsumogiri":false}"#);
+    assert!(cans.can_ron_agari);
+    // 20 + 10 for menzen ron + 8 for the concealed 1m triplet + 4 for the
+    // double east pair + 2 for tanki.
+    assert_eq!(
+        ps.agari_points(true, &[]).unwrap(),
+        Point::calc(true, 50, 1),
+    );
+}
+
+#[test]
+fn single_player_tables_json() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","5mr","9p","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let tables = ps.single_player_tables().unwrap();
+
+    let value: serde_json::Value = serde_json::from_str(&tables.to_json()).unwrap();
+    let table = value["max_ev_table"].as_array().unwrap();
+    assert_eq!(table.len(), tables.max_ev_table.len());
+
+    let tiles: Vec<_> = table.iter().map(|c| c["tile"].as_str().unwrap()).collect();
+    assert!(tiles.contains(&"5mr"));
+    assert!(!tiles.contains(&"5m"));
+    assert!(tiles.contains(&"N"));
+    for (c, raw) in table.iter().zip(&tables.max_ev_table) {
+        assert_eq!(c["tile"], raw.tile.to_string());
+        assert_eq!(
+            c["exp_values"].as_array().unwrap().len(),
+            raw.exp_values.len()
+        );
+        assert_eq!(
+            c["num_required_tiles"].as_u64().unwrap(),
+            raw.num_required_tiles as u64
+        );
+    }
+}
+
+#[test]
+fn pao_daisangen_tsumo() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["P","P","F","F","C","C","1m","2m","3m","4s","5s","6s","9p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":0,"target":1,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"F","tsumogiri":true}
+        {"type":"pon","actor":0,"target":2,"pai":"F","consumed":["F","F"]}
+        {"type":"dahai","actor":0,"pai":"6s","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.pao_actor(), None);
+
+    let next = r#"
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"C","tsumogiri":true}
+        {"type":"pon","actor":0,"target":3,"pai":"C","consumed":["C","C"]}
+        {"type":"dahai","actor":0,"pai":"5s","tsumogiri":false}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"4s"}
+    "#;
+    for line in next.trim().split('\n') {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.pao_actor(), Some(3));
+    assert!(ps.last_cans.can_tsumo_agari);
+
+    // Player 3 pays the whole tsumo.
+    let (point, liable) = ps.agari_points_with_liable(false, &[]).unwrap();
+    assert_eq!(liable, Some(3));
+    assert_eq!(point, Point::yakuman(true, 1));
+    assert_eq!(point.ron, point.tsumo_total(true));
+}
+
+#[test]
+fn ryukyoku_payment_delta() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","E","E","N","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let tenpai = PlayerState::from_log(0, log);
+    assert!(tenpai.is_tenpai());
+    assert_eq!(
+        tenpai.ryukyoku_payment_delta([true, false, true]),
+        [1000, 1000, -3000, 1000],
+    );
+    assert_eq!(
+        tenpai.ryukyoku_payment_delta([false, true, false]),
+        [1500, -1500, 1500, -1500],
+    );
+    assert_eq!(
+        tenpai.ryukyoku_payment_delta([false; 3]),
+        [3000, -1000, -1000, -1000],
+    );
+    assert_eq!(tenpai.ryukyoku_payment_delta([true; 3]), [0; 4]);
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","4m","7m","2p","5p","8p","3s","6s","9s","E","S","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let noten = PlayerState::from_log(0, log);
+    assert!(!noten.is_tenpai());
+    assert_eq!(
+        noten.ryukyoku_payment_delta([true, true, true]),
+        [-3000, 1000, 1000, 1000],
+    );
+    assert_eq!(
+        noten.ryukyoku_payment_delta([true, false, true]),
+        [-1500, 1500, -1500, 1500],
+    );
+    assert_eq!(noten.ryukyoku_payment_delta([false; 3]), [0; 4]);
+}
+
+#[test]
+fn explain_discard() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+
+    let n = ps.explain_discard(t!(N)).unwrap();
+    assert!(n.is_legal);
+    assert_eq!(n.shanten, 0);
+    assert!(n.keeps_tenpai);
+    assert!(!n.is_furiten);
+    assert_eq!(n.ukeire, 8);
+    assert!(n.ukeire_tiles[tuz!(1s)] && n.ukeire_tiles[tuz!(4s)]);
+    assert_eq!(n.ukeire_tiles.iter().filter(|&&b| b).count(), 2);
+
+    let e = ps.explain_discard(t!(E)).unwrap();
+    assert!(e.is_legal);
+    assert_eq!(e.shanten, 1);
+    assert!(!e.keeps_tenpai);
+    assert!(!e.is_furiten);
+
+    ps.explain_discard(t!(5mr)).unwrap_err();
+    ps.explain_discard(t!(S)).unwrap_err();
+
+    // We have discarded 4s before.
+    ps.discarded_tiles[tuz!(4s)] = true;
+    assert!(ps.explain_discard(t!(N)).unwrap().is_furiten);
+    assert!(!ps.explain_discard(t!(E)).unwrap().is_furiten);
+
+    // Not 3n+2 anymore.
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#);
+    ps.explain_discard(t!(E)).unwrap_err();
+}
+
+#[test]
+fn dora_detail() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"4m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["5mr","5m","5m","5m","2p","3p","4p","6s","7s","8s","E","E","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"ankan","actor":0,"consumed":["5mr","5m","5m","5m"]}
+        {"type":"dora","dora_marker":"1p"}
+        {"type":"tsumo","actor":0,"pai":"9p"}
+        {"type":"reach","actor":0}
+        {"type":"dahai","actor":0,"pai":"9p","tsumogiri":true}
+        {"type":"reach_accepted","actor":0}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let ura = t![N, 3p];
+
+    // 5555m with 5mr, 2p, EE by the ura N, and 4p by the kan-ura 3p.
+    let expected = DoraDetail {
+        dora: 4,
+        kan_dora: 1,
+        aka: 1,
+        ura: 3,
+    };
+    assert_eq!(ps.dora_detail(&ura), expected);
+    assert_eq!(expected.total(), ps.doras_owned[0] + 3);
+
+    // The ron tile counts.
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":1,"pai":"E","tsumogiri":true}"#);
+    assert!(ps.last_cans.can_ron_agari);
+    assert_eq!(ps.dora_detail(&ura), DoraDetail { ura: 4, ..expected });
+    assert_eq!(
+        ps.dora_detail(&t![S, 3p]),
+        DoraDetail { ura: 1, ..expected }
+    );
+
+    // No ura without riichi.
+    ps.riichi_accepted[0] = false;
+    assert_eq!(ps.dora_detail(&ura).ura, 0);
+}
+
+#[test]
+fn kiriage_mangan() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"7p","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","3s","4s","5s","6s","7s","8p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"5s","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    // 断幺九 平和 ドラ2, 30 fu
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point, Point::calc(false, 30, 4));
+    assert_eq!(point.ron, 7700);
+
+    let ps = ps.with_kiriage_mangan(true);
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point, Point::mangan(false));
+    assert_eq!(point.ron, 8000);
+}
+
+#[test]
+fn kazoe_yakuman() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"7m","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["2m","2m","3m","3m","4m","4m","5mr","5m","6m","6m","7m","7m","8m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"8m","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    // 清一色 二盃口 断幺九 平和 ドラ2 赤ドラ1, 14 han
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point, Point::yakuman(false, 1));
+    assert_eq!(point.limit(false), Limit::Yakuman(1));
+
+    let ps = ps.with_kazoe_yakuman(false);
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point, Point::calc(false, 30, 12));
+    assert_eq!(point.ron, 24000);
+    assert_eq!(point.limit(false), Limit::Sanbaiman);
+}
+
+#[test]
+fn tsumo_rounding() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"N","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","6p","7p","3s","4s","5s","6s","7s","8p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"8s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+
+    // 門前清自摸和 断幺九 平和, 20 fu
+    let point = ps.agari_points(false, &[]).unwrap();
+    assert_eq!((point.tsumo_ko, point.tsumo_oya), (700, 1300));
+
+    let ps = ps.with_tsumo_rounding(TsumoRounding::Total);
+    let point = ps.agari_points(false, &[]).unwrap();
+    assert_eq!((point.tsumo_ko, point.tsumo_oya), (700, 1200));
+    assert_eq!(point.tsumo_total(false), point.ron);
+}
+
+#[test]
+fn local_yaku() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2p","2p","3p","3p","4p","4p","5p","5p","6p","6p","7p","7p","8p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"8p","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_ron_agari);
+
+    // 清一色 二盃口 平和 断幺九, 三倍満
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point.ron, 36000);
+
+    // 大車輪
+    let ps = ps.with_local_yaku(LocalYaku {
+        daisharin: true,
+        ..Default::default()
+    });
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point.ron, 48000);
+}
+
+#[test]
+fn legal_actions() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":2,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"1p","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(
+        ps.legal_actions(),
+        LegalActions {
+            discards: [false; 37],
+            can_riichi: false,
+            chis: vec![],
+            pon: Some([t!(1p), t!(1p)]),
+            daiminkan: Some(t!(1p)),
+            ankans: vec![],
+            kakans: vec![],
+            can_ron_agari: false,
+            can_tsumo_agari: false,
+            can_ryukyoku: false,
+            target: Some((2, t!(1p))),
+        },
+    );
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["3m","4m","5mr","5m","6m","7m","1p","1p","1p","9s","9s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1p"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let legal = ps.legal_actions();
+    assert_eq!(legal.discards, ps.discard_candidates_aka());
+    assert!(legal.discards[t!(5mr).as_usize()]);
+    assert!(!legal.discards[t!(2m).as_usize()]);
+    assert_eq!(legal.ankans, [t!(1p)]);
+    assert!(legal.pon.is_none() && legal.daiminkan.is_none() && legal.chis.is_empty());
+    assert!(!legal.can_ron_agari);
+    assert_eq!(legal.target, None);
+}
+
+#[test]
+fn rule_based_ryukyoku_renchan_rule() {
+    // All-last oya in the last place.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"S","dora_marker":"9m","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[30000,26000,25000,19000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","9m","1p","9p","1s","9s","E","S","W","2m","4m","6p","8s"]]}
+        {"type":"tsumo","actor":3,"pai":"7s"}
+    "#;
+    let ps = PlayerState::from_log(3, log);
+    assert!(ps.is_all_last);
+    assert_eq!(ps.rank, 3);
+    assert!(ps.can_kyushu_kyuhai());
+    assert!(ps.rule_based_ryukyoku());
+    let ps = ps.with_renchan_rule(RenchanRule::Agari);
+    assert!(!ps.rule_based_ryukyoku());
+
+    // Not in the last place, so it doesn't matter.
+    let log = log.replace("[30000,26000,25000,19000]", "[30000,26000,19000,25000]");
+    let ps = PlayerState::from_log(3, &log).with_renchan_rule(RenchanRule::Agari);
+    assert_eq!(ps.rank, 2);
+    assert!(ps.rule_based_ryukyoku());
+}
+
+#[test]
+fn atozuke() {
+    // Shanpon on 5m and C, only the latter has a yaku.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","5m","5m","C","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"C","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let mut ps_nashi = PlayerState::new(0).with_atozuke(false);
+    for line in log.trim().split('\n') {
+        ps_nashi.test_update_json(line);
+    }
+    assert!(ps.last_cans.can_ron_agari);
+    assert!(!ps_nashi.last_cans.can_ron_agari);
+
+    for line in [
+        r#"{"type":"tsumo","actor":2,"pai":"?"}"#,
+        r#"{"type":"dahai","actor":2,"pai":"5m","tsumogiri":true}"#,
+    ] {
+        ps.test_update_json(line);
+        ps_nashi.test_update_json(line);
+    }
+    assert!(!ps.last_cans.can_ron_agari);
+    assert!(!ps_nashi.last_cans.can_ron_agari);
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","5m","5m","C","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.discard_candidates_with_unconditional_tenpai()[tuz!(N)]);
+    let ps = ps.with_atozuke(false);
+    assert!(!ps.discard_candidates_with_unconditional_tenpai()[tuz!(N)]);
+}
+
+#[test]
+fn can_kyushu_kyuhai() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","9p","1s","9s","E","S","W","2m","3m","4m","5m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"6m"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.yaokyuu_kind_count(), 9);
+    assert!(ps.can_kyushu_kyuhai());
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"6m","tsumogiri":true}"#);
+    assert!(!ps.can_kyushu_kyuhai());
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","9p","1s","9s","E","S","6p","2m","3m","4m","5m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"7m"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.yaokyuu_kind_count(), 8);
+    assert!(!ps.can_kyushu_kyuhai());
+
+    // A call before our first draw breaks the go-around.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","9p","1s","9s","E","S","W","2m","3m","4m","5m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":2,"target":3,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":2,"pai":"7p","tsumogiri":false}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"8p","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"6m"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.yaokyuu_kind_count(), 9);
+    assert!(!ps.can_kyushu_kyuhai());
+}
+
+#[test]
+fn riichi_declare_candidates() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","3s","5s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_riichi);
+    let mut expected = [false; 34];
+    expected[tuz!(N)] = true;
+    assert_eq!(ps.riichi_declare_candidates(), expected);
+    assert_eq!(ps.discard_candidates().iter().filter(|&&b| b).count(), 13);
+
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","3s","5s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"9s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(!ps.last_cans.can_riichi);
+    assert_eq!(ps.riichi_declare_candidates(), [false; 34]);
+}
+
+#[test]
+fn renhou() {
+    // No yaku other than 人和.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","3s","9s","9s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"2s","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(!ps.last_cans.can_ron_agari);
+
+    let mut ps = PlayerState::new(0).with_renhou_rule(RenhouRule::Mangan);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(ps.last_cans.can_ron_agari);
+    assert_eq!(ps.agari_points(true, &[]).unwrap(), Point::mangan(false));
+
+    let mut ps = PlayerState::new(0).with_renhou_rule(RenhouRule::Yakuman);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(ps.last_cans.can_ron_agari);
+    assert_eq!(
+        ps.agari_points(true, &[]).unwrap(),
+        Point::yakuman(false, 1)
+    );
+
+    // The hand's own value is taken if it is higher than a mangan.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","1m","2m","3m","4m","5m","6m","7m","6m","7m","8m","9m","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"9m","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::new(0).with_renhou_rule(RenhouRule::Mangan);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert_eq!(
+        ps.agari_points(true, &[]).unwrap(),
+        Point::calc(false, 40, 6)
+    );
+
+    // A call before our first draw breaks it.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":2,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","3s","9s","9s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"P","tsumogiri":true}
+        {"type":"pon","actor":3,"target":2,"pai":"P","consumed":["P","P"]}
+        {"type":"dahai","actor":3,"pai":"2s","tsumogiri":false}
+    "#;
+    let mut ps = PlayerState::new(0).with_renhou_rule(RenhouRule::Yakuman);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(!ps.last_cans.can_ron_agari);
+}
+
+#[test]
+fn opponent_suit_read() {
+    let mut ps = PlayerState::new(0);
+    // Dumping every manzu and pinzu.
+    ps.kawa_overview[2] = t![N, 1m, 9p, 3m, W, 2p, 7m, 5pr, 8m, 4p, 1p]
+        .into_iter()
+        .collect();
+    let read = ps.opponent_suit_read(2);
+    assert!(read.likelihood[..2].iter().all(|&l| l < 0.2));
+    assert!((read.likelihood[2] - 1.).abs() < 1e-6);
+    assert_eq!(read.likely_flush, Some(2));
+
+    // A single souzu discard lowers the purity, but it is still likely.
+    ps.kawa_overview[2].push(t!(9s));
+    let read = ps.opponent_suit_read(2);
+    assert!((read.likelihood[2] - 0.81).abs() < 1e-6);
+    assert_eq!(read.likely_flush, Some(2));
+
+    // A pinzu meld rules souzu out.
+    ps.melds[2].push(Meld::Pon {
+        pai: t!(5p),
+        consumed: t![5p, 5p],
+        target: 1,
+    });
+    let read = ps.opponent_suit_read(2);
+    assert!(read.likelihood[2] == 0.);
+    assert_eq!(read.likely_flush, None);
+
+    // Too few discards to tell, and jihai melds don't matter.
+    ps.kawa_overview[1] = t![1m, 2p].into_iter().collect();
+    ps.melds[1].push(Meld::Pon {
+        pai: t!(C),
+        consumed: t![C, C],
+        target: 2,
+    });
+    let read = ps.opponent_suit_read(1);
+    assert!(read.likelihood.iter().all(|&l| l < 0.5));
+    assert!(read.likelihood[2] > 0.);
+    assert_eq!(read.likely_flush, None);
+    assert_eq!(ps.opponent_suit_read(3), SuitRead::default());
+}
+
+#[test]
+fn estimated_deal_in_risk() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"4m","tsumogiri":false}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"7p","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"C","tsumogiri":true}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let early = ps.estimated_deal_in_risk(1);
+    assert!(ps.estimated_tenpai_prob(1) < 0.05);
+    assert!(early.iter().all(|&r| r < 0.005));
+    assert!(early[tuz!(4m)] == 0.);
+    // 1m and 7m are suji of 4m.
+    assert!(early[tuz!(1m)] < early[tuz!(2m)]);
+    assert!(early[tuz!(7m)] < early[tuz!(5m)]);
+    // Passed after their discard in this cycle.
+    assert!(early[tuz!(7p)] == 0.);
+
+    // All of 3s gone rules out the 23s ryanmen on 1s and 4s alike, but not
+    // the 45s one on 6s.
+    let mut kabe_ps = ps.clone();
+    kabe_ps.tiles_seen[tuz!(3s)] = 4;
+    let kabe = kabe_ps.estimated_deal_in_risk(1);
+    assert!(kabe[tuz!(1s)] < early[tuz!(1s)]);
+    assert!(kabe[tuz!(4s)] < early[tuz!(4s)]);
+    assert!(kabe[tuz!(1s)] < kabe[tuz!(9s)]);
+    assert!((kabe[tuz!(6s)] - early[tuz!(6s)]).abs() < f32::EPSILON);
+
+    // Late in the kyoku with 3 melds.
+    let mut late_ps = ps.clone();
+    for t in t![9s, 1p, N, W, 2p, 8p, 3s, 6m, 9p, 1s, S] {
+        late_ps.kawa_overview[1].push(t);
+    }
+    for t in t![P, F, 6s] {
+        late_ps.melds[1].push(Meld::Pon {
+            pai: t,
+            consumed: [t; 2],
+            target: 2,
+        });
+    }
+    let late = late_ps.estimated_deal_in_risk(1);
+    assert!(late_ps.estimated_tenpai_prob(1) > 0.6);
+    for (tid, (&e, &l)) in early.iter().zip(&late).enumerate() {
+        assert!(e == 0. && l == 0. || l > e * 10., "{}", must_tile!(tid));
+    }
+    assert!(late[tuz!(5m)] > 0.05);
+
+    let mut riichi_ps = ps;
+    riichi_ps.riichi_declared[1] = true;
+    assert!((riichi_ps.estimated_tenpai_prob(1) - 1.).abs() < f32::EPSILON);
+}
+
+/// Picks a random legal reaction, leaving out kans involving akas to keep
+/// `consumed` simple.
+fn random_reaction(state: &PlayerState, rng: &mut impl Rng) -> Event {
+    let actor = state.player_id;
+    let legal = state.legal_actions();
+    let no_aka = |t: &Tile| t.is_jihai() || t.as_u8() % 9 != 4;
+
+    if (legal.can_tsumo_agari || legal.can_ron_agari) && rng.random_bool(0.5) {
+        let target = legal.target.map_or(actor, |(target, _)| target);
+        return Event::Hora {
+            actor,
+            target,
+            deltas: None,
+            ura_markers: None,
+        };
+    }
+    if let Some((target, pai)) = legal.target {
+        if let Some(consumed) = legal.pon
+            && rng.random_bool(0.3)
+        {
+            return Event::Pon {
+                actor,
+                target,
+                pai,
+                consumed,
+            };
+        }
+        if let Some(pai) = legal.daiminkan.filter(no_aka)
+            && rng.random_bool(0.3)
+        {
+            return Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed: [pai; 3],
+            };
+        }
+        if !legal.chis.is_empty() && rng.random_bool(0.3) {
+            return Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed: *legal.chis.choose(rng).unwrap(),
+            };
+        }
+        return Event::None;
+    }
+    if !legal.discards.contains(&true) {
+        return Event::None;
+    }
+
+    if legal.can_riichi && rng.random_bool(0.5) {
+        return Event::Reach { actor };
+    }
+    if let Some(&pai) = legal.ankans.iter().find(|t| no_aka(t))
+        && rng.random_bool(0.5)
+    {
+        return Event::Ankan {
+            actor,
+            consumed: [pai; 4],
+        };
+    }
+    if let Some(&pai) = legal.kakans.iter().find(|t| no_aka(t))
+        && rng.random_bool(0.5)
+    {
+        return Event::Kakan {
+            actor,
+            pai,
+            consumed: [pai; 3],
+        };
+    }
+    let discards: Vec<_> = (0..37).filter(|&t| legal.discards[t]).collect();
+    let pai = must_tile!(*discards.choose(rng).unwrap());
+    Event::Dahai {
+        actor,
+        pai,
+        tsumogiri: state.last_self_tsumo == Some(pai),
+    }
+}
+
+#[test]
+fn encode_decode() {
+    let mut rng = ChaCha12Rng::seed_from_u64(313);
+    let mut count = 0;
+    let (mut seen_fuuro, mut seen_kan, mut seen_riichi) = (false, false, false);
+    for kyoku in 0..32 {
+        let mut board = Board {
+            kyoku: kyoku % 8,
+            honba: kyoku % 3,
+            kyotaku: kyoku % 2,
+            scores: [25000; 4],
+            ..Default::default()
+        };
+        board.init_from_seed((kyoku.into(), 313));
+        let mut board_state = board.into_state();
+
+        let mut reactions: [EventExt; 4] = Default::default();
+        loop {
+            let poll = board_state.poll(reactions).unwrap();
+            let states = board_state.agent_context().player_states;
+            for state in states {
+                let decoded = PlayerState::decode(&state.encode());
+                assert!(decoded.ok().as_ref() == Some(state));
+                count += 1;
+                seen_fuuro |= state.fuuro_tiles(0).next().is_some();
+                seen_kan |= state.kans_on_board > 0;
+                seen_riichi |= state.riichi_accepted[0];
+            }
+            if matches!(poll, Poll::End) {
+                break;
+            }
+            reactions = array::from_fn(|i| random_reaction(&states[i], &mut rng).into());
+        }
+    }
+    assert!(count > 1000, "{count}");
+    assert!(seen_fuuro && seen_kan && seen_riichi);
+
+    let state = PlayerState::with_aka_config(2, AkaConfig { counts: [1, 2, 1] })
+        .with_kan_ura_rule(KanUraRule::OwnKanOrTsumo)
+        .with_renhou_rule(RenhouRule::Yakuman)
+        .with_kazoe_yakuman(false)
+        .with_tsumo_rounding(TsumoRounding::Total)
+        .with_local_yaku(LocalYaku {
+            sanrenkou: true,
+            ..Default::default()
+        });
+    let encoded = state.encode();
+    assert!(PlayerState::decode(&encoded).ok() == Some(state));
+
+    let decode_err = |bytes: &[u8]| PlayerState::decode(bytes).err().unwrap();
+    decode_err(&[]);
+    decode_err(&encoded[..encoded.len() - 1]);
+    let mut bad = encoded.clone();
+    bad.push(0);
+    decode_err(&bad);
+    let mut bad = encoded.clone();
+    bad[0] ^= 1;
+    decode_err(&bad);
+    let mut bad = encoded;
+    bad[1] += 1;
+    let err = decode_err(&bad);
+    assert!(err.to_string().contains("unsupported version"), "{err}");
+}
+
+#[test]
+fn hand_string() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["3m","5mr","6m","7m","5p","5p","5pr","2s","3s","E","E","E","E"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"C"}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.tehai_str(), "3067m 055p 23s 11117z");
+    assert_eq!(ps.to_hand_string(), ps.tehai_str());
+
+    ps.test_update_json(r#"{"type":"ankan","actor":0,"consumed":["E","E","E","E"]}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":0,"pai":"9s"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"C","tsumogiri":false}"#);
+    ps.test_update_json(r#"{"type":"tsumo","actor":1,"pai":"?"}"#);
+    ps.test_update_json(r#"{"type":"dahai","actor":1,"pai":"5p","tsumogiri":true}"#);
+    ps.test_update_json(
+        r#"{"type":"pon","actor":0,"target":1,"pai":"5p","consumed":["5p","5pr"]}"#,
+    );
+    assert_eq!(ps.tehai_str(), "3067m 5p 239s");
+    assert_eq!(ps.to_hand_string(), "3067m 5p 239s [1111z] [055p]");
+
+    // The concealed hand round-trips through the parser.
+    let tiles = hand_with_aka(&ps.tehai_str()).unwrap();
+    assert_eq!(tiles37_to_string(&tiles), ps.tehai_str());
+    assert_eq!(hand(&ps.tehai_str()).unwrap(), ps.tehai);
+    assert_eq!(tiles[tuz!(5mr)..], ps.akas_in_hand);
+}
+
+#[test]
+fn should_riichi() {
+    // Yakuless kanchan tenpai, for which dama can only win by tsumo.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","1s","3s","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_riichi);
+    assert_eq!(ps.should_riichi(), Some(true));
+
+    // Not legal after the discard.
+    let mut ps = ps;
+    ps.test_update_json(r#"{"type":"dahai","actor":0,"pai":"N","tsumogiri":true}"#);
+    assert_eq!(ps.should_riichi(), None);
+
+    // Tenpai with both possible tanki waits dead, on a hand that already has
+    // 一気通貫 for dama, so riichi could only lose the stick.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","1p","2p","3p","5s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"W"}
+        {"type":"dahai","actor":0,"pai":"W","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9s","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"E","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"9s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_riichi);
+    assert_eq!(ps.should_riichi(), Some(false));
+
+    // 混一色 一気通貫 東 ドラ1 on a 西 tanki, already a baiman by tsumo as dama.
+    // Riichi still scores a bit more, but only on the rare 2 uras to
+    // sanbaiman, which is not worth the stick.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"8m","kyoku":2,"honba":0,"kyotaku":0,"oya":1,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","E","E","E","S"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"S","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"P"}
+        {"type":"dahai","actor":0,"pai":"P","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"F","tsumogiri":true}
+        {"type":"tsumo","actor":3,"pai":"?"}
+        {"type":"dahai","actor":3,"pai":"P","tsumogiri":true}
+        {"type":"tsumo","actor":0,"pai":"W"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert!(ps.last_cans.can_riichi);
+    let tables = ps.single_player_tables_with_dama().unwrap();
+    let riichi = tables
+        .max_ev_table
+        .iter()
+        .find(|c| c.tile == t!(S))
+        .unwrap();
+    let dama = tables
+        .dama_table
+        .as_ref()
+        .unwrap()
+        .iter()
+        .find(|c| c.tile == t!(S))
+        .unwrap();
+    assert!(riichi.exp_values[0] > dama.exp_values[0]);
+    assert!(riichi.exp_values[0] + riichi.riichi_stick_ev < dama.exp_values[0]);
+    assert_eq!(ps.should_riichi(), Some(false));
+}
+
+#[test]
+fn mark_seen() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"4p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4m","5m","6m","7p","8p","9p","1s","1s","3s","4s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+        {"type":"dahai","actor":0,"pai":"N","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"dahai","actor":1,"pai":"2s","tsumogiri":true}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"2s","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::from_log(0, log);
+    let before = ps.encode();
+    assert!(ps.waits[tuz!(2s)]);
+    assert!(ps.waits[tuz!(5s)]);
+
+    // The last unseen copy of a wait.
+    ps.mark_seen(t!(2s)).unwrap();
+    assert_eq!(ps.tiles_seen[tuz!(2s)], 3);
+    assert!(ps.waits[tuz!(2s)]);
+    ps.mark_seen(t!(2s)).unwrap();
+    assert_eq!(ps.tiles_seen[tuz!(2s)], 4);
+    assert!(!ps.waits[tuz!(2s)]);
+    ps.mark_seen(t!(2s)).unwrap_err();
+    ps.unmark_seen(t!(2s)).unwrap();
+    assert!(ps.waits[tuz!(2s)]);
+    ps.unmark_seen(t!(2s)).unwrap();
+    assert_eq!(ps.encode(), before);
+
+    // Akas and doras.
+    ps.mark_seen(t!(5pr)).unwrap();
+    assert_eq!(ps.tiles_seen[tuz!(5p)], 1);
+    assert_eq!(ps.akas_seen, [0, 1, 0]);
+    assert_eq!(ps.doras_seen, 2);
+    ps.mark_seen(t!(5pr)).unwrap_err();
+    ps.mark_seen(t!(5p)).unwrap();
+    assert_eq!(ps.doras_seen, 3);
+    ps.unmark_seen(t!(5pr)).unwrap();
+    assert_eq!(ps.akas_seen, [0, 0, 0]);
+    ps.unmark_seen(t!(5pr)).unwrap_err();
+    ps.unmark_seen(t!(5p)).unwrap();
+    assert_eq!(ps.encode(), before);
+
+    // Plain copies and akas are counted apart. Only the aka is seen apart
+    // from the plain 5m in our hand, which can't be unmarked as a 5m.
+    ps.mark_seen(t!(5mr)).unwrap();
+    ps.unmark_seen(t!(5m)).unwrap_err();
+    for _ in 0..2 {
+        ps.mark_seen(t!(5m)).unwrap();
+    }
+    assert_eq!(ps.tiles_seen[tuz!(5m)], 4);
+    ps.mark_seen(t!(5m)).unwrap_err();
+    ps.mark_seen(t!(5mr)).unwrap_err();
+    for _ in 0..2 {
+        ps.unmark_seen(t!(5m)).unwrap();
+    }
+    ps.unmark_seen(t!(5m)).unwrap_err();
+    ps.unmark_seen(t!(5mr)).unwrap();
+    assert_eq!(ps.encode(), before);
+
+    // The aka leaves only 3 plain 5p.
+    for _ in 0..3 {
+        ps.mark_seen(t!(5p)).unwrap();
+    }
+    ps.mark_seen(t!(5p)).unwrap_err();
+    for _ in 0..3 {
+        ps.unmark_seen(t!(5p)).unwrap();
+    }
+    assert_eq!(ps.encode(), before);
+
+    // Tiles in our hand can't be unmarked.
+    ps.unmark_seen(t!(1s)).unwrap_err();
+    ps.unmark_seen(t!(E)).unwrap_err();
+    assert_eq!(ps.encode(), before);
+}
+
+#[test]
+fn discard_candidates_preserving_yaku() {
+    // 234m 555p 345s 66s 78s + 1m. Discarding 1m keeps 断幺九 on 6s of the
+    // 6-9s wait, while discarding 4m leaves 123m in a yakuless tenpai.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","5p","5p","3s","4s","5s","6s","6s","7s","8s"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"1m"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    let discards = ps.discard_candidates_preserving_yaku();
+    for (tid, &b) in discards.iter().enumerate() {
+        assert_eq!(b, tid == tuz!(1m), "{}", must_tile!(tid));
+    }
+
+    // After a riichi declaration, every legal discard is a tenpai discard and
+    // keeps a yaku, which includes 1-4m nobetan ones.
+    let mut ps = ps;
+    ps.test_update_json(r#"{"type":"reach","actor":0}"#);
+    let discards = ps.discard_candidates_preserving_yaku();
+    assert_eq!(discards, ps.discard_candidates());
+    assert!(discards[tuz!(4m)]);
+    assert!(discards[tuz!(3s)]);
+
+    // From 1 shanten, 234m 555p 345s 66s 7s N + 8s, where discarding N makes
+    // the same tanyao-dependent tenpai.
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9m","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["2m","3m","4m","5p","5p","5p","3s","4s","5s","6s","6s","7s","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"8s"}
+    "#;
+    let ps = PlayerState::from_log(0, log);
+    assert_eq!(ps.shanten, 1);
+    assert!(ps.discard_candidates_with_unconditional_tenpai()[tuz!(N)]);
+    let discards = ps.discard_candidates_preserving_yaku();
+    for (tid, &b) in discards.iter().enumerate() {
+        assert_eq!(b, tid == tuz!(N), "{}", must_tile!(tid));
+    }
+}
+
+#[test]
+fn pass_first_turn() {
+    let start = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"9p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","1p","1s","9s","E","S","W","N","P","F","C","C"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+    "#;
+    let tsumo = r#"{"type":"tsumo","actor":0,"pai":"9p"}"#;
+
+    let mut ps = PlayerState::from_log(0, start);
+    let cans = ps.test_update_json(tsumo);
+    assert!(cans.can_ryukyoku);
+    // 国士無双 as 天和, counted as a single yakuman.
+    assert_eq!(
+        ps.agari_points(false, &[]).unwrap(),
+        Point::yakuman(true, 1)
+    );
+
+    let mut ps = PlayerState::from_log(0, start);
+    ps.pass_first_turn().unwrap();
+    let cans = ps.test_update_json(tsumo);
+    assert!(!cans.can_ryukyoku);
+    assert!(cans.can_tsumo_agari);
+    assert!(!ps.can_w_riichi);
+    // No made-up events, so nothing is in the kawa or seen but our own hand
+    // and the dora indicator.
+    assert!(ps.kawa[0].is_empty());
+    assert_eq!(ps.tiles_seen.iter().sum::<u8>(), 15);
+    assert_eq!(ps.tiles_left, 69);
+
+    ps.pass_first_turn().unwrap_err();
+}
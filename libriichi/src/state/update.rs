@@ -1,7 +1,7 @@
 use super::PlayerState;
-use super::action::ActionCandidate;
+use super::action::{ActionCandidate, KanType};
 use super::item::{ChiPon, KawaItem, Sutehai};
-use crate::algo::agari::{self, AgariCalculator};
+use super::meld::Meld;
 use crate::algo::shanten;
 use crate::mjai::Event;
 use crate::rankings::Rankings;
@@ -137,11 +137,14 @@ impl PlayerState {
         self.waits.fill(false);
         self.dora_factor.fill(0);
         self.tiles_seen.fill(0);
-        self.akas_seen.fill(false);
+        self.akas_seen.fill(0);
         self.keep_shanten_discards.fill(false);
         self.next_shanten_discards.fill(false);
         self.forbidden_tiles.fill(false);
         self.discarded_tiles.fill(false);
+        self.safe_tiles = [[false; 34]; 4];
+        self.safe_tiles_this_cycle = [[false; 34]; 4];
+        self.own_discard_called = false;
 
         self.bakaze = bakaze;
         self.honba = honba;
@@ -159,9 +162,11 @@ impl PlayerState {
         self.scores.rotate_left(self.player_id as usize);
 
         self.dora_indicators.clear();
+        self.pending_kan_actors.clear();
+        self.kan_dora_actors.clear();
         self.doras_owned.fill(0);
         self.doras_seen = 0;
-        self.akas_in_hand.fill(false);
+        self.akas_in_hand.fill(0);
 
         self.ankan_candidates.clear();
         self.kakan_candidates.clear();
@@ -173,8 +178,10 @@ impl PlayerState {
         self.to_mark_same_cycle_furiten = None;
 
         self.is_menzen = true;
+        self.pao_actor = None;
         self.can_w_riichi = true;
         self.is_w_riichi = false;
+        self.is_open_riichi = false;
         self.chis.clear();
         self.pons.clear();
         self.minkans.clear();
@@ -183,14 +190,13 @@ impl PlayerState {
         self.kans_on_board = 0;
         self.tehai_len_div3 = 4;
         self.has_next_shanten_discard = false;
-        self.tiles_left = 70;
+        self.tiles_left = self.wall_rule.initial_tiles_left();
         self.at_turn = 0;
 
         self.kawa.iter_mut().for_each(|k| k.clear());
         self.last_tedashis.fill(None);
         self.kawa_overview.iter_mut().for_each(|k| k.clear());
-        self.fuuro_overview.iter_mut().for_each(|k| k.clear());
-        self.ankan_overview.iter_mut().for_each(|k| k.clear());
+        self.melds.iter_mut().for_each(|k| k.clear());
         self.intermediate_kan.clear();
         self.intermediate_chi_pon = None;
 
@@ -250,19 +256,8 @@ impl PlayerState {
             {
                 self.last_cans.can_tsumo_agari = true;
             } else {
-                let agari_calc = AgariCalculator {
-                    tehai: &self.tehai,
-                    is_menzen: self.is_menzen,
-                    chis: &self.chis,
-                    pons: &self.pons,
-                    minkans: &self.minkans,
-                    ankans: &self.ankans,
-                    bakaze: self.bakaze.as_u8(),
-                    jikaze: self.jikaze.as_u8(),
-                    winning_tile: pai.deaka().as_u8(),
-                    is_ron: false,
-                };
-                self.last_cans.can_tsumo_agari = agari_calc.has_yaku();
+                self.last_cans.can_tsumo_agari =
+                    self.has_yaku_on(&self.tehai, pai.deaka().as_usize(), false);
             }
         }
 
@@ -272,13 +267,9 @@ impl PlayerState {
         }
 
         if self.riichi_accepted[0] {
-            if self.kans_on_board < 4 {
-                // Using Tenhou rule here.
-                self.last_cans.can_ankan =
-                    agari::check_ankan_after_riichi(&self.tehai, self.tehai_len_div3, pai, false);
-                if self.last_cans.can_ankan {
-                    self.ankan_candidates.push(pai.deaka());
-                }
+            self.last_cans.can_ankan = self.can_ankan_after_riichi(pai);
+            if self.last_cans.can_ankan {
+                self.ankan_candidates.push(pai.deaka());
             }
             return Ok(());
         }
@@ -301,7 +292,7 @@ impl PlayerState {
         }
 
         self.last_cans.can_riichi = self.is_menzen
-            && self.tiles_left >= 4
+            && self.tiles_left >= self.wall_rule.num_players
             && self.scores[0] >= 1000
             && (self.shanten == 0 || self.shanten == 1 && self.has_next_shanten_discard);
 
@@ -331,6 +322,7 @@ impl PlayerState {
         self.kawa[actor_rel].push(Some(kawa_item));
         self.kawa_overview[actor_rel].push(pai);
         self.last_kawa_tile = Some(pai);
+        self.update_safe_tiles(actor_rel, pai);
 
         if !tsumogiri {
             self.last_tedashis[actor_rel] = Some(sutehai);
@@ -369,26 +361,15 @@ impl PlayerState {
         }
 
         if !self.at_furiten && self.waits[pai.deaka().as_usize()] {
-            if self.riichi_accepted[0] || self.tiles_left == 0 {
-                // 立直 or 河底撈魚
+            if self.riichi_accepted[0] || self.tiles_left == 0 || self.at_renhou() {
+                // 立直, 河底撈魚 or 人和
                 self.last_cans.can_ron_agari = true;
             } else {
                 let mut tehai_with_winning_tile = self.tehai;
                 tehai_with_winning_tile[pai.deaka().as_usize()] += 1;
 
-                let agari_calc = AgariCalculator {
-                    tehai: &tehai_with_winning_tile,
-                    is_menzen: self.is_menzen,
-                    chis: &self.chis,
-                    pons: &self.pons,
-                    minkans: &self.minkans,
-                    ankans: &self.ankans,
-                    bakaze: self.bakaze.as_u8(),
-                    jikaze: self.jikaze.as_u8(),
-                    winning_tile: pai.deaka().as_u8(),
-                    is_ron: true,
-                };
-                self.last_cans.can_ron_agari = agari_calc.has_yaku();
+                self.last_cans.can_ron_agari =
+                    self.has_yaku_on(&tehai_with_winning_tile, pai.deaka().as_usize(), true);
             }
 
             // Track same-cycle furiten
@@ -428,8 +409,14 @@ impl PlayerState {
 
     fn chi(&mut self, actor: u8, pai: Tile, consumed: [Tile; 2]) -> Result<()> {
         let actor_rel = self.rel(actor);
-        let full_set = consumed.into_iter().chain(iter::once(pai)).collect();
-        self.fuuro_overview[actor_rel].push(full_set);
+        if actor_rel == 1 {
+            self.own_discard_called = true;
+        }
+        self.melds[actor_rel].push(Meld::Chi {
+            pai,
+            consumed,
+            target: (actor_rel as u8 + 3) % 4,
+        });
         self.intermediate_chi_pon = Some(ChiPon {
             consumed,
             target_tile: pai,
@@ -439,7 +426,7 @@ impl PlayerState {
             for t in consumed {
                 self.witness_tile(t)?;
             }
-            for t in full_set {
+            for t in consumed.into_iter().chain(iter::once(pai)) {
                 self.update_doras_owned(actor_rel, t);
             }
             self.can_w_riichi = false;
@@ -496,8 +483,14 @@ impl PlayerState {
 
     fn pon(&mut self, actor: u8, target: u8, pai: Tile, consumed: [Tile; 2]) -> Result<()> {
         let actor_rel = self.rel(actor);
-        let full_set = consumed.into_iter().chain(iter::once(pai)).collect();
-        self.fuuro_overview[actor_rel].push(full_set);
+        if self.rel(target) == 0 {
+            self.own_discard_called = true;
+        }
+        self.melds[actor_rel].push(Meld::Pon {
+            pai,
+            consumed,
+            target: self.rel(target) as u8,
+        });
         self.intermediate_chi_pon = Some(ChiPon {
             consumed,
             target_tile: pai,
@@ -508,7 +501,7 @@ impl PlayerState {
             for t in consumed {
                 self.witness_tile(t)?;
             }
-            for t in full_set {
+            for t in consumed.into_iter().chain(iter::once(pai)) {
                 self.update_doras_owned(actor_rel, t);
             }
             self.can_w_riichi = false;
@@ -528,6 +521,7 @@ impl PlayerState {
             self.move_tile(t, MoveType::FuuroConsume)?;
         }
         self.pons.push(pai.deaka().as_u8());
+        self.update_pao_actor(target, pai);
 
         if self.tehai[pai.deaka().as_usize()] > 0 {
             self.forbidden_tiles[pai.deaka().as_usize()] = true;
@@ -543,17 +537,24 @@ impl PlayerState {
 
     fn daiminkan(&mut self, actor: u8, target: u8, pai: Tile, consumed: [Tile; 3]) -> Result<()> {
         let actor_rel = self.rel(actor);
-        let full_set = consumed.into_iter().chain(iter::once(pai)).collect();
-        self.fuuro_overview[actor_rel].push(full_set);
+        if self.rel(target) == 0 {
+            self.own_discard_called = true;
+        }
+        self.melds[actor_rel].push(Meld::Minkan {
+            pai,
+            consumed,
+            target: self.rel(target) as u8,
+        });
         self.intermediate_kan.push(pai);
         self.pad_kawa_for_pon_or_daiminkan(actor, target);
         self.kans_on_board += 1;
+        self.pending_kan_actors.push(actor_rel as u8);
 
         if actor_rel != 0 {
             for t in consumed {
                 self.witness_tile(t)?;
             }
-            for t in full_set {
+            for t in consumed.into_iter().chain(iter::once(pai)) {
                 self.update_doras_owned(actor_rel, t);
             }
             self.can_w_riichi = false;
@@ -570,6 +571,7 @@ impl PlayerState {
             self.move_tile(t, MoveType::FuuroConsume)?;
         }
         self.minkans.push(pai.deaka().as_u8());
+        self.update_pao_actor(target, pai);
 
         // The shanten number and the shape of tenpai (if any) may be
         // changed after a daiminkan.
@@ -583,14 +585,26 @@ impl PlayerState {
 
     fn kakan(&mut self, actor: u8, pai: Tile) -> Result<()> {
         let actor_rel = self.rel(actor);
-        for fuuro in &mut self.fuuro_overview[actor_rel] {
-            if fuuro[0].deaka() == pai.deaka() {
-                fuuro.push(pai);
+        for meld in &mut self.melds[actor_rel] {
+            if let Meld::Pon {
+                pai: pon_pai,
+                consumed,
+                target,
+            } = *meld
+                && pon_pai.deaka() == pai.deaka()
+            {
+                *meld = Meld::Shouminkan {
+                    pai: pon_pai,
+                    consumed,
+                    added: pai,
+                    target,
+                };
                 break;
             }
         }
         self.intermediate_kan.push(pai);
         self.kans_on_board += 1;
+        self.pending_kan_actors.push(actor_rel as u8);
 
         if actor_rel != 0 {
             self.witness_tile(pai)?;
@@ -598,7 +612,7 @@ impl PlayerState {
             self.last_kawa_tile = Some(pai); // for getting winning tile in self.agari
 
             // 槍槓
-            if !self.at_furiten && self.waits[pai.deaka().as_usize()] {
+            if self.can_chankan(KanType::Kakan, pai) {
                 self.last_cans.can_ron_agari = true;
                 self.to_mark_same_cycle_furiten = Some(());
                 self.chankan_chance = Some(());
@@ -630,9 +644,10 @@ impl PlayerState {
     fn ankan(&mut self, actor: u8, consumed: [Tile; 4]) -> Result<()> {
         let actor_rel = self.rel(actor);
         let tile = consumed[0].deaka();
-        self.ankan_overview[actor_rel].push(tile);
+        self.melds[actor_rel].push(Meld::Ankan { consumed });
         self.intermediate_kan.push(tile);
         self.kans_on_board += 1;
+        self.pending_kan_actors.push(actor_rel as u8);
 
         self.can_w_riichi = false;
         self.at_ippatsu = false;
@@ -642,6 +657,14 @@ impl PlayerState {
                 self.witness_tile(t)?;
                 self.update_doras_owned(actor_rel, t);
             }
+
+            // 国士無双の暗槓搶槓
+            if self.can_chankan(KanType::Ankan, tile) {
+                self.last_kawa_tile = Some(tile); // for getting winning tile in self.agari
+                self.last_cans.can_ron_agari = true;
+                self.to_mark_same_cycle_furiten = Some(());
+                self.chankan_chance = Some(());
+            }
             return Ok(());
         }
 
@@ -682,9 +705,15 @@ impl PlayerState {
         self.update_rank();
         if actor_rel == 0 {
             self.at_ippatsu = true;
+            self.calls_at_riichi = self.calls_on_board();
         }
     }
 
+    /// Kakans are not counted, as 槍槓 can still be ippatsu.
+    pub(super) fn calls_on_board(&self) -> usize {
+        self.melds.iter().map(|m| m.len()).sum()
+    }
+
     pub(super) const fn rel(&self, actor: u8) -> usize {
         ((actor + 4 - self.player_id) % 4) as usize
     }
@@ -707,24 +736,94 @@ impl PlayerState {
         *seen += 1;
 
         self.doras_seen += self.dora_factor[tile_id];
-        match tile.as_u8() {
-            tu8!(5mr) => {
-                self.akas_seen[0] = true;
-                self.doras_seen += 1;
-            }
-            tu8!(5pr) => {
-                self.akas_seen[1] = true;
-                self.doras_seen += 1;
-            }
-            tu8!(5sr) => {
-                self.akas_seen[2] = true;
-                self.doras_seen += 1;
-            }
-            _ => (),
+        if tile.is_aka() {
+            let aka_id = tile.as_usize() - tuz!(5mr);
+            let seen = &mut self.akas_seen[aka_id];
+            ensure!(
+                *seen < self.aka_config.counts[aka_id],
+                "rule violation: attempt to witness more {tile} than the ruleset has",
+            );
+            *seen += 1;
+            self.doras_seen += 1;
         }
         Ok(())
     }
 
+    /// Marks one more `tile` as seen outside of the events, for
+    /// counterfactual tools, keeping `tiles_seen`, `akas_seen`, `doras_seen`
+    /// and `waits` consistent. At 3n+1, marking the last unseen copy of a wait
+    /// removes it from `waits`, just like `update_waits_and_furiten` does,
+    /// while furiten is left as it is.
+    ///
+    /// Akas and the plain copies of a 5 are counted apart, so this errors if
+    /// all 4 copies, all akas of the ruleset for an aka, or all the other
+    /// copies for a plain 5 are already seen, in which case nothing is
+    /// changed.
+    pub fn mark_seen(&mut self, tile: Tile) -> Result<()> {
+        if let Some(aka_id) = aka_id_of(tile) {
+            let aka_count = self.aka_config.counts[aka_id];
+            let (seen, max) = if tile.is_aka() {
+                (self.akas_seen[aka_id], aka_count)
+            } else {
+                let tile_id = tile.as_usize();
+                (
+                    self.tiles_seen[tile_id] - self.akas_seen[aka_id],
+                    4 - aka_count,
+                )
+            };
+            ensure!(
+                seen < max,
+                "attempt to mark more {tile} than the ruleset has"
+            );
+        }
+        self.witness_tile(tile)?;
+        self.refresh_wait_on(tile.deaka().as_usize());
+        Ok(())
+    }
+
+    /// The inverse of `mark_seen`.
+    ///
+    /// Errors if no copy of `tile` is seen other than those in our hand, in
+    /// which case nothing is changed. As in `mark_seen`, only akas count for
+    /// an aka, and only the plain copies for a plain 5.
+    pub fn unmark_seen(&mut self, tile: Tile) -> Result<()> {
+        ensure!(!tile.is_unknown(), "attempt to unmark an unknown tile");
+        let tile_id = tile.deaka().as_usize();
+        let (seen, in_hand) = match aka_id_of(tile) {
+            Some(aka_id) if tile.is_aka() => (self.akas_seen[aka_id], self.akas_in_hand[aka_id]),
+            Some(aka_id) => (
+                self.tiles_seen[tile_id] - self.akas_seen[aka_id],
+                self.tehai[tile_id] - self.akas_in_hand[aka_id],
+            ),
+            None => (self.tiles_seen[tile_id], self.tehai[tile_id]),
+        };
+        ensure!(
+            seen > in_hand,
+            "attempt to unmark {tile} held in hand or not seen",
+        );
+        if tile.is_aka() {
+            self.akas_seen[tile.as_usize() - tuz!(5mr)] -= 1;
+            self.doras_seen -= 1;
+        }
+        self.tiles_seen[tile_id] -= 1;
+        self.doras_seen -= self.dora_factor[tile_id];
+        self.refresh_wait_on(tile_id);
+        Ok(())
+    }
+
+    /// Updates `waits[tile_id]` for a change in `tiles_seen[tile_id]`, the
+    /// same way as `update_waits_and_furiten`. `waits` is only maintained at
+    /// 3n+1.
+    fn refresh_wait_on(&mut self, tile_id: usize) {
+        if self.last_cans.can_discard || self.shanten > 0 || self.tehai[tile_id] == 4 {
+            return;
+        }
+        let mut tehai_after = self.tehai;
+        tehai_after[tile_id] += 1;
+        self.waits[tile_id] = self.tiles_seen[tile_id] < 4
+            && shanten::calc_all(&tehai_after, self.tehai_len_div3) == -1;
+    }
+
     /// Updates `tehai`, `akas_in_hand` and `doras_owned`, but does not update
     /// `tiles_seen` or `doras_seen`.
     ///
@@ -759,15 +858,23 @@ impl PlayerState {
             let aka_id = tile.as_usize() - tuz!(5mr);
             match move_type {
                 MoveType::Tsumo => {
-                    self.akas_in_hand[aka_id] = true;
+                    self.akas_in_hand[aka_id] += 1;
                     self.doras_owned[0] += 1;
                 }
                 MoveType::Discard => {
-                    self.akas_in_hand[aka_id] = false;
+                    ensure!(
+                        self.akas_in_hand[aka_id] > 0,
+                        "rule violation: attempt to discard {tile} from void",
+                    );
+                    self.akas_in_hand[aka_id] -= 1;
                     self.doras_owned[0] -= 1;
                 }
                 MoveType::FuuroConsume => {
-                    self.akas_in_hand[aka_id] = false;
+                    ensure!(
+                        self.akas_in_hand[aka_id] > 0,
+                        "rule violation: attempt to consume {tile} from void",
+                    );
+                    self.akas_in_hand[aka_id] -= 1;
                 }
             }
         }
@@ -778,28 +885,30 @@ impl PlayerState {
     /// recounts doras (`doras_seen` and `doras_owned`) based on all the seen
     /// tiles.
     pub(super) fn add_dora_indicator(&mut self, tile: Tile) -> Result<()> {
+        if !self.dora_indicators.is_empty() {
+            let actor_rel =
+                (!self.pending_kan_actors.is_empty()).then(|| self.pending_kan_actors.remove(0));
+            self.kan_dora_actors.push(actor_rel);
+        }
         self.dora_indicators.push(tile);
 
         // Witness the tile so it can be added to `tiles_seen`, possibly also to
         // `doras_seen`. This must be done before adding `dora_factor`.
         self.witness_tile(tile)?;
 
-        let next = tile.next();
+        let next = tile.dora_from_indicator();
         self.dora_factor[next.as_usize()] += 1;
 
         // Count new dora in my tehai
         self.doras_owned[0] += self.tehai[next.as_usize()];
 
         // Count new dora in everyone's fuuro
-        for i in 0..4 {
-            self.doras_owned[i] += self.fuuro_overview[i]
+        for (owned, melds) in self.doras_owned.iter_mut().zip(&self.melds) {
+            *owned += melds
                 .iter()
-                .flatten()
+                .flat_map(|m| m.tiles())
                 .filter(|t| t.deaka() == next)
                 .count() as u8;
-            if self.ankan_overview[i].contains(&next) {
-                self.doras_owned[i] += 4;
-            }
         }
 
         // Add `doras_seen` based on `tiles_seen`
@@ -807,6 +916,39 @@ impl PlayerState {
         Ok(())
     }
 
+    /// Must be called after our pon or daiminkan of `pai` from `target` has
+    /// been added to `self.pons` or `self.minkans`.
+    fn update_pao_actor(&mut self, target: u8, pai: Tile) {
+        if !pai.is_jihai() {
+            return;
+        }
+        let mut jihais = 0_u8;
+        self.pons
+            .iter()
+            .chain(&self.minkans)
+            .filter(|&&t| t >= tu8!(E))
+            .for_each(|&t| jihais |= 1 << (t - tu8!(E)));
+        let daisangen_confirmed = (jihais & 0b1110000) == 0b1110000;
+        let daisuushi_confirmed = (jihais & 0b0001111) == 0b0001111;
+        if daisangen_confirmed && pai.is_dragon() || daisuushi_confirmed && pai.is_wind() {
+            self.pao_actor = Some(target);
+        }
+    }
+
+    fn update_safe_tiles(&mut self, actor_rel: usize, pai: Tile) {
+        let tid = pai.deaka().as_usize();
+        self.safe_tiles[actor_rel][tid] = true;
+        self.safe_tiles_this_cycle[actor_rel].fill(false);
+        for i in (0..4).filter(|&i| i != actor_rel) {
+            // Any tile passed after riichi is furiten forever.
+            if self.riichi_accepted[i] {
+                self.safe_tiles[i][tid] = true;
+            } else {
+                self.safe_tiles_this_cycle[i][tid] = true;
+            }
+        }
+    }
+
     pub(super) fn pad_kawa_for_pon_or_daiminkan(&mut self, abs_actor: u8, abs_target: u8) {
         let mut i = (abs_target + 1) % 4;
         while i != abs_actor {
@@ -873,6 +1015,11 @@ impl PlayerState {
     /// allow `-1` and it will be written as `0` in order for
     /// `_shanten_discards` to be calculated properly.
     pub(super) fn update_shanten(&mut self) {
+        debug_assert!(
+            shanten::is_len_consistent(&self.tehai, self.tehai_len_div3),
+            "tehai does not match tehai_len_div3 {}",
+            self.tehai_len_div3,
+        );
         self.shanten = shanten::calc_all(&self.tehai, self.tehai_len_div3).max(0);
         debug_assert!(matches!(self.shanten, 0..=6));
     }
@@ -971,3 +1118,14 @@ impl PlayerState {
         Rankings::new(scores_abs).rank_by_player[self.player_id as usize]
     }
 }
+
+/// The index into `akas_seen` of the 5 that `tile` is a copy of, which may
+/// be the aka itself or a plain one.
+const fn aka_id_of(tile: Tile) -> Option<usize> {
+    let aka = tile.akaize();
+    if aka.is_aka() {
+        Some(aka.as_usize() - tuz!(5mr))
+    } else {
+        None
+    }
+}
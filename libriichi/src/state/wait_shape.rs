@@ -0,0 +1,57 @@
+use crate::tile::Tile;
+
+/// The form of a single wait. Waits of 七対子 and 国士無双 count as tanki.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WaitKind {
+    Ryanmen,
+    Kanchan,
+    Penchan,
+    Shanpon,
+    Tanki,
+}
+
+/// The wait forms of a tenpai hand, see `PlayerState::wait_shape`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WaitShape {
+    /// Every tile that completes the hand, in tile order, each with all the
+    /// distinct kinds it completes the hand as over every decomposition, in
+    /// `WaitKind` order.
+    pub waits: Vec<(Tile, Vec<WaitKind>)>,
+}
+
+impl WaitKind {
+    /// The fu added by the wait, which is 2 for kanchan, penchan and tanki.
+    #[inline]
+    #[must_use]
+    pub const fn fu(self) -> u8 {
+        match self {
+            Self::Kanchan | Self::Penchan | Self::Tanki => 2,
+            Self::Ryanmen | Self::Shanpon => 0,
+        }
+    }
+}
+
+impl WaitShape {
+    /// The distinct kinds over all the waits, in `WaitKind` order.
+    #[must_use]
+    pub fn kinds(&self) -> Vec<WaitKind> {
+        let mut kinds: Vec<_> = self
+            .waits
+            .iter()
+            .flat_map(|(_, kinds)| kinds.iter().copied())
+            .collect();
+        kinds.sort_unstable();
+        kinds.dedup();
+        kinds
+    }
+
+    /// Whether winning on `tile` can be read as a ryanmen wait, which is
+    /// required by 平和.
+    #[must_use]
+    pub fn is_ryanmen_on(&self, tile: Tile) -> bool {
+        let tile = tile.deaka();
+        self.waits
+            .iter()
+            .any(|(t, kinds)| *t == tile && kinds.contains(&WaitKind::Ryanmen))
+    }
+}
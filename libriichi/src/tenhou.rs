@@ -0,0 +1,567 @@
+//! Conversion from tenhou.net/6 format logs to mjai events.
+//!
+//! In tenhou.net/6 format, each kyoku only records the haipai, takes and
+//! discards of every player separately, so the actual order of events has to
+//! be rebuilt by replaying the turns. Calls are matched against the discard by
+//! peeking the next take of the other players, where pon and kan take
+//! precedence over chi.
+
+use crate::mjai::Event;
+use crate::tile::Tile;
+use crate::{must_tile, tu8};
+use std::collections::VecDeque;
+use std::mem;
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json as json;
+
+/// A kyoku converted from a tenhou.net/6 log.
+#[derive(Debug, Clone)]
+pub struct Kyoku {
+    /// From `start_kyoku` to `end_kyoku`, which can be fed into `PlayerState`
+    /// directly.
+    pub events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+struct RawLog {
+    log: Vec<Vec<json::Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawItem {
+    Tile(u8),
+    Naki(String),
+}
+
+#[derive(Default)]
+struct Replayer {
+    events: Vec<Event>,
+    takes: [VecDeque<RawItem>; 4],
+    discards: [VecDeque<RawItem>; 4],
+    kan_doras: VecDeque<Tile>,
+    riichi_declared: [bool; 4],
+
+    riichi_to_be_accepted: Option<u8>,
+    need_new_dora_at_discard: bool,
+    need_new_dora_at_tsumo: bool,
+}
+
+/// Parses a tenhou.net/6 format log into kyokus of mjai events.
+pub fn parse_log(json: &str) -> Result<Vec<Kyoku>> {
+    let raw: RawLog = json::from_str(json).context("not a tenhou.net/6 log")?;
+    raw.log
+        .into_iter()
+        .enumerate()
+        .map(|(i, kyoku)| parse_kyoku(kyoku).with_context(|| format!("in log[{i}]")))
+        .collect()
+}
+
+fn parse_kyoku(raw: Vec<json::Value>) -> Result<Kyoku> {
+    ensure!(
+        raw.len() == 17,
+        "a kyoku must have 17 items, got {}",
+        raw.len(),
+    );
+    let mut raw = raw.into_iter();
+
+    let [kyoku_idx, honba, kyotaku]: [u8; 3] = take(&mut raw)?;
+    ensure!(kyoku_idx < 16, "invalid kyoku index {kyoku_idx}");
+    let scores: [i32; 4] = take(&mut raw)?;
+    let doras: Vec<u8> = take(&mut raw)?;
+    let uras: Vec<u8> = take(&mut raw)?;
+
+    let mut replayer = Replayer::default();
+    let mut tehais = [[Tile::default(); 13]; 4];
+    for (i, tehai) in tehais.iter_mut().enumerate() {
+        let haipai: Vec<u8> = take(&mut raw)?;
+        ensure!(haipai.len() == 13, "haipai of {i} is not 13 tiles");
+        for (t, id) in tehai.iter_mut().zip(haipai) {
+            *t = tile_from_id(id)?;
+        }
+        replayer.takes[i] = take(&mut raw)?;
+        replayer.discards[i] = take(&mut raw)?;
+    }
+    let result: Vec<json::Value> = take(&mut raw)?;
+
+    let mut doras = doras.into_iter().map(tile_from_id);
+    let dora_marker = doras.next().context("no dora indicator")??;
+    replayer.kan_doras = doras.collect::<Result<_>>()?;
+    let uras = uras
+        .into_iter()
+        .map(tile_from_id)
+        .collect::<Result<Vec<_>>>()?;
+
+    let oya = kyoku_idx % 4;
+    replayer.events.push(Event::StartKyoku {
+        bakaze: must_tile!(tu8!(E) + kyoku_idx / 4),
+        dora_marker,
+        kyoku: oya + 1,
+        honba,
+        kyotaku,
+        oya,
+        scores,
+        tehais,
+    });
+    replayer.replay(oya)?;
+    replayer.finish(&result, &uras)?;
+
+    Ok(Kyoku {
+        events: replayer.events,
+    })
+}
+
+impl Replayer {
+    fn replay(&mut self, oya: u8) -> Result<()> {
+        let mut actor = oya;
+        let mut need_tsumo = true;
+        let mut last_tsumo = None;
+
+        loop {
+            let actor_idx = actor as usize;
+            if need_tsumo {
+                let Some(item) = self.takes[actor_idx].pop_front() else {
+                    // ryukyoku or ron
+                    break;
+                };
+                let RawItem::Tile(id) = item else {
+                    bail!("{actor} is expected to tsumo but got {item:?}");
+                };
+                let pai = tile_from_id(id)?;
+
+                self.check_riichi_accepted();
+                if mem::take(&mut self.need_new_dora_at_tsumo) {
+                    self.add_new_dora()?;
+                }
+                self.events.push(Event::Tsumo { actor, pai });
+                last_tsumo = Some(pai);
+            }
+
+            let Some(item) = self.discards[actor_idx].pop_front() else {
+                // tsumo agari or 九種九牌
+                break;
+            };
+            let (pai, tsumogiri) = match item {
+                RawItem::Tile(60) => (last_tsumo.context("tsumogiri without tsumo")?, true),
+                RawItem::Tile(id) => (tile_from_id(id)?, false),
+                RawItem::Naki(naki) => {
+                    if let Some(rest) = naki.strip_prefix('r') {
+                        self.events.push(Event::Reach { actor });
+                        self.riichi_declared[actor_idx] = true;
+                        self.riichi_to_be_accepted = Some(actor);
+                        match rest.parse().context("invalid riichi discard")? {
+                            60 => (last_tsumo.context("tsumogiri without tsumo")?, true),
+                            id => (tile_from_id(id)?, false),
+                        }
+                    } else if naki.contains('a') {
+                        let (tile, others, _) = split_naki(&naki, 'a')?;
+                        let consumed = [tile, others[0], others[1], others[2]];
+                        if mem::take(&mut self.need_new_dora_at_discard) {
+                            self.add_new_dora()?;
+                        }
+                        self.events.push(Event::Ankan { actor, consumed });
+                        self.add_new_dora()?;
+                        need_tsumo = true;
+                        continue;
+                    } else if naki.contains('k') {
+                        let (pai, others, _) = split_naki(&naki, 'k')?;
+                        let consumed = [others[0], others[1], others[2]];
+                        if self.need_new_dora_at_discard {
+                            self.need_new_dora_at_tsumo = true;
+                        }
+                        self.events.push(Event::Kakan {
+                            actor,
+                            pai,
+                            consumed,
+                        });
+                        self.need_new_dora_at_discard = true;
+                        need_tsumo = true;
+                        continue;
+                    } else {
+                        bail!("unexpected {naki} in discards of {actor}");
+                    }
+                }
+            };
+
+            if mem::take(&mut self.need_new_dora_at_discard) {
+                self.add_new_dora()?;
+            }
+            self.events.push(Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            });
+
+            let Some(call) = self.find_call(actor, pai)? else {
+                actor = (actor + 1) % 4;
+                need_tsumo = true;
+                continue;
+            };
+            self.check_riichi_accepted();
+            match call {
+                Event::Chi { actor: caller, .. } | Event::Pon { actor: caller, .. } => {
+                    self.takes[caller as usize].pop_front();
+                    self.events.push(call);
+                    actor = caller;
+                    need_tsumo = false;
+                }
+                Event::Daiminkan { actor: caller, .. } => {
+                    self.takes[caller as usize].pop_front();
+                    // The discard after a daiminkan is recorded as 0.
+                    let placeholder = self.discards[caller as usize].pop_front();
+                    ensure!(
+                        matches!(placeholder, Some(RawItem::Tile(0))),
+                        "expected 0 in discards of {caller} after daiminkan",
+                    );
+                    if self.need_new_dora_at_discard {
+                        self.need_new_dora_at_tsumo = true;
+                    }
+                    self.events.push(call);
+                    self.need_new_dora_at_discard = true;
+                    actor = caller;
+                    need_tsumo = true;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the chi, pon or daiminkan event if any player calls `pai`
+    /// discarded by `target`.
+    fn find_call(&self, target: u8, pai: Tile) -> Result<Option<Event>> {
+        for rel in 1..4 {
+            let actor = (target + rel) % 4;
+            let Some(RawItem::Naki(naki)) = self.takes[actor as usize].front() else {
+                continue;
+            };
+            let (marker, kamicha_pos, toimen_pos, shimocha_pos) = if naki.contains('p') {
+                ('p', 0, 2, 4)
+            } else if naki.contains('m') {
+                ('m', 0, 2, 6)
+            } else {
+                continue;
+            };
+
+            let (called, others, pos) = split_naki(naki, marker)?;
+            let from_rel = match pos {
+                p if p == kamicha_pos => 3,
+                p if p == toimen_pos => 2,
+                p if p == shimocha_pos => 1,
+                _ => bail!("invalid naki {naki}"),
+            };
+            if (actor + from_rel) % 4 != target || called != pai {
+                continue;
+            }
+
+            return Ok(Some(if marker == 'p' {
+                ensure!(others.len() == 2, "invalid pon {naki}");
+                Event::Pon {
+                    actor,
+                    target,
+                    pai,
+                    consumed: [others[0], others[1]],
+                }
+            } else {
+                ensure!(others.len() == 3, "invalid daiminkan {naki}");
+                Event::Daiminkan {
+                    actor,
+                    target,
+                    pai,
+                    consumed: [others[0], others[1], others[2]],
+                }
+            }));
+        }
+
+        // Chi is only possible from kamicha.
+        let actor = (target + 1) % 4;
+        if let Some(RawItem::Naki(naki)) = self.takes[actor as usize].front()
+            && naki.starts_with('c')
+        {
+            let (called, others, _) = split_naki(naki, 'c')?;
+            ensure!(
+                called == pai && others.len() == 2,
+                "invalid chi {naki} on {pai}",
+            );
+            return Ok(Some(Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed: [others[0], others[1]],
+            }));
+        }
+
+        Ok(None)
+    }
+
+    fn finish(&mut self, result: &[json::Value], uras: &[Tile]) -> Result<()> {
+        let kind = result
+            .first()
+            .and_then(|v| v.as_str())
+            .context("invalid kyoku result")?;
+
+        if kind == "和了" {
+            ensure!(
+                result.len() >= 3 && !result.len().is_multiple_of(2),
+                "invalid hora result",
+            );
+            for pair in result[1..].chunks_exact(2) {
+                let deltas: [i32; 4] = json::from_value(pair[0].clone())?;
+                let info: Vec<json::Value> = json::from_value(pair[1].clone())?;
+                let (actor, target) = match info.as_slice() {
+                    [json::Value::Number(a), json::Value::Number(t), ..] => (
+                        a.as_u64().context("invalid hora actor")? as u8,
+                        t.as_u64().context("invalid hora target")? as u8,
+                    ),
+                    _ => bail!("invalid hora info"),
+                };
+                ensure!(actor < 4 && target < 4, "invalid hora info");
+
+                let ura_markers = self.riichi_declared[actor as usize].then(|| uras.to_vec());
+                self.events.push(Event::Hora {
+                    actor,
+                    target,
+                    deltas: Some(deltas),
+                    ura_markers,
+                });
+            }
+        } else {
+            let deltas = result
+                .get(1)
+                .map(|v| json::from_value(v.clone()))
+                .transpose()?;
+            self.events.push(Event::Ryukyoku { deltas });
+        }
+
+        self.events.push(Event::EndKyoku);
+        Ok(())
+    }
+
+    fn check_riichi_accepted(&mut self) {
+        if let Some(actor) = self.riichi_to_be_accepted.take() {
+            self.events.push(Event::ReachAccepted { actor });
+        }
+    }
+
+    fn add_new_dora(&mut self) -> Result<()> {
+        let dora_marker = self
+            .kan_doras
+            .pop_front()
+            .context("kan without a new dora indicator")?;
+        self.events.push(Event::Dora { dora_marker });
+        Ok(())
+    }
+}
+
+fn take<T: DeserializeOwned>(raw: &mut impl Iterator<Item = json::Value>) -> Result<T> {
+    let v = raw.next().context("unexpected end of kyoku")?;
+    Ok(json::from_value(v)?)
+}
+
+/// Converts tenhou's tile representation, where 11-19 are 1m-9m, 21-29 are
+/// 1p-9p, 31-39 are 1s-9s, 41-47 are ESWNPFC and 51-53 are 5mr, 5pr and 5sr.
+fn tile_from_id(id: u8) -> Result<Tile> {
+    let tid = match id {
+        11..=19 => id - 11,
+        21..=29 => id - 21 + 9,
+        31..=39 => id - 31 + 18,
+        41..=47 => id - 41 + 27,
+        51..=53 => id - 51 + tu8!(5mr),
+        _ => bail!("invalid tile {id}"),
+    };
+    Ok(must_tile!(tid))
+}
+
+/// Splits a naki string like `41p4141` into the tile right after `marker`, the
+/// other tiles in order, and the position of `marker`.
+fn split_naki(naki: &str, marker: char) -> Result<(Tile, Vec<Tile>, usize)> {
+    let pos = naki.find(marker).context("marker not found")?;
+    let parse = |s: &str| -> Result<Vec<Tile>> {
+        ensure!(
+            s.len().is_multiple_of(2) && s.is_ascii(),
+            "invalid naki {naki}"
+        );
+        (0..s.len())
+            .step_by(2)
+            .map(|i| tile_from_id(s[i..i + 2].parse()?))
+            .collect()
+    };
+
+    let after = parse(&naki[pos + 1..])?;
+    let (&tile, rest) = after.split_first().context("no tile after marker")?;
+    let mut others = parse(&naki[..pos])?;
+    others.extend_from_slice(rest);
+    Ok((tile, others, pos))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::PlayerState;
+    use crate::t;
+
+    #[test]
+    fn tile_ids() {
+        assert_eq!(tile_from_id(11).unwrap(), t!(1m));
+        assert_eq!(tile_from_id(29).unwrap(), t!(9p));
+        assert_eq!(tile_from_id(35).unwrap(), t!(5s));
+        assert_eq!(tile_from_id(45).unwrap(), t!(P));
+        assert_eq!(tile_from_id(52).unwrap(), t!(5pr));
+        tile_from_id(10).unwrap_err();
+        tile_from_id(60).unwrap_err();
+    }
+
+    #[test]
+    fn parse_and_replay() {
+        let log = r#"{
+            "title": ["", ""],
+            "name": ["A", "B", "C", "D"],
+            "rule": {"disp": "般南喰赤", "aka": 1},
+            "log": [
+                [
+                    [0, 0, 0], [25000, 25000, 25000, 25000], [11], [19],
+                    [11, 12, 13, 24, 52, 26, 37, 38, 39, 32, 33, 41, 41], [47, 16], ["r60", 60],
+                    [14, 15, 17, 22, 23, 27, 28, 29, 31, 34, 35, 36, 42], [45, 21], [60, 34],
+                    [14, 17, 18, 21, 22, 27, 29, 31, 35, 36, 43, 44, 46], [], [],
+                    [45, 45, 11, 22, 23, 24, 25, 33, 34, 35, 42, 43, 19], ["45p4545"], [19],
+                    ["和了", [13000, -12000, 0, 0], [0, 1, 0, "満貫12000点", "立直(1飜)", "ドラ(1飜)", "赤ドラ(1飜)", "裏ドラ(1飜)"]]
+                ],
+                [
+                    [0, 1, 0], [37000, 13000, 25000, 25000], [35], [],
+                    [11, 19, 21, 29, 31, 39, 41, 42, 43, 44, 12, 13, 14], [45], [],
+                    [15, 16, 17, 18, 22, 23, 24, 25, 26, 27, 32, 33, 34], [], [],
+                    [15, 16, 17, 18, 22, 23, 24, 25, 26, 27, 32, 33, 34], [], [],
+                    [15, 16, 17, 18, 22, 23, 24, 25, 26, 27, 32, 33, 34], [], [],
+                    ["九種九牌"]
+                ]
+            ]
+        }"#;
+        let kyokus = parse_log(log).unwrap();
+        assert_eq!(kyokus.len(), 2);
+
+        let events = &kyokus[0].events;
+        let pon_idx = events
+            .iter()
+            .position(|ev| matches!(ev, Event::Pon { .. }))
+            .unwrap();
+        assert_eq!(
+            events[pon_idx],
+            Event::Pon {
+                actor: 3,
+                target: 1,
+                pai: t!(P),
+                consumed: [t!(P), t!(P)],
+            },
+        );
+        assert_eq!(
+            events[pon_idx - 1],
+            Event::Dahai {
+                actor: 1,
+                pai: t!(P),
+                tsumogiri: true,
+            }
+        );
+
+        let mut states = [0, 1, 2, 3].map(PlayerState::new);
+        let mut scores = [0; 4];
+        for ev in events {
+            for state in &mut states {
+                state.update(ev).unwrap();
+            }
+            match *ev {
+                Event::StartKyoku { scores: s, .. } => scores = s,
+                Event::ReachAccepted { actor } => scores[actor as usize] -= 1000,
+                Event::Hora {
+                    deltas: Some(deltas),
+                    ..
+                } => {
+                    for (s, d) in scores.iter_mut().zip(deltas) {
+                        *s += d;
+                    }
+                }
+                _ => (),
+            }
+        }
+        let Event::StartKyoku {
+            scores: next_scores,
+            ..
+        } = kyokus[1].events[0]
+        else {
+            panic!("not a start_kyoku");
+        };
+        assert_eq!(scores, next_scores);
+
+        let events = &kyokus[1].events;
+        assert!(matches!(events[1], Event::Tsumo { actor: 0, .. }));
+        assert_eq!(events[2], Event::Ryukyoku { deltas: None });
+        assert_eq!(events[3], Event::EndKyoku);
+    }
+
+    #[test]
+    fn calls_kan_and_hora() {
+        // Player 2 chis from 1, 1 pons from 3 and 0 daiminkans from 2, before
+        // 3 rons the discard of 1 with a tanyao pinfu and the kan dora.
+        let log = r#"{
+            "title": ["", ""],
+            "name": ["A", "B", "C", "D"],
+            "rule": {"disp": "般南喰赤", "aka": 1},
+            "log": [[
+                [0, 0, 0], [25000, 25000, 25000, 25000], [34, 16], [],
+                [11, 19, 19, 19, 22, 23, 27, 33, 34, 35, 41, 43, 46], [42, "19m191919", 47], [11, 0, 60],
+                [14, 18, 21, 24, 26, 29, 31, 38, 44, 45, 45, 47, 47], [44, "45p4545", 13], [26, 21, 38],
+                [12, 15, 18, 27, 28, 31, 32, 33, 36, 39, 42, 44, 46], ["c262728", 19], [46, 60],
+                [12, 13, 14, 15, 16, 17, 23, 24, 25, 32, 32, 36, 37], [45], [60],
+                ["和了", [0, -3900, 0, 3900], [3, 1, 3, "30符3飜3900点", "平和(1飜)", "断幺九(1飜)", "ドラ(1飜)"]]
+            ]]
+        }"#;
+        let expected = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"4s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","9m","9m","9m","2p","3p","7p","3s","4s","5s","E","W","F"],["4m","8m","1p","4p","6p","9p","1s","8s","N","P","P","C","C"],["2m","5m","8m","7p","8p","1s","2s","3s","6s","9s","S","N","F"],["2m","3m","4m","5m","6m","7m","3p","4p","5p","2s","2s","6s","7s"]]}
+            {"type":"tsumo","actor":0,"pai":"S"}
+            {"type":"dahai","actor":0,"pai":"1m","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"N"}
+            {"type":"dahai","actor":1,"pai":"6p","tsumogiri":false}
+            {"type":"chi","actor":2,"target":1,"pai":"6p","consumed":["7p","8p"]}
+            {"type":"dahai","actor":2,"pai":"F","tsumogiri":false}
+            {"type":"tsumo","actor":3,"pai":"P"}
+            {"type":"dahai","actor":3,"pai":"P","tsumogiri":true}
+            {"type":"pon","actor":1,"target":3,"pai":"P","consumed":["P","P"]}
+            {"type":"dahai","actor":1,"pai":"1p","tsumogiri":false}
+            {"type":"tsumo","actor":2,"pai":"9m"}
+            {"type":"dahai","actor":2,"pai":"9m","tsumogiri":true}
+            {"type":"daiminkan","actor":0,"target":2,"pai":"9m","consumed":["9m","9m","9m"]}
+            {"type":"tsumo","actor":0,"pai":"C"}
+            {"type":"dora","dora_marker":"6m"}
+            {"type":"dahai","actor":0,"pai":"C","tsumogiri":true}
+            {"type":"tsumo","actor":1,"pai":"3m"}
+            {"type":"dahai","actor":1,"pai":"8s","tsumogiri":false}
+            {"type":"hora","actor":3,"target":1,"deltas":[0,-3900,0,3900]}
+            {"type":"end_kyoku"}
+        "#;
+        let expected: Vec<Event> = expected
+            .trim()
+            .lines()
+            .map(|l| json::from_str(l).unwrap())
+            .collect();
+
+        let kyokus = parse_log(log).unwrap();
+        assert_eq!(kyokus.len(), 1);
+        let events = &kyokus[0].events;
+        assert_eq!(*events, expected);
+
+        let mut states = [0, 1, 2, 3].map(PlayerState::new);
+        for ev in events {
+            if matches!(ev, Event::Hora { .. }) {
+                assert!(states[3].last_cans().can_ron_agari);
+                let point = states[3].agari_points(true, &[]).unwrap();
+                assert_eq!(point.ron, 3900);
+            }
+            for state in &mut states {
+                state.update(ev).unwrap();
+            }
+        }
+    }
+}
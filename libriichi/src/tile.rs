@@ -7,6 +7,7 @@ use std::sync::LazyLock;
 
 use ahash::AHashMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tinyvec::ArrayVec;
 
 const MJAI_PAI_STRINGS_LEN: usize = 3 * 9 + 4 + 3 + 3 + 1;
 const MJAI_PAI_STRINGS: [&str; MJAI_PAI_STRINGS_LEN] = [
@@ -17,6 +18,14 @@ const MJAI_PAI_STRINGS: [&str; MJAI_PAI_STRINGS_LEN] = [
     "5mr", "5pr", "5sr", // aka
     "?",   // unknown
 ];
+const TENHOU_PAI_STRINGS: [&str; MJAI_PAI_STRINGS_LEN] = [
+    "1m", "2m", "3m", "4m", "5m", "6m", "7m", "8m", "9m", // m
+    "1p", "2p", "3p", "4p", "5p", "6p", "7p", "8p", "9p", // p
+    "1s", "2s", "3s", "4s", "5s", "6s", "7s", "8s", "9s", // s
+    "1z", "2z", "3z", "4z", "5z", "6z", "7z", // z
+    "0m", "0p", "0s", // aka
+    "?",  // unknown
+];
 const DISCARD_PRIORITIES: [u8; 38] = [
     6, 5, 4, 3, 2, 3, 4, 5, 6, // m
     6, 5, 4, 3, 2, 3, 4, 5, 6, // p
@@ -33,6 +42,13 @@ static MJAI_PAI_STRINGS_MAP: LazyLock<AHashMap<&'static str, Tile>> = LazyLock::
         .map(|(id, &s)| (s, Tile::try_from(id).unwrap()))
         .collect()
 });
+static TENHOU_PAI_STRINGS_MAP: LazyLock<AHashMap<&'static str, Tile>> = LazyLock::new(|| {
+    TENHOU_PAI_STRINGS
+        .iter()
+        .enumerate()
+        .map(|(id, &s)| (s, Tile::try_from(id).unwrap()))
+        .collect()
+});
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Tile(u8);
@@ -43,6 +59,15 @@ pub enum InvalidTile {
     String(String),
 }
 
+/// Number of aka fives of each suit (m, p, s) in the wall.
+///
+/// All copies of the same aka share the same tile ID, e.g. both red 5p of the
+/// 4-aka ruleset are `5pr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AkaConfig {
+    pub counts: [u8; 3],
+}
+
 impl Tile {
     /// # Safety
     /// Calling this method with an out-of-bounds tile ID is undefined behavior.
@@ -106,6 +131,35 @@ impl Tile {
         )
     }
 
+    /// 風牌
+    #[inline]
+    #[must_use]
+    pub const fn is_wind(self) -> bool {
+        matches_tu8!(self.0, E | S | W | N)
+    }
+
+    /// 三元牌
+    #[inline]
+    #[must_use]
+    pub const fn is_dragon(self) -> bool {
+        matches_tu8!(self.0, P | F | C)
+    }
+
+    /// 中張牌, including akas.
+    #[inline]
+    #[must_use]
+    pub const fn is_simple(self) -> bool {
+        !self.is_yaokyuu() && !self.is_unknown()
+    }
+
+    /// Whether a triplet of the tile counts as 役牌, i.e. it is a dragon, the
+    /// round wind or the seat wind.
+    #[inline]
+    #[must_use]
+    pub const fn is_yakuhai(self, bakaze: Self, jikaze: Self) -> bool {
+        self.is_dragon() || self.0 == bakaze.0 || self.0 == jikaze.0
+    }
+
     #[inline]
     #[must_use]
     pub const fn is_unknown(self) -> bool {
@@ -149,6 +203,23 @@ impl Tile {
         }
     }
 
+    /// Returns the dora indicated by `self` as an indicator. Suited tiles wrap
+    /// around from 9 to 1, winds cycle E -> S -> W -> N -> E and dragons
+    /// cycle P -> F -> C -> P. Akas are deaka'd.
+    #[inline]
+    #[must_use]
+    pub const fn dora_from_indicator(self) -> Self {
+        self.next()
+    }
+
+    /// The inverse of `dora_from_indicator`, i.e. the indicator that makes
+    /// `self` a dora, which wraps around from 1 to 9, E to N and P to C.
+    #[inline]
+    #[must_use]
+    pub const fn dora_indicator_for(self) -> Self {
+        self.prev()
+    }
+
     #[inline]
     #[must_use]
     pub const fn augment(self) -> Self {
@@ -166,6 +237,50 @@ impl Tile {
         if self.is_aka() { ret.akaize() } else { ret }
     }
 
+    /// Returns the suji (筋) partners of `self`, which are the tiles 3 apart
+    /// in the same suit, e.g. 1m and 7m for 4m. 1, 2, 3, 7, 8 and 9 have only
+    /// one, and jihai have none. The results are never aka.
+    #[must_use]
+    pub fn suji_partners(self) -> ArrayVec<[Self; 2]> {
+        let mut ret = ArrayVec::new();
+        if self.is_unknown() || self.is_jihai() {
+            return ret;
+        }
+        let tid = self.deaka().0;
+        let num = tid % 9;
+        if num >= 3 {
+            ret.push(Self(tid - 3));
+        }
+        if num <= 5 {
+            ret.push(Self(tid + 3));
+        }
+        ret
+    }
+
+    /// Returns the shuntsu starting at `self`, e.g. 7m 8m 9m for 7m, or `None`
+    /// for jihai and the tiles that would run past 9. The results are never
+    /// aka.
+    #[inline]
+    #[must_use]
+    pub const fn seq(self) -> Option<[Self; 3]> {
+        if self.is_unknown() || self.is_jihai() {
+            return None;
+        }
+        let tid = self.deaka().0;
+        if tid % 9 > 6 {
+            return None;
+        }
+        Some([Self(tid), Self(tid + 1), Self(tid + 2)])
+    }
+
+    /// Returns the kotsu of `self`. The results are never aka.
+    #[inline]
+    #[must_use]
+    pub const fn triplet(self) -> [Self; 3] {
+        let tile = self.deaka();
+        [tile; 3]
+    }
+
     /// `Ordering::Equal` iff `self == other`
     #[inline]
     #[must_use]
@@ -177,6 +292,57 @@ impl Tile {
             o => o,
         }
     }
+
+    /// Parses a single tile in tenhou.net/2 notation, where 0m, 0p and 0s are
+    /// the akas and 1z to 7z are E, S, W, N, P, F and C. `?` is accepted for
+    /// unknown tiles as well.
+    ///
+    /// `FromStr` and `Display` use the mjai notation instead.
+    pub fn from_tenhou_str(s: &str) -> Result<Self, InvalidTile> {
+        TENHOU_PAI_STRINGS_MAP
+            .get(s)
+            .copied()
+            .ok_or_else(|| InvalidTile::String(s.to_owned()))
+    }
+
+    /// The inverse of `from_tenhou_str`.
+    #[inline]
+    #[must_use]
+    pub const fn to_tenhou_str(self) -> &'static str {
+        TENHOU_PAI_STRINGS[self.0 as usize]
+    }
+}
+
+/// Marks the suited tiles that can't be waited on by any ryanmen, because for
+/// each ryanmen shape that could wait on it, all 4 copies of one of its
+/// tiles are already visible in `tiles_seen` (壁, or no chance). For example,
+/// all 4 of 2m visible makes 1m marked, and all 4 of 3m makes both 1m and 2m
+/// marked. Jihai are never marked.
+#[must_use]
+pub fn kabe(tiles_seen: &[u8; 34]) -> [bool; 34] {
+    let gone = |tid: usize| tiles_seen[tid] >= 4;
+    let mut ret = [false; 34];
+    for (tid, marked) in ret.iter_mut().enumerate().take(3 * 9) {
+        let num = tid % 9;
+        // ryanmen of (n+1, n+2) and (n-2, n-1)
+        let upper_gone = num > 5 || gone(tid + 1) || gone(tid + 2);
+        let lower_gone = num < 3 || gone(tid - 1) || gone(tid - 2);
+        *marked = upper_gone && lower_gone;
+    }
+    ret
+}
+
+/// Returns how many doras each tile is worth with `indicators`, which is
+/// what `PlayerState` keeps as `dora_factor`. A tile indicated more than once
+/// counts once for each indicator. Unknown tiles are ignored, and akas are not
+/// counted, as they are worth one more dora regardless of the indicators.
+#[must_use]
+pub fn doras_from_indicators(indicators: &[Tile]) -> [u8; 34] {
+    let mut factors = [0; 34];
+    for ind in indicators.iter().filter(|t| !t.is_unknown()) {
+        factors[ind.dora_from_indicator().as_usize()] += 1;
+    }
+    factors
 }
 
 impl Default for Tile {
@@ -185,6 +351,25 @@ impl Default for Tile {
     }
 }
 
+impl AkaConfig {
+    pub const NONE: Self = Self { counts: [0, 0, 0] };
+    pub const STANDARD: Self = Self { counts: [1, 1, 1] };
+    /// With two red 5p.
+    pub const FOUR: Self = Self { counts: [1, 2, 1] };
+
+    #[inline]
+    #[must_use]
+    pub const fn total(self) -> u8 {
+        self.counts[0] + self.counts[1] + self.counts[2]
+    }
+}
+
+impl Default for AkaConfig {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
 impl TryFrom<u8> for Tile {
     type Error = InvalidTile;
 
@@ -264,6 +449,7 @@ impl Error for InvalidTile {}
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{must_tile, tuz};
 
     #[test]
     fn convert() {
@@ -281,6 +467,184 @@ mod test {
         Tile::try_from(u8::MAX).unwrap_err();
     }
 
+    #[test]
+    fn classification() {
+        let yaokyuu = tuz![1m, 9m, 1p, 9p, 1s, 9s, E, S, W, N, P, F, C];
+        for tid in 0..34 {
+            let tile = must_tile!(tid);
+            let is_honor = tid >= tuz!(E);
+            let is_wind = (tuz!(E)..=tuz!(N)).contains(&tid);
+            let is_dragon = (tuz!(P)..=tuz!(C)).contains(&tid);
+            assert_eq!(tile.is_jihai(), is_honor, "{tile}");
+            assert_eq!(tile.is_wind(), is_wind, "{tile}");
+            assert_eq!(tile.is_dragon(), is_dragon, "{tile}");
+            assert_eq!(tile.is_yaokyuu(), yaokyuu.contains(&tid), "{tile}");
+            assert_eq!(tile.is_simple(), !yaokyuu.contains(&tid), "{tile}");
+            assert_eq!(tile.is_simple(), tile.akaize().is_simple(), "{tile}");
+
+            // 南場 seen from 西家
+            let is_yakuhai = is_dragon || tid == tuz!(S) || tid == tuz!(W);
+            assert_eq!(tile.is_yakuhai(t!(S), t!(W)), is_yakuhai, "{tile}");
+            // 連風牌
+            let is_yakuhai = is_dragon || tid == tuz!(E);
+            assert_eq!(tile.is_yakuhai(t!(E), t!(E)), is_yakuhai, "{tile}");
+        }
+        assert!(!t!(?).is_simple());
+        assert!(!t!(?).is_yaokyuu());
+    }
+
+    #[test]
+    fn tenhou_notation() {
+        for tid in 0..MJAI_PAI_STRINGS_LEN {
+            let tile = Tile::try_from(tid).unwrap();
+            let s = tile.to_tenhou_str();
+            assert_eq!(Tile::from_tenhou_str(s).unwrap(), tile);
+            assert_eq!(Tile::from_tenhou_str(s).unwrap().to_tenhou_str(), s);
+        }
+
+        assert_eq!(Tile::from_tenhou_str("0m").unwrap(), t!(5mr));
+        assert_eq!(Tile::from_tenhou_str("0p").unwrap(), t!(5pr));
+        assert_eq!(Tile::from_tenhou_str("0s").unwrap(), t!(5sr));
+        assert_eq!(Tile::from_tenhou_str("5s").unwrap(), t!(5s));
+        assert_eq!(Tile::from_tenhou_str("1z").unwrap(), t!(E));
+        assert_eq!(Tile::from_tenhou_str("7z").unwrap(), t!(C));
+        assert_eq!(t!(N).to_tenhou_str(), "4z");
+        assert_eq!(t!(5pr).to_tenhou_str(), "0p");
+
+        Tile::from_tenhou_str("0z").unwrap_err();
+        Tile::from_tenhou_str("8z").unwrap_err();
+        Tile::from_tenhou_str("E").unwrap_err();
+        Tile::from_tenhou_str("5mr").unwrap_err();
+        Tile::from_tenhou_str("").unwrap_err();
+    }
+
+    #[test]
+    fn dora_wrap() {
+        let cases = [
+            (t!(1m), t!(2m)),
+            (t!(9m), t!(1m)),
+            (t!(5pr), t!(6p)),
+            (t!(9p), t!(1p)),
+            (t!(9s), t!(1s)),
+            (t!(E), t!(S)),
+            (t!(W), t!(N)),
+            (t!(N), t!(E)),
+            (t!(P), t!(F)),
+            (t!(F), t!(C)),
+            (t!(C), t!(P)),
+        ];
+        for (ind, dora) in cases {
+            assert_eq!(ind.dora_from_indicator(), dora);
+            assert_eq!(dora.dora_indicator_for(), ind.deaka());
+        }
+        assert_eq!(t!(?).dora_from_indicator(), t!(?));
+    }
+
+    #[test]
+    fn suji_partners() {
+        let expected: [&[u8]; 9] = [
+            &[4],
+            &[5],
+            &[6],
+            &[1, 7],
+            &[2, 8],
+            &[3, 9],
+            &[4],
+            &[5],
+            &[6],
+        ];
+        for suit in ["m", "p", "s"] {
+            for (num, partners) in (1..=9).zip(expected) {
+                let tile: Tile = format!("{num}{suit}").parse().unwrap();
+                let expected: Vec<Tile> = partners
+                    .iter()
+                    .map(|p| format!("{p}{suit}").parse().unwrap())
+                    .collect();
+                assert_eq!(tile.suji_partners().as_slice(), expected, "{tile}");
+                for partner in tile.suji_partners() {
+                    assert!(partner.suji_partners().contains(&tile), "{tile}");
+                }
+            }
+        }
+        assert_eq!(t!(5mr).suji_partners().as_slice(), t![2m, 8m]);
+        assert_eq!(t!(5sr).suji_partners().as_slice(), t![2s, 8s]);
+        for tile in t![E, S, W, N, P, F, C, ?] {
+            assert!(tile.suji_partners().is_empty(), "{tile}");
+        }
+    }
+
+    #[test]
+    fn kabe() {
+        let marked = |visible: &[Tile]| {
+            let mut tiles_seen = [0; 34];
+            for t in visible {
+                tiles_seen[t.as_usize()] = 4;
+            }
+            let ret = super::kabe(&tiles_seen);
+            (0..34)
+                .filter(|&tid| ret[tid])
+                .map(|tid| Tile::try_from(tid).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert!(marked(&[]).is_empty());
+        assert_eq!(marked(&t![2m,]), t![1m,]);
+        assert_eq!(marked(&t![3p,]), t![1p, 2p]);
+        assert_eq!(marked(&t![7s,]), t![8s, 9s]);
+        assert_eq!(marked(&t![4m, 6m]), t![2m, 3m, 5m, 7m, 8m]);
+        assert_eq!(marked(&t![3m, 7m]), t![1m, 2m, 5m, 8m, 9m]);
+        assert!(marked(&t![E, C]).is_empty());
+    }
+
+    #[test]
+    fn seq_triplet() {
+        for kind in 0..3 {
+            for num in 0..9 {
+                let tile = Tile::try_from(kind * 9 + num).unwrap();
+                let seq = tile.seq();
+                if num <= 6 {
+                    let seq = seq.unwrap();
+                    assert_eq!(seq[0], tile);
+                    assert_eq!(seq[1], tile.next());
+                    assert_eq!(seq[2], tile.next().next());
+                    assert!(seq.iter().all(|t| t.as_u8() / 9 == kind));
+                } else {
+                    assert_eq!(seq, None);
+                }
+            }
+        }
+        assert_eq!(t!(7m).seq(), Some(t![7m, 8m, 9m]));
+        assert_eq!(t!(8m).seq(), None);
+        assert_eq!(t!(9m).seq(), None);
+        assert_eq!(t!(1p).seq(), Some(t![1p, 2p, 3p]));
+        assert_eq!(t!(5sr).seq(), Some(t![5s, 6s, 7s]));
+        for tile in t![E, S, W, N, P, F, C, ?] {
+            assert_eq!(tile.seq(), None);
+        }
+
+        assert_eq!(t!(7m).triplet(), t![7m, 7m, 7m]);
+        assert_eq!(t!(5mr).triplet(), t![5m, 5m, 5m]);
+        assert_eq!(t!(C).triplet(), t![C, C, C]);
+    }
+
+    #[test]
+    fn doras_from_indicators() {
+        let factors = super::doras_from_indicators(&t![9m, 5mr, N, C, 4m, ?]);
+        let mut expected = [0; 34];
+        expected[tuz!(1m)] = 1;
+        expected[tuz!(5m)] = 1;
+        expected[tuz!(6m)] = 1;
+        expected[tuz!(E)] = 1;
+        expected[tuz!(P)] = 1;
+        assert_eq!(factors, expected);
+
+        let factors = super::doras_from_indicators(&t![3s, 3s, W]);
+        assert_eq!(factors[tuz!(4s)], 2);
+        assert_eq!(factors[tuz!(N)], 1);
+        assert_eq!(factors.iter().sum::<u8>(), 3);
+
+        assert_eq!(super::doras_from_indicators(&[]), [0; 34]);
+    }
+
     #[test]
     fn next_prev() {
         MJAI_PAI_STRINGS.iter().take(37).for_each(|&s| {
@@ -0,0 +1,202 @@
+//! Deterministic walls for self-play, so that a whole game can be replayed
+//! from a single `u64`.
+//!
+//! A wall is stored in draw order. The first 52 tiles are the haipai, dealt
+//! 4-4-4-1 starting from oya, followed by the 70 tiles of the live wall, and
+//! the last 14 tiles make up the dead wall.
+//!
+//! The dead wall is 7 stacks of 2, where stack 0 is the one at the far end.
+//! Rinshan tiles are drawn from stack 0 and 1, upper tile first. Dora
+//! indicators are the upper tiles of stack 2 to 6 in reveal order, and the ura
+//! indicators are the lower tiles right below them.
+
+use crate::tile::{AkaConfig, Tile};
+use crate::{must_tile, tu8};
+use std::array;
+
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use sha3::{Digest, Sha3_256};
+
+const HAIPAI_LEN: usize = 13 * 4;
+const LIVE_WALL_LEN: usize = 70;
+const DEAD_WALL_START: usize = HAIPAI_LEN + LIVE_WALL_LEN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wall {
+    tiles: [Tile; 136],
+}
+
+impl Wall {
+    /// Shuffles a wall with `AkaConfig::STANDARD`.
+    #[must_use]
+    pub fn from_seed(seed: u64) -> Self {
+        Self::from_seed_with_aka(seed, AkaConfig::STANDARD)
+    }
+
+    #[must_use]
+    pub fn from_seed_with_aka(seed: u64, aka_config: AkaConfig) -> Self {
+        let mut tiles = unshuffled(aka_config);
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        tiles.shuffle(&mut rng);
+        Self { tiles }
+    }
+
+    /// Derives the wall of a specific kyoku from the seed of the whole game.
+    #[must_use]
+    pub fn for_kyoku(game_seed: u64, kyoku: u8, honba: u8, aka_config: AkaConfig) -> Self {
+        let hash: [u8; 32] = Sha3_256::new()
+            .chain_update(game_seed.to_le_bytes())
+            .chain_update([kyoku, honba])
+            .finalize()
+            .into();
+        let seed = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        Self::from_seed_with_aka(seed, aka_config)
+    }
+
+    /// All the 136 tiles in draw order.
+    #[inline]
+    #[must_use]
+    pub const fn tiles(&self) -> &[Tile; 136] {
+        &self.tiles
+    }
+
+    /// `oya` is the absolute seat of oya, and the return value is indexed by
+    /// absolute seats as well.
+    #[must_use]
+    pub fn haipai(&self, oya: u8) -> [[Tile; 13]; 4] {
+        let mut haipai = [[Tile::default(); 13]; 4];
+        let mut tiles = self.tiles[..HAIPAI_LEN].iter().copied();
+        for round in 0..4 {
+            let (start, len) = if round < 3 { (round * 4, 4) } else { (12, 1) };
+            for i in 0..4 {
+                let seat = (oya as usize + i) % 4;
+                for slot in &mut haipai[seat][start..start + len] {
+                    *slot = tiles.next().unwrap();
+                }
+            }
+        }
+        haipai
+    }
+
+    /// The 70 tiles to tsumo from, in order.
+    #[inline]
+    #[must_use]
+    pub fn live_wall(&self) -> &[Tile] {
+        &self.tiles[HAIPAI_LEN..DEAD_WALL_START]
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn dead_wall(&self) -> &[Tile] {
+        &self.tiles[DEAD_WALL_START..]
+    }
+
+    /// Rinshan tiles in draw order.
+    #[must_use]
+    pub fn rinshan(&self) -> [Tile; 4] {
+        array::from_fn(|i| self.dead_wall()[i])
+    }
+
+    /// Dora indicators in reveal order.
+    #[must_use]
+    pub fn dora_indicators(&self) -> [Tile; 5] {
+        array::from_fn(|i| self.dead_wall()[4 + i * 2])
+    }
+
+    /// Ura indicators in the same order as `dora_indicators`.
+    #[must_use]
+    pub fn ura_indicators(&self) -> [Tile; 5] {
+        array::from_fn(|i| self.dead_wall()[5 + i * 2])
+    }
+}
+
+fn unshuffled(aka_config: AkaConfig) -> [Tile; 136] {
+    array::from_fn(|i| {
+        let tid = (i / 4) as u8;
+        let copy = (i % 4) as u8;
+        let tile = must_tile!(tid);
+        let akas = match tid {
+            tu8!(5m) => aka_config.counts[0],
+            tu8!(5p) => aka_config.counts[1],
+            tu8!(5s) => aka_config.counts[2],
+            _ => 0,
+        };
+        if copy < akas { tile.akaize() } else { tile }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::t;
+
+    fn counts(tiles: &[Tile]) -> [u8; 37] {
+        let mut counts = [0; 37];
+        for t in tiles {
+            counts[t.as_usize()] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn deterministic() {
+        assert_eq!(Wall::from_seed(42), Wall::from_seed(42));
+        assert_ne!(Wall::from_seed(42), Wall::from_seed(43));
+        assert_eq!(
+            Wall::for_kyoku(42, 1, 0, AkaConfig::STANDARD),
+            Wall::for_kyoku(42, 1, 0, AkaConfig::STANDARD),
+        );
+        assert_ne!(
+            Wall::for_kyoku(42, 1, 0, AkaConfig::STANDARD),
+            Wall::for_kyoku(42, 1, 1, AkaConfig::STANDARD),
+        );
+    }
+
+    #[test]
+    fn aka_counts() {
+        for aka_config in [AkaConfig::NONE, AkaConfig::STANDARD, AkaConfig::FOUR] {
+            let counts = counts(Wall::from_seed_with_aka(7, aka_config).tiles());
+            for (tid, &c) in counts.iter().enumerate().take(34) {
+                let akas = match tid as u8 {
+                    tu8!(5m) => aka_config.counts[0],
+                    tu8!(5p) => aka_config.counts[1],
+                    tu8!(5s) => aka_config.counts[2],
+                    _ => 0,
+                };
+                assert_eq!(c, 4 - akas);
+            }
+            assert_eq!(counts[t!(5mr).as_usize()], aka_config.counts[0]);
+            assert_eq!(counts[t!(5pr).as_usize()], aka_config.counts[1]);
+            assert_eq!(counts[t!(5sr).as_usize()], aka_config.counts[2]);
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let wall = Wall::from_seed(0);
+        let tiles = wall.tiles();
+
+        let haipai = wall.haipai(2);
+        assert_eq!(haipai[2][..4], tiles[..4]);
+        assert_eq!(haipai[3][..4], tiles[4..8]);
+        assert_eq!(haipai[1][..4], tiles[12..16]);
+        assert_eq!(haipai[2][12], tiles[48]);
+        assert_eq!(haipai[1][12], tiles[51]);
+
+        assert_eq!(wall.live_wall().len(), 70);
+        assert_eq!(wall.live_wall()[0], tiles[52]);
+        assert_eq!(wall.dead_wall().len(), 14);
+        assert_eq!(wall.rinshan(), tiles[122..126]);
+        assert_eq!(wall.dora_indicators()[0], tiles[126]);
+        assert_eq!(wall.ura_indicators()[0], tiles[127]);
+        assert_eq!(wall.ura_indicators()[4], tiles[135]);
+
+        let mut all = haipai.concat();
+        all.extend_from_slice(wall.live_wall());
+        all.extend_from_slice(&wall.rinshan());
+        all.extend_from_slice(&wall.dora_indicators());
+        all.extend_from_slice(&wall.ura_indicators());
+        assert_eq!(counts(&all), counts(tiles));
+    }
+}
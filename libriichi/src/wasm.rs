@@ -0,0 +1,142 @@
+//! Hand analysis for the browser, enabled by the `wasm` feature.
+//!
+//! Tiles cross the boundary as mjai strings, in JSON. Build for
+//! `wasm32-unknown-unknown` with `--no-default-features --features wasm`, as
+//! `python` and `mimalloc` do not target it.
+
+use crate::algo::point::Point;
+use crate::mjai::Event;
+use crate::state::PlayerState;
+use crate::t;
+use crate::tile::Tile;
+
+use anyhow::{Result, ensure};
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnalyzeRequest {
+    /// 14 tiles, the last of which is the tsumo.
+    tehai: Vec<Tile>,
+    dora_marker: Tile,
+    #[serde(default = "default_bakaze")]
+    bakaze: Tile,
+    /// The relative seat of the oya, 0 for ourselves.
+    #[serde(default)]
+    oya: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct AnalyzeResponse {
+    shanten: i8,
+    /// Akas are listed on their own.
+    discard_candidates: Vec<Tile>,
+    can_tsumo_agari: bool,
+    /// Only present if `can_tsumo_agari`, without ura doras.
+    tsumo_agari_point: Option<Point>,
+}
+
+const fn default_bakaze() -> Tile {
+    t!(E)
+}
+
+/// Analyzes a closed hand at tsumo, returning its shanten, the tiles it can
+/// discard and the points of a tsumo agari if it is one. The hand is taken as
+/// past its first turn.
+///
+/// `hand_json` is like
+/// `{"tehai":["1m","2m",...],"dora_marker":"5p","bakaze":"E","oya":0}`,
+/// where `bakaze` and `oya` are optional.
+#[wasm_bindgen]
+pub fn analyze(hand_json: &str) -> Result<String, JsError> {
+    analyze_json(hand_json).map_err(|err| JsError::new(&format!("{err:#}")))
+}
+
+fn analyze_json(hand_json: &str) -> Result<String> {
+    let req: AnalyzeRequest = json::from_str(hand_json)?;
+    ensure!(
+        req.tehai.len() == 14,
+        "expected 14 tiles, got {}",
+        req.tehai.len(),
+    );
+    ensure!(req.oya < 4, "invalid oya {}", req.oya);
+
+    let mut state = PlayerState::new(0);
+    let mut tehais = [[t!(?); 13]; 4];
+    tehais[0].copy_from_slice(&req.tehai[..13]);
+    state.update(&Event::StartKyoku {
+        bakaze: req.bakaze,
+        dora_marker: req.dora_marker,
+        kyoku: req.oya + 1,
+        honba: 0,
+        kyotaku: 0,
+        oya: req.oya,
+        scores: [25000; 4],
+        tehais,
+    })?;
+    // Not on the first turn, where 天和, 地和 and ダブル立直 apply.
+    state.pass_first_turn()?;
+    let cans = state.update(&Event::Tsumo {
+        actor: 0,
+        pai: req.tehai[13],
+    })?;
+
+    let discard_candidates = state
+        .discard_candidates_aka()
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b)
+        .map(|(i, _)| Tile::try_from(i).unwrap())
+        .collect();
+    let tsumo_agari_point = if cans.can_tsumo_agari {
+        Some(state.agari_points(false, &[])?)
+    } else {
+        None
+    };
+    let res = AnalyzeResponse {
+        shanten: state.real_time_shanten(),
+        discard_candidates,
+        can_tsumo_agari: cans.can_tsumo_agari,
+        tsumo_agari_point,
+    };
+    Ok(json::to_string(&res)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn analyze_hand() {
+        let res = analyze_json(
+            r#"{"tehai":["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","E","1s"],"dora_marker":"9p"}"#,
+        )
+        .unwrap();
+        let res: json::Value = json::from_str(&res).unwrap();
+        assert_eq!(res["shanten"], -1);
+        assert_eq!(res["can_tsumo_agari"], true);
+        // 門前清自摸和 only, 30 fu with the 連風牌 pair, 500 all as oya.
+        assert_eq!(res["tsumo_agari_point"]["tsumo_ko"], 500);
+
+        let res = analyze_json(
+            r#"{"tehai":["1m","2m","3m","4p","5p","6p","7s","8s","9s","2s","3s","E","N","5sr"],"dora_marker":"9p","oya":2}"#,
+        )
+        .unwrap();
+        let res: json::Value = json::from_str(&res).unwrap();
+        assert_eq!(res["shanten"], 1);
+        assert_eq!(res["can_tsumo_agari"], false);
+        assert!(res["tsumo_agari_point"].is_null());
+        assert!(
+            res["discard_candidates"]
+                .as_array()
+                .unwrap()
+                .contains(&"5sr".into())
+        );
+
+        analyze_json(r#"{"tehai":["1m"],"dora_marker":"9p"}"#).unwrap_err();
+        analyze_json(r#"{"tehai":[],"dora_marker":"0z"}"#).unwrap_err();
+    }
+}